@@ -0,0 +1,124 @@
+// Copyright (c) Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Report the bytes sent on the wire and written to the wal, and the CPU spent compressing, for
+//! blocks shaped like the ones filled by the `TransactionGenerator`.
+//! Run with `cargo bench -p mysticeti-core --bench compression`.
+
+use std::time::{Duration, Instant};
+
+use mysticeti_core::{
+    compression::Compression,
+    config::ClientParameters,
+    types::{BaseStatement, BlockReference, StatementBlock, Transaction},
+    wal::walf,
+};
+
+const BLOCKS: usize = 100;
+/// Transactions per second, the generator fills one block every 100ms.
+const LOADS: [usize; 3] = [100, 10_000, 50_000];
+
+/// Same transactions as the generator: a timestamp, a counter and zeros.
+fn blocks(load: usize) -> Vec<Vec<u8>> {
+    let transaction_size = ClientParameters::default().transaction_size;
+    let mut counter = 0u64;
+    (0..BLOCKS)
+        .map(|round| {
+            let timestamp = (1_700_000_000_000 + round as u64 * 100).to_le_bytes();
+            let statements = (0..load / 10)
+                .map(|_| {
+                    counter += 1;
+                    let mut transaction = Vec::with_capacity(transaction_size);
+                    transaction.extend_from_slice(&timestamp);
+                    transaction.extend_from_slice(&counter.to_le_bytes());
+                    transaction.resize(transaction_size, 0);
+                    BaseStatement::Share(Transaction::new(transaction))
+                })
+                .collect();
+            let includes = (0..4)
+                .map(|authority| *StatementBlock::new_genesis(authority).reference())
+                .collect::<Vec<BlockReference>>();
+            let block = StatementBlock::new(
+                0,
+                round as u64 + 1,
+                includes,
+                statements,
+                0,
+                false,
+                Default::default(),
+            );
+            bincode::serialize(&block).unwrap()
+        })
+        .collect()
+}
+
+/// The bytes of the network frames carrying the blocks and the time spent encoding and
+/// decoding them.
+fn wire(compression: Compression, blocks: &[Vec<u8>]) -> (usize, Duration) {
+    let start = Instant::now();
+    let mut bytes = 0;
+    for block in blocks {
+        // Length prefix, plus the compression of the frame on compressed connections
+        bytes += 4;
+        if compression == Compression::None {
+            bytes += block.len();
+            continue;
+        }
+        bytes += 1;
+        match compression.maybe_compress(block) {
+            Some(compressed) => {
+                bytes += compressed.len();
+                let decompressed = compression.decompress(&compressed, block.len()).unwrap();
+                assert_eq!(decompressed.len(), block.len());
+            }
+            None => bytes += block.len(),
+        }
+    }
+    (bytes, start.elapsed())
+}
+
+/// The size of a wal holding the blocks and the time spent writing and reading them back.
+fn disk(compression: Compression, blocks: &[Vec<u8>]) -> (u64, Duration) {
+    let file = tempfile::tempfile().unwrap();
+    let (mut writer, reader) = walf(file.try_clone().unwrap()).unwrap();
+    writer.set_compression(compression);
+    let start = Instant::now();
+    let positions: Vec<_> = blocks
+        .iter()
+        .map(|block| writer.write(1, block).unwrap())
+        .collect();
+    for (position, block) in positions.into_iter().zip(blocks) {
+        let (_, read) = reader.read(position).unwrap();
+        assert_eq!(read.len(), block.len());
+    }
+    let elapsed = start.elapsed();
+    (file.metadata().unwrap().len(), elapsed)
+}
+
+fn main() {
+    println!(
+        "{:>11}  {:>11}  {:>14}  {:>14}  {:>14}  {:>14}",
+        "load (tx/s)",
+        "compression",
+        "wire (bytes)",
+        "wire cpu (ms)",
+        "disk (bytes)",
+        "disk cpu (ms)"
+    );
+    for load in LOADS {
+        let blocks = blocks(load);
+        for compression in [Compression::None, Compression::Deflate] {
+            let (wire_bytes, wire_time) = wire(compression, &blocks);
+            let (disk_bytes, disk_time) = disk(compression, &blocks);
+            println!(
+                "{:>11}  {:>11}  {:>14}  {:>14.2}  {:>14}  {:>14.2}",
+                load,
+                format!("{compression:?}"),
+                wire_bytes,
+                wire_time.as_secs_f64() * 1000.0,
+                disk_bytes,
+                disk_time.as_secs_f64() * 1000.0,
+            );
+        }
+    }
+}
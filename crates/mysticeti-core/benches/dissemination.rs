@@ -0,0 +1,112 @@
+// Copyright (c) Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Compare the throughput and latency of a local committee embedding transactions in blocks and
+//! disseminating them in batches. Every run boots a fresh committee on localhost and reads the
+//! committed transactions off the metrics of the validators.
+//! Run with `cargo bench -p mysticeti-core --bench dissemination` (from a scratch directory, the
+//! validators write their files to the working directory).
+
+use std::{fs, time::Duration};
+
+use mysticeti_core::{
+    batch::Dissemination,
+    committee::Committee,
+    config::{ClientParameters, NodePrivateConfig, NodePublicConfig},
+    metrics::LATENCY_S,
+    prometheus::METRICS_ROUTE,
+    resolver::PeerAddress,
+    types::AuthorityIndex,
+    validator::Validator,
+};
+
+const COMMITTEE_SIZE: usize = 4;
+/// Transactions per second submitted to each validator.
+const LOADS: [usize; 3] = [1_000, 10_000, 30_000];
+const WARMUP: Duration = Duration::from_secs(5);
+const DURATION: Duration = Duration::from_secs(20);
+
+/// The committed transactions and the sum of their latencies (in seconds), across validators.
+async fn committed(addresses: &[PeerAddress]) -> (f64, f64) {
+    let (mut count, mut sum) = (0.0, 0.0);
+    for address in addresses {
+        let metrics = reqwest::get(format!("http://{address}{METRICS_ROUTE}"))
+            .await
+            .unwrap()
+            .text()
+            .await
+            .unwrap();
+        for line in metrics.lines() {
+            let Some((name, value)) = line.rsplit_once(' ') else {
+                continue;
+            };
+            if name == format!("{LATENCY_S}_count{{workload=\"shared\"}}") {
+                count += value.parse::<f64>().unwrap();
+            } else if name == format!("{LATENCY_S}_sum{{workload=\"shared\"}}") {
+                sum += value.parse::<f64>().unwrap();
+            }
+        }
+    }
+    (count, sum)
+}
+
+/// Returns the committed transactions per second and their average latency in milliseconds.
+async fn run(dissemination: Dissemination, load: usize, port_offset: u16) -> (f64, f64) {
+    let committee = Committee::new_for_benchmarks(COMMITTEE_SIZE);
+    let mut public_config =
+        NodePublicConfig::new_for_tests(COMMITTEE_SIZE).with_port_offset(port_offset);
+    public_config.parameters.dissemination = dissemination;
+    let client_parameters = ClientParameters {
+        load,
+        initial_delay: Duration::ZERO,
+        ..Default::default()
+    };
+    let dir = tempfile::tempdir().unwrap();
+    let private_configs = NodePrivateConfig::new_for_benchmarks(dir.path(), COMMITTEE_SIZE);
+    let mut validators = Vec::new();
+    for (i, private_config) in private_configs.into_iter().enumerate() {
+        fs::create_dir_all(&private_config.storage_path).unwrap();
+        let validator = Validator::start(
+            i as AuthorityIndex,
+            committee.clone(),
+            public_config.clone(),
+            private_config,
+            client_parameters.clone(),
+        )
+        .await
+        .unwrap();
+        validators.push(validator);
+    }
+    let addresses: Vec<_> = public_config.all_metric_addresses().collect();
+
+    tokio::time::sleep(WARMUP).await;
+    let (start_count, start_sum) = committed(&addresses).await;
+    tokio::time::sleep(DURATION).await;
+    let (end_count, end_sum) = committed(&addresses).await;
+
+    for validator in validators {
+        validator.stop().await;
+    }
+    // Every validator commits every transaction
+    let transactions = (end_count - start_count) / COMMITTEE_SIZE as f64;
+    let latency = (end_sum - start_sum) / (end_count - start_count) * 1000.0;
+    (transactions / DURATION.as_secs_f64(), latency)
+}
+
+fn main() {
+    println!("load (tx/s)  dissemination  committed (tx/s)  latency (ms)");
+    let mut port_offset = 1000;
+    for load in LOADS {
+        for dissemination in [Dissemination::Inline, Dissemination::Batches] {
+            // A fresh runtime per run, dropping it stops all the tasks of the committee
+            let runtime = tokio::runtime::Runtime::new().unwrap();
+            let (throughput, latency) = runtime.block_on(run(dissemination, load, port_offset));
+            runtime.shutdown_timeout(Duration::from_secs(1));
+            port_offset += 2 * COMMITTEE_SIZE as u16;
+            println!(
+                "{load:>11}  {:>13}  {throughput:>16.0}  {latency:>12.0}",
+                format!("{dissemination:?}")
+            );
+        }
+    }
+}
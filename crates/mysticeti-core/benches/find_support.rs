@@ -0,0 +1,119 @@
+// Copyright (c) Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Compare the time the commit rule takes to decide the leaders of a dag when every block it
+//! traverses (see `BaseCommitter::find_support`) is read from the wal (cold block cache) and when
+//! the dag fits in the block cache (warm).
+//! Run with `cargo bench -p mysticeti-core --bench find_support`.
+
+use std::{sync::Arc, time::Instant};
+
+use mysticeti_core::{
+    block_store::{BlockStore, BlockWriter},
+    committee::Committee,
+    config::NodeParameters,
+    consensus::universal_committer::UniversalCommitterBuilder,
+    data::Data,
+    metrics::Metrics,
+    types::{BaseStatement, BlockReference, RoundNumber, StatementBlock, Transaction},
+    wal::walf,
+};
+use prometheus::Registry;
+
+const COMMITTEE_SIZES: [usize; 3] = [4, 10, 20];
+const ROUNDS: RoundNumber = 50;
+/// Transactions of 512 bytes in every block.
+const TRANSACTIONS: usize = 20;
+
+/// Write a fully connected dag to a wal, returns the wal file.
+fn write_dag(committee: &Arc<Committee>) -> std::fs::File {
+    let file = tempfile::tempfile().unwrap();
+    let (mut wal_writer, wal_reader) = walf(file.try_clone().unwrap()).unwrap();
+    let (metrics, _reporter) = Metrics::new(&Registry::new(), None);
+    let block_store =
+        BlockStore::open(0, Arc::new(wal_reader), &wal_writer, metrics, committee, 0).block_store;
+    let mut includes: Vec<BlockReference> = committee
+        .authorities()
+        .map(|authority| {
+            let genesis = StatementBlock::new_genesis(authority);
+            (&mut wal_writer, &block_store).insert_block(genesis.clone());
+            *genesis.reference()
+        })
+        .collect();
+    let statements: Vec<_> = (0..TRANSACTIONS)
+        .map(|_| BaseStatement::Share(Transaction::new(vec![0; 512])))
+        .collect();
+    for round in 1..=ROUNDS {
+        includes = committee
+            .authorities()
+            .map(|authority| {
+                let block = Data::new(StatementBlock::new(
+                    authority,
+                    round,
+                    includes.clone(),
+                    statements.clone(),
+                    0,
+                    false,
+                    Default::default(),
+                ));
+                (&mut wal_writer, &block_store).insert_block(block.clone());
+                *block.reference()
+            })
+            .collect();
+    }
+    file
+}
+
+/// Decide all the leaders of the dag twice, the first pass warms up the block cache. Returns the
+/// time the second pass takes (in milliseconds), the blocks it reads and its cache hit rate (in
+/// percent).
+fn decide(
+    committee: &Arc<Committee>,
+    file: &std::fs::File,
+    block_cache_size: usize,
+) -> (f64, u64, f64) {
+    let (wal_writer, wal_reader) = walf(file.try_clone().unwrap()).unwrap();
+    let (metrics, _reporter) = Metrics::new(&Registry::new(), None);
+    let block_store = BlockStore::open(
+        0,
+        Arc::new(wal_reader),
+        &wal_writer,
+        metrics.clone(),
+        committee,
+        block_cache_size,
+    )
+    .block_store;
+    let parameters = NodeParameters::default();
+    let committer = UniversalCommitterBuilder::new(committee.clone(), block_store, metrics.clone())
+        .with_wave_length(parameters.wave_length)
+        .with_number_of_leaders(parameters.number_of_leaders)
+        .with_pipeline(parameters.enable_pipelining)
+        .build();
+
+    committer.leader_statuses(1..=ROUNDS);
+    let (hits, misses) = (
+        metrics.block_store_cache_hits.get(),
+        metrics.block_store_loaded_blocks.get(),
+    );
+    let start = Instant::now();
+    committer.leader_statuses(1..=ROUNDS);
+    let elapsed = start.elapsed().as_secs_f64() * 1000.0;
+    let hits = metrics.block_store_cache_hits.get() - hits;
+    let misses = metrics.block_store_loaded_blocks.get() - misses;
+    let hit_rate = hits as f64 * 100.0 / (hits + misses).max(1) as f64;
+    (elapsed, hits + misses, hit_rate)
+}
+
+fn main() {
+    println!("committee  blocks read  cold (ms)  warm (ms)  warm hits (%)");
+    for committee_size in COMMITTEE_SIZES {
+        let committee = Committee::new_for_benchmarks(committee_size);
+        let file = write_dag(&committee);
+        // Every block is read from the wal
+        let (cold, blocks, _) = decide(&committee, &file, 0);
+        // The whole dag fits in the cache
+        let size = file.metadata().unwrap().len() as usize;
+        let (warm, _, hit_rate) = decide(&committee, &file, size);
+        println!("{committee_size:>9}  {blocks:>11}  {cold:>9.1}  {warm:>9.1}  {hit_rate:>13.1}");
+    }
+}
@@ -0,0 +1,121 @@
+// Copyright (c) Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Measure how long a fresh proposal takes to reach a peer over a connection saturated by large
+//! sync responses. The proposals are either queued behind the responses, as when all the messages
+//! to a peer shared a single queue, or sent in their own priority class.
+//! Run with `cargo bench -p mysticeti-core --bench priority`.
+
+use std::{
+    net::{Ipv4Addr, SocketAddr, SocketAddrV4},
+    time::{Duration, Instant},
+};
+
+use mysticeti_core::{
+    committee::Committee,
+    compression::Compression,
+    metrics::Metrics,
+    network::{Network, NetworkMessage, TransportProtocol},
+    protocol::Capabilities,
+    topology::Topology,
+    types::BlockReference,
+};
+use prometheus::Registry;
+
+/// The size of the responses to sync requests.
+const RESPONSE_SIZES: [usize; 3] = [64 * 1024, 1024 * 1024, 4 * 1024 * 1024];
+const PROPOSALS: usize = 100;
+const PROPOSAL_INTERVAL: Duration = Duration::from_millis(20);
+
+/// Returns the propagation latency of every proposal.
+async fn run(response_size: usize, prioritized: bool, port: u16) -> Vec<Duration> {
+    let committee = Committee::new_for_benchmarks(2);
+    let addresses: Vec<_> = (0..2)
+        .map(|i| SocketAddr::V4(SocketAddrV4::new(Ipv4Addr::LOCALHOST, port + i)))
+        .collect();
+    let networks = addresses.iter().enumerate().map(|(i, address)| {
+        Network::from_socket_addresses(
+            &addresses,
+            &[],
+            i,
+            *address,
+            Metrics::new(&Registry::default(), Some(&committee)).0,
+            Capabilities::new(Compression::None),
+            TransportProtocol::Tcp,
+            &Topology::Mesh,
+        )
+    });
+    let mut networks = futures::future::join_all(networks).await;
+    let mut connections = Vec::new();
+    for network in &mut networks {
+        connections.push(network.connection_receiver().recv().await.unwrap());
+    }
+    let mut receiver = connections.pop().unwrap();
+    let sender = connections.pop().unwrap();
+
+    // Responses listing as many references as fit in `response_size`
+    let reference_size = bincode::serialized_size(&BlockReference::default()).unwrap() as usize;
+    let response = vec![BlockReference::default(); response_size / reference_size];
+    let sync_sender = sender.sync_sender.clone();
+    let load = tokio::spawn(async move {
+        while sync_sender
+            .send(NetworkMessage::BlockNotFound(response.clone()))
+            .await
+            .is_ok()
+        {}
+    });
+
+    // Proposals carry the time they were sent at, in microseconds since the start
+    let start = Instant::now();
+    let proposals = if prioritized {
+        sender.sender.clone()
+    } else {
+        sender.sync_sender.clone()
+    };
+    let propose = tokio::spawn(async move {
+        for _ in 0..PROPOSALS {
+            tokio::time::sleep(PROPOSAL_INTERVAL).await;
+            let sent = start.elapsed().as_micros() as u64;
+            proposals
+                .send(NetworkMessage::SubscribeOwnFrom(sent))
+                .await
+                .ok();
+        }
+    });
+    let mut latencies = Vec::with_capacity(PROPOSALS);
+    while latencies.len() < PROPOSALS {
+        if let Some(NetworkMessage::SubscribeOwnFrom(sent)) = receiver.receiver.recv().await {
+            latencies.push(start.elapsed() - Duration::from_micros(sent));
+        }
+    }
+    propose.await.unwrap();
+    load.abort();
+    latencies
+}
+
+fn percentile(latencies: &mut [Duration], percentile: usize) -> f64 {
+    latencies.sort();
+    let index = (latencies.len() * percentile / 100).min(latencies.len() - 1);
+    latencies[index].as_secs_f64() * 1000.0
+}
+
+fn main() {
+    println!("response (KiB)  queueing     p50 (ms)  p99 (ms)");
+    let mut port = 6100;
+    for response_size in RESPONSE_SIZES {
+        for prioritized in [false, true] {
+            // A fresh runtime per run, dropping it stops all the tasks of the networks
+            let runtime = tokio::runtime::Runtime::new().unwrap();
+            let mut latencies = runtime.block_on(run(response_size, prioritized, port));
+            runtime.shutdown_timeout(Duration::from_secs(1));
+            port += 2;
+            println!(
+                "{:>14}  {:<11}  {:>8.1}  {:>8.1}",
+                response_size / 1024,
+                if prioritized { "prioritized" } else { "shared" },
+                percentile(&mut latencies, 50),
+                percentile(&mut latencies, 99)
+            );
+        }
+    }
+}
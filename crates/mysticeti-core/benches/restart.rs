@@ -0,0 +1,148 @@
+// Copyright (c) Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Compare the time a validator takes to restart with and without an index checkpoint, for
+//! increasing wal sizes. Every run lets a fresh committee grow its wal on localhost, stops it,
+//! then restarts the first validator from its index checkpoint and, after removing the
+//! checkpoint, from a replay of its whole wal.
+//! Run with `cargo bench -p mysticeti-core --bench restart`.
+
+use std::{
+    fs,
+    path::Path,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use mysticeti_core::{
+    committee::Committee,
+    config::{ClientParameters, NodePrivateConfig, NodePublicConfig},
+    types::AuthorityIndex,
+    validator::Validator,
+};
+
+const COMMITTEE_SIZE: usize = 4;
+/// Transactions per second submitted to each validator.
+const LOAD: usize = 10_000;
+/// How long the committee runs before the restart, the wal grows with it.
+const DURATIONS: [Duration; 3] = [
+    Duration::from_secs(5),
+    Duration::from_secs(15),
+    Duration::from_secs(30),
+];
+const INDEX_CHECKPOINT_PERIOD: u64 = 100;
+
+fn client_parameters() -> ClientParameters {
+    ClientParameters {
+        load: LOAD,
+        initial_delay: Duration::ZERO,
+        ..Default::default()
+    }
+}
+
+/// Start the validators and let them run for `duration`.
+async fn grow_wal(
+    committee: &Arc<Committee>,
+    public_config: &NodePublicConfig,
+    dir: &Path,
+    duration: Duration,
+) {
+    let private_configs = NodePrivateConfig::new_for_benchmarks(dir, COMMITTEE_SIZE);
+    let mut validators = Vec::new();
+    for (i, private_config) in private_configs.into_iter().enumerate() {
+        fs::create_dir_all(&private_config.storage_path).unwrap();
+        let validator = Validator::start(
+            i as AuthorityIndex,
+            committee.clone(),
+            public_config.clone(),
+            private_config,
+            client_parameters(),
+        )
+        .await
+        .unwrap();
+        validators.push(validator);
+    }
+    tokio::time::sleep(duration).await;
+    for validator in validators {
+        validator.stop().await;
+    }
+}
+
+/// The time the first validator takes to start.
+async fn restart(
+    committee: &Arc<Committee>,
+    public_config: NodePublicConfig,
+    private_config: NodePrivateConfig,
+) -> Duration {
+    let start = Instant::now();
+    let validator = Validator::start(
+        0,
+        committee.clone(),
+        public_config,
+        private_config,
+        client_parameters(),
+    )
+    .await
+    .unwrap();
+    let elapsed = start.elapsed();
+    validator.stop().await;
+    elapsed
+}
+
+/// Returns the size of the wal and the restart times with and without the index checkpoint.
+async fn run(duration: Duration, port_offset: u16) -> (u64, Duration, Duration) {
+    let committee = Committee::new_for_benchmarks(COMMITTEE_SIZE);
+    let public_config = |port_offset| {
+        let mut public_config =
+            NodePublicConfig::new_for_tests(COMMITTEE_SIZE).with_port_offset(port_offset);
+        public_config.parameters.index_checkpoint_period = INDEX_CHECKPOINT_PERIOD;
+        public_config
+    };
+    let dir = tempfile::tempdir().unwrap();
+    grow_wal(
+        &committee,
+        &public_config(port_offset),
+        dir.path(),
+        duration,
+    )
+    .await;
+
+    // The restarted validator listens on other ports, the committee ones may still be in use
+    let private_config = || {
+        NodePrivateConfig::new_for_benchmarks(dir.path(), COMMITTEE_SIZE)
+            .into_iter()
+            .next()
+            .unwrap()
+    };
+    let wal_size = fs::metadata(private_config().wal()).unwrap().len();
+    assert!(
+        private_config().index_checkpoint().exists(),
+        "No index checkpoint was taken"
+    );
+    let offset = port_offset + 2 * COMMITTEE_SIZE as u16;
+    let with_checkpoint = restart(&committee, public_config(offset), private_config()).await;
+    fs::remove_file(private_config().index_checkpoint()).unwrap();
+    let offset = offset + 2 * COMMITTEE_SIZE as u16;
+    let without_checkpoint = restart(&committee, public_config(offset), private_config()).await;
+    (wal_size, with_checkpoint, without_checkpoint)
+}
+
+fn main() {
+    println!("run (s)  wal (MB)  with checkpoint (ms)  without checkpoint (ms)");
+    let mut port_offset = 1100;
+    for duration in DURATIONS {
+        // A fresh runtime per run, dropping it stops all the tasks of the committee
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        let (wal_size, with_checkpoint, without_checkpoint) =
+            runtime.block_on(run(duration, port_offset));
+        runtime.shutdown_timeout(Duration::from_secs(1));
+        port_offset += 6 * COMMITTEE_SIZE as u16;
+        println!(
+            "{:>7}  {:>8.1}  {:>20}  {:>23}",
+            duration.as_secs(),
+            wal_size as f64 / 1e6,
+            with_checkpoint.as_millis(),
+            without_checkpoint.as_millis()
+        );
+    }
+}
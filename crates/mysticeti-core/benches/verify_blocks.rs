@@ -0,0 +1,73 @@
+// Copyright (c) Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Compare the throughput of verifying incoming blocks one by one and in batches.
+//! Run with `cargo bench -p mysticeti-core --bench verify_blocks`.
+
+use std::time::Instant;
+
+use mysticeti_core::{
+    block_verifier::{verify_blocks, MAX_BATCH_SIZE},
+    committee::Committee,
+    config::NodePrivateConfig,
+    types::{BlockReference, StatementBlock},
+};
+
+const COMMITTEE_SIZES: [usize; 3] = [10, 50, 100];
+const BLOCKS: usize = 2_000;
+
+fn signed_blocks(committee_size: usize) -> Vec<StatementBlock> {
+    let dir = tempfile::tempdir().unwrap();
+    let mut signers: Vec<_> = NodePrivateConfig::new_for_benchmarks(dir.path(), committee_size)
+        .into_iter()
+        .map(|config| config.into_block_signer().unwrap())
+        .collect();
+    let genesis: Vec<BlockReference> = (0..committee_size)
+        .map(|authority| *StatementBlock::new_genesis(authority as u64).reference())
+        .collect();
+    (0..BLOCKS)
+        .map(|i| {
+            let authority = i % committee_size;
+            // Blocks of the same authority differ by their creation time
+            StatementBlock::new_with_signer(
+                authority as u64,
+                1,
+                genesis.clone(),
+                vec![],
+                i as u128,
+                false,
+                signers[authority].as_mut(),
+            )
+            .unwrap()
+        })
+        .collect()
+}
+
+fn blocks_per_second(blocks: usize, start: Instant) -> f64 {
+    blocks as f64 / start.elapsed().as_secs_f64()
+}
+
+fn main() {
+    println!("committee  individual (blocks/s)  batched (blocks/s)");
+    for committee_size in COMMITTEE_SIZES {
+        let committee = Committee::new_for_benchmarks(committee_size);
+        let blocks = signed_blocks(committee_size);
+
+        let start = Instant::now();
+        for block in &blocks {
+            block.verify(&committee).unwrap();
+        }
+        let individual = blocks_per_second(blocks.len(), start);
+
+        let start = Instant::now();
+        for chunk in blocks.chunks(MAX_BATCH_SIZE) {
+            let chunk: Vec<_> = chunk.iter().collect();
+            for result in verify_blocks(&committee, &chunk) {
+                result.unwrap();
+            }
+        }
+        let batched = blocks_per_second(blocks.len(), start);
+
+        println!("{committee_size:>9}  {individual:>21.0}  {batched:>18.0}");
+    }
+}
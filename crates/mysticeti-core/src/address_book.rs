@@ -0,0 +1,302 @@
+// Copyright (c) Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! The addresses of the peers may change while we run. A validator moving to a new address
+//! announces it to its peers in an `AddressUpdate` signed with its authority key, before moving.
+//! The peers relay the update to their own peers and reconnect to the new address. Updates carry a
+//! sequence number, so that a stale update relayed late never overrides a newer one. Operators
+//! may also reload the addresses from the public config, see `AddressBook::reload`.
+
+use std::{
+    collections::HashMap,
+    fmt,
+    fs::{File, OpenOptions},
+    io,
+    os::unix::fs::FileExt,
+    path::Path,
+    sync::Arc,
+};
+
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use tokio::sync::{mpsc, watch};
+
+use crate::{
+    block_signer::BlockSigner,
+    committee::Committee,
+    crypto::{address_signing_digest, SignatureBytes},
+    network::NetworkMessage,
+    resolver::PeerAddress,
+    types::AuthorityIndex,
+};
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct AddressUpdate {
+    pub authority: AuthorityIndex,
+    /// Updates of the same authority with a higher sequence number replace the earlier ones.
+    pub sequence: u64,
+    /// The address the authority accepts connections on from now on.
+    pub address: PeerAddress,
+    signature: SignatureBytes,
+}
+
+impl AddressUpdate {
+    pub fn new(
+        authority: AuthorityIndex,
+        sequence: u64,
+        address: PeerAddress,
+        signer: &mut dyn BlockSigner,
+    ) -> io::Result<Self> {
+        let signature = signer.sign_address(authority, sequence, &address)?;
+        Ok(Self {
+            authority,
+            sequence,
+            address,
+            signature,
+        })
+    }
+
+    /// Check that the update is signed by its authority.
+    pub fn verify(&self, committee: &Committee) -> Result<(), ed25519_consensus::Error> {
+        let public_key = committee
+            .get_public_key(self.authority)
+            .ok_or(ed25519_consensus::Error::InvalidSignature)?;
+        let digest = address_signing_digest(self.authority, self.sequence, &self.address);
+        public_key.verify_digest(&digest, &self.signature)
+    }
+}
+
+impl fmt::Debug for AddressUpdate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}@{}#{}", self.authority, self.address, self.sequence)
+    }
+}
+
+/// The sequence number of our last address update, persisted so that the sequence numbers keep
+/// growing across restarts, whatever the clock does.
+pub struct AddressSequence {
+    file: Option<File>,
+    last: u64,
+}
+
+impl AddressSequence {
+    pub fn open(path: impl AsRef<Path>) -> io::Result<Self> {
+        let file = OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .read(true)
+            .write(true)
+            .open(path)?;
+        let mut buf = [0u8; 8];
+        let last = match file.metadata()?.len() {
+            0 => 0,
+            8 => {
+                file.read_exact_at(&mut buf, 0)?;
+                u64::from_le_bytes(buf)
+            }
+            length => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("Corrupted address sequence of {length} bytes"),
+                ))
+            }
+        };
+        Ok(Self {
+            file: Some(file),
+            last,
+        })
+    }
+
+    /// A sequence that is not persisted, only suitable for tests.
+    pub fn new_in_memory() -> Self {
+        Self {
+            file: None,
+            last: 0,
+        }
+    }
+
+    /// Durably take the next sequence number.
+    pub fn advance(&mut self) -> io::Result<u64> {
+        let next = self.last + 1;
+        if let Some(file) = &self.file {
+            file.write_all_at(&next.to_le_bytes(), 0)?;
+            file.sync_data()?;
+        }
+        self.last = next;
+        Ok(next)
+    }
+}
+
+/// The current addresses of the peers, shared by the network and the network syncer.
+#[derive(Clone, Default)]
+pub struct AddressBook {
+    inner: Arc<Mutex<AddressBookInner>>,
+}
+
+#[derive(Default)]
+struct AddressBookInner {
+    // The address of every peer we connect to, watched by the worker connecting to the peer
+    addresses: HashMap<usize, watch::Sender<PeerAddress>>,
+    // The latest update of every authority, sent to the peers as they connect
+    updates: HashMap<AuthorityIndex, AddressUpdate>,
+    // The connected peers, to relay the updates to
+    peers: HashMap<AuthorityIndex, mpsc::Sender<NetworkMessage>>,
+}
+
+impl AddressBook {
+    pub fn new(addresses: impl IntoIterator<Item = (usize, PeerAddress)>) -> Self {
+        let addresses = addresses
+            .into_iter()
+            .map(|(peer, address)| (peer, watch::channel(address).0))
+            .collect();
+        Self {
+            inner: Arc::new(Mutex::new(AddressBookInner {
+                addresses,
+                ..Default::default()
+            })),
+        }
+    }
+
+    pub fn address(&self, peer: usize) -> Option<PeerAddress> {
+        let inner = self.inner.lock();
+        inner
+            .addresses
+            .get(&peer)
+            .map(|address| address.borrow().clone())
+    }
+
+    /// Watch the address of a peer, the worker connecting to the peer reconnects as it changes.
+    pub(crate) fn subscribe(&self, peer: usize) -> watch::Receiver<PeerAddress> {
+        let inner = self.inner.lock();
+        inner
+            .addresses
+            .get(&peer)
+            .expect("Unknown peer")
+            .subscribe()
+    }
+
+    /// Replace the addresses of the peers by the addresses of the validators, in the order of the
+    /// authority index (as listed in the public config).
+    pub fn reload(&self, addresses: &[PeerAddress]) {
+        let inner = self.inner.lock();
+        for (peer, address) in addresses.iter().enumerate() {
+            let Some(current) = inner.addresses.get(&peer) else {
+                continue;
+            };
+            if *current.borrow() != *address {
+                tracing::info!("Reloaded the address of {peer}: {address}");
+                current.send_replace(address.clone());
+            }
+        }
+    }
+
+    /// Register a connected peer. Returns the updates known so far, to send to the peer.
+    pub fn register_peer(
+        &self,
+        peer: AuthorityIndex,
+        sender: mpsc::Sender<NetworkMessage>,
+    ) -> Vec<AddressUpdate> {
+        let mut inner = self.inner.lock();
+        inner.peers.insert(peer, sender);
+        inner.updates.values().cloned().collect()
+    }
+
+    pub fn remove_peer(&self, peer: AuthorityIndex) {
+        self.inner.lock().peers.remove(&peer);
+    }
+
+    /// Apply a verified update received from `from` (or announced by us). Returns the peers to
+    /// relay the update to, none if it is not newer than the updates known so far.
+    pub fn apply(
+        &self,
+        update: AddressUpdate,
+        from: AuthorityIndex,
+    ) -> Vec<mpsc::Sender<NetworkMessage>> {
+        let mut inner = self.inner.lock();
+        if let Some(known) = inner.updates.get(&update.authority) {
+            if known.sequence >= update.sequence {
+                return vec![];
+            }
+        }
+        if let Some(current) = inner.addresses.get(&(update.authority as usize)) {
+            tracing::info!("Authority {} moved to {}", update.authority, update.address);
+            current.send_replace(update.address.clone());
+        }
+        let authority = update.authority;
+        inner.updates.insert(authority, update);
+        inner
+            .peers
+            .iter()
+            .filter(|(peer, _)| **peer != from && **peer != authority)
+            .map(|(_, sender)| sender.clone())
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{committee::Authority, crypto::Signer};
+
+    fn address(port: u16) -> PeerAddress {
+        format!("validator:{port}").parse().unwrap()
+    }
+
+    #[test]
+    fn verify() {
+        let mut signers = Signer::new_for_test(2);
+        let committee = Committee::new(
+            signers
+                .iter()
+                .map(|signer| Authority::new(1, signer.public_key()))
+                .collect(),
+        );
+        let mut update = AddressUpdate::new(0, 1, address(1000), &mut signers[0]).unwrap();
+        assert!(update.verify(&committee).is_ok());
+        // The address and sequence number are covered by the signature
+        update.address = address(1001);
+        assert!(update.verify(&committee).is_err());
+        // Only the authority itself may announce its address
+        let forged = AddressUpdate::new(0, 1, address(1000), &mut signers[1]).unwrap();
+        assert!(forged.verify(&committee).is_err());
+    }
+
+    #[test]
+    fn apply() {
+        let mut signer = Signer::new_for_test(1).pop().unwrap();
+        let book = AddressBook::new([(1, address(1001)), (2, address(1002))]);
+        let mut watch = book.subscribe(1);
+        let (sender, _receiver) = mpsc::channel(1);
+        assert!(book.register_peer(2, sender.clone()).is_empty());
+        book.register_peer(3, sender);
+
+        let update = AddressUpdate::new(1, 2, address(2001), &mut signer).unwrap();
+        // Relayed to all the other peers, but not back to the peer it came from
+        assert_eq!(book.apply(update.clone(), 3).len(), 1);
+        assert!(watch.has_changed().unwrap());
+        assert_eq!(*watch.borrow_and_update(), address(2001));
+
+        // Updates already known, or older than the ones known, are dropped
+        assert!(book.apply(update, 2).is_empty());
+        let stale = AddressUpdate::new(1, 1, address(3001), &mut signer).unwrap();
+        assert!(book.apply(stale, 2).is_empty());
+        assert_eq!(book.address(1), Some(address(2001)));
+        assert_eq!(book.register_peer(4, mpsc::channel(1).0).len(), 1);
+
+        book.reload(&[address(1000), address(1001), address(1002)]);
+        assert_eq!(*watch.borrow_and_update(), address(1001));
+        assert_eq!(book.address(2), Some(address(1002)));
+    }
+
+    #[test]
+    fn address_sequence_persists() {
+        let dir = tempdir::TempDir::new("address_sequence_persists").unwrap();
+        let path = dir.path().join("sequence");
+        let mut sequence = AddressSequence::open(&path).unwrap();
+        assert_eq!(sequence.advance().unwrap(), 1);
+        assert_eq!(sequence.advance().unwrap(), 2);
+        drop(sequence);
+        let mut sequence = AddressSequence::open(&path).unwrap();
+        assert_eq!(sequence.advance().unwrap(), 3);
+    }
+}
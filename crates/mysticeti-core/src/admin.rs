@@ -0,0 +1,232 @@
+// Copyright (c) Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! JSON routes for the operators of a validator. They report the state of consensus, of the
+//! connections to the peers and of the storage, and allow to announce a new address to the peers
+//! (see `NetworkSyncer::announce_address`) and to stop the validator gracefully (see
+//! `Validator::await_shutdown_request`). Unlike the metrics, they are served by their own server,
+//! listening on the loopback interface unless configured otherwise (see
+//! `NodePrivateConfig::admin_address`), along with the routes reloading the parameters (see
+//! `reload`).
+
+use std::{
+    net::SocketAddr,
+    sync::{Arc, Weak},
+};
+
+use axum::{
+    extract::Query,
+    http::{header, StatusCode},
+    routing::{get, post},
+    Extension, Json, Router, Server,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    block_handler::BlockHandler,
+    dag_export::DagFormat,
+    net_sync::NetworkSyncerInner,
+    resolver::PeerAddress,
+    runtime::{Handle, JoinHandle},
+    syncer::CommitObserver,
+    types::{AuthorityIndex, RoundNumber},
+};
+
+pub const STATUS_ROUTE: &str = "/status";
+pub const PEERS_ROUTE: &str = "/peers";
+pub const MISSING_BLOCKS_ROUTE: &str = "/missing_blocks";
+pub const WAL_ROUTE: &str = "/wal";
+/// Exports part of the dag, see `DagQuery` for the parameters.
+pub const DAG_ROUTE: &str = "/dag";
+/// Announces that the validator accepts connections on a new address (POST), for instance
+/// `/address?address=10.0.0.1:1500`.
+pub const ADDRESS_ROUTE: &str = "/address";
+/// Stops the validator gracefully (POST).
+pub const SHUTDOWN_ROUTE: &str = "/shutdown";
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct NodeStatus {
+    pub authority: AuthorityIndex,
+    /// The round of the threshold clock, that is the round of the next block to propose.
+    pub threshold_clock_round: RoundNumber,
+    pub last_proposed_round: RoundNumber,
+    /// The last leader committed, for instance `B12` (None before the first commit).
+    pub last_committed_leader: Option<String>,
+    pub epoch: EpochStatus,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum EpochStatus {
+    Open,
+    /// The epoch change began, the validator no longer includes transactions in its blocks.
+    Changing,
+    Closed,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct PeerStatus {
+    pub authority: AuthorityIndex,
+    pub address: Option<String>,
+    pub connected: bool,
+    /// The highest round of the blocks of the peer in our store.
+    pub last_seen_round: RoundNumber,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct MissingBlocks {
+    pub authority: AuthorityIndex,
+    /// The blocks of the authority referenced by blocks we received, but not received yet.
+    pub blocks: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct WalStatus {
+    pub size_bytes: u64,
+}
+
+/// The query of `DAG_ROUTE`, for instance `/dag?from=10&to=20&format=json&annotate=true`. The last
+/// rounds are exported in the DOT format by default.
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct DagQuery {
+    pub from: Option<RoundNumber>,
+    pub to: Option<RoundNumber>,
+    #[serde(default)]
+    pub format: DagFormat,
+    /// Annotate the votes and certificates for the leaders.
+    #[serde(default)]
+    pub annotate: bool,
+}
+
+/// The query of `ADDRESS_ROUTE`.
+#[derive(Deserialize, Clone, Debug)]
+pub struct AddressQuery {
+    pub address: PeerAddress,
+}
+
+/// The admin routes of the validator running `node`. The routes only keep a weak reference to
+/// the node, which can be shut down while the admin server keeps running.
+pub fn routes<H: BlockHandler + 'static, C: CommitObserver + 'static>(
+    node: Weak<NetworkSyncerInner<H, C>>,
+) -> Router {
+    Router::new()
+        .route(STATUS_ROUTE, get(status::<H, C>))
+        .route(PEERS_ROUTE, get(peers::<H, C>))
+        .route(MISSING_BLOCKS_ROUTE, get(missing_blocks::<H, C>))
+        .route(WAL_ROUTE, get(wal::<H, C>))
+        .route(DAG_ROUTE, get(dag::<H, C>))
+        .route(ADDRESS_ROUTE, post(address::<H, C>))
+        .route(SHUTDOWN_ROUTE, post(shutdown::<H, C>))
+        .layer(Extension(node))
+}
+
+/// Serve the admin `routes` (see `routes` and `reload::routes`) on `address`.
+pub fn start_admin_server(
+    address: SocketAddr,
+    routes: Router,
+) -> JoinHandle<Result<(), hyper::Error>> {
+    if address.ip().is_loopback() {
+        tracing::info!("Admin server booted on {address}");
+    } else {
+        tracing::warn!("Admin server booted on {address}, reachable from other hosts");
+    }
+    Handle::current().spawn(async move {
+        Server::bind(&address)
+            .serve(routes.into_make_service())
+            .await
+    })
+}
+
+type Node<H, C> = Extension<Weak<NetworkSyncerInner<H, C>>>;
+
+fn running<H: BlockHandler, C: CommitObserver>(
+    node: &Node<H, C>,
+) -> Result<Arc<NetworkSyncerInner<H, C>>, (StatusCode, &'static str)> {
+    node.upgrade()
+        .ok_or((StatusCode::SERVICE_UNAVAILABLE, "Node is stopped\n"))
+}
+
+async fn status<H: BlockHandler + 'static, C: CommitObserver + 'static>(
+    node: Node<H, C>,
+) -> Result<Json<NodeStatus>, (StatusCode, &'static str)> {
+    Ok(Json(running(&node)?.status().await))
+}
+
+async fn peers<H: BlockHandler + 'static, C: CommitObserver + 'static>(
+    node: Node<H, C>,
+) -> Result<Json<Vec<PeerStatus>>, (StatusCode, &'static str)> {
+    Ok(Json(running(&node)?.peers().await))
+}
+
+async fn missing_blocks<H: BlockHandler + 'static, C: CommitObserver + 'static>(
+    node: Node<H, C>,
+) -> Result<Json<Vec<MissingBlocks>>, (StatusCode, &'static str)> {
+    Ok(Json(running(&node)?.missing_blocks().await))
+}
+
+async fn wal<H: BlockHandler + 'static, C: CommitObserver + 'static>(
+    node: Node<H, C>,
+) -> Result<Json<WalStatus>, (StatusCode, &'static str)> {
+    let size_bytes = running(&node)?.wal_size().await;
+    Ok(Json(WalStatus { size_bytes }))
+}
+
+async fn dag<H: BlockHandler + 'static, C: CommitObserver + 'static>(
+    node: Node<H, C>,
+    Query(query): Query<DagQuery>,
+) -> Result<([(header::HeaderName, &'static str); 1], String), (StatusCode, String)> {
+    let node = running(&node).map_err(|(status, message)| (status, message.to_string()))?;
+    let (from, to, annotate) = (query.from, query.to, query.annotate);
+    let export = tokio::task::spawn_blocking(move || node.export_dag(from, to, annotate))
+        .await
+        .map_err(|e| {
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("Export failed: {e}\n"),
+            )
+        })?
+        .map_err(|e| (StatusCode::BAD_REQUEST, format!("{e}\n")))?;
+    let content_type = match query.format {
+        DagFormat::Dot => "text/vnd.graphviz",
+        DagFormat::Json => "application/json",
+        DagFormat::Draw => "text/plain",
+    };
+    Ok((
+        [(header::CONTENT_TYPE, content_type)],
+        export.render(query.format),
+    ))
+}
+
+async fn address<H: BlockHandler + 'static, C: CommitObserver + 'static>(
+    node: Node<H, C>,
+    Query(query): Query<AddressQuery>,
+) -> (StatusCode, String) {
+    let node = match running(&node) {
+        Ok(node) => node,
+        Err((status, message)) => return (status, message.to_string()),
+    };
+    tracing::info!(
+        "Announcing address {} through {ADDRESS_ROUTE}",
+        query.address
+    );
+    match node.announce_address(query.address).await {
+        Ok(()) => (StatusCode::ACCEPTED, "Address announced\n".to_string()),
+        Err(err) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            format!("Failed to announce the address: {err}\n"),
+        ),
+    }
+}
+
+async fn shutdown<H: BlockHandler + 'static, C: CommitObserver + 'static>(
+    node: Node<H, C>,
+) -> (StatusCode, &'static str) {
+    match running(&node) {
+        Ok(node) => {
+            tracing::info!("Shutdown requested through {SHUTDOWN_ROUTE}");
+            node.request_shutdown();
+            (StatusCode::ACCEPTED, "Shutting down\n")
+        }
+        Err(stopped) => stopped,
+    }
+}
@@ -0,0 +1,698 @@
+// Copyright (c) Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Transaction dissemination decoupled from consensus. Each validator packs the transactions it
+//! receives into batches and sends them to its peers ahead of consensus. Peers store the batches
+//! and acknowledge them; once enough stake acknowledged a batch, at least one honest validator
+//! holds it and the validator includes its digest (rather than the transactions) in its next
+//! block. Blocks are only added to the dag once their batches are stored locally, so the commit
+//! path can always resolve the digests back to the transactions.
+
+use std::{
+    collections::{HashMap, HashSet},
+    fmt,
+    sync::Arc,
+    time::Duration,
+};
+
+use eyre::ensure;
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use tokio::sync::mpsc;
+
+use crate::{
+    committee::{Committee, StakeAggregator, ValidityThreshold},
+    data::Data,
+    network::NetworkMessage,
+    types::{
+        format_authority_index,
+        AuthorityIndex,
+        BatchDigest,
+        BlockReference,
+        StatementBlock,
+        Transaction,
+    },
+};
+
+/// The maximum number of transactions in a batch.
+pub const MAX_BATCH_TRANSACTIONS: usize = 10_000;
+/// The maximum size of the transactions of a batch, in bytes.
+pub const MAX_BATCH_BYTES: usize = 4 * 1024 * 1024;
+/// The maximum number of batches an author sends us ahead of the blocks referencing them, and
+/// their maximum size.
+pub const MAX_UNREFERENCED_BATCHES: usize = 1_000;
+pub const MAX_UNREFERENCED_BATCH_BYTES: usize = 64 * 1024 * 1024;
+/// How long a batch of an author counts towards its quota without being referenced.
+const UNREFERENCED_BATCH_TIMEOUT: Duration = Duration::from_secs(60);
+/// The maximum number of blocks of an author waiting for their batches.
+pub const MAX_AWAITING_BLOCKS: usize = 100;
+/// How long we wait for a requested batch before requesting it from another peer.
+pub const BATCH_REQUEST_TIMEOUT: Duration = Duration::from_secs(2);
+/// How long a block waits for its batches before being dropped. It is fetched again once
+/// referenced by other blocks.
+const AWAITING_BATCHES_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// How validators disseminate the transactions they propose.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, Eq, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Dissemination {
+    /// Transactions are embedded in the blocks.
+    #[default]
+    Inline,
+    /// Transactions are disseminated in batches, blocks only carry the batch digests.
+    Batches,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Batch {
+    author: AuthorityIndex,
+    transactions: Vec<Transaction>,
+    digest: BatchDigest,
+}
+
+impl Batch {
+    pub fn new(author: AuthorityIndex, transactions: Vec<Transaction>) -> Self {
+        let digest = BatchDigest::new(author, &transactions);
+        Self {
+            author,
+            transactions,
+            digest,
+        }
+    }
+
+    pub fn author(&self) -> AuthorityIndex {
+        self.author
+    }
+
+    pub fn transactions(&self) -> &[Transaction] {
+        &self.transactions
+    }
+
+    pub fn digest(&self) -> &BatchDigest {
+        &self.digest
+    }
+
+    /// Pack transactions into batches within `MAX_BATCH_TRANSACTIONS` and `MAX_BATCH_BYTES`.
+    pub fn pack(author: AuthorityIndex, transactions: Vec<Transaction>) -> Vec<Self> {
+        let mut batches = vec![];
+        let mut current = vec![];
+        let mut bytes = 0;
+        for transaction in transactions {
+            let size = transaction.data().len();
+            if !current.is_empty()
+                && (current.len() == MAX_BATCH_TRANSACTIONS || bytes + size > MAX_BATCH_BYTES)
+            {
+                batches.push(Self::new(author, std::mem::take(&mut current)));
+                bytes = 0;
+            }
+            bytes += size;
+            current.push(transaction);
+        }
+        if !current.is_empty() {
+            batches.push(Self::new(author, current));
+        }
+        batches
+    }
+
+    /// The size of the transactions of the batch, in bytes.
+    pub fn size(&self) -> usize {
+        self.transactions
+            .iter()
+            .map(|transaction| transaction.data().len())
+            .sum()
+    }
+
+    pub fn verify(&self, committee: &Committee) -> eyre::Result<()> {
+        ensure!(
+            committee.known_authority(self.author),
+            "Unknown batch author {}",
+            self.author
+        );
+        ensure!(
+            self.transactions.len() <= MAX_BATCH_TRANSACTIONS,
+            "Batch has {} transactions, more than {MAX_BATCH_TRANSACTIONS}",
+            self.transactions.len()
+        );
+        let size = self.size();
+        ensure!(
+            size <= MAX_BATCH_BYTES,
+            "Batch has {size} bytes of transactions, more than {MAX_BATCH_BYTES}"
+        );
+        let digest = BatchDigest::new(self.author, &self.transactions);
+        ensure!(
+            digest == self.digest,
+            "Digest does not match, calculated {:?}, provided {:?}",
+            digest,
+            self.digest
+        );
+        Ok(())
+    }
+}
+
+impl fmt::Debug for Batch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}{}({} transactions)",
+            format_authority_index(self.author),
+            self.digest,
+            self.transactions.len()
+        )
+    }
+}
+
+/// Handed by the block handler to the network syncer: the transactions to pack into batches, and
+/// where to report the digests of the batches that became available.
+pub struct BatchWorker {
+    pub transactions: mpsc::Receiver<Vec<Transaction>>,
+    pub available: mpsc::UnboundedSender<BatchDigest>,
+}
+
+/// Tracks the acknowledgements of our own batches and reports the batches acknowledged by a
+/// validity quorum, which are then safe to reference from our blocks.
+pub struct BatchAvailability {
+    committee: Arc<Committee>,
+    authority: AuthorityIndex,
+    available: mpsc::UnboundedSender<BatchDigest>,
+    inner: Mutex<BatchAvailabilityInner>,
+}
+
+#[derive(Default)]
+struct BatchAvailabilityInner {
+    pending: HashMap<BatchDigest, (Data<Batch>, StakeAggregator<ValidityThreshold>)>,
+    peers: HashMap<AuthorityIndex, mpsc::Sender<NetworkMessage>>,
+}
+
+impl BatchAvailability {
+    pub fn new(
+        committee: Arc<Committee>,
+        authority: AuthorityIndex,
+        available: mpsc::UnboundedSender<BatchDigest>,
+    ) -> Self {
+        Self {
+            committee,
+            authority,
+            available,
+            inner: Default::default(),
+        }
+    }
+
+    pub fn authority(&self) -> AuthorityIndex {
+        self.authority
+    }
+
+    /// Register a connected peer, returns the batches it still has to acknowledge.
+    pub fn register_peer(
+        &self,
+        peer: AuthorityIndex,
+        sender: mpsc::Sender<NetworkMessage>,
+    ) -> Vec<Data<Batch>> {
+        let mut inner = self.inner.lock();
+        inner.peers.insert(peer, sender);
+        inner
+            .pending
+            .values()
+            .filter(|(_, acknowledgements)| !acknowledgements.voters().any(|voter| voter == peer))
+            .map(|(batch, _)| batch.clone())
+            .collect()
+    }
+
+    pub fn remove_peer(&self, peer: AuthorityIndex) {
+        self.inner.lock().peers.remove(&peer);
+    }
+
+    /// Track a new batch of ours (already stored locally), returns the peers to send it to.
+    pub fn add_own_batch(&self, batch: Data<Batch>) -> Vec<mpsc::Sender<NetworkMessage>> {
+        let digest = *batch.digest();
+        let mut acknowledgements = StakeAggregator::new();
+        if acknowledgements.add(self.authority, &self.committee) {
+            self.report_available(digest);
+            return vec![];
+        }
+        let mut inner = self.inner.lock();
+        inner.pending.insert(digest, (batch, acknowledgements));
+        inner.peers.values().cloned().collect()
+    }
+
+    pub fn acknowledge(&self, peer: AuthorityIndex, digest: BatchDigest) {
+        let mut inner = self.inner.lock();
+        // Late acknowledgements of available batches are ignored
+        let Some((_, acknowledgements)) = inner.pending.get_mut(&digest) else {
+            return;
+        };
+        if acknowledgements.add(peer, &self.committee) {
+            inner.pending.remove(&digest);
+            self.report_available(digest);
+        }
+    }
+
+    fn report_available(&self, digest: BatchDigest) {
+        // The block handler only goes away when the validator stops
+        self.available.send(digest).ok();
+    }
+}
+
+/// The batches the authors sent us ahead of their blocks and that none of their blocks
+/// referenced yet, so that an author can not make us store an unbounded amount of batches.
+#[derive(Default)]
+pub struct BatchQuota {
+    unreferenced: Mutex<HashMap<AuthorityIndex, UnreferencedBatches>>,
+}
+
+#[derive(Default)]
+struct UnreferencedBatches {
+    // The size of each batch and when it was received
+    batches: HashMap<BatchDigest, (usize, Duration)>,
+    bytes: usize,
+}
+
+impl BatchQuota {
+    /// Whether a batch disseminated by its author fits in its quota, in which case it counts
+    /// towards the quota until a block of the author references it.
+    pub fn admit(&self, batch: &Batch, now: Duration) -> bool {
+        let mut unreferenced = self.unreferenced.lock();
+        let author = unreferenced.entry(batch.author()).or_default();
+        if author.batches.contains_key(batch.digest()) {
+            return true;
+        }
+        let size = batch.size();
+        if author.batches.len() >= MAX_UNREFERENCED_BATCHES
+            || author.bytes + size > MAX_UNREFERENCED_BATCH_BYTES
+        {
+            // Batches the author never referenced (for example before it restarted) expire
+            let mut expired = 0;
+            author.batches.retain(|_, (size, received)| {
+                let retain = now.saturating_sub(*received) < UNREFERENCED_BATCH_TIMEOUT;
+                if !retain {
+                    expired += *size;
+                }
+                retain
+            });
+            author.bytes -= expired;
+        }
+        if author.batches.len() >= MAX_UNREFERENCED_BATCHES
+            || author.bytes + size > MAX_UNREFERENCED_BATCH_BYTES
+        {
+            return false;
+        }
+        author.batches.insert(*batch.digest(), (size, now));
+        author.bytes += size;
+        true
+    }
+
+    /// The batches referenced by a block of their author no longer count towards its quota.
+    pub fn referenced(&self, block: &StatementBlock) {
+        let mut unreferenced = self.unreferenced.lock();
+        let Some(author) = unreferenced.get_mut(&block.author()) else {
+            return;
+        };
+        for digest in block.batches() {
+            if let Some((size, _)) = author.batches.remove(digest) {
+                author.bytes -= size;
+            }
+        }
+    }
+}
+
+/// The blocks received from the peers that wait for some of their batches to be stored before
+/// being added to the dag. Missing batches are first requested from the peer that sent us the
+/// block, then from the other peers in turn until one of them sends it.
+#[derive(Default)]
+pub struct BatchFetcher {
+    inner: Mutex<BatchFetcherInner>,
+}
+
+#[derive(Default)]
+struct BatchFetcherInner {
+    blocks: HashMap<BlockReference, AwaitingBlock>,
+    blocks_per_author: HashMap<AuthorityIndex, usize>,
+    missing: HashMap<BatchDigest, MissingBatch>,
+    peers: HashMap<AuthorityIndex, mpsc::Sender<NetworkMessage>>,
+}
+
+struct AwaitingBlock {
+    block: Data<StatementBlock>,
+    since: Duration,
+}
+
+struct MissingBatch {
+    // The blocks waiting for the batch
+    blocks: HashSet<BlockReference>,
+    requested_from: Vec<AuthorityIndex>,
+    requested_at: Duration,
+}
+
+/// The batches to request from each peer.
+pub type BatchRequests = Vec<(mpsc::Sender<NetworkMessage>, Vec<BatchDigest>)>;
+
+/// The outcome of `BatchFetcher::add`.
+#[derive(Debug, PartialEq)]
+pub enum AwaitBatches {
+    /// All the batches of the block are stored.
+    Ready,
+    /// The block waits for its batches, the listed ones should be requested from the peer.
+    Request(Vec<BatchDigest>),
+    /// The author of the block already has too many blocks waiting.
+    Rejected,
+}
+
+impl BatchFetcher {
+    pub fn register_peer(&self, peer: AuthorityIndex, sender: mpsc::Sender<NetworkMessage>) {
+        self.inner.lock().peers.insert(peer, sender);
+    }
+
+    pub fn remove_peer(&self, peer: AuthorityIndex) {
+        self.inner.lock().peers.remove(&peer);
+    }
+
+    /// Add a block sent by `peer` unless all its batches are stored, `stored` tells whether a
+    /// batch is stored.
+    pub fn add(
+        &self,
+        block: Data<StatementBlock>,
+        peer: AuthorityIndex,
+        now: Duration,
+        stored: impl Fn(&BatchDigest) -> bool,
+    ) -> AwaitBatches {
+        // Holding the lock, a batch stored concurrently either is seen as stored or releases
+        // the block in `batch_stored`
+        let mut inner = self.inner.lock();
+        let missing: Vec<_> = block.batches().filter(|digest| !stored(digest)).collect();
+        if missing.is_empty() {
+            return AwaitBatches::Ready;
+        }
+        let reference = *block.reference();
+        if inner.blocks.contains_key(&reference) {
+            return AwaitBatches::Request(vec![]);
+        }
+        let awaiting = inner.blocks_per_author.entry(block.author()).or_default();
+        if *awaiting >= MAX_AWAITING_BLOCKS {
+            return AwaitBatches::Rejected;
+        }
+        *awaiting += 1;
+        let mut request = vec![];
+        for digest in missing {
+            let missing = inner.missing.entry(*digest).or_insert_with(|| {
+                request.push(*digest);
+                MissingBatch {
+                    blocks: HashSet::new(),
+                    requested_from: vec![peer],
+                    requested_at: now,
+                }
+            });
+            missing.blocks.insert(reference);
+        }
+        inner
+            .blocks
+            .insert(reference, AwaitingBlock { block, since: now });
+        AwaitBatches::Request(request)
+    }
+
+    /// Whether a block waits for this batch.
+    pub fn is_missing(&self, digest: &BatchDigest) -> bool {
+        self.inner.lock().missing.contains_key(digest)
+    }
+
+    /// A batch was stored, returns the blocks whose batches are now all stored.
+    pub fn batch_stored(
+        &self,
+        digest: &BatchDigest,
+        stored: impl Fn(&BatchDigest) -> bool,
+    ) -> Vec<Data<StatementBlock>> {
+        let mut inner = self.inner.lock();
+        let Some(missing) = inner.missing.remove(digest) else {
+            return vec![];
+        };
+        let mut ready = vec![];
+        for reference in missing.blocks {
+            let complete = inner
+                .blocks
+                .get(&reference)
+                .is_some_and(|awaiting| awaiting.block.batches().all(&stored));
+            if complete {
+                ready.push(inner.remove_block(&reference));
+            }
+        }
+        ready
+    }
+
+    /// Drop the blocks that waited too long for their batches and request the batches that
+    /// were not received in time from another peer. Returns the requests to send, and the number
+    /// of dropped blocks.
+    pub fn retry(&self, now: Duration) -> (BatchRequests, usize) {
+        let mut inner = self.inner.lock();
+        let expired: Vec<_> = inner
+            .blocks
+            .iter()
+            .filter(|(_, awaiting)| now.saturating_sub(awaiting.since) >= AWAITING_BATCHES_TIMEOUT)
+            .map(|(reference, _)| *reference)
+            .collect();
+        for reference in &expired {
+            inner.remove_block(reference);
+        }
+
+        let inner = &mut *inner;
+        let mut requests: HashMap<AuthorityIndex, Vec<BatchDigest>> = HashMap::new();
+        for (digest, missing) in &mut inner.missing {
+            if now.saturating_sub(missing.requested_at) < BATCH_REQUEST_TIMEOUT {
+                continue;
+            }
+            // Ask the peers we did not ask yet first, then start over
+            let mut candidates: Vec<_> = inner.peers.keys().copied().collect();
+            candidates.sort();
+            let next = candidates
+                .iter()
+                .find(|peer| !missing.requested_from.contains(peer))
+                .or_else(|| {
+                    missing.requested_from.clear();
+                    candidates.first()
+                });
+            let Some(peer) = next else {
+                continue;
+            };
+            missing.requested_from.push(*peer);
+            missing.requested_at = now;
+            requests.entry(*peer).or_default().push(*digest);
+        }
+        let requests = requests
+            .into_iter()
+            .map(|(peer, digests)| (inner.peers[&peer].clone(), digests))
+            .collect();
+        (requests, expired.len())
+    }
+}
+
+impl BatchFetcherInner {
+    fn remove_block(&mut self, reference: &BlockReference) -> Data<StatementBlock> {
+        let awaiting = self
+            .blocks
+            .remove(reference)
+            .expect("Removed block should be awaiting");
+        if let Some(count) = self.blocks_per_author.get_mut(&reference.authority) {
+            *count -= 1;
+        }
+        for digest in awaiting.block.batches() {
+            if let Some(missing) = self.missing.get_mut(digest) {
+                missing.blocks.remove(reference);
+                if missing.blocks.is_empty() {
+                    self.missing.remove(digest);
+                }
+            }
+        }
+        awaiting.block
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::committee;
+
+    fn transactions(n: u64) -> Vec<Transaction> {
+        (0..n)
+            .map(|i| Transaction::new(i.to_le_bytes().to_vec()))
+            .collect()
+    }
+
+    #[test]
+    fn batch_digest() {
+        let committee = committee(4);
+        let batch = Batch::new(0, transactions(10));
+        batch.verify(&committee).unwrap();
+        // The digest covers both the author and the transactions
+        assert_ne!(batch.digest(), Batch::new(1, transactions(10)).digest());
+        assert_ne!(batch.digest(), Batch::new(0, transactions(9)).digest());
+        // Transactions are length-prefixed, the same bytes split differently do not collide
+        let merged = Transaction::new([0u64.to_le_bytes(), 1u64.to_le_bytes()].concat());
+        assert_ne!(
+            Batch::new(0, transactions(2)).digest(),
+            Batch::new(0, vec![merged]).digest()
+        );
+
+        let mut tampered = batch.clone();
+        tampered.transactions.pop();
+        assert!(tampered.verify(&committee).is_err());
+        let unknown = Batch::new(7, transactions(10));
+        assert!(unknown.verify(&committee).is_err());
+    }
+
+    #[test]
+    fn batch_availability() {
+        let committee = committee(4);
+        let (sender, mut available) = mpsc::unbounded_channel();
+        let availability = BatchAvailability::new(committee, 0, sender);
+        let (peer, _receiver) = mpsc::channel(1);
+        assert!(availability.register_peer(1, peer.clone()).is_empty());
+
+        let batch = Data::new(Batch::new(0, transactions(10)));
+        let digest = *batch.digest();
+        assert_eq!(availability.add_own_batch(batch).len(), 1);
+        // Peers connecting later are sent the batches they did not acknowledge yet
+        assert_eq!(availability.register_peer(2, peer.clone()).len(), 1);
+        assert!(available.try_recv().is_err());
+
+        // Our own acknowledgement and one from a peer form a validity quorum
+        availability.acknowledge(1, digest);
+        assert_eq!(available.try_recv().unwrap(), digest);
+        assert!(availability.register_peer(3, peer).is_empty());
+        availability.acknowledge(2, digest);
+        assert!(available.try_recv().is_err());
+    }
+
+    #[test]
+    fn batch_limits() {
+        let committee = committee(4);
+        let batches = Batch::pack(0, transactions(2 * MAX_BATCH_TRANSACTIONS as u64 + 1));
+        assert_eq!(batches.len(), 3);
+        assert_eq!(batches[0].transactions().len(), MAX_BATCH_TRANSACTIONS);
+        batches[0].verify(&committee).unwrap();
+        let oversized = Batch::new(0, transactions(MAX_BATCH_TRANSACTIONS as u64 + 1));
+        assert!(oversized.verify(&committee).is_err());
+
+        let large = || Transaction::new(vec![0; MAX_BATCH_BYTES / 2 + 1]);
+        let batches = Batch::pack(0, vec![large(), large()]);
+        assert_eq!(batches.len(), 2);
+        assert!(Batch::new(0, vec![large(), large()])
+            .verify(&committee)
+            .is_err());
+    }
+
+    #[test]
+    fn batch_quota() {
+        let quota = BatchQuota::default();
+        let now = Duration::from_secs(1);
+        let batches: Vec<_> = (0..=MAX_UNREFERENCED_BATCHES as u64)
+            .map(|i| Batch::new(1, transactions(i + 1)))
+            .collect();
+        for batch in &batches[..MAX_UNREFERENCED_BATCHES] {
+            assert!(quota.admit(batch, now));
+        }
+        assert!(!quota.admit(&batches[MAX_UNREFERENCED_BATCHES], now));
+        // Other authors have their own quota
+        assert!(quota.admit(&Batch::new(2, transactions(1)), now));
+
+        // Batches referenced by a block of the author free the quota
+        let block = StatementBlock::new(
+            1,
+            1,
+            vec![],
+            vec![crate::types::BaseStatement::Batch(*batches[0].digest())],
+            0,
+            false,
+            Default::default(),
+        );
+        quota.referenced(&block);
+        assert!(quota.admit(&batches[MAX_UNREFERENCED_BATCHES], now));
+        assert!(!quota.admit(&Batch::new(1, transactions(5000)), now));
+        // As do the batches never referenced
+        let later = now + UNREFERENCED_BATCH_TIMEOUT;
+        assert!(quota.admit(&Batch::new(1, transactions(5000)), later));
+    }
+
+    #[test]
+    fn batch_fetcher() {
+        let fetcher = BatchFetcher::default();
+        let (sender, _receiver) = mpsc::channel(1);
+        for peer in 1..4 {
+            fetcher.register_peer(peer, sender.clone());
+        }
+        let batches: Vec<_> = (1..=2).map(|i| Batch::new(1, transactions(i))).collect();
+        let block = |round, batches: &[Batch]| {
+            Data::new(StatementBlock::new(
+                1,
+                round,
+                vec![],
+                batches
+                    .iter()
+                    .map(|batch| crate::types::BaseStatement::Batch(*batch.digest()))
+                    .collect(),
+                0,
+                false,
+                Default::default(),
+            ))
+        };
+        let stored = Mutex::new(HashSet::new());
+        let is_stored = |digest: &BatchDigest| stored.lock().contains(digest);
+        let now = Duration::from_secs(1);
+
+        assert_eq!(
+            fetcher.add(block(1, &[]), 1, now, is_stored),
+            AwaitBatches::Ready
+        );
+        let first = block(1, &batches);
+        let digests: Vec<_> = batches.iter().map(|batch| *batch.digest()).collect();
+        assert_eq!(
+            fetcher.add(first.clone(), 1, now, is_stored),
+            AwaitBatches::Request(digests.clone())
+        );
+        // Duplicates and batches already requested are not requested again
+        assert_eq!(
+            fetcher.add(first.clone(), 2, now, is_stored),
+            AwaitBatches::Request(vec![])
+        );
+        let second = block(2, &batches[..1]);
+        assert_eq!(
+            fetcher.add(second.clone(), 2, now, is_stored),
+            AwaitBatches::Request(vec![])
+        );
+        assert!(fetcher.is_missing(&digests[0]));
+
+        // Batches not received in time are requested from another peer
+        assert!(fetcher.retry(now).0.is_empty());
+        let (requests, dropped) = fetcher.retry(now + BATCH_REQUEST_TIMEOUT);
+        assert_eq!(dropped, 0);
+        assert_eq!(requests.len(), 1);
+        assert_eq!(requests[0].1.len(), 2);
+
+        stored.lock().insert(digests[0]);
+        let ready = fetcher.batch_stored(&digests[0], is_stored);
+        assert_eq!(ready.len(), 1);
+        assert_eq!(ready[0].reference(), second.reference());
+        stored.lock().insert(digests[1]);
+        let ready = fetcher.batch_stored(&digests[1], is_stored);
+        assert_eq!(ready[0].reference(), first.reference());
+        assert!(!fetcher.is_missing(&digests[1]));
+
+        // The blocks of an author waiting for their batches are bounded and expire
+        let missing = Batch::new(1, transactions(3));
+        for round in 0..MAX_AWAITING_BLOCKS as u64 {
+            let awaiting = block(round + 10, std::slice::from_ref(&missing));
+            assert_ne!(
+                fetcher.add(awaiting, 1, now, is_stored),
+                AwaitBatches::Rejected
+            );
+        }
+        let rejected = block(1000, std::slice::from_ref(&missing));
+        assert_eq!(
+            fetcher.add(rejected.clone(), 1, now, is_stored),
+            AwaitBatches::Rejected
+        );
+        let (_, dropped) = fetcher.retry(now + AWAITING_BATCHES_TIMEOUT);
+        assert_eq!(dropped, MAX_AWAITING_BLOCKS);
+        assert!(!fetcher.is_missing(missing.digest()));
+        assert_ne!(
+            fetcher.add(rejected, 1, now, is_stored),
+            AwaitBatches::Rejected
+        );
+    }
+}
@@ -0,0 +1,256 @@
+// Copyright (c) Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! The blocks the block store keeps in memory, bounded by their serialized size. The least recently
+//! used blocks are evicted first: the blocks of the recent rounds and the blocks the commit rule
+//! keeps visiting (leaders and their voters) stay loaded, the others are read back from the wal
+//! when needed. `BlockStore::cleanup` also evicts the blocks below the retained rounds, and the
+//! blocks of the rounds pinned by the core (see `pin_rounds`) are never evicted for capacity.
+//!
+//! Reads only take the read lock of the shard of the block and stamp its last access: the eviction
+//! order is updated lazily, when a block stamped since it was queued reaches the front of it.
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    mem,
+    ops::RangeInclusive,
+    sync::atomic::{AtomicU64, Ordering},
+};
+
+use parking_lot::{Mutex, RwLock};
+
+use crate::{
+    data::Data,
+    types::{BlockReference, RoundNumber, StatementBlock},
+};
+
+const CACHE_SHARDS: usize = 16;
+
+pub struct BlockCache {
+    /// The maximum size of the cached blocks, in bytes.
+    capacity: usize,
+    shards: Vec<RwLock<HashMap<BlockReference, CachedBlock>>>,
+    // Locked by insertions and evictions only, before the shards
+    order: Mutex<CacheOrder>,
+    next_access: AtomicU64,
+}
+
+struct CachedBlock {
+    block: Data<StatementBlock>,
+    access: AtomicU64,
+}
+
+struct CacheOrder {
+    size: usize,
+    // The cached blocks by access when queued, the first one is the least recently used unless
+    // accessed since
+    accesses: BTreeMap<u64, BlockReference>,
+    // The cached blocks by round, with their access when queued
+    rounds: BTreeMap<RoundNumber, HashMap<BlockReference, u64>>,
+    pinned: RangeInclusive<RoundNumber>,
+}
+
+impl BlockCache {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            shards: (0..CACHE_SHARDS)
+                .map(|_| RwLock::new(HashMap::new()))
+                .collect(),
+            order: Mutex::new(CacheOrder {
+                size: 0,
+                accesses: BTreeMap::new(),
+                rounds: BTreeMap::new(),
+                pinned: RangeInclusive::new(1, 0),
+            }),
+            next_access: AtomicU64::new(0),
+        }
+    }
+
+    pub fn get(&self, reference: &BlockReference) -> Option<Data<StatementBlock>> {
+        let shard = self.shard(reference).read();
+        let cached = shard.get(reference)?;
+        cached.access.store(self.next_access(), Ordering::Relaxed);
+        Some(cached.block.clone())
+    }
+
+    /// Cache `block` as the most recently used block. Returns the number of blocks evicted to make
+    /// room for it.
+    pub fn insert(&self, block: Data<StatementBlock>) -> usize {
+        let reference = *block.reference();
+        let size = block.serialized_bytes().len();
+        let mut order = self.order.lock();
+        if size > self.capacity || self.get(&reference).is_some() {
+            return 0;
+        }
+        let access = self.next_access();
+        self.shard(&reference).write().insert(
+            reference,
+            CachedBlock {
+                block,
+                access: AtomicU64::new(access),
+            },
+        );
+        order.queue(reference, access);
+        order.size += size;
+
+        let mut evicted = 0;
+        let mut pinned = Vec::new();
+        while order.size > self.capacity {
+            // The pinned blocks may exceed the capacity
+            let Some((queued, reference)) = order.accesses.pop_first() else {
+                break;
+            };
+            let mut shard = self.shard(&reference).write();
+            let cached = shard.get(&reference).expect("Queued block is not cached");
+            let access = cached.access.load(Ordering::Relaxed);
+            if access != queued {
+                order.queue(reference, access);
+            } else if order.pinned.contains(&reference.round) {
+                pinned.push((queued, reference));
+            } else {
+                let cached = shard
+                    .remove(&reference)
+                    .expect("Queued block is not cached");
+                order.size -= cached.block.serialized_bytes().len();
+                order.unqueue_round(&reference);
+                evicted += 1;
+            }
+        }
+        order.accesses.extend(pinned);
+        evicted
+    }
+
+    /// Evict the blocks from below or equal `threshold_round`, pinned or not, returns their number.
+    pub fn evict_below_round(&self, threshold_round: RoundNumber) -> usize {
+        let mut order = self.order.lock();
+        let retained = order.rounds.split_off(&(threshold_round + 1));
+        let rounds = mem::replace(&mut order.rounds, retained);
+        let mut evicted = 0;
+        for (reference, queued) in rounds.into_values().flatten() {
+            order.accesses.remove(&queued);
+            let cached = self
+                .shard(&reference)
+                .write()
+                .remove(&reference)
+                .expect("Queued block is not cached");
+            order.size -= cached.block.serialized_bytes().len();
+            evicted += 1;
+        }
+        evicted
+    }
+
+    /// Keep the blocks of `rounds` cached regardless of the capacity, until the next call. The
+    /// core pins the rounds of the leaders it has yet to decide and of their certificates.
+    pub fn pin_rounds(&self, rounds: RangeInclusive<RoundNumber>) {
+        self.order.lock().pinned = rounds;
+    }
+
+    pub fn len(&self) -> usize {
+        self.order.lock().accesses.len()
+    }
+
+    /// The size of the cached blocks, in bytes.
+    pub fn size(&self) -> usize {
+        self.order.lock().size
+    }
+
+    fn shard(&self, reference: &BlockReference) -> &RwLock<HashMap<BlockReference, CachedBlock>> {
+        let index = reference.authority.wrapping_add(reference.round) as usize % CACHE_SHARDS;
+        &self.shards[index]
+    }
+
+    fn next_access(&self) -> u64 {
+        self.next_access.fetch_add(1, Ordering::Relaxed)
+    }
+}
+
+impl CacheOrder {
+    fn queue(&mut self, reference: BlockReference, access: u64) {
+        self.accesses.insert(access, reference);
+        self.rounds
+            .entry(reference.round)
+            .or_default()
+            .insert(reference, access);
+    }
+
+    fn unqueue_round(&mut self, reference: &BlockReference) {
+        if let Some(references) = self.rounds.get_mut(&reference.round) {
+            references.remove(reference);
+            if references.is_empty() {
+                self.rounds.remove(&reference.round);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn block(authority: u64, round: RoundNumber) -> Data<StatementBlock> {
+        Data::new(StatementBlock::new(
+            authority,
+            round,
+            vec![],
+            vec![],
+            0,
+            false,
+            Default::default(),
+        ))
+    }
+
+    #[test]
+    fn evicts_least_recently_used() {
+        let blocks: Vec<_> = (1..=4).map(|round| block(0, round)).collect();
+        let block_size = blocks[0].serialized_bytes().len();
+        let cache = BlockCache::new(3 * block_size);
+        for block in &blocks[..3] {
+            assert_eq!(cache.insert(block.clone()), 0);
+        }
+        // The first block becomes the most recently used one
+        assert!(cache.get(blocks[0].reference()).is_some());
+        assert_eq!(cache.insert(blocks[3].clone()), 1);
+        assert!(cache.get(blocks[1].reference()).is_none());
+        assert!(cache.get(blocks[0].reference()).is_some());
+        assert_eq!(cache.len(), 3);
+        assert_eq!(cache.size(), 3 * block_size);
+
+        assert_eq!(cache.evict_below_round(3), 2);
+        assert_eq!(cache.len(), 1);
+        assert_eq!(cache.size(), block_size);
+        // The evicted blocks no longer count towards the capacity
+        assert_eq!(cache.insert(blocks[0].clone()), 0);
+        assert_eq!(cache.insert(blocks[1].clone()), 0);
+        assert_eq!(cache.insert(blocks[2].clone()), 1);
+        assert!(cache.get(blocks[3].reference()).is_none());
+
+        let empty = BlockCache::new(0);
+        assert_eq!(empty.insert(blocks[0].clone()), 0);
+        assert!(empty.get(blocks[0].reference()).is_none());
+    }
+
+    #[test]
+    fn keeps_pinned_rounds() {
+        let blocks: Vec<_> = (1..=4).map(|round| block(0, round)).collect();
+        let block_size = blocks[0].serialized_bytes().len();
+        let cache = BlockCache::new(2 * block_size);
+        cache.pin_rounds(1..=2);
+        assert_eq!(cache.insert(blocks[0].clone()), 0);
+        assert_eq!(cache.insert(blocks[1].clone()), 0);
+        // The pinned blocks fill the capacity, the other blocks are evicted first
+        assert_eq!(cache.insert(blocks[2].clone()), 1);
+        assert!(cache.get(blocks[2].reference()).is_none());
+        cache.pin_rounds(2..=3);
+        assert_eq!(cache.insert(blocks[2].clone()), 1);
+        assert!(cache.get(blocks[0].reference()).is_none());
+        assert_eq!(cache.insert(blocks[3].clone()), 1);
+        assert!(cache.get(blocks[3].reference()).is_none());
+        assert_eq!(cache.len(), 2);
+        assert_eq!(cache.size(), 2 * block_size);
+        // Pinned blocks still go below the retained rounds
+        assert_eq!(cache.evict_below_round(2), 1);
+        assert!(cache.get(blocks[1].reference()).is_none());
+        assert!(cache.get(blocks[2].reference()).is_some());
+    }
+}
@@ -14,6 +14,7 @@ use parking_lot::Mutex;
 use tokio::sync::mpsc;
 use tokio::sync::mpsc::{Receiver, Sender};
 use crate::{
+    batch::{BatchWorker, Dissemination},
     block_store::BlockStore,
     committee::{Committee, ProcessedTransactionHandler, QuorumThreshold, TransactionAggregator},
     consensus::linearizer::{CommittedSubDag, Linearizer},
@@ -26,6 +27,7 @@ use crate::{
     types::{
         AuthorityIndex,
         BaseStatement,
+        BatchDigest,
         BlockReference,
         StatementBlock,
         Transaction,
@@ -47,7 +49,16 @@ pub trait BlockHandler: Send + Sync {
 
     fn recover_state(&mut self, _state: &Bytes);
 
+    /// Called when the validator joined from a snapshot: blocks at or below the frontier are
+    /// not available locally.
+    fn recover_snapshot_frontier(&mut self, _frontier: &[BlockReference]) {}
+
     fn cleanup(&self) {}
+
+    /// The worker disseminating our transactions in batches, if enabled. Taken once on startup.
+    fn take_batch_worker(&mut self) -> Option<BatchWorker> {
+        None
+    }
 }
 
 const REAL_BLOCK_HANDLER_TXN_SIZE: usize = 512;
@@ -68,9 +79,13 @@ pub struct RealBlockHandler {
     authority: AuthorityIndex,
     block_store: BlockStore,
     metrics: Arc<Metrics>,
-    receiver: mpsc::Receiver<Vec<Transaction>>,
+    // None when the transactions are disseminated in batches
+    receiver: Option<mpsc::Receiver<Vec<Transaction>>>,
     pending_transactions: usize,
     consensus_only: bool,
+    batch_worker: Option<BatchWorker>,
+    // The digests of our batches acknowledged by enough peers to be proposed
+    available_batches: Option<mpsc::UnboundedReceiver<BatchDigest>>,
 }
 
 /// The max number of transactions per block.
@@ -85,8 +100,20 @@ impl RealBlockHandler {
         block_store: BlockStore,
         metrics: Arc<Metrics>,
         consensus_only: bool,
+        dissemination: Dissemination,
     ) -> (Self, mpsc::Sender<Vec<Transaction>>) {
         let (sender, receiver) = mpsc::channel(1024);
+        let (receiver, batch_worker, available_batches) = match dissemination {
+            Dissemination::Inline => (Some(receiver), None, None),
+            Dissemination::Batches => {
+                let (available, available_batches) = mpsc::unbounded_channel();
+                let batch_worker = BatchWorker {
+                    transactions: receiver,
+                    available,
+                };
+                (None, Some(batch_worker), Some(available_batches))
+            }
+        };
         let transaction_log = TransactionLog::start(certified_transactions_log_path)
             .expect("Failed to open certified transaction log for write");
 
@@ -100,6 +127,8 @@ impl RealBlockHandler {
             receiver,
             pending_transactions: 0, // todo - need to initialize correctly when loaded from disk
             consensus_only,
+            batch_worker,
+            available_batches,
         };
         (this, sender)
     }
@@ -110,7 +139,7 @@ impl RealBlockHandler {
         if self.pending_transactions >= SOFT_MAX_PROPOSED_PER_BLOCK {
             return None;
         }
-        let received = self.receiver.try_recv().ok()?;
+        let received = self.receiver.as_mut()?.try_recv().ok()?;
         self.pending_transactions += received.len();
         Some(received)
     }
@@ -165,6 +194,11 @@ impl BlockHandler for RealBlockHandler {
                     response.push(BaseStatement::Share(tx));
                 }
             }
+            if let Some(available_batches) = &mut self.available_batches {
+                while let Ok(digest) = available_batches.try_recv() {
+                    response.push(BaseStatement::Batch(digest));
+                }
+            }
         }
         let transaction_time = self.transaction_time.lock();
         for block in blocks {
@@ -216,12 +250,20 @@ impl BlockHandler for RealBlockHandler {
         self.transaction_votes.with_state(state);
     }
 
+    fn recover_snapshot_frontier(&mut self, frontier: &[BlockReference]) {
+        self.transaction_votes.with_snapshot_frontier(frontier);
+    }
+
     fn cleanup(&self) {
         let _timer = self.metrics.block_handler_cleanup_util.utilization_timer();
         // todo - all of this should go away and we should measure tx latency differently
         let mut l = self.transaction_time.lock();
         l.retain(|_k, v| v.elapsed() < Duration::from_secs(10));
     }
+
+    fn take_batch_worker(&mut self) -> Option<BatchWorker> {
+        self.batch_worker.take()
+    }
 }
 
 // Immediately votes and generates new transactions
@@ -232,6 +274,7 @@ pub struct TestBlockHandler {
     committee: Arc<Committee>,
     authority: AuthorityIndex,
     pub proposed: Vec<TransactionLocator>,
+    transaction_size: usize,
 
     metrics: Arc<Metrics>,
 }
@@ -250,10 +293,17 @@ impl TestBlockHandler {
             committee,
             authority,
             proposed: Default::default(),
+            transaction_size: 0,
             metrics,
         }
     }
 
+    /// Pad the transactions generated from now on to `size` bytes, e.g. for a validator to
+    /// propose larger blocks than the others.
+    pub fn set_transaction_size(&mut self, size: usize) {
+        self.transaction_size = size;
+    }
+
     pub fn is_certified(&self, locator: &TransactionLocator) -> bool {
         self.transaction_votes.is_processed(locator)
     }
@@ -284,7 +334,9 @@ impl BlockHandler for TestBlockHandler {
                 }
             }
             self.last_transaction += 1;
-            let next_transaction = Self::make_transaction(self.last_transaction);
+            let mut next_transaction = Self::make_transaction(self.last_transaction).into_data();
+            next_transaction.resize(next_transaction.len().max(self.transaction_size), 0);
+            let next_transaction = Transaction::new(next_transaction);
             response.push(BaseStatement::Share(next_transaction));
         }
         let transaction_time = self.transaction_time.lock();
@@ -334,6 +386,10 @@ impl BlockHandler for TestBlockHandler {
         self.transaction_votes.with_state(&transaction_votes);
         self.last_transaction = last_transaction;
     }
+
+    fn recover_snapshot_frontier(&mut self, frontier: &[BlockReference]) {
+        self.transaction_votes.with_snapshot_frontier(frontier);
+    }
 }
 
 pub struct TestCommitHandler<H = HashSet<TransactionLocator>> {
@@ -512,6 +568,11 @@ for TestCommitHandler<H>
                     );
                 }
             }
+            for batch in &commit.batches {
+                for transaction in batch.transactions() {
+                    self.update_metrics(None, current_timestamp, transaction);
+                }
+            }
             // self.committed_dags.push(commit);
         }
         self.metrics
@@ -533,4 +594,9 @@ for TestCommitHandler<H>
         }
         self.commit_interpreter.committed = committed;
     }
+
+    fn recover_snapshot_frontier(&mut self, frontier: &[BlockReference]) {
+        self.commit_interpreter.with_snapshot_frontier(frontier);
+        self.transaction_votes.with_snapshot_frontier(frontier);
+    }
 }
@@ -10,7 +10,7 @@ use crate::{
     block_store::{BlockStore, BlockWriter},
     committee::Committee,
     data::Data,
-    types::{BlockReference, StatementBlock},
+    types::{BlockReference, RoundNumber, StatementBlock},
     wal::WalPosition,
 };
 
@@ -24,6 +24,9 @@ pub struct BlockManager {
     /// Keeps all blocks that need to be synced in order to unblock the processing of other pending
     /// blocks. The indices of the vector correspond the authority indices.
     missing: Vec<HashSet<BlockReference>>,
+    /// Highest round of each authority included in the snapshot this validator started from (if
+    /// any). Blocks at or below the frontier are not available locally and never will be.
+    snapshot_frontier: Option<Vec<RoundNumber>>,
     block_store: BlockStore,
 }
 
@@ -33,10 +36,33 @@ impl BlockManager {
             blocks_pending: Default::default(),
             block_references_waiting: Default::default(),
             missing: (0..committee.len()).map(|_| HashSet::new()).collect(),
+            snapshot_frontier: None,
             block_store,
         }
     }
 
+    /// Consider all blocks at or below the snapshot frontier as available, and process the
+    /// pending blocks that were only waiting for such blocks.
+    pub fn set_snapshot_frontier(
+        &mut self,
+        frontier: &[BlockReference],
+        block_writer: &mut impl BlockWriter,
+    ) -> Vec<(WalPosition, Data<StatementBlock>)> {
+        self.snapshot_frontier = Some(frontier.iter().map(|r| r.round()).collect());
+        let pending: Vec<_> = self.blocks_pending.drain().map(|(_, block)| block).collect();
+        self.block_references_waiting.clear();
+        self.missing.iter_mut().for_each(HashSet::clear);
+        self.add_blocks(pending, block_writer)
+    }
+
+    fn below_snapshot_frontier(&self, reference: &BlockReference) -> bool {
+        self.snapshot_frontier.as_ref().is_some_and(|frontier| {
+            frontier
+                .get(reference.authority as usize)
+                .is_some_and(|round| reference.round() <= *round)
+        })
+    }
+
     pub fn add_blocks(
         &mut self,
         blocks: Vec<Data<StatementBlock>>,
@@ -49,8 +75,10 @@ impl BlockManager {
 
             // check whether we have already processed this block and skip it if so.
             let block_reference = block.reference();
+            // Blocks below the snapshot frontier are already committed, there is no need to store them.
             if self.block_store.block_exists(*block_reference)
                 || self.blocks_pending.contains_key(block_reference)
+                || self.below_snapshot_frontier(block_reference)
             {
                 continue;
             }
@@ -58,7 +86,9 @@ impl BlockManager {
             let mut processed = true;
             for included_reference in block.includes() {
                 // If we are missing a reference then we insert into pending and update the waiting index
-                if !self.block_store.block_exists(*included_reference) {
+                if !self.block_store.block_exists(*included_reference)
+                    && !self.below_snapshot_frontier(included_reference)
+                {
                     processed = false;
                     self.block_references_waiting
                         .entry(*included_reference)
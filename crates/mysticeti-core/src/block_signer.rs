@@ -0,0 +1,466 @@
+// Copyright (c) Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+use std::{
+    io,
+    io::{Read, Write},
+    os::unix::net::{UnixListener, UnixStream},
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use eyre::ensure;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+use crate::{
+    committee::Committee,
+    crypto::{
+        address_signing_digest,
+        own_round_signing_digest,
+        SignatureBytes,
+        Signer,
+        SigningDigest,
+    },
+    network::MAX_FRAME_SIZE,
+    resolver::PeerAddress,
+    signing_guard::SigningGuard,
+    topology::OwnRoundStatement,
+    types::{
+        AuthorityIndex,
+        BaseStatement,
+        BlockDigest,
+        BlockReference,
+        EpochStatus,
+        PublicKey,
+        RoundNumber,
+        TimestampNs,
+    },
+};
+
+/// Signs the blocks proposed by a validator, its address updates and own round statements. The
+/// key may live in the validator process or in a separate signer daemon.
+pub trait BlockSigner: Send {
+    fn public_key(&self) -> PublicKey;
+
+    /// Sign our block with the specified fields.
+    fn sign(&mut self, block: &UnsignedBlock) -> io::Result<SignatureBytes>;
+
+    /// Sign the announcement of our new address, see `AddressUpdate`.
+    fn sign_address(
+        &mut self,
+        authority: AuthorityIndex,
+        sequence: u64,
+        address: &PeerAddress,
+    ) -> io::Result<SignatureBytes>;
+
+    /// Sign a request or report relayed through the overlay, see `OwnRoundStatement`.
+    fn sign_own_round(&mut self, statement: &OwnRoundStatement) -> io::Result<SignatureBytes>;
+}
+
+/// The fields of a block covered by its signature. The signer computes the digest it signs from
+/// them, so that a signer daemon knows the round of the block it signs.
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+pub struct UnsignedBlock {
+    pub author: AuthorityIndex,
+    pub round: RoundNumber,
+    pub includes: Vec<BlockReference>,
+    pub statements: Vec<BaseStatement>,
+    pub meta_creation_time_ns: TimestampNs,
+    pub epoch_marker: EpochStatus,
+}
+
+impl UnsignedBlock {
+    pub fn signing_digest(&self) -> SigningDigest {
+        BlockDigest::signing_digest(
+            self.author,
+            self.round,
+            &self.includes,
+            &self.statements,
+            self.meta_creation_time_ns,
+            self.epoch_marker,
+        )
+    }
+}
+
+impl BlockSigner for Signer {
+    fn public_key(&self) -> PublicKey {
+        Signer::public_key(self)
+    }
+
+    fn sign(&mut self, block: &UnsignedBlock) -> io::Result<SignatureBytes> {
+        Ok(self.sign_digest(&block.signing_digest()))
+    }
+
+    fn sign_own_round(&mut self, statement: &OwnRoundStatement) -> io::Result<SignatureBytes> {
+        Ok(self.sign_digest(&own_round_signing_digest(statement)))
+    }
+
+    fn sign_address(
+        &mut self,
+        authority: AuthorityIndex,
+        sequence: u64,
+        address: &PeerAddress,
+    ) -> io::Result<SignatureBytes> {
+        Ok(self.sign_digest(&address_signing_digest(authority, sequence, address)))
+    }
+}
+
+const SIGNER_TIMEOUT: Duration = Duration::from_secs(5);
+// Blocks are sent whole to the daemon, up to the largest frame accepted from the network
+const MAX_MESSAGE_SIZE: usize = MAX_FRAME_SIZE as usize;
+
+// The daemon computes the digests itself, so that it only signs what it checked: blocks of its
+// authority above the rounds it signed, and no block digest as an address
+#[derive(Serialize, Deserialize)]
+enum SignerRequest {
+    PublicKey,
+    Sign(UnsignedBlock),
+    SignAddress(AuthorityIndex, u64, PeerAddress),
+    SignOwnRound(OwnRoundStatement),
+}
+
+#[derive(Serialize, Deserialize)]
+enum SignerResponse {
+    PublicKey(PublicKey),
+    Signature(SignatureBytes),
+    Refused(String),
+}
+
+/// Signs blocks through a signer daemon listening on a Unix socket, so that the key never
+/// enters the validator process.
+pub struct RemoteSigner {
+    path: PathBuf,
+    stream: Option<UnixStream>,
+    public_key: PublicKey,
+}
+
+impl RemoteSigner {
+    pub fn connect(path: impl Into<PathBuf>) -> io::Result<Self> {
+        let path = path.into();
+        let mut stream = Self::open_stream(&path)?;
+        let public_key = match exchange(&mut stream, &SignerRequest::PublicKey)? {
+            SignerResponse::PublicKey(public_key) => public_key,
+            response => return Err(unexpected_response(response)),
+        };
+        Ok(Self {
+            path,
+            stream: Some(stream),
+            public_key,
+        })
+    }
+
+    fn open_stream(path: &Path) -> io::Result<UnixStream> {
+        let stream = UnixStream::connect(path)?;
+        stream.set_read_timeout(Some(SIGNER_TIMEOUT))?;
+        stream.set_write_timeout(Some(SIGNER_TIMEOUT))?;
+        Ok(stream)
+    }
+
+    fn request(&mut self, request: &SignerRequest) -> io::Result<SignerResponse> {
+        // Reconnect once in case the daemon was restarted. Retrying a signing request is safe
+        // since the daemon returns the same signature for the same block.
+        let mut result = self.try_request(request);
+        if result.is_err() {
+            self.stream = None;
+            result = self.try_request(request);
+        }
+        result
+    }
+
+    fn try_request(&mut self, request: &SignerRequest) -> io::Result<SignerResponse> {
+        let stream = match &mut self.stream {
+            Some(stream) => stream,
+            None => self.stream.insert(Self::open_stream(&self.path)?),
+        };
+        exchange(stream, request)
+    }
+}
+
+impl BlockSigner for RemoteSigner {
+    fn public_key(&self) -> PublicKey {
+        self.public_key.clone()
+    }
+
+    fn sign(&mut self, block: &UnsignedBlock) -> io::Result<SignatureBytes> {
+        match self.request(&SignerRequest::Sign(block.clone()))? {
+            SignerResponse::Signature(signature) => Ok(signature),
+            SignerResponse::Refused(reason) => Err(io::Error::new(
+                io::ErrorKind::PermissionDenied,
+                format!("Signer refused to sign round {}: {reason}", block.round),
+            )),
+            response => Err(unexpected_response(response)),
+        }
+    }
+
+    fn sign_own_round(&mut self, statement: &OwnRoundStatement) -> io::Result<SignatureBytes> {
+        match self.request(&SignerRequest::SignOwnRound(*statement))? {
+            SignerResponse::Signature(signature) => Ok(signature),
+            SignerResponse::Refused(reason) => Err(io::Error::new(
+                io::ErrorKind::PermissionDenied,
+                format!("Signer refused to sign {statement:?}: {reason}"),
+            )),
+            response => Err(unexpected_response(response)),
+        }
+    }
+
+    fn sign_address(
+        &mut self,
+        authority: AuthorityIndex,
+        sequence: u64,
+        address: &PeerAddress,
+    ) -> io::Result<SignatureBytes> {
+        let request = SignerRequest::SignAddress(authority, sequence, address.clone());
+        match self.request(&request)? {
+            SignerResponse::Signature(signature) => Ok(signature),
+            SignerResponse::Refused(reason) => Err(io::Error::new(
+                io::ErrorKind::PermissionDenied,
+                format!("Signer refused to sign address {address}: {reason}"),
+            )),
+            response => Err(unexpected_response(response)),
+        }
+    }
+}
+
+/// Holds the key of a validator and signs its blocks on request. The daemon keeps its own
+/// signing guard and never signs two different blocks for the same round, even if the validator
+/// lost its storage. It only signs statements of its own authority, and address updates with
+/// increasing sequence numbers.
+pub struct SignerDaemon {
+    authority: AuthorityIndex,
+    signer: Signer,
+    signing_guard: SigningGuard,
+    // The last block signed, so that a validator retrying a request gets the same signature
+    last_signed: Option<(UnsignedBlock, SignatureBytes)>,
+    last_address_sequence: Option<u64>,
+}
+
+impl SignerDaemon {
+    pub fn new(
+        authority: AuthorityIndex,
+        signer: Signer,
+        signing_guard_path: impl AsRef<Path>,
+        committee: &Committee,
+    ) -> eyre::Result<Self> {
+        ensure!(
+            committee.get_public_key(authority) == Some(&signer.public_key()),
+            "The key does not match the public key of authority {authority} in the committee"
+        );
+        let signing_guard = SigningGuard::open(signing_guard_path, committee)?;
+        Ok(Self::with_signing_guard(authority, signer, signing_guard))
+    }
+
+    fn with_signing_guard(
+        authority: AuthorityIndex,
+        signer: Signer,
+        signing_guard: SigningGuard,
+    ) -> Self {
+        Self {
+            authority,
+            signer,
+            signing_guard,
+            last_signed: None,
+            last_address_sequence: None,
+        }
+    }
+
+    /// Serve the validator connections one after the other, forever.
+    pub fn serve(&mut self, listener: UnixListener) -> io::Result<()> {
+        for stream in listener.incoming() {
+            let mut stream = stream?;
+            tracing::info!("Validator connected to the signer");
+            while let Ok(request) = read_message::<SignerRequest>(&mut stream) {
+                let response = self.handle(request);
+                if let Err(e) = write_message(&mut stream, &response) {
+                    tracing::warn!("Failed to respond to the validator: {e}");
+                    break;
+                }
+            }
+            tracing::info!("Validator disconnected from the signer");
+        }
+        Ok(())
+    }
+
+    fn handle(&mut self, request: SignerRequest) -> SignerResponse {
+        match request {
+            SignerRequest::PublicKey => SignerResponse::PublicKey(self.signer.public_key()),
+            SignerRequest::Sign(block) => {
+                if let Some((last_block, signature)) = &self.last_signed {
+                    if *last_block == block {
+                        return SignerResponse::Signature(*signature);
+                    }
+                }
+                if block.author != self.authority {
+                    tracing::warn!("Refusing to sign a block of authority {}", block.author);
+                    return SignerResponse::Refused(format!("not authority {}", block.author));
+                }
+                let round = block.round;
+                if !self.signing_guard.may_sign(round) {
+                    tracing::warn!(
+                        "Refusing to sign round {round}, already signed up to round {}",
+                        self.signing_guard.highest_signed_round()
+                    );
+                    return SignerResponse::Refused(format!(
+                        "already signed up to round {}",
+                        self.signing_guard.highest_signed_round()
+                    ));
+                }
+                if let Err(e) = self.signing_guard.record(round) {
+                    return SignerResponse::Refused(format!(
+                        "failed to persist signing guard: {e}"
+                    ));
+                }
+                let signature = self.signer.sign_digest(&block.signing_digest());
+                self.last_signed = Some((block, signature));
+                SignerResponse::Signature(signature)
+            }
+            SignerRequest::SignAddress(authority, sequence, address) => {
+                if authority != self.authority {
+                    tracing::warn!("Refusing to sign address {address} of authority {authority}");
+                    return SignerResponse::Refused(format!("not authority {authority}"));
+                }
+                if let Some(last_sequence) = self.last_address_sequence {
+                    if sequence <= last_sequence {
+                        tracing::warn!(
+                            "Refusing to sign address {address} with sequence {sequence}, \
+                            already signed sequence {last_sequence}"
+                        );
+                        return SignerResponse::Refused(format!(
+                            "already signed address sequence {last_sequence}"
+                        ));
+                    }
+                }
+                tracing::info!("Signing address {address} with sequence {sequence}");
+                self.last_address_sequence = Some(sequence);
+                let digest = address_signing_digest(authority, sequence, &address);
+                SignerResponse::Signature(self.signer.sign_digest(&digest))
+            }
+            SignerRequest::SignOwnRound(statement) => {
+                if statement.author() != self.authority {
+                    return SignerResponse::Refused(format!(
+                        "not authority {}",
+                        statement.author()
+                    ));
+                }
+                let digest = own_round_signing_digest(&statement);
+                SignerResponse::Signature(self.signer.sign_digest(&digest))
+            }
+        }
+    }
+}
+
+fn exchange(stream: &mut UnixStream, request: &SignerRequest) -> io::Result<SignerResponse> {
+    write_message(stream, request)?;
+    read_message(stream)
+}
+
+/// Messages are serialized with bincode and prefixed with their length.
+fn write_message<T: Serialize>(stream: &mut UnixStream, message: &T) -> io::Result<()> {
+    let serialized = bincode::serialize(message).expect("Serialization failed");
+    stream.write_all(&(serialized.len() as u32).to_le_bytes())?;
+    stream.write_all(&serialized)
+}
+
+fn read_message<T: DeserializeOwned>(stream: &mut UnixStream) -> io::Result<T> {
+    let mut size = [0u8; 4];
+    stream.read_exact(&mut size)?;
+    let size = u32::from_le_bytes(size) as usize;
+    if size > MAX_MESSAGE_SIZE {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("Signer message too big: {size}"),
+        ));
+    }
+    let mut buf = vec![0u8; size];
+    stream.read_exact(&mut buf)?;
+    bincode::deserialize(&buf).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+fn unexpected_response(response: SignerResponse) -> io::Error {
+    let response = match response {
+        SignerResponse::PublicKey(_) => "public key",
+        SignerResponse::Signature(_) => "signature",
+        SignerResponse::Refused(_) => "refusal",
+    };
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("Unexpected response from signer: {response}"),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+
+    use super::*;
+    use crate::test_util::{committee, committee_and_cores_with_signer};
+
+    fn start_daemon(dir: &Path, signer: Signer) -> PathBuf {
+        let path = dir.join("signer.sock");
+        let listener = UnixListener::bind(&path).unwrap();
+        let mut daemon =
+            SignerDaemon::with_signing_guard(0, signer, SigningGuard::new_in_memory(&committee(4)));
+        thread::spawn(move || daemon.serve(listener));
+        path
+    }
+
+    #[test]
+    fn remote_signer() {
+        let dir = tempdir::TempDir::new("remote_signer").unwrap();
+        let key = Signer::new_for_test(1).pop().unwrap();
+        let path = start_daemon(dir.path(), Signer::new_for_test(1).pop().unwrap());
+
+        let mut remote = RemoteSigner::connect(&path).unwrap();
+        assert_eq!(BlockSigner::public_key(&remote), key.public_key());
+        let block = |round, meta_creation_time_ns| UnsignedBlock {
+            author: 0,
+            round,
+            includes: vec![],
+            statements: vec![],
+            meta_creation_time_ns,
+            epoch_marker: false,
+        };
+        let signature = remote.sign(&block(1, 1)).unwrap();
+        assert!(signature == key.sign_digest(&block(1, 1).signing_digest()));
+        // Retrying the same request returns the same signature
+        assert!(remote.sign(&block(1, 1)).unwrap() == signature);
+
+        // The daemon refuses to sign a different block for a round it already signed
+        assert!(remote.sign(&block(1, 2)).is_err());
+        assert!(remote.sign(&block(0, 2)).is_err());
+        remote.sign(&block(2, 2)).unwrap();
+        // Or a block of another authority
+        let foreign = UnsignedBlock {
+            author: 1,
+            ..block(3, 2)
+        };
+        assert!(remote.sign(&foreign).is_err());
+
+        // Validators reconnecting to the daemon are subject to the same checks
+        drop(remote);
+        let mut remote = RemoteSigner::connect(&path).unwrap();
+        assert!(remote.sign(&block(2, 3)).is_err());
+        remote.sign(&block(3, 3)).unwrap();
+
+        // Address updates are signed by the daemon as they would be locally
+        let address = "validator-0:1500".parse().unwrap();
+        let signature = remote.sign_address(0, 1, &address).unwrap();
+        let mut key = key;
+        assert!(signature == key.sign_address(0, 1, &address).unwrap());
+        // But only for its own authority and with increasing sequence numbers
+        assert!(remote.sign_address(1, 2, &address).is_err());
+        assert!(remote.sign_address(0, 1, &address).is_err());
+        remote.sign_address(0, 2, &address).unwrap();
+    }
+
+    #[test]
+    fn signing_latency_metrics() {
+        let dir = tempdir::TempDir::new("signing_latency_metrics").unwrap();
+        let path = start_daemon(dir.path(), Signer::new_for_test(1).pop().unwrap());
+
+        let (_, mut cores, _) =
+            committee_and_cores_with_signer(1, |_| Box::new(RemoteSigner::connect(&path).unwrap()));
+        let core = &mut cores[0];
+        assert!(core.try_new_block().is_some());
+        assert_eq!(core.metrics.block_signing_latency_s.get_sample_count(), 1);
+    }
+}
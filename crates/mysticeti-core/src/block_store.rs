@@ -4,7 +4,9 @@
 use std::{
     cmp::max,
     collections::{BTreeMap, HashMap},
+    io,
     io::IoSlice,
+    ops::RangeInclusive,
     sync::Arc,
     time::Instant,
 };
@@ -14,14 +16,19 @@ use parking_lot::RwLock;
 use serde::{Deserialize, Serialize};
 
 use crate::{
+    batch::Batch,
+    block_cache::BlockCache,
     committee::Committee,
     consensus::linearizer::CommittedSubDag,
     data::Data,
+    index_checkpoint::IndexCheckpoint,
     metrics::{Metrics, UtilizationTimerExt},
+    snapshot::Snapshot,
     state::{RecoveredState, RecoveredStateBuilder},
     types::{
         AuthorityIndex,
         BaseStatement,
+        BatchDigest,
         BlockDigest,
         BlockReference,
         RoundNumber,
@@ -35,18 +42,22 @@ use crate::{
 #[derive(Clone)]
 pub struct BlockStore {
     inner: Arc<RwLock<BlockStoreInner>>,
+    // The loaded blocks, see `block_cache`
+    cache: Arc<BlockCache>,
     block_wal_reader: Arc<WalReader>,
     metrics: Arc<Metrics>,
 }
 
 #[derive(Default)]
 struct BlockStoreInner {
-    index: BTreeMap<RoundNumber, HashMap<(AuthorityIndex, BlockDigest), IndexEntry>>,
+    index: BTreeMap<RoundNumber, HashMap<(AuthorityIndex, BlockDigest), WalPosition>>,
     own_blocks: BTreeMap<RoundNumber, BlockDigest>,
     highest_round: RoundNumber,
     authority: AuthorityIndex,
     last_seen_by_authority: Vec<RoundNumber>,
     last_own_block: Option<BlockReference>,
+    // Batches are never unloaded, they are read from the wal whenever needed
+    batches: HashMap<BatchDigest, WalPosition>,
 }
 
 pub trait BlockWriter {
@@ -54,10 +65,10 @@ pub trait BlockWriter {
     fn insert_own_block(&mut self, block: &OwnBlockData);
 }
 
-#[derive(Clone)]
-enum IndexEntry {
-    WalPosition(WalPosition),
-    Loaded(WalPosition, Data<StatementBlock>),
+#[derive(Clone, Copy)]
+struct IndexEntry {
+    reference: BlockReference,
+    position: WalPosition,
 }
 
 impl BlockStore {
@@ -67,6 +78,28 @@ impl BlockStore {
         wal_writer: &WalWriter,
         metrics: Arc<Metrics>,
         committee: &Committee,
+        block_cache_size: usize,
+    ) -> RecoveredState {
+        Self::open_with_checkpoint(
+            authority,
+            block_wal_reader,
+            wal_writer,
+            metrics,
+            committee,
+            block_cache_size,
+            None,
+        )
+    }
+
+    /// Same as `open`, but only replays the wal written after the checkpoint (if it is valid).
+    pub fn open_with_checkpoint(
+        authority: AuthorityIndex,
+        block_wal_reader: Arc<WalReader>,
+        wal_writer: &WalWriter,
+        metrics: Arc<Metrics>,
+        committee: &Committee,
+        block_cache_size: usize,
+        checkpoint: Option<IndexCheckpoint>,
     ) -> RecoveredState {
         let last_seen_by_authority = committee.authorities().map(|_| 0).collect();
         let mut inner = BlockStoreInner {
@@ -74,48 +107,79 @@ impl BlockStore {
             last_seen_by_authority,
             ..Default::default()
         };
+        let cache = BlockCache::new(block_cache_size);
         let mut builder = RecoveredStateBuilder::new();
-        let mut replay_started: Option<Instant> = None;
+        let mut replay_from = WalPosition::default();
         let mut block_count = 0u64;
-        for (pos, (tag, data)) in block_wal_reader.iter_until(wal_writer) {
+        if let Some(checkpoint) = checkpoint {
+            match Self::check_checkpoint(&checkpoint, &block_wal_reader, wal_writer) {
+                Ok(checkpoint_builder) => {
+                    tracing::info!(
+                        "Loaded index checkpoint of {} blocks, replaying the wal from position {}",
+                        checkpoint.blocks.len(),
+                        checkpoint.wal_position
+                    );
+                    builder = checkpoint_builder;
+                    for (reference, position) in &checkpoint.blocks {
+                        inner.add_block(reference, *position);
+                    }
+                    inner.batches.extend(checkpoint.batches.iter().copied());
+                    block_count = checkpoint.blocks.len() as u64;
+                    replay_from = checkpoint.wal_position;
+                }
+                Err(e) => tracing::warn!("Ignoring index checkpoint, replaying the whole wal: {e}"),
+            }
+        }
+        let mut replay_started: Option<Instant> = None;
+        for entry in block_wal_reader.iter_from(replay_from, wal_writer) {
+            let (pos, (tag, data)) = entry.unwrap_or_else(|e| {
+                panic!("Failed to read the wal, see `mysticeti wal verify`: {e}")
+            });
             if replay_started.is_none() {
                 replay_started = Some(Instant::now());
                 tracing::info!("Wal is not empty, starting replay");
             }
-            let block = match tag {
-                WAL_ENTRY_BLOCK => {
-                    let block = Data::<StatementBlock>::from_bytes(data)
-                        .expect("Failed to deserialize data from wal");
+            let entry = WalEntry::decode(tag, data).unwrap_or_else(|e| {
+                panic!("Failed to deserialize wal entry with tag {tag} at position {pos}: {e}")
+            });
+            let block = match entry {
+                WalEntry::Block(block) => {
                     builder.block(pos, &block);
                     block
                 }
-                WAL_ENTRY_PAYLOAD => {
-                    builder.payload(pos, data);
+                WalEntry::Payload(payload) => {
+                    builder.payload(pos, payload);
                     continue;
                 }
-                WAL_ENTRY_OWN_BLOCK => {
-                    let (own_block_data, own_block) = OwnBlockData::from_bytes(data)
-                        .expect("Failed to deserialized own block data from wal");
+                WalEntry::OwnBlock(own_block_data) => {
+                    let own_block = own_block_data.block.clone();
                     builder.own_block(own_block_data);
                     own_block
                 }
-                WAL_ENTRY_STATE => {
-                    builder.state(data);
+                WalEntry::State(state) => {
+                    builder.state(state);
                     continue;
                 }
-                WAL_ENTRY_COMMIT => {
-                    let (commit_data, state) = bincode::deserialize(&data)
-                        .expect("Failed to deserialized commit data from wal");
+                WalEntry::Commit(commit_data, state) => {
                     builder.commit_data(commit_data, state);
                     continue;
                 }
-                _ => panic!("Unknown wal tag {tag} at position {pos}"),
+                WalEntry::Snapshot(snapshot) => {
+                    builder.snapshot(snapshot);
+                    continue;
+                }
+                WalEntry::Batch(batch) => {
+                    inner.batches.insert(*batch.digest(), pos);
+                    continue;
+                }
             };
-            // todo - we want to keep some last blocks in the cache
             block_count += 1;
-            inner.add_unloaded(block.reference(), pos);
+            inner.add_block(block.reference(), pos);
+            // The blocks replayed last stay in the cache
+            cache.insert(block);
         }
         metrics.block_store_entries.inc_by(block_count);
+        metrics.block_store_cache_bytes.set(cache.size() as i64);
         if let Some(replay_started) = replay_started {
             tracing::info!("Wal replay completed in {:?}", replay_started.elapsed());
         } else {
@@ -124,20 +188,75 @@ impl BlockStore {
         let this = Self {
             block_wal_reader,
             inner: Arc::new(RwLock::new(inner)),
+            cache: Arc::new(cache),
             metrics,
         };
         builder.build(this)
     }
 
+    pub(crate) fn check_checkpoint(
+        checkpoint: &IndexCheckpoint,
+        block_wal_reader: &WalReader,
+        wal_writer: &WalWriter,
+    ) -> io::Result<RecoveredStateBuilder> {
+        // The wal may have been truncated or replaced since the checkpoint was taken
+        if checkpoint.wal_position.start() > wal_writer.size() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "the checkpoint covers {} bytes of wal, but the wal holds {} bytes",
+                    checkpoint.wal_position,
+                    wal_writer.size()
+                ),
+            ));
+        }
+        if block_wal_reader.crc_before(checkpoint.wal_position)? != checkpoint.wal_crc {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "the wal before position {} differs from the wal checkpointed",
+                    checkpoint.wal_position
+                ),
+            ));
+        }
+        RecoveredStateBuilder::from_checkpoint(checkpoint, block_wal_reader)
+    }
+
+    /// See `WalReader::crc_before`.
+    pub fn wal_crc_before(&self, position: WalPosition) -> io::Result<u32> {
+        self.block_wal_reader.crc_before(position)
+    }
+
+    pub fn insert_batch(&self, batch: &Batch, position: WalPosition) {
+        self.inner.write().batches.insert(*batch.digest(), position);
+    }
+
+    pub fn contains_batch(&self, digest: &BatchDigest) -> bool {
+        self.inner.read().batches.contains_key(digest)
+    }
+
+    pub fn get_batch(&self, digest: &BatchDigest) -> Option<Data<Batch>> {
+        let position = *self.inner.read().batches.get(digest)?;
+        let (tag, data) = self
+            .block_wal_reader
+            .read(position)
+            .expect("Failed to read wal");
+        assert_eq!(
+            tag, WAL_ENTRY_BATCH,
+            "Trying to load batch at position {position}, found tag {tag}"
+        );
+        Some(Data::from_bytes(data).expect("Failed to deserialize batch from wal"))
+    }
+
     pub fn insert_block(&self, block: Data<StatementBlock>, position: WalPosition) {
         self.metrics.block_store_entries.inc();
-        self.inner.write().add_loaded(position, block);
+        self.inner.write().add_block(block.reference(), position);
+        self.cache_block(block);
     }
 
     pub fn get_block(&self, reference: BlockReference) -> Option<Data<StatementBlock>> {
         let entry = self.inner.read().get_block(reference);
-        // todo - consider adding loaded entries back to cache
-        entry.map(|pos| self.read_index(pos))
+        entry.map(|entry| self.read_index(entry))
     }
 
     pub fn get_blocks_by_round(&self, round: RoundNumber) -> Vec<Data<StatementBlock>> {
@@ -209,6 +328,34 @@ impl BlockStore {
             .flatten()
     }
 
+    /// The position of all the blocks in the wal, see `IndexCheckpoint`.
+    pub fn block_positions(&self) -> Vec<(BlockReference, WalPosition)> {
+        self.inner
+            .read()
+            .index
+            .iter()
+            .flat_map(|(round, map)| {
+                map.iter().map(|((authority, digest), position)| {
+                    let reference = BlockReference {
+                        authority: *authority,
+                        round: *round,
+                        digest: *digest,
+                    };
+                    (reference, *position)
+                })
+            })
+            .collect()
+    }
+
+    pub fn batch_positions(&self) -> Vec<(BatchDigest, WalPosition)> {
+        self.inner
+            .read()
+            .batches
+            .iter()
+            .map(|(digest, position)| (*digest, *position))
+            .collect()
+    }
+
     pub fn len_expensive(&self) -> usize {
         let inner = self.inner.read();
         inner.index.values().map(HashMap::len).sum()
@@ -223,10 +370,19 @@ impl BlockStore {
             return;
         }
         let _timer = self.metrics.block_store_cleanup_util.utilization_timer();
-        let unloaded = self.inner.write().unload_below_round(threshold_round);
+        let unloaded = self.cache.evict_below_round(threshold_round);
+        if unloaded > 0 {
+            tracing::debug!(
+                "Unloaded {unloaded} entries from block store cache, {} remain",
+                self.cache.len()
+            );
+        }
         self.metrics
             .block_store_unloaded_blocks
             .inc_by(unloaded as u64);
+        self.metrics
+            .block_store_cache_bytes
+            .set(self.cache.size() as i64);
         let retained_maps = self.block_wal_reader.cleanup();
         self.metrics.wal_mappings.set(retained_maps as i64);
     }
@@ -262,29 +418,45 @@ impl BlockStore {
     }
 
     fn read_index(&self, entry: IndexEntry) -> Data<StatementBlock> {
-        match entry {
-            IndexEntry::WalPosition(position) => {
-                self.metrics.block_store_loaded_blocks.inc();
-                let (tag, data) = self
-                    .block_wal_reader
-                    .read(position)
-                    .expect("Failed to read wal");
-                match tag {
-                    WAL_ENTRY_BLOCK => {
-                        Data::from_bytes(data).expect("Failed to deserialize data from wal")
-                    }
-                    WAL_ENTRY_OWN_BLOCK => {
-                        OwnBlockData::from_bytes(data)
-                            .expect("Failed to deserialized own block from wal")
-                            .1
-                    }
-                    _ => {
-                        panic!("Trying to load index entry at position {position}, found tag {tag}")
-                    }
-                }
-            }
-            IndexEntry::Loaded(_, block) => block,
+        if let Some(block) = self.cache.get(&entry.reference) {
+            self.metrics.block_store_cache_hits.inc();
+            return block;
         }
+        // Cache miss
+        self.metrics.block_store_loaded_blocks.inc();
+        let position = entry.position;
+        let (tag, data) = self
+            .block_wal_reader
+            .read(position)
+            .expect("Failed to read wal");
+        let block = match tag {
+            WAL_ENTRY_BLOCK => Data::from_bytes(data).expect("Failed to deserialize data from wal"),
+            WAL_ENTRY_OWN_BLOCK => {
+                OwnBlockData::from_bytes(data)
+                    .expect("Failed to deserialized own block from wal")
+                    .1
+            }
+            _ => {
+                panic!("Trying to load index entry at position {position}, found tag {tag}")
+            }
+        };
+        self.cache_block(block.clone());
+        block
+    }
+
+    fn cache_block(&self, block: Data<StatementBlock>) {
+        let evicted = self.cache.insert(block);
+        self.metrics
+            .block_store_unloaded_blocks
+            .inc_by(evicted as u64);
+        self.metrics
+            .block_store_cache_bytes
+            .set(self.cache.size() as i64);
+    }
+
+    /// Keep the blocks of `rounds` in memory, see `BlockCache::pin_rounds`.
+    pub fn pin_rounds(&self, rounds: RangeInclusive<RoundNumber>) {
+        self.cache.pin_rounds(rounds);
     }
 
     fn read_index_vec(&self, entries: Vec<IndexEntry>) -> Vec<Data<StatementBlock>> {
@@ -334,13 +506,8 @@ impl BlockStoreInner {
         };
         blocks
             .iter()
-            .filter_map(|((a, _), entry)| {
-                if *a == authority {
-                    Some(entry.clone())
-                } else {
-                    None
-                }
-            })
+            .filter(|((a, _), _)| *a == authority)
+            .map(|((a, d), position)| IndexEntry::new(*a, round, *d, *position))
             .collect()
     }
 
@@ -348,61 +515,31 @@ impl BlockStoreInner {
         let Some(blocks) = self.index.get(&round) else {
             return vec![];
         };
-        blocks.values().cloned().collect()
+        blocks
+            .iter()
+            .map(|((a, d), position)| IndexEntry::new(*a, round, *d, *position))
+            .collect()
     }
 
     pub fn get_block(&self, reference: BlockReference) -> Option<IndexEntry> {
-        self.index
+        let position = self
+            .index
             .get(&reference.round)?
-            .get(&(reference.authority, reference.digest))
-            .cloned()
-    }
-
-    // todo - also specify LRU criteria
-    /// Unload all entries from below or equal threshold_round
-    pub fn unload_below_round(&mut self, threshold_round: RoundNumber) -> usize {
-        let mut unloaded = 0usize;
-        for (round, map) in self.index.iter_mut() {
-            // todo - try BTreeMap for self.index?
-            if *round > threshold_round {
-                continue;
-            }
-            for entry in map.values_mut() {
-                match entry {
-                    IndexEntry::WalPosition(_) => {}
-                    // Unload entry
-                    IndexEntry::Loaded(position, _) => {
-                        unloaded += 1;
-                        *entry = IndexEntry::WalPosition(*position);
-                    }
-                }
-            }
-        }
-        if unloaded > 0 {
-            tracing::debug!("Unloaded {unloaded} entries from block store cache");
-        }
-        unloaded
+            .get(&(reference.authority, reference.digest))?;
+        Some(IndexEntry {
+            reference,
+            position: *position,
+        })
     }
 
-    pub fn add_unloaded(&mut self, reference: &BlockReference, position: WalPosition) {
+    pub fn add_block(&mut self, reference: &BlockReference, position: WalPosition) {
         self.highest_round = max(self.highest_round, reference.round());
         let map = self.index.entry(reference.round()).or_default();
-        map.insert(reference.author_digest(), IndexEntry::WalPosition(position));
+        map.insert(reference.author_digest(), position);
         self.add_own_index(reference);
         self.update_last_seen_by_authority(reference);
     }
 
-    pub fn add_loaded(&mut self, position: WalPosition, block: Data<StatementBlock>) {
-        self.highest_round = max(self.highest_round, block.round());
-        self.add_own_index(block.reference());
-        self.update_last_seen_by_authority(block.reference());
-        let map = self.index.entry(block.round()).or_default();
-        map.insert(
-            (block.author(), block.digest()),
-            IndexEntry::Loaded(position, block),
-        );
-    }
-
     pub fn last_seen_by_authority(&self, authority: AuthorityIndex) -> RoundNumber {
         *self
             .last_seen_by_authority
@@ -482,6 +619,24 @@ impl BlockStoreInner {
     }
 }
 
+impl IndexEntry {
+    fn new(
+        authority: AuthorityIndex,
+        round: RoundNumber,
+        digest: BlockDigest,
+        position: WalPosition,
+    ) -> Self {
+        Self {
+            reference: BlockReference {
+                authority,
+                round,
+                digest,
+            },
+            position,
+        }
+    }
+}
+
 pub const WAL_ENTRY_BLOCK: Tag = 1;
 pub const WAL_ENTRY_PAYLOAD: Tag = 2;
 pub const WAL_ENTRY_OWN_BLOCK: Tag = 3;
@@ -489,6 +644,54 @@ pub const WAL_ENTRY_STATE: Tag = 4;
 // Commit entry includes both commit interpreter incremental state and committed transactions aggregator
 // todo - They could be separated for better performance, but this will require catching up for committed transactions aggregator state
 pub const WAL_ENTRY_COMMIT: Tag = 5;
+// Snapshot installed when the validator joined from a snapshot rather than from genesis
+pub const WAL_ENTRY_SNAPSHOT: Tag = 6;
+// Transactions disseminated ahead of the blocks referencing them by digest
+pub const WAL_ENTRY_BATCH: Tag = 7;
+
+/// The entries of the wal, see `BlockStore::open` for how they are replayed.
+pub enum WalEntry {
+    Block(Data<StatementBlock>),
+    /// The serialized statements of the next own block (see `RecoveredStateBuilder::payload`).
+    Payload(Bytes),
+    OwnBlock(OwnBlockData),
+    State(Bytes),
+    Commit(Vec<CommitData>, Bytes),
+    Snapshot(Snapshot),
+    Batch(Data<Batch>),
+}
+
+impl WalEntry {
+    pub fn decode(tag: Tag, data: Bytes) -> bincode::Result<Self> {
+        Ok(match tag {
+            WAL_ENTRY_BLOCK => Self::Block(Data::from_bytes(data)?),
+            WAL_ENTRY_PAYLOAD => Self::Payload(data),
+            WAL_ENTRY_OWN_BLOCK => Self::OwnBlock(OwnBlockData::from_bytes(data)?.0),
+            WAL_ENTRY_STATE => Self::State(data),
+            WAL_ENTRY_COMMIT => {
+                let (commit_data, state) = bincode::deserialize(&data)?;
+                Self::Commit(commit_data, state)
+            }
+            WAL_ENTRY_SNAPSHOT => Self::Snapshot(bincode::deserialize(&data)?),
+            WAL_ENTRY_BATCH => Self::Batch(Data::from_bytes(data)?),
+            _ => return Err(bincode::ErrorKind::Custom(format!("Unknown wal tag {tag}")).into()),
+        })
+    }
+
+    /// The name of the constant of a tag, for instance `WAL_ENTRY_BLOCK`.
+    pub fn tag_name(tag: Tag) -> Option<&'static str> {
+        match tag {
+            WAL_ENTRY_BLOCK => Some("WAL_ENTRY_BLOCK"),
+            WAL_ENTRY_PAYLOAD => Some("WAL_ENTRY_PAYLOAD"),
+            WAL_ENTRY_OWN_BLOCK => Some("WAL_ENTRY_OWN_BLOCK"),
+            WAL_ENTRY_STATE => Some("WAL_ENTRY_STATE"),
+            WAL_ENTRY_COMMIT => Some("WAL_ENTRY_COMMIT"),
+            WAL_ENTRY_SNAPSHOT => Some("WAL_ENTRY_SNAPSHOT"),
+            WAL_ENTRY_BATCH => Some("WAL_ENTRY_BATCH"),
+            _ => None,
+        }
+    }
+}
 
 impl BlockWriter for (&mut WalWriter, &BlockStore) {
     fn insert_block(&mut self, block: Data<StatementBlock>) -> WalPosition {
@@ -558,6 +761,7 @@ impl From<&CommittedSubDag> for CommitData {
 #[cfg(test)]
 mod test {
     use super::*;
+    use crate::{test_util::test_metrics, wal::walf};
 
     #[test]
     fn own_block_serialization_test() {
@@ -565,4 +769,78 @@ mod test {
         let serialized = bincode::serialize(&next_entry).unwrap();
         assert_eq!(serialized.len(), OWN_BLOCK_HEADER_SIZE);
     }
+
+    #[test]
+    fn block_cache_test() {
+        let committee = Committee::new_test(vec![1; 4]);
+        let blocks: Vec<_> = (1..=4)
+            .map(|round| {
+                Data::new(StatementBlock::new(
+                    0,
+                    round,
+                    vec![],
+                    vec![],
+                    0,
+                    false,
+                    Default::default(),
+                ))
+            })
+            .collect();
+        // The cache holds two blocks
+        let cache_size = 2 * blocks[0].serialized_bytes().len();
+        let file = tempfile::tempfile().unwrap();
+        let open = |metrics: Arc<Metrics>| {
+            let (wal_writer, wal_reader) = walf(file.try_clone().unwrap()).unwrap();
+            let recovered = BlockStore::open(
+                0,
+                Arc::new(wal_reader),
+                &wal_writer,
+                metrics,
+                &committee,
+                cache_size,
+            );
+            (wal_writer, recovered.block_store)
+        };
+
+        let metrics = test_metrics();
+        let (mut wal_writer, block_store) = open(metrics.clone());
+        for block in &blocks {
+            (&mut wal_writer, &block_store).insert_block(block.clone());
+        }
+        assert_eq!(metrics.block_store_unloaded_blocks.get(), 2);
+        assert_eq!(
+            block_store.get_block(*blocks[3].reference()).unwrap(),
+            blocks[3]
+        );
+        assert_eq!(metrics.block_store_cache_hits.get(), 1);
+        // A miss loads the block back in the cache, evicting the least recently used block
+        assert_eq!(
+            block_store.get_block(*blocks[0].reference()).unwrap(),
+            blocks[0]
+        );
+        assert_eq!(metrics.block_store_loaded_blocks.get(), 1);
+        block_store.get_block(*blocks[0].reference()).unwrap();
+        block_store.get_block(*blocks[3].reference()).unwrap();
+        assert_eq!(metrics.block_store_cache_hits.get(), 3);
+        assert_eq!(metrics.block_store_loaded_blocks.get(), 1);
+        block_store.get_block(*blocks[2].reference()).unwrap();
+        assert_eq!(metrics.block_store_loaded_blocks.get(), 2);
+        assert_eq!(metrics.block_store_unloaded_blocks.get(), 4);
+
+        block_store.cleanup(3);
+        assert_eq!(metrics.block_store_unloaded_blocks.get(), 5);
+        assert_eq!(
+            metrics.block_store_cache_bytes.get() as usize,
+            cache_size / 2
+        );
+        drop(block_store);
+
+        // The last blocks replayed are cached
+        let metrics = test_metrics();
+        let (_wal_writer, block_store) = open(metrics.clone());
+        assert_eq!(block_store.get_blocks_by_round(4), vec![blocks[3].clone()]);
+        assert_eq!(block_store.get_blocks_by_round(3), vec![blocks[2].clone()]);
+        assert_eq!(metrics.block_store_cache_hits.get(), 2);
+        assert_eq!(metrics.block_store_loaded_blocks.get(), 0);
+    }
 }
@@ -0,0 +1,384 @@
+// Copyright (c) Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    sync::{mpsc, Arc},
+    thread,
+};
+
+use eyre::eyre;
+use parking_lot::Mutex;
+use tokio::sync::oneshot;
+
+use crate::{
+    committee::{Committee, QuorumThreshold, StakeAggregator},
+    crypto::PublicKey,
+    data::Data,
+    metrics::Metrics,
+    types::{AuthorityIndex, BlockReference, RoundNumber, StatementBlock},
+};
+
+/// The maximum number of blocks whose signatures are verified in a single batch.
+pub const MAX_BATCH_SIZE: usize = 256;
+/// How many rounds below the highest linked reference we keep linked references for.
+const LINKED_REFERENCES_ROUNDS: RoundNumber = 1_000;
+
+/// Verifies the blocks received from all connections on a dedicated pool of threads. Each
+/// thread takes all the blocks pending verification (up to `MAX_BATCH_SIZE`) and checks their
+/// signatures as a single batch.
+///
+/// Some blocks referenced by already verified blocks do not need their signature checked: the
+/// digest of a block covers its signature, so a block matching a trusted reference is the one its
+/// author signed. A reference is trusted once its own author included it (its previous blocks)
+/// or verified blocks from a quorum of authorities included it, a single Byzantine validator can
+/// not vouch for a block forged in the name of another authority. This makes fetching missing
+/// ancestors considerably cheaper.
+pub struct BlockVerifier {
+    state: Arc<VerifierState>,
+    sender: mpsc::Sender<VerificationRequest>,
+}
+
+struct VerifierState {
+    committee: Arc<Committee>,
+    linked_references: Mutex<LinkedReferences>,
+    metrics: Arc<Metrics>,
+}
+
+/// The references included by verified blocks.
+#[derive(Default)]
+struct LinkedReferences {
+    references: BTreeMap<RoundNumber, HashMap<BlockReference, Links>>,
+}
+
+/// The authorities whose verified blocks include a reference.
+struct Links {
+    authorities: StakeAggregator<QuorumThreshold>,
+    trusted: bool,
+}
+
+/// How the authenticity of a block was established.
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum Authentication {
+    Signature,
+    DigestLinkage,
+}
+
+struct VerificationRequest {
+    block: Data<StatementBlock>,
+    result: oneshot::Sender<eyre::Result<()>>,
+}
+
+impl BlockVerifier {
+    pub fn start(committee: Arc<Committee>, threads: usize, metrics: Arc<Metrics>) -> Self {
+        let state = Arc::new(VerifierState {
+            committee,
+            linked_references: Default::default(),
+            metrics,
+        });
+        let (sender, receiver) = mpsc::channel();
+        let receiver = Arc::new(Mutex::new(receiver));
+        for i in 0..threads.max(1) {
+            let state = state.clone();
+            let receiver = receiver.clone();
+            thread::Builder::new()
+                .name(format!("block-verifier-{i}"))
+                .spawn(move || state.run(&receiver))
+                .unwrap();
+        }
+        Self { state, sender }
+    }
+
+    /// Verify a block received from the network. Blocks are only rejected for their own faults,
+    /// a bad block verified in the same batch does not affect the others.
+    pub async fn verify(&self, block: Data<StatementBlock>) -> eyre::Result<()> {
+        if cfg!(feature = "simulator") {
+            // Keep the simulation deterministic by verifying on the simulated runtime.
+            return self.state.verify_batch(&[&block]).pop().unwrap();
+        }
+        let (sender, receiver) = oneshot::channel();
+        let request = VerificationRequest {
+            block,
+            result: sender,
+        };
+        self.sender
+            .send(request)
+            .expect("Block verifier threads are not expected to stop");
+        receiver
+            .await
+            .map_err(|_| eyre!("Block verifier dropped the request"))?
+    }
+}
+
+impl VerifierState {
+    fn run(&self, receiver: &Mutex<mpsc::Receiver<VerificationRequest>>) {
+        loop {
+            let mut requests = Vec::new();
+            {
+                // Wait for the first block, then take whatever else is already pending.
+                let receiver = receiver.lock();
+                let Ok(request) = receiver.recv() else {
+                    return;
+                };
+                requests.push(request);
+                while requests.len() < MAX_BATCH_SIZE {
+                    let Ok(request) = receiver.try_recv() else {
+                        break;
+                    };
+                    requests.push(request);
+                }
+            }
+            let blocks: Vec<_> = requests.iter().map(|request| &*request.block).collect();
+            let results = self.verify_batch(&blocks);
+            for (request, result) in requests.into_iter().zip(results) {
+                // The connection may have been closed in the meantime.
+                request.result.send(result).ok();
+            }
+        }
+    }
+
+    fn verify_batch(&self, blocks: &[&StatementBlock]) -> Vec<eyre::Result<()>> {
+        // The signatures are checked without holding the lock, the other threads verify meanwhile
+        let trusted: Vec<_> = {
+            let linked_references = self.linked_references.lock();
+            blocks
+                .iter()
+                .map(|block| linked_references.is_trusted(block.reference()))
+                .collect()
+        };
+        let results = verify_blocks_linked(&self.committee, blocks, &trusted);
+        let mut linked_references = self.linked_references.lock();
+        results
+            .into_iter()
+            .zip(blocks)
+            .map(|(result, block)| {
+                let authentication = result?;
+                linked_references.insert(&self.committee, block.author(), block.includes());
+                let method = match authentication {
+                    Authentication::Signature => "signature",
+                    Authentication::DigestLinkage => "digest",
+                };
+                self.metrics
+                    .blocks_verified_total
+                    .with_label_values(&[method])
+                    .inc();
+                Ok(())
+            })
+            .collect()
+    }
+}
+
+impl LinkedReferences {
+    /// Whether the block with this reference is known to be authentic.
+    fn is_trusted(&self, reference: &BlockReference) -> bool {
+        self.references
+            .get(&reference.round)
+            .and_then(|references| references.get(reference))
+            .is_some_and(|links| links.trusted)
+    }
+
+    /// Record the includes of a verified block of `author`.
+    fn insert(
+        &mut self,
+        committee: &Committee,
+        author: AuthorityIndex,
+        references: &[BlockReference],
+    ) {
+        for reference in references {
+            let links = self
+                .references
+                .entry(reference.round)
+                .or_default()
+                .entry(*reference)
+                .or_insert_with(|| Links {
+                    authorities: StakeAggregator::new(),
+                    trusted: false,
+                });
+            let quorum = links.authorities.add(author, committee);
+            links.trusted |= quorum || reference.authority == author;
+        }
+        if let Some(highest) = self.references.keys().next_back() {
+            let threshold = highest.saturating_sub(LINKED_REFERENCES_ROUNDS);
+            self.references = self.references.split_off(&threshold);
+        }
+    }
+}
+
+/// Verify the blocks, checking all their signatures as a single batch. Returns the result of
+/// `StatementBlock::verify` for every block, in order.
+pub fn verify_blocks(committee: &Committee, blocks: &[&StatementBlock]) -> Vec<eyre::Result<()>> {
+    verify_blocks_linked(committee, blocks, &vec![false; blocks.len()])
+        .into_iter()
+        .map(|result| result.map(|_| ()))
+        .collect()
+}
+
+/// Same as `verify_blocks`, but skips the signature check of the blocks whose reference is
+/// trusted (see `BlockVerifier`), as listed in `trusted`. All other checks (including
+/// recomputing the digest) are still performed on every block.
+fn verify_blocks_linked(
+    committee: &Committee,
+    blocks: &[&StatementBlock],
+    trusted: &[bool],
+) -> Vec<eyre::Result<Authentication>> {
+    let results: Vec<_> = blocks
+        .iter()
+        .zip(trusted)
+        .map(|(block, linked)| {
+            // Linked blocks have no public key to check their signature against
+            block
+                .verify_without_signature(committee)
+                .map(|public_key| Some(public_key).filter(|_| !linked))
+        })
+        .collect();
+    let signed: Vec<(&PublicKey, &StatementBlock)> = results
+        .iter()
+        .zip(blocks)
+        .filter_map(|(result, block)| Some(((*result.as_ref().ok()?)?, *block)))
+        .collect();
+    let mut signatures = PublicKey::verify_blocks(&signed).into_iter();
+    results
+        .into_iter()
+        .map(|result| match result? {
+            None => Ok(Authentication::DigestLinkage),
+            Some(_) => signatures
+                .next()
+                .expect("One signature result per signed block")
+                .map(|_| Authentication::Signature)
+                .map_err(|e| eyre!("Block signature verification has failed: {:?}", e)),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use prometheus::Registry;
+
+    use super::*;
+    use crate::test_util::committee;
+
+    fn block(authority: u64, includes: Vec<BlockReference>) -> Data<StatementBlock> {
+        Data::new(StatementBlock::new(
+            authority,
+            1,
+            includes,
+            vec![],
+            0,
+            false,
+            Default::default(),
+        ))
+    }
+
+    fn genesis() -> Vec<BlockReference> {
+        (0..4)
+            .map(|authority| *StatementBlock::new_genesis(authority).reference())
+            .collect()
+    }
+
+    #[test]
+    fn verify_blocks_identifies_culprit() {
+        let committee = committee(4);
+        let valid = block(0, genesis());
+        // Not enough includes from the previous round
+        let invalid = block(1, genesis()[..1].to_vec());
+        // Unknown author
+        let unknown = block(7, genesis());
+
+        let results = verify_blocks(&committee, &[&valid, &invalid, &unknown, &valid]);
+        assert_eq!(results.len(), 4);
+        assert!(results[0].is_ok());
+        assert!(results[1].is_err());
+        assert!(results[2].is_err());
+        assert!(results[3].is_ok());
+    }
+
+    fn verify_linked(
+        committee: &Committee,
+        block: &StatementBlock,
+        linked_references: &LinkedReferences,
+    ) -> eyre::Result<Authentication> {
+        let trusted = linked_references.is_trusted(block.reference());
+        verify_blocks_linked(committee, &[block], &[trusted])
+            .pop()
+            .unwrap()
+    }
+
+    #[test]
+    fn verify_by_digest_linkage() {
+        let committee = committee(4);
+        let ancestor = block(0, genesis());
+        let mut linked_references = LinkedReferences::default();
+
+        let result = verify_linked(&committee, &ancestor, &linked_references);
+        assert_eq!(result.unwrap(), Authentication::Signature);
+
+        // Included by the next block of its author
+        linked_references.insert(&committee, 0, &[*ancestor.reference()]);
+        let result = verify_linked(&committee, &ancestor, &linked_references);
+        assert_eq!(result.unwrap(), Authentication::DigestLinkage);
+
+        // All other checks are still performed on linked blocks
+        let invalid = block(1, genesis()[..1].to_vec());
+        linked_references.insert(&committee, 1, &[*invalid.reference()]);
+        let result = verify_linked(&committee, &invalid, &linked_references);
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn forged_link_rejected() {
+        let committee = committee(4);
+        // A block forged in the name of authority 0, linked by a Byzantine authority 1
+        let forged = block(0, genesis());
+        let includes = [*forged.reference()];
+        let mut linked_references = LinkedReferences::default();
+        linked_references.insert(&committee, 1, &includes);
+
+        // The signature of the forged block is still checked
+        let result = verify_linked(&committee, &forged, &linked_references);
+        assert_eq!(result.unwrap(), Authentication::Signature);
+        linked_references.insert(&committee, 1, &includes);
+        linked_references.insert(&committee, 2, &includes);
+        assert!(!linked_references.is_trusted(forged.reference()));
+
+        // Unless a quorum of authorities links it
+        linked_references.insert(&committee, 3, &includes);
+        let result = verify_linked(&committee, &forged, &linked_references);
+        assert_eq!(result.unwrap(), Authentication::DigestLinkage);
+    }
+
+    #[test]
+    fn linked_references_cleanup() {
+        let committee = committee(4);
+        let mut linked_references = LinkedReferences::default();
+        let old = BlockReference::new_test(0, 1);
+        linked_references.insert(&committee, 0, &[old]);
+        assert!(linked_references.is_trusted(&old));
+        let recent = BlockReference::new_test(0, LINKED_REFERENCES_ROUNDS + 2);
+        linked_references.insert(&committee, 0, &[recent]);
+        assert!(!linked_references.is_trusted(&old));
+        assert!(linked_references.is_trusted(&recent));
+    }
+
+    #[tokio::test]
+    async fn block_verifier() {
+        let committee = committee(4);
+        let metrics = Metrics::new(&Registry::default(), Some(&committee)).0;
+        let verifier = BlockVerifier::start(committee, 2, metrics.clone());
+        let valid = (0..4).map(|authority| verifier.verify(block(authority, genesis())));
+        for result in futures::future::join_all(valid).await {
+            result.unwrap();
+        }
+        assert!(verifier
+            .verify(block(0, genesis()[..2].to_vec()))
+            .await
+            .is_err());
+        let verified = |method| {
+            metrics
+                .blocks_verified_total
+                .with_label_values(&[method])
+                .get()
+        };
+        assert_eq!(verified("signature"), 4);
+        assert_eq!(verified("digest"), 0);
+    }
+}
@@ -3,7 +3,7 @@
 
 use std::{
     borrow::Borrow,
-    collections::{HashMap, HashSet},
+    collections::{BTreeMap, HashMap, HashSet},
     fmt::Display,
     hash::Hash,
     marker::PhantomData,
@@ -25,6 +25,7 @@ use crate::{
         AuthoritySet,
         BaseStatement,
         BlockReference,
+        RoundNumber,
         Stake,
         StatementBlock,
         TransactionLocator,
@@ -165,10 +166,7 @@ impl Committee {
         Self::new(
             Signer::new_for_test(committee_size)
                 .into_iter()
-                .map(|keypair| Authority {
-                    stake: 1,
-                    public_key: keypair.public_key(),
-                })
+                .map(|keypair| Authority::new(1, keypair.public_key()))
                 .collect(),
         )
     }
@@ -181,6 +179,10 @@ pub struct Authority {
 }
 
 impl Authority {
+    pub fn new(stake: Stake, public_key: PublicKey) -> Self {
+        Self { stake, public_key }
+    }
+
     pub fn test_from_stake(stake: Stake) -> Self {
         Self {
             stake,
@@ -243,6 +245,9 @@ pub struct TransactionAggregator<TH, H = HashSet<TransactionLocator>> {
     // Currently we skip serialization for test handler,
     // but it also means some invariants wrt unknown_transaction might be potentially broken in some tests
     handler: H,
+    // Highest round of each authority covered by the snapshot we started from (if any). Votes for
+    // transactions of these blocks may arrive after they were processed, so they are ignored.
+    snapshot_frontier: Option<Vec<RoundNumber>>,
 }
 
 pub trait TransactionAggregatorKey:
@@ -312,6 +317,7 @@ impl<TH: CommitteeThreshold, H: ProcessedTransactionHandler<TransactionLocator>
         Self {
             pending: Default::default(),
             handler: Default::default(),
+            snapshot_frontier: None,
         }
     }
 }
@@ -323,15 +329,31 @@ impl<TH: CommitteeThreshold, H: ProcessedTransactionHandler<TransactionLocator>>
         Self {
             pending: Default::default(),
             handler,
+            snapshot_frontier: None,
         }
     }
 
     pub fn state(&self) -> Bytes {
-        bincode::serialize(&self.pending)
+        // Serialize in a canonical order so that identical states have identical bytes
+        let pending: BTreeMap<_, _> = self.pending.iter().collect();
+        bincode::serialize(&pending)
             .expect("Serialization failed")
             .into()
     }
 
+    /// Ignore votes for transactions included in blocks at or below the snapshot frontier.
+    pub fn with_snapshot_frontier(&mut self, frontier: &[BlockReference]) {
+        self.snapshot_frontier = Some(frontier.iter().map(|r| r.round()).collect());
+    }
+
+    fn below_snapshot_frontier(&self, block: &BlockReference) -> bool {
+        self.snapshot_frontier.as_ref().is_some_and(|frontier| {
+            frontier
+                .get(block.authority as usize)
+                .is_some_and(|round| block.round() <= *round)
+        })
+    }
+
     pub fn with_state(&mut self, state: &Bytes) {
         assert!(self.pending.is_empty());
         self.pending = bincode::deserialize(state).expect("Deserialization failed");
@@ -368,9 +390,11 @@ impl<TH: CommitteeThreshold, H: ProcessedTransactionHandler<TransactionLocator>>
         committee: &Committee,
         processed: &mut Vec<TransactionLocator>,
     ) {
+        let below_snapshot_frontier = self.below_snapshot_frontier(locator_range.block());
         if let Some(range_map) = self.pending.get_mut(locator_range.block()) {
             range_map.mutate_range(locator_range.range(), |range, aggregator_opt| {
                 match aggregator_opt {
+                    None if below_snapshot_frontier => {}
                     None => {
                         for l in range {
                             let k = TransactionLocator::new(*locator_range.block(), l);
@@ -394,7 +418,7 @@ impl<TH: CommitteeThreshold, H: ProcessedTransactionHandler<TransactionLocator>>
             if range_map.is_empty() {
                 self.pending.remove(locator_range.block());
             }
-        } else {
+        } else if !below_snapshot_frontier {
             for l in locator_range.locators() {
                 // todo - make unknown_transaction take TransactionLocatorRange instead
                 self.handler.unknown_transaction(l, vote);
@@ -453,6 +477,8 @@ impl<TH: CommitteeThreshold, H: ProcessedTransactionHandler<TransactionLocator>>
                 BaseStatement::VoteRange(range) => {
                     self.vote(*range, block.author(), committee, &mut processed);
                 }
+                // Batched transactions are only ordered by consensus, they are not voted on
+                BaseStatement::Batch(_) => {}
             }
         }
         processed
@@ -0,0 +1,92 @@
+// Copyright (c) Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+use std::io;
+
+use serde::{Deserialize, Serialize};
+
+/// Entries smaller than this are never compressed, the savings would not be worth the CPU.
+const MIN_COMPRESSED_SIZE: usize = 512;
+/// Favour speed over ratio: blocks are mostly made of highly redundant transactions.
+const DEFLATE_LEVEL: u8 = 1;
+
+/// The compression applied to blocks sent over the network and to WAL entries. Each algorithm is
+/// identified on the wire and on disk by its id, so that new ones can be added without breaking
+/// existing peers and WAL files.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, Eq, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Compression {
+    #[default]
+    None,
+    Deflate,
+}
+
+impl Compression {
+    pub fn id(&self) -> u8 {
+        match self {
+            Self::None => 0,
+            Self::Deflate => 1,
+        }
+    }
+
+    pub fn from_id(id: u8) -> Option<Self> {
+        match id {
+            0 => Some(Self::None),
+            1 => Some(Self::Deflate),
+            _ => None,
+        }
+    }
+
+    /// Compress the data, unless it is too small to benefit from it or does not shrink.
+    pub fn maybe_compress(&self, data: &[u8]) -> Option<Vec<u8>> {
+        if data.len() < MIN_COMPRESSED_SIZE {
+            return None;
+        }
+        let compressed = self.compress(data)?;
+        (compressed.len() < data.len()).then_some(compressed)
+    }
+
+    /// Compress the data, returns None if this is `Compression::None`.
+    pub fn compress(&self, data: &[u8]) -> Option<Vec<u8>> {
+        match self {
+            Self::None => None,
+            Self::Deflate => Some(miniz_oxide::deflate::compress_to_vec(data, DEFLATE_LEVEL)),
+        }
+    }
+
+    /// Decompress data, failing if it decompresses to more than `max_size` bytes.
+    pub fn decompress(&self, data: &[u8], max_size: usize) -> io::Result<Vec<u8>> {
+        match self {
+            Self::None => Ok(data.to_vec()),
+            Self::Deflate => miniz_oxide::inflate::decompress_to_vec_with_limit(data, max_size)
+                .map_err(|e| {
+                    io::Error::new(io::ErrorKind::InvalidData, format!("Inflate failed: {e}"))
+                }),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn compress_decompress() {
+        let data: Vec<u8> = (0..4096).map(|i| (i % 7) as u8).collect();
+        let compressed = Compression::Deflate.maybe_compress(&data).unwrap();
+        assert!(compressed.len() < data.len());
+        let decompressed = Compression::Deflate
+            .decompress(&compressed, data.len())
+            .unwrap();
+        assert_eq!(decompressed, data);
+
+        // Decompression is bounded
+        assert!(Compression::Deflate
+            .decompress(&compressed, data.len() - 1)
+            .is_err());
+
+        // Small entries are left alone
+        assert!(Compression::Deflate.maybe_compress(&[0u8; 16]).is_none());
+        assert!(Compression::None.maybe_compress(&data).is_none());
+    }
+}
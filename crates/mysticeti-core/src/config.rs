@@ -4,15 +4,29 @@
 use std::{
     fs,
     io,
+    io::Write,
     net::{IpAddr, Ipv4Addr, SocketAddr},
+    os::unix::fs::OpenOptionsExt,
     path::{Path, PathBuf},
+    sync::Arc,
     time::Duration,
 };
 
+use eyre::{ensure, eyre};
+use rand::rngs::OsRng;
 use serde::{de::DeserializeOwned, Deserialize, Serialize};
 
 use crate::{
+    batch::Dissemination,
+    block_signer::{BlockSigner, RemoteSigner},
+    committee::{Authority, Committee},
+    compression::Compression,
     crypto::{dummy_signer, Signer},
+    index_checkpoint::IndexCheckpoint,
+    network::TransportProtocol,
+    rate_limit::RateLimits,
+    resolver::{Host, PeerAddress},
+    topology::Topology,
     types::{AuthorityIndex, PublicKey, RoundNumber},
 };
 
@@ -51,6 +65,45 @@ pub struct NodeParameters {
     pub consensus_only: bool,
     #[serde(default = "node_defaults::default_enable_synchronizer")]
     pub enable_synchronizer: bool,
+    /// Take a snapshot of the committed state every `snapshot_period` rounds (0 disables snapshots).
+    #[serde(default = "node_defaults::default_snapshot_period")]
+    pub snapshot_period: RoundNumber,
+    /// Whether a validator starting with an empty storage downloads a snapshot from its peers
+    /// instead of syncing the DAG from genesis.
+    #[serde(default = "node_defaults::default_enable_state_sync")]
+    pub enable_state_sync: bool,
+    /// Persist the index of the block store every `index_checkpoint_period` committed rounds, so
+    /// that a restart only replays the wal written since the last checkpoint (0 disables them).
+    #[serde(default = "node_defaults::default_index_checkpoint_period")]
+    pub index_checkpoint_period: RoundNumber,
+    /// The maximum size in bytes of the blocks the block store keeps in memory, see `block_cache`.
+    #[serde(default = "node_defaults::default_block_cache_size")]
+    pub block_cache_size: usize,
+    /// The number of threads verifying the blocks received from the network.
+    #[serde(default = "node_defaults::default_block_verification_threads")]
+    pub block_verification_threads: usize,
+    /// The compression offered to peers. A connection is compressed if both ends offer the same.
+    #[serde(default)]
+    pub network_compression: Compression,
+    /// The compression of the entries written to the wal.
+    #[serde(default)]
+    pub wal_compression: Compression,
+    /// How validators connect to each other, our own protocol over TCP or QUIC.
+    #[serde(default)]
+    pub network_transport: TransportProtocol,
+    /// Whether transactions are embedded in blocks or disseminated separately in batches.
+    #[serde(default)]
+    pub dissemination: Dissemination,
+    /// Own blocks of at least this many bytes are erasure-coded across the committee rather than
+    /// sent in full to every peer, see `erasure`.
+    #[serde(default)]
+    pub erasure_coding_threshold: Option<usize>,
+    /// How validators connect to each other, see `topology`.
+    #[serde(default)]
+    pub topology: Topology,
+    /// The budgets of every peer, see `rate_limit`.
+    #[serde(default)]
+    pub rate_limits: RateLimits,
 }
 
 pub mod node_defaults {
@@ -90,6 +143,26 @@ pub mod node_defaults {
     pub fn default_enable_synchronizer() -> bool {
         true
     }
+
+    pub fn default_snapshot_period() -> super::RoundNumber {
+        100
+    }
+
+    pub fn default_enable_state_sync() -> bool {
+        false
+    }
+
+    pub fn default_index_checkpoint_period() -> super::RoundNumber {
+        1000
+    }
+
+    pub fn default_block_cache_size() -> usize {
+        256 * 1024 * 1024
+    }
+
+    pub fn default_block_verification_threads() -> usize {
+        2
+    }
 }
 
 impl Default for NodeParameters {
@@ -104,6 +177,18 @@ impl Default for NodeParameters {
             enable_pipelining: node_defaults::default_enable_pipelining(),
             consensus_only: node_defaults::default_consensus_only(),
             enable_synchronizer: node_defaults::default_enable_synchronizer(),
+            snapshot_period: node_defaults::default_snapshot_period(),
+            enable_state_sync: node_defaults::default_enable_state_sync(),
+            index_checkpoint_period: node_defaults::default_index_checkpoint_period(),
+            block_cache_size: node_defaults::default_block_cache_size(),
+            block_verification_threads: node_defaults::default_block_verification_threads(),
+            network_compression: Compression::default(),
+            network_transport: TransportProtocol::default(),
+            wal_compression: Compression::default(),
+            dissemination: Dissemination::default(),
+            erasure_coding_threshold: None,
+            topology: Topology::default(),
+            rate_limits: RateLimits::default(),
         }
     }
 }
@@ -113,13 +198,38 @@ impl ImportExport for NodeParameters {}
 #[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct NodeIdentifier {
     pub public_key: PublicKey,
+    /// The address the peers dial, which may name the host rather than its IP address.
+    pub network_address: PeerAddress,
+    pub metrics_address: PeerAddress,
+    /// The address to accept connections on, when it differs from the advertised one (behind
+    /// a NAT for instance). Defaults to all interfaces on the port of `network_address`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub listen_address: Option<SocketAddr>,
+}
+
+impl NodeIdentifier {
+    pub fn default_filename(authority: AuthorityIndex) -> PathBuf {
+        format!("identifier-{authority}.yaml").into()
+    }
+}
+
+impl ImportExport for NodeIdentifier {}
+
+/// A read-only node that follows consensus without voting. Observers are not part of the
+/// committee: they never propose blocks and do not count toward stake.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct ObserverIdentifier {
     pub network_address: SocketAddr,
     pub metrics_address: SocketAddr,
+    /// The validators this observer subscribes to for blocks.
+    pub follow: Vec<AuthorityIndex>,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct NodePublicConfig {
     pub identifiers: Vec<NodeIdentifier>,
+    #[serde(default)]
+    pub observers: Vec<ObserverIdentifier>,
     pub parameters: NodeParameters,
 }
 
@@ -136,67 +246,171 @@ impl NodePublicConfig {
             let public_key = key.public_key();
             let network_port = Self::PORT_OFFSET_FOR_TESTS + i as u16;
             let metrics_port = benchmark_port_offset + network_port;
-            let network_address = SocketAddr::new(ip, network_port);
-            let metrics_address = SocketAddr::new(ip, metrics_port);
+            let network_address = SocketAddr::new(ip, network_port).into();
+            let metrics_address = SocketAddr::new(ip, metrics_port).into();
             identifiers.push(NodeIdentifier {
                 public_key,
                 network_address,
                 metrics_address,
+                listen_address: None,
             });
         }
 
         Self {
             identifiers,
+            observers: Vec::new(),
             parameters: NodeParameters::default(),
         }
     }
 
-    pub fn new_for_benchmarks(ips: Vec<IpAddr>, node_parameters: Option<NodeParameters>) -> Self {
-        let default_with_ips = Self::new_for_tests(ips.len()).with_ips(ips);
+    /// Assemble the committee and the public config from the identifiers collected from every
+    /// validator. Authority indices follow the order of the identifiers and all validators get
+    /// the same stake.
+    pub fn new_for_genesis(
+        identifiers: Vec<NodeIdentifier>,
+        parameters: NodeParameters,
+    ) -> eyre::Result<(Arc<Committee>, Self)> {
+        ensure!(!identifiers.is_empty(), "The committee can not be empty");
+        ensure!(
+            identifiers.len() <= 128,
+            "The committee can not have more than 128 validators"
+        );
+        for (i, a) in identifiers.iter().enumerate() {
+            for (j, b) in identifiers.iter().enumerate().skip(i + 1) {
+                ensure!(
+                    a.public_key != b.public_key,
+                    "Validators {i} and {j} have the same public key"
+                );
+                ensure!(
+                    a.network_address != b.network_address,
+                    "Validators {i} and {j} have the same network address"
+                );
+            }
+        }
+        let committee = Committee::new(
+            identifiers
+                .iter()
+                .map(|id| Authority::new(1, id.public_key.clone()))
+                .collect(),
+        );
+        let public_config = Self {
+            identifiers,
+            observers: Vec::new(),
+            parameters,
+        };
+        Ok((committee, public_config))
+    }
+
+    /// The validators are reached at `hosts`, IP addresses or hostnames.
+    pub fn new_for_benchmarks(
+        hosts: Vec<impl Into<Host>>,
+        node_parameters: Option<NodeParameters>,
+    ) -> Self {
+        let hosts: Vec<_> = hosts.into_iter().map(Into::into).collect();
+        let default_with_hosts = Self::new_for_tests(hosts.len()).with_hosts(hosts);
         Self {
-            identifiers: default_with_ips.identifiers,
+            identifiers: default_with_hosts.identifiers,
+            observers: Vec::new(),
             parameters: node_parameters.unwrap_or_default(),
         }
     }
 
-    pub fn with_ips(mut self, ips: Vec<IpAddr>) -> Self {
-        for (id, ip) in self.identifiers.iter_mut().zip(ips) {
-            id.network_address.set_ip(ip);
-            id.metrics_address.set_ip(ip);
+    pub fn with_hosts(mut self, hosts: Vec<Host>) -> Self {
+        for (id, host) in self.identifiers.iter_mut().zip(hosts) {
+            id.network_address.host = host.clone();
+            id.metrics_address.host = host;
         }
         self
     }
 
     pub fn with_port_offset(mut self, port_offset: u16) -> Self {
         for id in self.identifiers.iter_mut() {
-            id.network_address
-                .set_port(id.network_address.port() + port_offset);
-            id.metrics_address
-                .set_port(id.metrics_address.port() + port_offset);
+            id.network_address.port += port_offset;
+            id.metrics_address.port += port_offset;
         }
+        for observer in self.observers.iter_mut() {
+            observer
+                .network_address
+                .set_port(observer.network_address.port() + port_offset);
+            observer
+                .metrics_address
+                .set_port(observer.metrics_address.port() + port_offset);
+        }
+        self
+    }
+
+    /// Add an observer following the specified validators. Observers are assigned the ports
+    /// following the network and metrics ports of the validators.
+    pub fn with_observer(mut self, follow: Vec<AuthorityIndex>) -> Self {
+        let first = self
+            .identifiers
+            .first()
+            .expect("Committee should not be empty");
+        let ip = first
+            .network_address
+            .socket_addr()
+            .expect("Observers are only assigned addresses next to validators with IP addresses")
+            .ip();
+        let port =
+            first.network_address.port + 2 * (self.identifiers.len() + self.observers.len()) as u16;
+        self.observers.push(ObserverIdentifier {
+            network_address: SocketAddr::new(ip, port),
+            metrics_address: SocketAddr::new(ip, port + 1),
+            follow,
+        });
         self
     }
 
     /// Return all network addresses (including our own) in the order of the authority index.
-    pub fn all_network_addresses(&self) -> impl Iterator<Item = SocketAddr> + '_ {
-        self.identifiers.iter().map(|id| id.network_address)
+    pub fn all_network_addresses(&self) -> impl Iterator<Item = PeerAddress> + '_ {
+        self.identifiers.iter().map(|id| id.network_address.clone())
     }
 
     /// Return all metric addresses (including our own) in the order of the authority index.
-    pub fn all_metric_addresses(&self) -> impl Iterator<Item = SocketAddr> + '_ {
-        self.identifiers.iter().map(|id| id.metrics_address)
+    pub fn all_metric_addresses(&self) -> impl Iterator<Item = PeerAddress> + '_ {
+        self.identifiers.iter().map(|id| id.metrics_address.clone())
     }
 
-    pub fn network_address(&self, authority: AuthorityIndex) -> Option<SocketAddr> {
+    pub fn network_address(&self, authority: AuthorityIndex) -> Option<PeerAddress> {
         self.identifiers
             .get(authority as usize)
-            .map(|id| id.network_address)
+            .map(|id| id.network_address.clone())
     }
 
-    pub fn metrics_address(&self, authority: AuthorityIndex) -> Option<SocketAddr> {
+    pub fn metrics_address(&self, authority: AuthorityIndex) -> Option<PeerAddress> {
         self.identifiers
             .get(authority as usize)
-            .map(|id| id.metrics_address)
+            .map(|id| id.metrics_address.clone())
+    }
+
+    /// The address the authority accepts connections on, see `NodeIdentifier::listen_address`.
+    pub fn listen_address(&self, authority: AuthorityIndex) -> Option<SocketAddr> {
+        self.identifiers.get(authority as usize).map(|id| {
+            id.listen_address
+                .unwrap_or_else(|| id.network_address.default_listen_address())
+        })
+    }
+
+    pub fn observer(&self, observer: usize) -> Option<&ObserverIdentifier> {
+        self.observers.get(observer)
+    }
+
+    /// Observers do not have an authority index. They are identified on the network by their
+    /// position in the list of observers, offset by the size of the committee.
+    pub fn observer_peer_id(&self, observer: usize) -> usize {
+        self.identifiers.len() + observer
+    }
+
+    /// Return the network addresses (and peer ids) of all observers following the authority.
+    pub fn observers_following(
+        &self,
+        authority: AuthorityIndex,
+    ) -> impl Iterator<Item = (usize, SocketAddr)> + '_ {
+        self.observers
+            .iter()
+            .enumerate()
+            .filter(move |(_, observer)| observer.follow.contains(&authority))
+            .map(|(i, observer)| (self.observer_peer_id(i), observer.network_address))
     }
 }
 
@@ -205,16 +419,58 @@ impl ImportExport for NodePublicConfig {}
 #[derive(Serialize, Deserialize)]
 pub struct NodePrivateConfig {
     authority: AuthorityIndex,
-    pub keypair: Signer,
+    /// The key signing our blocks, unless they are signed by a signer daemon.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub keypair: Option<Signer>,
+    /// The Unix socket of the signer daemon holding our key (if any).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub remote_signer: Option<PathBuf>,
     pub storage_path: PathBuf,
+    /// The file recording the highest round we signed (see `SigningGuard`). The guard protects
+    /// against a wiped or restored storage, so it must not live in the storage directory: it
+    /// defaults to `signing-guard-{authority}` next to it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub signing_guard_path: Option<PathBuf>,
+    /// The address of the admin server (see `admin`). It defaults to the loopback interface on
+    /// the metrics port offset by `ADMIN_PORT_OFFSET`, so that only the host reaches it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub admin_address: Option<SocketAddr>,
 }
 
 impl NodePrivateConfig {
+    pub const ADMIN_PORT_OFFSET: u16 = 10000;
+
+    pub fn new(authority: AuthorityIndex, keypair: Signer, storage_path: PathBuf) -> Self {
+        Self {
+            authority,
+            keypair: Some(keypair),
+            remote_signer: None,
+            storage_path,
+            signing_guard_path: None,
+            admin_address: None,
+        }
+    }
+
+    /// Generate a fresh keypair for the specified authority.
+    pub fn generate(authority: AuthorityIndex, storage_path: PathBuf) -> Self {
+        Self {
+            authority,
+            keypair: Some(Signer::generate(&mut OsRng)),
+            remote_signer: None,
+            storage_path,
+            signing_guard_path: None,
+            admin_address: None,
+        }
+    }
+
     pub fn new_for_tests(index: AuthorityIndex) -> Self {
         Self {
             authority: index,
-            keypair: dummy_signer(),
+            keypair: Some(dummy_signer()),
+            remote_signer: None,
             storage_path: PathBuf::from("storage"),
+            signing_guard_path: None,
+            admin_address: None,
         }
     }
 
@@ -227,8 +483,11 @@ impl NodePrivateConfig {
                 let path = working_dir.join(NodePrivateConfig::default_storage_path(authority));
                 Self {
                     authority,
-                    keypair,
+                    keypair: Some(keypair),
+                    remote_signer: None,
                     storage_path: path,
+                    signing_guard_path: None,
+                    admin_address: None,
                 }
             })
             .collect()
@@ -253,9 +512,129 @@ impl NodePrivateConfig {
     pub fn wal(&self) -> PathBuf {
         self.storage_path.join("wal")
     }
+
+    pub fn index_checkpoint(&self) -> PathBuf {
+        self.storage_path.join(IndexCheckpoint::DEFAULT_FILENAME)
+    }
+
+    pub fn signing_guard(&self) -> PathBuf {
+        match &self.signing_guard_path {
+            Some(path) => path.clone(),
+            None => self
+                .storage_path
+                .parent()
+                .unwrap_or(Path::new(""))
+                .join(format!("signing-guard-{}", self.authority)),
+        }
+    }
+
+    /// The signing guard of a signer daemon holding this private config, next to the signing
+    /// guard of the validator.
+    pub fn signer_daemon_guard(&self) -> PathBuf {
+        self.signing_guard()
+            .with_file_name(format!("signer-daemon-guard-{}", self.authority))
+    }
+
+    /// The sequence number of our address announcements, next to the signing guard so that it
+    /// outlives the storage as well.
+    pub fn address_sequence(&self) -> PathBuf {
+        self.signing_guard()
+            .with_file_name(format!("address-sequence-{}", self.authority))
+    }
+
+    /// The address to serve the admin routes on, see `admin_address`.
+    pub fn admin_address(&self, metrics_address: &PeerAddress) -> SocketAddr {
+        self.admin_address.unwrap_or_else(|| {
+            SocketAddr::new(
+                Ipv4Addr::LOCALHOST.into(),
+                metrics_address.port + Self::ADMIN_PORT_OFFSET,
+            )
+        })
+    }
+
+    pub fn authority(&self) -> AuthorityIndex {
+        self.authority
+    }
+
+    /// The signer of our blocks: either our own keypair or the signer daemon.
+    pub fn into_block_signer(self) -> io::Result<Box<dyn BlockSigner>> {
+        match (self.keypair, &self.remote_signer) {
+            (Some(keypair), None) => Ok(Box::new(keypair)),
+            (None, Some(path)) => Ok(Box::new(RemoteSigner::connect(path)?)),
+            _ => Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "Exactly one of the keypair and the remote signer should be specified",
+            )),
+        }
+    }
+
+    pub fn public_key(&self) -> io::Result<PublicKey> {
+        match &self.keypair {
+            Some(keypair) => Ok(keypair.public_key()),
+            None => self
+                .remote_signer
+                .as_ref()
+                .map(|path| Ok(RemoteSigner::connect(path)?.public_key()))
+                .unwrap_or_else(|| {
+                    Err(io::Error::new(
+                        io::ErrorKind::InvalidInput,
+                        "Neither a keypair nor a remote signer is specified",
+                    ))
+                }),
+        }
+    }
+
+    /// Check that our keypair matches the public key registered for our authority index in both
+    /// the committee and the public config.
+    pub fn verify(
+        &self,
+        committee: &Committee,
+        public_config: &NodePublicConfig,
+    ) -> eyre::Result<()> {
+        let authority = self.authority;
+        ensure!(
+            committee.len() == public_config.identifiers.len(),
+            "The committee has {} validators but the public config has {}",
+            committee.len(),
+            public_config.identifiers.len()
+        );
+        let public_key = self.public_key()?;
+        let committee_key = committee
+            .get_public_key(authority)
+            .ok_or_else(|| eyre!("Authority {authority} is not in the committee"))?;
+        ensure!(
+            committee_key == &public_key,
+            "The keypair does not match the public key of authority {authority} in the committee"
+        );
+        let identifier = &public_config.identifiers[authority as usize];
+        ensure!(
+            identifier.public_key == public_key,
+            "The keypair does not match the public key of authority {authority} in the public config"
+        );
+        Ok(())
+    }
 }
 
-impl ImportExport for NodePrivateConfig {}
+impl ImportExport for NodePrivateConfig {
+    fn print<P: AsRef<Path>>(&self, path: P) -> Result<(), io::Error> {
+        print_owner_only(self, path)
+    }
+}
+
+/// Files holding keys are only readable by their owner.
+pub(crate) fn print_owner_only<T: Serialize, P: AsRef<Path>>(
+    object: &T,
+    path: P,
+) -> Result<(), io::Error> {
+    let content = serde_yaml::to_string(object).expect("Failed to serialize object to YAML string");
+    let mut file = fs::OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .mode(0o600)
+        .open(&path)?;
+    file.write_all(content.as_bytes())
+}
 
 #[derive(Serialize, Deserialize, Clone)]
 pub struct ClientParameters {
@@ -297,3 +676,115 @@ impl Default for ClientParameters {
 }
 
 impl ImportExport for ClientParameters {}
+
+#[cfg(test)]
+mod tests {
+    use rand::{rngs::StdRng, SeedableRng};
+
+    use super::*;
+
+    fn private_config(authority: AuthorityIndex, keypair: Signer) -> NodePrivateConfig {
+        NodePrivateConfig::new(authority, keypair, "storage".into())
+    }
+
+    #[test]
+    fn admin_address_on_loopback() {
+        let metrics_address: PeerAddress = "validator-0:1504".parse().unwrap();
+        let mut config = private_config(0, dummy_signer());
+        let admin_address = config.admin_address(&metrics_address);
+        assert!(admin_address.ip().is_loopback());
+        assert_eq!(admin_address.port(), 11504);
+
+        let configured: SocketAddr = "10.0.0.1:9000".parse().unwrap();
+        config.admin_address = Some(configured);
+        assert_eq!(config.admin_address(&metrics_address), configured);
+    }
+
+    #[test]
+    fn signing_guard_outside_storage() {
+        let mut config = NodePrivateConfig::new_for_benchmarks(Path::new("/var/mysticeti"), 1)
+            .pop()
+            .unwrap();
+        assert_eq!(
+            config.signing_guard(),
+            PathBuf::from("/var/mysticeti/signing-guard-0")
+        );
+        assert!(!config.signing_guard().starts_with(&config.storage_path));
+
+        config.signing_guard_path = Some("/etc/mysticeti/guard".into());
+        assert_eq!(
+            config.signing_guard(),
+            PathBuf::from("/etc/mysticeti/guard")
+        );
+        assert_eq!(
+            config.signer_daemon_guard(),
+            PathBuf::from("/etc/mysticeti/signer-daemon-guard-0")
+        );
+    }
+
+    fn identifiers(keys: &[Signer]) -> Vec<NodeIdentifier> {
+        keys.iter()
+            .enumerate()
+            .map(|(i, key)| NodeIdentifier {
+                public_key: key.public_key(),
+                network_address: format!("validator-{i}:1000").parse().unwrap(),
+                metrics_address: format!("validator-{i}:2000").parse().unwrap(),
+                listen_address: None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn genesis_ceremony() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let keys: Vec<_> = (0..4).map(|_| Signer::generate(&mut rng)).collect();
+        let (committee, public_config) =
+            NodePublicConfig::new_for_genesis(identifiers(&keys), NodeParameters::default())
+                .unwrap();
+        assert_eq!(committee.len(), 4);
+
+        let mut keys = keys.into_iter();
+        let config = private_config(0, keys.next().unwrap());
+        config.verify(&committee, &public_config).unwrap();
+
+        // The keypair of validator 1 does not match the slot of validator 2
+        let config = private_config(2, keys.next().unwrap());
+        assert!(config.verify(&committee, &public_config).is_err());
+
+        let config = private_config(4, keys.next().unwrap());
+        assert!(config.verify(&committee, &public_config).is_err());
+    }
+
+    #[test]
+    fn genesis_rejects_duplicate_keys() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let keys: Vec<_> = (0..3).map(|_| Signer::generate(&mut rng)).collect();
+        let mut identifiers = identifiers(&keys);
+        identifiers[2].public_key = identifiers[0].public_key.clone();
+        assert!(NodePublicConfig::new_for_genesis(identifiers, NodeParameters::default()).is_err());
+    }
+
+    #[test]
+    fn listen_address() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let keys: Vec<_> = (0..2).map(|_| Signer::generate(&mut rng)).collect();
+        let mut identifiers = identifiers(&keys);
+        identifiers[1].listen_address = Some("127.0.0.1:1500".parse().unwrap());
+        let (_, public_config) =
+            NodePublicConfig::new_for_genesis(identifiers, NodeParameters::default()).unwrap();
+        assert_eq!(
+            public_config.listen_address(0),
+            Some("[::]:1000".parse().unwrap())
+        );
+        assert_eq!(
+            public_config.listen_address(1),
+            Some("127.0.0.1:1500".parse().unwrap())
+        );
+
+        // The listen address is only written when set
+        let yaml = serde_yaml::to_string(&public_config.identifiers[0]).unwrap();
+        assert!(!yaml.contains("listen_address"));
+        let identifier: NodeIdentifier = serde_yaml::from_str(&yaml).unwrap();
+        assert_eq!(identifier.network_address.to_string(), "validator-0:1000");
+    }
+}
@@ -3,7 +3,7 @@
 
 use std::{fmt::Display, sync::Arc};
 
-use super::{LeaderStatus, DEFAULT_WAVE_LENGTH};
+use super::{LeaderStatus, LeaderSupport, DEFAULT_WAVE_LENGTH};
 use crate::{
     block_store::BlockStore,
     committee::{Committee, QuorumThreshold, StakeAggregator, SkipThreshold},
@@ -150,6 +150,11 @@ impl BaseCommitter {
     ) -> bool {
         let mut votes_stake_aggregator = StakeAggregator::<QuorumThreshold>::new();
         for reference in potential_certificate.includes() {
+            // Votes for the leader are necessarily above its round. This also avoids loading
+            // blocks that may not be available locally (e.g. below a snapshot frontier).
+            if reference.round() <= leader_block.round() {
+                continue;
+            }
             let potential_vote = self
                 .block_store
                 .get_block(*reference)
@@ -165,6 +170,30 @@ impl BaseCommitter {
         false
     }
 
+    /// The votes and certificates for the specified leader (`leader_block`), as counted by the
+    /// direct decision rule.
+    pub fn leader_support(&self, leader_block: &Data<StatementBlock>) -> LeaderSupport {
+        let decision_round = self.decision_round(self.wave_number(leader_block.round()));
+        let votes = self
+            .block_store
+            .get_blocks_by_round(decision_round - 1)
+            .into_iter()
+            .filter(|block| self.is_vote(block, leader_block))
+            .map(|block| *block.reference())
+            .collect();
+        let certificates = self
+            .block_store
+            .get_blocks_by_round(decision_round)
+            .into_iter()
+            .filter(|block| self.is_certificate(block, leader_block))
+            .map(|block| *block.reference())
+            .collect();
+        LeaderSupport {
+            votes,
+            certificates,
+        }
+    }
+
     /// Decide the status of a target leader from the specified anchor. We commit the target leader
     /// if it has a certified link to the anchor. Otherwise, we skip the target leader.
     fn decide_leader_from_anchor(
@@ -4,9 +4,10 @@
 use std::{collections::HashSet, fmt};
 
 use crate::{
+    batch::Batch,
     block_store::BlockStore,
     data::Data,
-    types::{BlockReference, StatementBlock},
+    types::{BlockReference, RoundNumber, StatementBlock},
 };
 
 /// The output of consensus is an ordered list of [`CommittedSubDag`]. The application can arbitrarily
@@ -16,18 +17,45 @@ pub struct CommittedSubDag {
     pub anchor: BlockReference,
     /// All the committed blocks that are part of this sub-dag
     pub blocks: Vec<Data<StatementBlock>>,
+    /// The batches shared by these blocks, in the order of the blocks
+    pub batches: Vec<Data<Batch>>,
 }
 
 impl CommittedSubDag {
     /// Create new (empty) sub-dag.
     pub fn new(anchor: BlockReference, blocks: Vec<Data<StatementBlock>>) -> Self {
-        Self { anchor, blocks }
+        Self {
+            anchor,
+            blocks,
+            batches: vec![],
+        }
     }
 
     /// Sort the blocks of the sub-dag by round number. Any deterministic algorithm works.
     pub fn sort(&mut self) {
         self.blocks.sort_by_key(|x| x.round());
     }
+
+    /// Resolve the batch digests of the (sorted) blocks back to the batches they stand for.
+    /// Blocks only enter the dag once the batches they reference are stored: a missing batch
+    /// would make the committed transactions differ between validators, so it stops the
+    /// validator instead.
+    pub fn resolve_batches(&mut self, block_store: &BlockStore) {
+        self.batches = self
+            .blocks
+            .iter()
+            .flat_map(|block| block.batches().map(move |digest| (block, digest)))
+            .map(|(block, digest)| {
+                block_store.get_batch(digest).unwrap_or_else(|| {
+                    panic!(
+                        "Batch {} referenced by committed block {} is not stored",
+                        digest,
+                        block.reference()
+                    )
+                })
+            })
+            .collect();
+    }
 }
 
 /// Expand a committed sequence of leader into a sequence of sub-dags.
@@ -35,6 +63,8 @@ impl CommittedSubDag {
 pub struct Linearizer {
     /// Keep track of all committed blocks to avoid committing the same block twice.
     pub committed: HashSet<BlockReference>,
+    /// Highest round of each authority committed before the snapshot we started from (if any).
+    snapshot_frontier: Option<Vec<RoundNumber>>,
 }
 
 impl Linearizer {
@@ -42,6 +72,19 @@ impl Linearizer {
         Self::default()
     }
 
+    /// Consider all blocks at or below the frontier as committed.
+    pub fn with_snapshot_frontier(&mut self, frontier: &[BlockReference]) {
+        self.snapshot_frontier = Some(frontier.iter().map(|r| r.round()).collect());
+    }
+
+    fn below_snapshot_frontier(&self, reference: &BlockReference) -> bool {
+        self.snapshot_frontier.as_ref().is_some_and(|frontier| {
+            frontier
+                .get(reference.authority as usize)
+                .is_some_and(|round| reference.round() <= *round)
+        })
+    }
+
     /// Collect the sub-dag from a specific anchor excluding any duplicates or blocks that
     /// have already been committed (within previous sub-dags).
     fn collect_sub_dag(
@@ -57,6 +100,10 @@ impl Linearizer {
         while let Some(x) = buffer.pop() {
             to_commit.push(x.clone());
             for reference in x.includes() {
+                // Blocks below the snapshot frontier are committed and not available locally.
+                if self.below_snapshot_frontier(reference) {
+                    continue;
+                }
                 // The block manager may have cleaned up blocks passed the latest committed rounds.
                 let block = block_store
                     .get_block(*reference)
@@ -84,6 +131,7 @@ impl Linearizer {
 
             // [Optional] sort the sub-dag using a deterministic algorithm.
             sub_dag.sort();
+            sub_dag.resolve_batches(block_store);
             committed.push(sub_dag);
         }
         committed
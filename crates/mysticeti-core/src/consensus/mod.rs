@@ -5,7 +5,7 @@ use std::fmt::Display;
 
 use crate::{
     data::Data,
-    types::{format_authority_round, AuthorityIndex, RoundNumber, StatementBlock},
+    types::{format_authority_round, AuthorityIndex, BlockReference, RoundNumber, StatementBlock},
 };
 
 pub mod base_committer;
@@ -66,6 +66,15 @@ impl LeaderStatus {
     }
 }
 
+/// The blocks supporting a leader block, reported to debug the commit rule (see `dag_export`).
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub struct LeaderSupport {
+    /// The blocks of the last voting round voting for the leader.
+    pub votes: Vec<BlockReference>,
+    /// The blocks of the decision round certifying the leader.
+    pub certificates: Vec<BlockReference>,
+}
+
 impl PartialOrd for LeaderStatus {
     fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
         Some(self.cmp(other))
@@ -1,18 +1,23 @@
 // Copyright (c) Mysten Labs, Inc.
 // SPDX-License-Identifier: Apache-2.0
 
-use std::{collections::VecDeque, sync::Arc};
+use std::{collections::VecDeque, ops::RangeInclusive, sync::Arc};
 use std::collections::HashMap;
 
-use super::{base_committer::BaseCommitter, LeaderStatus, DEFAULT_WAVE_LENGTH};
+use super::{base_committer::BaseCommitter, LeaderStatus, LeaderSupport, DEFAULT_WAVE_LENGTH};
 use crate::{
     block_store::BlockStore,
     committee::Committee,
     consensus::base_committer::BaseCommitterOptions,
+    data::Data,
     metrics::Metrics,
-    types::{format_authority_round, AuthorityIndex, BlockReference, RoundNumber},
+    types::{format_authority_round, AuthorityIndex, BlockReference, RoundNumber, StatementBlock},
 };
 
+/// The number of waves above a leader inspected to decide it, see `leader_statuses`. Leaders
+/// still undecided past this horizon are reported as undecided.
+pub const DECISION_HORIZON_WAVES: RoundNumber = 4;
+
 /// A universal committer uses a collection of committers to commit a sequence of leaders.
 /// It can be configured to use a combination of different commit strategies, including
 /// multi-leaders, backup leaders, and pipelines.
@@ -113,6 +118,44 @@ impl UniversalCommitter {
             .collect()
     }
 
+    /// Return the status of the leaders of the specified rounds, decided from the blocks known up
+    /// to `DECISION_HORIZON_WAVES` waves above the rounds. Unlike `try_commit`, this neither
+    /// remembers the decided leaders nor updates the metrics: it is meant to inspect the dag, see
+    /// `dag_export`.
+    pub fn leader_statuses(&self, rounds: RangeInclusive<RoundNumber>) -> Vec<LeaderStatus> {
+        let horizon = rounds
+            .end()
+            .saturating_add(DECISION_HORIZON_WAVES * self.wave_length)
+            .min(self.block_store.highest_round());
+        let mut leaders = VecDeque::new();
+        for round in (*rounds.start()..=horizon).rev() {
+            for committer in self.committers.iter().rev() {
+                let Some(leader) = committer.elect_leader(round) else {
+                    continue;
+                };
+                let mut status = committer.try_direct_decide(leader, round);
+                if !status.is_decided() {
+                    status = committer.try_indirect_decide(leader, round, leaders.iter());
+                }
+                leaders.push_front(status);
+            }
+        }
+        leaders
+            .into_iter()
+            .filter(|x| x.round() > 0 && rounds.contains(&x.round()))
+            .collect()
+    }
+
+    /// Return the votes and certificates for the specified block, or None if its author is not a
+    /// leader of its round.
+    pub fn leader_support(&self, leader_block: &Data<StatementBlock>) -> Option<LeaderSupport> {
+        let (author, round) = leader_block.author_round();
+        self.committers
+            .iter()
+            .find(|committer| committer.elect_leader(round) == Some(author))
+            .map(|committer| committer.leader_support(leader_block))
+    }
+
     /// Return list of leaders for the round. Syncer may give those leaders some extra time.
     /// To preserve (theoretical) liveness, we should wait `Delta` time for at least the first leader.
     /// Can return empty vec if round does not have a designated leader.
@@ -123,6 +166,10 @@ impl UniversalCommitter {
             .collect()
     }
 
+    pub fn wave_length(&self) -> RoundNumber {
+        self.wave_length
+    }
+
     /// Update metrics.
     fn update_metrics(&self, leader: &LeaderStatus, direct_decide: bool) {
         let authority = leader.authority().to_string();
@@ -3,7 +3,9 @@
 
 use std::{
     collections::{HashSet, VecDeque},
+    io,
     mem,
+    path::PathBuf,
     sync::{atomic::AtomicU64, Arc},
 };
 use std::time::Duration;
@@ -11,32 +13,43 @@ use rand::Rng;
 use tokio::sync::mpsc;
 use tokio::sync::mpsc::{Sender, Receiver};
 use minibytes::Bytes;
+use serde::{Deserialize, Serialize};
 
 use crate::{
+    address_book::{AddressSequence, AddressUpdate},
+    admin::{EpochStatus, NodeStatus},
+    batch::Batch,
     block_handler::BlockHandler,
     block_manager::BlockManager,
+    block_signer::BlockSigner,
     block_store::{
         BlockStore,
         BlockWriter,
         CommitData,
         OwnBlockData,
+        WAL_ENTRY_BATCH,
         WAL_ENTRY_COMMIT,
         WAL_ENTRY_PAYLOAD,
+        WAL_ENTRY_SNAPSHOT,
         WAL_ENTRY_STATE,
     },
     committee::Committee,
-    config::{NodePrivateConfig, NodePublicConfig},
+    config::NodePublicConfig,
     consensus::{
         linearizer::CommittedSubDag,
         universal_committer::{UniversalCommitter, UniversalCommitterBuilder},
     },
-    crypto::Signer,
     data::Data,
     epoch_close::EpochManager,
+    index_checkpoint::IndexCheckpointer,
     metrics::{Metrics, UtilizationTimerVecExt},
+    resolver::PeerAddress,
     runtime::timestamp_utc,
+    signing_guard::SigningGuard,
+    snapshot::Snapshot,
     state::RecoveredState,
     threshold_clock::ThresholdClockAggregator,
+    topology::{OwnRoundStatement, SignedOwnRound},
     types::{AuthorityIndex, BaseStatement, BlockReference, RoundNumber, StatementBlock},
     wal::{WalPosition, WalSyncer, WalWriter},
 };
@@ -44,7 +57,8 @@ use crate::{
 pub struct Core<H: BlockHandler> {
     block_manager: BlockManager,
     pending: VecDeque<(WalPosition, MetaStatement)>,
-    last_own_block: OwnBlockData,
+    // Observers never propose blocks, hence they have no own block and no signer
+    last_own_block: Option<OwnBlockData>,
     block_handler: H,
     authority: AuthorityIndex,
     threshold_clock: ThresholdClockAggregator,
@@ -54,19 +68,39 @@ pub struct Core<H: BlockHandler> {
     block_store: BlockStore,
     pub(crate) metrics: Arc<Metrics>,
     options: CoreOptions,
-    signer: Signer,
+    signer: Option<Box<dyn BlockSigner>>,
     // todo - ugly, probably need to merge syncer and core
     recovered_committed_blocks: Option<(HashSet<BlockReference>, Option<Bytes>)>,
     epoch_manager: EpochManager,
     rounds_in_epoch: RoundNumber,
     committer: UniversalCommitter,
+    // The highest committed block of each authority
+    committed_frontier: Vec<Data<StatementBlock>>,
+    // The round of the last leader handled by the commit observer
+    committed_round: RoundNumber,
+    snapshot_period: RoundNumber,
+    // The latest snapshot taken (or installed) by this validator
+    snapshot: Option<Snapshot>,
+    // The frontier of the snapshot this validator started from, if any
+    snapshot_frontier: Option<Vec<BlockReference>>,
+    // Protects against signing two blocks for the same round (None for observers)
+    signing_guard: Option<SigningGuard>,
+    // The highest round of our own blocks known to the peers
+    peers_own_round: RoundNumber,
+    // Set when index checkpoints are enabled
+    index_checkpointer: Option<IndexCheckpointer>,
+    address_sequence: AddressSequence,
 }
 
 pub struct CoreOptions {
     fsync: bool,
+    // Where to store the index checkpoints, see `index_checkpoint`
+    index_checkpoint: Option<PathBuf>,
+    // Where to persist the sequence number of our address updates, see `address_book`
+    address_sequence: Option<PathBuf>,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum MetaStatement {
     Include(BlockReference),
     Payload(Vec<BaseStatement>),
@@ -75,34 +109,117 @@ pub enum MetaStatement {
 impl<H: BlockHandler> Core<H> {
     #[allow(clippy::too_many_arguments)]
     pub fn open(
+        block_handler: H,
+        authority: AuthorityIndex,
+        committee: Arc<Committee>,
+        signer: Box<dyn BlockSigner>,
+        signing_guard: SigningGuard,
+        public_config: &NodePublicConfig,
+        metrics: Arc<Metrics>,
+        recovered: RecoveredState,
+        wal_writer: WalWriter,
+        options: CoreOptions,
+    ) -> Self {
+        Self::open_inner(
+            block_handler,
+            authority,
+            committee,
+            Some((signer, signing_guard)),
+            public_config,
+            metrics,
+            recovered,
+            wal_writer,
+            options,
+        )
+    }
+
+    /// Open the core of an observer. The observer stores the blocks it receives and runs the
+    /// commit rule locally, but it never proposes blocks. The observer id should not collide
+    /// with the index of any authority.
+    #[allow(clippy::too_many_arguments)]
+    pub fn open_observer(
+        block_handler: H,
+        observer_id: AuthorityIndex,
+        committee: Arc<Committee>,
+        public_config: &NodePublicConfig,
+        metrics: Arc<Metrics>,
+        recovered: RecoveredState,
+        wal_writer: WalWriter,
+        options: CoreOptions,
+    ) -> Self {
+        assert!(
+            !committee.known_authority(observer_id),
+            "Observer id {observer_id} collides with an authority"
+        );
+        Self::open_inner(
+            block_handler,
+            observer_id,
+            committee,
+            None,
+            public_config,
+            metrics,
+            recovered,
+            wal_writer,
+            options,
+        )
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn open_inner(
         mut block_handler: H,
         authority: AuthorityIndex,
         committee: Arc<Committee>,
-        private_config: NodePrivateConfig,
+        signer: Option<(Box<dyn BlockSigner>, SigningGuard)>,
         public_config: &NodePublicConfig,
         metrics: Arc<Metrics>,
         recovered: RecoveredState,
         mut wal_writer: WalWriter,
         options: CoreOptions,
     ) -> Self {
+        let index_checkpointer = options.index_checkpoint.clone().map(|path| {
+            IndexCheckpointer::new(
+                path,
+                public_config.parameters.index_checkpoint_period,
+                &recovered,
+                wal_writer.syncer().expect("Failed to clone the wal file"),
+            )
+        });
+        let address_sequence = match &options.address_sequence {
+            Some(path) => AddressSequence::open(path).expect("Failed to open the address sequence"),
+            None => AddressSequence::new_in_memory(),
+        };
         let RecoveredState {
             block_store,
             last_own_block,
             mut pending,
             state,
-            unprocessed_blocks,
+            mut unprocessed_blocks,
             last_committed_leader,
             committed_blocks,
             committed_state,
+            snapshot,
         } = recovered;
         let mut threshold_clock = ThresholdClockAggregator::new(0);
-        let last_own_block = if let Some(own_block) = last_own_block {
+        let last_own_block = if signer.is_none() {
+            // Observers do not accumulate pending statements since they never propose
+            pending.clear();
+            unprocessed_blocks.clear();
+            let mut block_writer = (&mut wal_writer, &block_store);
+            for block in committee.authorities().map(StatementBlock::new_genesis) {
+                let reference = *block.reference();
+                threshold_clock.add_block(reference, &committee);
+                if !block_store.block_exists(reference) {
+                    block_writer.insert_block(block);
+                }
+            }
+            None
+        } else if let Some(own_block) = last_own_block {
             for (_, pending_block) in pending.iter() {
                 if let MetaStatement::Include(include) = pending_block {
                     threshold_clock.add_block(*include, &committee);
                 }
             }
-            own_block
+            Some(own_block)
         } else {
             // todo(fix) - this technically has a race condition if node crashes after genesis
             assert!(pending.is_empty());
@@ -123,14 +240,35 @@ impl<H: BlockHandler> Core<H> {
                 block: own_genesis_block,
             };
             block_writer.insert_own_block(&own_block_data);
-            own_block_data
+            Some(own_block_data)
         };
-        let block_manager = BlockManager::new(block_store.clone(), &committee);
+        let mut block_manager = BlockManager::new(block_store.clone(), &committee);
 
         if let Some(state) = state {
             block_handler.recover_state(&state);
         }
 
+        let mut committed_frontier = match &snapshot {
+            Some(snapshot) => snapshot.frontier.clone(),
+            None => committee
+                .authorities()
+                .map(StatementBlock::new_genesis)
+                .collect(),
+        };
+        for reference in committed_blocks.iter() {
+            let highest = &mut committed_frontier[reference.authority as usize];
+            if reference.round() > highest.round() {
+                *highest = block_store
+                    .get_block(*reference)
+                    .expect("Committed block should be stored");
+            }
+        }
+        let snapshot_frontier = snapshot.as_ref().map(Snapshot::frontier_references);
+        if let Some(frontier) = &snapshot_frontier {
+            block_manager.set_snapshot_frontier(frontier, &mut (&mut wal_writer, &block_store));
+            block_handler.recover_snapshot_frontier(frontier);
+        }
+
         let epoch_manager = EpochManager::new();
 
         let committer =
@@ -154,6 +292,19 @@ impl<H: BlockHandler> Core<H> {
 
         let (tx, mut rx): (Sender<(u128, u128, usize)>, Receiver<(u128, u128, usize)>) = mpsc::channel(10000);
 
+        let (signer, signing_guard) = signer.unzip();
+        if let (Some(guard), Some(own_block)) = (&signing_guard, &last_own_block) {
+            if guard.highest_signed_round() > own_block.block.round() {
+                tracing::warn!(
+                    "Wal is behind the signing guard (last own block {}, highest signed round {}), \
+                    will not propose until round {}",
+                    own_block.block.reference(),
+                    guard.highest_signed_round(),
+                    guard.highest_signed_round() + 1
+                );
+            }
+        }
+
         let mut this = Self {
             block_manager,
             pending,
@@ -167,11 +318,20 @@ impl<H: BlockHandler> Core<H> {
             block_store,
             metrics,
             options,
-            signer: private_config.keypair,
+            signer,
             recovered_committed_blocks: Some((committed_blocks, committed_state)),
             epoch_manager,
             rounds_in_epoch: public_config.parameters.rounds_in_epoch,
             committer,
+            committed_frontier,
+            committed_round: last_committed_leader.unwrap_or_default().round(),
+            snapshot_period: public_config.parameters.snapshot_period,
+            snapshot,
+            snapshot_frontier,
+            signing_guard,
+            peers_own_round: 0,
+            index_checkpointer,
+            address_sequence,
         };
 
         if !unprocessed_blocks.is_empty() {
@@ -199,15 +359,41 @@ impl<H: BlockHandler> Core<H> {
         let processed = self
             .block_manager
             .add_blocks(blocks, &mut (&mut self.wal_writer, &self.block_store));
+        self.handle_processed_blocks(processed)
+    }
+
+    /// Store batches received from the peers (or created by this validator). Batches must be
+    /// stored before any block referencing them is added.
+    pub fn add_batches(&mut self, batches: Vec<Data<Batch>>) {
+        for batch in batches {
+            if self.block_store.contains_batch(batch.digest()) {
+                continue;
+            }
+            let position = self
+                .wal_writer
+                .write(WAL_ENTRY_BATCH, batch.serialized_bytes())
+                .expect("Write to wal has failed");
+            self.block_store.insert_batch(&batch, position);
+        }
+    }
+
+    fn handle_processed_blocks(
+        &mut self,
+        processed: Vec<(WalPosition, Data<StatementBlock>)>,
+    ) -> Vec<Data<StatementBlock>> {
         let mut result = Vec::with_capacity(processed.len());
         for (position, processed) in processed.into_iter() {
             self.threshold_clock
                 .add_block(*processed.reference(), &self.committee);
-            self.pending
-                .push_back((position, MetaStatement::Include(*processed.reference())));
+            if !self.is_observer() {
+                self.pending
+                    .push_back((position, MetaStatement::Include(*processed.reference())));
+            }
             result.push(processed);
         }
-        self.run_block_handler(&result);
+        if !self.is_observer() {
+            self.run_block_handler(&result);
+        }
         result
     }
 
@@ -234,11 +420,24 @@ impl<H: BlockHandler> Core<H> {
             .metrics
             .utilization_timer
             .utilization_timer("Core::try_new_block");
+        let (Some(last_own_block), Some(_)) = (&self.last_own_block, &self.signer) else {
+            // Observers never propose blocks
+            return None;
+        };
         let clock_round = self.threshold_clock.get_round();
-        if clock_round <= self.last_proposed() {
+        if clock_round <= last_own_block.block.round() {
             // tracing::warn!("Did not create block because the TLC round {} is <= last proposed round {}", clock_round, self.last_proposed());
             return None;
         }
+        if clock_round <= self.peers_own_round
+            || !self
+                .signing_guard
+                .as_ref()
+                .is_some_and(|guard| guard.may_sign(clock_round))
+        {
+            // We may have already signed a block for this round before our wal was lost
+            return None;
+        }
 
         let mut includes = vec![];
         let mut statements = vec![];
@@ -258,7 +457,7 @@ impl<H: BlockHandler> Core<H> {
         // Compress the references in the block
         // Iterate through all the include statements in the block, and make a set of all the references in their includes.
         let mut references_in_block: HashSet<BlockReference> = HashSet::new();
-        references_in_block.extend(self.last_own_block.block.includes());
+        references_in_block.extend(last_own_block.block.includes());
         for (_, statement) in &taken {
             if let MetaStatement::Include(block_ref) = statement {
                 // for all the includes in the block, add the references in the block to the set
@@ -267,7 +466,7 @@ impl<H: BlockHandler> Core<H> {
                 }
             }
         }
-        includes.push(*self.last_own_block.block.reference());
+        includes.push(*last_own_block.block.reference());
         for (_, statement) in taken.into_iter() {
             match statement {
                 MetaStatement::Include(include) => {
@@ -285,15 +484,25 @@ impl<H: BlockHandler> Core<H> {
 
         assert!(!includes.is_empty());
         let time_ns = timestamp_utc().as_nanos();
+        self.signing_guard
+            .as_mut()
+            .expect("Validators have a signing guard")
+            .record(clock_round)
+            .expect("Failed to persist signing guard");
+        let epoch_changing = self.epoch_changing();
+        let signer = self.signer.as_deref_mut().expect("Validators have a signer");
+        let timer = self.metrics.block_signing_latency_s.start_timer();
         let block = StatementBlock::new_with_signer(
             self.authority,
             clock_round,
             includes,
             statements,
             time_ns,
-            self.epoch_changing(),
-            &self.signer,
-        );
+            epoch_changing,
+            signer,
+        )
+        .expect("Failed to sign block");
+        timer.observe_duration();
         assert_eq!(
             block.includes().get(0).unwrap().authority,
             self.authority,
@@ -320,11 +529,12 @@ impl<H: BlockHandler> Core<H> {
         } else {
             WalPosition::MAX
         };
-        self.last_own_block = OwnBlockData {
+        let own_block_data = OwnBlockData {
             next_entry,
             block: block.clone(),
         };
-        (&mut self.wal_writer, &self.block_store).insert_own_block(&self.last_own_block);
+        (&mut self.wal_writer, &self.block_store).insert_own_block(&own_block_data);
+        self.last_own_block = Some(own_block_data);
 
         if self.options.fsync {
             self.wal_writer.sync().expect("Wal sync failed");
@@ -353,6 +563,7 @@ impl<H: BlockHandler> Core<H> {
                 BaseStatement::Share(_) => transactions += 1,
                 BaseStatement::Vote(_, _) => votes += 1,
                 BaseStatement::VoteRange(range) => votes += range.len(),
+                BaseStatement::Batch(_) => {}
             }
         }
         self.metrics
@@ -372,6 +583,11 @@ impl<H: BlockHandler> Core<H> {
         if let Some(last) = sequence.last() {
             self.last_commit_leader = *last.reference();
         }
+        // The next leaders to decide and the blocks certifying them stay in memory
+        let last_commit_round = self.last_commit_leader.round();
+        self.block_store.pin_rounds(
+            last_commit_round + 1..=last_commit_round + 2 * self.committer.wave_length(),
+        );
 
         // todo: should ideally come from execution result of epoch smart contract
         if self.last_commit_leader.round() > self.rounds_in_epoch {
@@ -422,39 +638,206 @@ impl<H: BlockHandler> Core<H> {
         state: &Bytes,
     ) -> Vec<CommitData> {
         let mut commit_data = vec![];
+        let previous_committed_round = self.committed_round;
         for commit in &committed {
             for block in &commit.blocks {
                 self.epoch_manager
                     .observe_committed_block(block, &self.committee);
                 tracing::debug!("Committed block: {:?}", block.author_round());
+                let highest = &mut self.committed_frontier[block.author() as usize];
+                if block.round() > highest.round() {
+                    *highest = block.clone();
+                }
             }
+            self.committed_round = commit.anchor.round();
             commit_data.push(CommitData::from(commit));
         }
-        self.write_state(); // todo - this can be done less frequently to reduce IO
-        self.write_commits(&commit_data, state);
+        let state_position = self.write_state(); // todo - this can be done less frequently to reduce IO
+        let commit_position = self.write_commits(&commit_data, state);
+        self.checkpoint_index(
+            previous_committed_round,
+            &commit_data,
+            state_position,
+            commit_position,
+        );
+        if let Some(last) = committed.last() {
+            if self.crosses_snapshot_boundary(previous_committed_round, last.anchor.round()) {
+                self.snapshot = Some(Snapshot {
+                    last_committed_leader: last.anchor,
+                    frontier: self.committed_frontier.clone(),
+                    committed_state: state.clone(),
+                });
+                tracing::debug!("Took snapshot at {}", last.anchor);
+            }
+        }
         // todo - We should also persist state of the epoch manager, otherwise if validator
         // restarts during epoch change it will fork on the epoch change state.
         commit_data
     }
 
-    pub fn write_state(&mut self) {
+    /// Record the commits for the next index checkpoint, and store a checkpoint whenever the
+    /// committed round crosses a multiple of the checkpoint period.
+    fn checkpoint_index(
+        &mut self,
+        previous_committed_round: RoundNumber,
+        commits: &[CommitData],
+        state: Option<WalPosition>,
+        committed_state: WalPosition,
+    ) {
+        let Some(checkpointer) = &mut self.index_checkpointer else {
+            return;
+        };
+        checkpointer.commit_data(commits);
+        let Some(state) = state else {
+            return;
+        };
+        if !checkpointer.crosses_boundary(previous_committed_round, self.committed_round) {
+            return;
+        }
+        let _timer = self
+            .metrics
+            .utilization_timer
+            .utilization_timer("Core::checkpoint_index");
+        let checkpoint = checkpointer.checkpoint(
+            WalPosition::new(self.wal_writer.size()),
+            &self.block_store,
+            &self.pending,
+            self.last_own_block.as_ref(),
+            state,
+            committed_state,
+        );
+        match checkpoint {
+            Ok(checkpoint) => checkpointer.store(checkpoint),
+            Err(e) => tracing::warn!("Failed to take index checkpoint: {e}"),
+        }
+    }
+
+    fn crosses_snapshot_boundary(&self, from: RoundNumber, to: RoundNumber) -> bool {
+        self.snapshot_period != 0 && to / self.snapshot_period > from / self.snapshot_period
+    }
+
+    /// Return the index of the first leader at which a snapshot should be taken. The leaders up to
+    /// (and including) that index should be handled by the commit observer separately from the
+    /// remaining ones, so that all validators take the snapshot at the same point.
+    pub fn snapshot_boundary(&self, leaders: &[Data<StatementBlock>]) -> Option<usize> {
+        let mut previous = self.committed_round;
+        leaders.iter().position(|leader| {
+            let crosses = self.crosses_snapshot_boundary(previous, leader.round());
+            previous = leader.round();
+            crosses
+        })
+    }
+
+    pub fn snapshot(&self) -> Option<&Snapshot> {
+        self.snapshot.as_ref()
+    }
+
+    /// The frontier of the snapshot this validator started from, if any.
+    pub fn snapshot_frontier(&self) -> Option<&[BlockReference]> {
+        self.snapshot_frontier.as_deref()
+    }
+
+    /// Sign the announcement that we accept connections on `address` from now on, with the next
+    /// sequence number.
+    pub fn sign_address_update(&mut self, address: PeerAddress) -> io::Result<AddressUpdate> {
+        let signer = self
+            .signer
+            .as_deref_mut()
+            .expect("Validators have a signer");
+        let sequence = self.address_sequence.advance()?;
+        AddressUpdate::new(self.authority, sequence, address, signer)
+    }
+
+    /// Sign our request, or our report answering the request of another validator.
+    pub fn sign_own_round(&mut self, statement: OwnRoundStatement) -> io::Result<SignedOwnRound> {
+        let signer = self
+            .signer
+            .as_deref_mut()
+            .expect("Validators have a signer");
+        SignedOwnRound::new(statement, signer)
+    }
+
+    /// Join consensus from a snapshot (already verified) rather than from genesis. Only allowed
+    /// before proposing any block.
+    pub fn install_snapshot(&mut self, snapshot: Snapshot) -> Vec<Data<StatementBlock>> {
+        assert_eq!(
+            self.last_proposed(),
+            0,
+            "Snapshots can only be installed before proposing"
+        );
+        tracing::info!("Installing snapshot at {}", snapshot.last_committed_leader);
+        let serialized = bincode::serialize(&snapshot).expect("Snapshot serialization failed");
+        self.wal_writer
+            .write(WAL_ENTRY_SNAPSHOT, &serialized)
+            .expect("Write to wal has failed");
+
+        // Our own block has to be written first, pending entries before it are discarded on recovery
+        let own_block = &snapshot.frontier[self.authority as usize];
+        if !self.is_observer() && own_block.round() > 0 {
+            self.pending.clear();
+            let own_block_data = OwnBlockData {
+                next_entry: WalPosition::MAX,
+                block: own_block.clone(),
+            };
+            (&mut self.wal_writer, &self.block_store).insert_own_block(&own_block_data);
+            self.last_own_block = Some(own_block_data);
+        }
+        for block in &snapshot.frontier {
+            if self.block_store.block_exists(*block.reference()) {
+                continue;
+            }
+            let position =
+                (&mut self.wal_writer, &self.block_store).insert_block(block.clone());
+            if !self.is_observer() {
+                self.pending
+                    .push_back((position, MetaStatement::Include(*block.reference())));
+            }
+        }
+        // Frontier blocks do not advance the threshold clock: we only know one block per authority
+
+        let frontier = snapshot.frontier_references();
+        self.block_handler.recover_snapshot_frontier(&frontier);
+        let commits = [CommitData {
+            leader: snapshot.last_committed_leader,
+            sub_dag: vec![],
+        }];
+        self.write_commits(&commits, &snapshot.committed_state);
+        if let Some(checkpointer) = &mut self.index_checkpointer {
+            checkpointer.snapshot(snapshot.clone());
+            checkpointer.commit_data(&commits);
+        }
+        self.last_commit_leader = snapshot.last_committed_leader;
+        self.committed_round = snapshot.round();
+        self.committed_frontier.clone_from(&snapshot.frontier);
+        self.snapshot_frontier = Some(frontier.clone());
+        self.snapshot = Some(snapshot);
+
+        let processed = self
+            .block_manager
+            .set_snapshot_frontier(&frontier, &mut (&mut self.wal_writer, &self.block_store));
+        self.handle_processed_blocks(processed)
+    }
+
+    pub fn write_state(&mut self) -> Option<WalPosition> {
         #[cfg(feature = "simulator")]
         if self.block_handler().state().len() >= crate::wal::MAX_ENTRY_SIZE {
             // todo - this is something needs a proper fix
             // Need to revisit this after we have a proper synchronizer
             // We need to put some limit/backpressure on the accumulator state
-            return;
+            return None;
         }
-        self.wal_writer
+        let position = self
+            .wal_writer
             .write(WAL_ENTRY_STATE, &self.block_handler().state())
             .expect("Write to wal has failed");
+        Some(position)
     }
 
-    pub fn write_commits(&mut self, commits: &[CommitData], state: &Bytes) {
+    pub fn write_commits(&mut self, commits: &[CommitData], state: &Bytes) -> WalPosition {
         let commits = bincode::serialize(&(commits, state)).expect("Commits serialization failed");
         self.wal_writer
             .write(WAL_ENTRY_COMMIT, &commits)
-            .expect("Write to wal has failed");
+            .expect("Write to wal has failed")
     }
 
     pub fn take_recovered_committed_blocks(&mut self) -> (HashSet<BlockReference>, Option<Bytes>) {
@@ -468,11 +851,36 @@ impl<H: BlockHandler> Core<H> {
     }
 
     pub fn last_own_block(&self) -> &Data<StatementBlock> {
-        &self.last_own_block.block
+        &self
+            .last_own_block
+            .as_ref()
+            .expect("Observers do not have own blocks")
+            .block
     }
 
     pub fn last_proposed(&self) -> RoundNumber {
-        self.last_own_block.block.round()
+        self.last_own_block
+            .as_ref()
+            .map(|own_block| own_block.block.round())
+            .unwrap_or_default()
+    }
+
+    /// Do not propose blocks at or below the highest round of our own blocks known to the peers.
+    pub fn set_peers_own_round(&mut self, round: RoundNumber) {
+        if round > self.last_proposed() {
+            tracing::warn!(
+                "Peers know our block at round {round} while we last proposed round {}, \
+                will not propose until round {}",
+                self.last_proposed(),
+                round + 1
+            );
+        }
+        self.peers_own_round = round;
+    }
+
+    /// Whether this core belongs to an observer (which never proposes blocks).
+    pub fn is_observer(&self) -> bool {
+        self.signer.is_none()
     }
 
     pub fn authority(&self) -> AuthorityIndex {
@@ -506,6 +914,29 @@ impl<H: BlockHandler> Core<H> {
     pub fn epoch_closing_time(&self) -> Arc<AtomicU64> {
         self.epoch_manager.closing_time()
     }
+
+    pub fn status(&self) -> NodeStatus {
+        let epoch = if self.epoch_closed() {
+            EpochStatus::Closed
+        } else if self.epoch_changing() {
+            EpochStatus::Changing
+        } else {
+            EpochStatus::Open
+        };
+        NodeStatus {
+            authority: self.authority,
+            threshold_clock_round: self.threshold_clock.get_round(),
+            last_proposed_round: self.last_proposed(),
+            last_committed_leader: Some(self.last_commit_leader)
+                .filter(|leader| leader.round() > 0)
+                .map(|leader| leader.to_string()),
+            epoch,
+        }
+    }
+
+    pub fn wal_size(&self) -> u64 {
+        self.wal_writer.size()
+    }
 }
 
 impl Default for CoreOptions {
@@ -516,11 +947,31 @@ impl Default for CoreOptions {
 
 impl CoreOptions {
     pub fn test() -> Self {
-        Self { fsync: false }
+        Self {
+            fsync: false,
+            index_checkpoint: None,
+            address_sequence: None,
+        }
     }
 
     pub fn production() -> Self {
-        Self { fsync: true }
+        Self {
+            fsync: true,
+            index_checkpoint: None,
+            address_sequence: None,
+        }
+    }
+
+    /// Store index checkpoints at `path`, every `index_checkpoint_period` committed rounds.
+    pub fn with_index_checkpoint(mut self, path: PathBuf) -> Self {
+        self.index_checkpoint = Some(path);
+        self
+    }
+
+    /// Persist the sequence number of our address updates at `path`.
+    pub fn with_address_sequence(mut self, path: PathBuf) -> Self {
+        self.address_sequence = Some(path);
+        self
     }
 }
 
@@ -532,8 +983,18 @@ mod test {
 
     use super::*;
     use crate::{
-        test_util::{committee_and_cores, committee_and_cores_persisted},
+        consensus::linearizer::CommittedSubDag,
+        index_checkpoint::IndexCheckpoint,
+        test_util::{
+            committee_and_cores,
+            committee_and_cores_persisted,
+            committee_and_cores_persisted_with_index_checkpoint,
+            index_checkpoint_path,
+            test_metrics,
+        },
         threshold_clock,
+        types::Transaction,
+        wal::{open_file_for_wal, walf},
     };
 
     #[test]
@@ -690,7 +1151,9 @@ mod test {
             blocks.push(block.clone());
         }
         assert_eq!(proposed_transactions.len(), 4);
-        cores.iter_mut().for_each(Core::write_state);
+        cores.iter_mut().for_each(|core| {
+            core.write_state();
+        });
         drop(cores);
 
         let (_committee, mut cores, _) = committee_and_cores_persisted(4, Some(tmp.path()));
@@ -738,6 +1201,201 @@ mod test {
         }
     }
 
+    #[test]
+    fn test_core_batches_recovery() {
+        let tmp = tempdir::TempDir::new("test_core_batches_recovery").unwrap();
+        let (_committee, mut cores, _) = committee_and_cores_persisted(4, Some(tmp.path()));
+        let transactions = (0..10u64)
+            .map(|i| Transaction::new(i.to_le_bytes().to_vec()))
+            .collect();
+        let batch = Data::new(Batch::new(1, transactions));
+        let digest = *batch.digest();
+        cores[0].add_batches(vec![batch.clone()]);
+        // Adding the same batch twice is a no-op
+        cores[0].add_batches(vec![batch]);
+        assert!(cores[0].block_store().contains_batch(&digest));
+        drop(cores);
+
+        let (_committee, cores, _) = committee_and_cores_persisted(4, Some(tmp.path()));
+        let block_store = cores[0].block_store();
+        let recovered = block_store
+            .get_batch(&digest)
+            .expect("Batch was not recovered");
+        assert_eq!(recovered.transactions().len(), 10);
+        assert!(cores[1].block_store().get_batch(&digest).is_none());
+
+        // Committed blocks are resolved back to the batches they reference
+        let block = Data::new(StatementBlock::new(
+            1,
+            1,
+            vec![],
+            vec![BaseStatement::Batch(digest)],
+            0,
+            false,
+            Default::default(),
+        ));
+        let mut sub_dag = CommittedSubDag::new(*block.reference(), vec![block]);
+        sub_dag.resolve_batches(block_store);
+        assert_eq!(sub_dag.batches.len(), 1);
+        assert_eq!(sub_dag.batches[0].digest(), &digest);
+        // A missing batch is an invariant violation
+        let missing = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            sub_dag.resolve_batches(cores[1].block_store())
+        }));
+        assert!(missing.is_err());
+    }
+
+    #[test]
+    fn test_core_restored_wal() {
+        let tmp = tempdir::TempDir::new("test_core_restored_wal").unwrap();
+        let wal_path = tmp.path().join("000.wal");
+        let backup_path = tmp.path().join("backup.wal");
+        let (_committee, mut cores, _) = committee_and_cores_persisted(4, Some(tmp.path()));
+
+        let mut blocks_r1 = vec![];
+        for core in &mut cores {
+            core.run_block_handler(&[]);
+            blocks_r1.push(core.try_new_block().unwrap());
+        }
+        drop(cores);
+        std::fs::copy(&wal_path, &backup_path).unwrap();
+
+        let (_committee, mut cores, _) = committee_and_cores_persisted(4, Some(tmp.path()));
+        let mut blocks_r2 = vec![];
+        for core in &mut cores {
+            core.add_blocks(blocks_r1.clone());
+            blocks_r2.push(core.try_new_block().unwrap());
+        }
+        assert_eq!(blocks_r2[0].round(), 2);
+        drop(cores);
+
+        // Restore the wal of the first validator from the backup taken before it signed round 2
+        std::fs::copy(&backup_path, &wal_path).unwrap();
+        let (_committee, mut cores, _) = committee_and_cores_persisted(4, Some(tmp.path()));
+        let core = &mut cores[0];
+        assert_eq!(core.last_proposed(), 1);
+        core.add_blocks(blocks_r1.clone());
+        // Signing another block for round 2 would be an equivocation
+        assert!(core.try_new_block().is_none());
+        core.add_blocks(blocks_r2[1..].to_vec());
+        let block = core.try_new_block().unwrap();
+        assert_eq!(block.round(), 3);
+    }
+
+    #[test]
+    fn test_core_index_checkpoint_recovery() {
+        let tmp = tempdir::TempDir::new("test_core_index_checkpoint_recovery").unwrap();
+        let (committee, mut cores, _) =
+            committee_and_cores_persisted_with_index_checkpoint(4, tmp.path(), 2);
+
+        let mut blocks: Vec<Data<StatementBlock>> = vec![];
+        for round in 1..=6 {
+            let mut next = vec![];
+            for core in &mut cores {
+                if round == 1 {
+                    core.run_block_handler(&[]);
+                }
+                core.add_blocks(blocks.clone());
+                next.push(core.try_new_block().unwrap());
+            }
+            // Commit the blocks of the previous round every other round, leaving a tail of
+            // blocks after the last checkpoint (taken at round 4)
+            if round % 2 == 0 && round < 6 {
+                let anchor = *blocks[0].reference();
+                for core in &mut cores {
+                    let sub_dag = CommittedSubDag::new(anchor, blocks.clone());
+                    core.handle_committed_subdag(vec![sub_dag], &Bytes::from(vec![round as u8]));
+                }
+            }
+            blocks = next;
+        }
+        drop(cores);
+
+        let checkpoint_path = index_checkpoint_path(tmp.path(), 0);
+        let checkpoint = IndexCheckpoint::load(checkpoint_path).unwrap().unwrap();
+        assert_eq!(checkpoint.last_committed_leader.unwrap().round, 3);
+        let open = |checkpoint| {
+            let wal_file = open_file_for_wal(tmp.path().join("000.wal")).unwrap();
+            let (wal_writer, wal_reader) = walf(wal_file).unwrap();
+            assert!(wal_writer.size() > checkpoint_position(&checkpoint));
+            BlockStore::open_with_checkpoint(
+                0,
+                Arc::new(wal_reader),
+                &wal_writer,
+                test_metrics(),
+                &committee,
+                crate::config::node_defaults::default_block_cache_size(),
+                checkpoint,
+            )
+        };
+        let (wal_writer, wal_reader) =
+            walf(open_file_for_wal(tmp.path().join("000.wal")).unwrap()).unwrap();
+        // Otherwise the block store would silently replay the whole wal
+        assert!(BlockStore::check_checkpoint(&checkpoint, &wal_reader, &wal_writer).is_ok());
+        // A checkpoint is not applied to a wal rewritten since it was taken
+        let mut rewritten = checkpoint.clone();
+        rewritten.wal_crc ^= 1;
+        assert!(BlockStore::check_checkpoint(&rewritten, &wal_reader, &wal_writer).is_err());
+        let replayed = open(None);
+        let recovered = open(Some(checkpoint));
+
+        let sorted = |state: &RecoveredState| {
+            let mut blocks = state.block_store.block_positions();
+            blocks.sort();
+            blocks
+        };
+        assert_eq!(sorted(&recovered), sorted(&replayed));
+        for authority in committee.authorities() {
+            assert_eq!(
+                recovered.block_store.last_seen_by_authority(authority),
+                replayed.block_store.last_seen_by_authority(authority)
+            );
+        }
+        assert_eq!(
+            format!("{:?}", recovered.pending),
+            format!("{:?}", replayed.pending)
+        );
+        let own_block = |state: &RecoveredState| {
+            let own = state.last_own_block.as_ref().unwrap();
+            (own.next_entry, *own.block.reference())
+        };
+        assert_eq!(own_block(&recovered), own_block(&replayed));
+        assert_eq!(recovered.state, replayed.state);
+        let unprocessed = |state: &RecoveredState| {
+            let mut blocks: Vec<_> = state
+                .unprocessed_blocks
+                .iter()
+                .map(|block| *block.reference())
+                .collect();
+            blocks.sort();
+            blocks
+        };
+        assert_eq!(unprocessed(&recovered), unprocessed(&replayed));
+        // The blocks of rounds 5 and 6, including the own blocks
+        assert_eq!(unprocessed(&recovered).len(), 8);
+        assert_eq!(
+            recovered.last_committed_leader,
+            replayed.last_committed_leader
+        );
+        assert_eq!(recovered.committed_blocks, replayed.committed_blocks);
+        assert_eq!(recovered.committed_blocks.len(), 8);
+        assert_eq!(recovered.committed_state, replayed.committed_state);
+
+        // The cores resume from the checkpoints
+        let (_committee, mut cores, _) =
+            committee_and_cores_persisted_with_index_checkpoint(4, tmp.path(), 2);
+        for core in &mut cores {
+            core.add_blocks(blocks.clone());
+            assert_eq!(core.try_new_block().unwrap().round(), 7);
+        }
+    }
+
+    fn checkpoint_position(checkpoint: &Option<IndexCheckpoint>) -> u64 {
+        checkpoint
+            .as_ref()
+            .map_or(0, |checkpoint| checkpoint.wal_position.start())
+    }
+
     fn push_all(
         p: &mut Vec<Vec<Data<StatementBlock>>>,
         except: AuthorityIndex,
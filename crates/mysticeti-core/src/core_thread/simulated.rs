@@ -1,14 +1,20 @@
 // Copyright (c) Mysten Labs, Inc.
 // SPDX-License-Identifier: Apache-2.0
 
-use std::collections::HashSet;
+use std::{collections::HashSet, io};
 
 use parking_lot::Mutex;
 
 use crate::{
+    address_book::AddressUpdate,
+    admin::NodeStatus,
+    batch::Batch,
     block_handler::BlockHandler,
     data::Data,
+    resolver::PeerAddress,
+    snapshot::Snapshot,
     syncer::{CommitObserver, Syncer, SyncerSignals},
+    topology::{OwnRoundStatement, SignedOwnRound},
     types::{AuthorityIndex, BlockReference, RoundNumber, StatementBlock},
 };
 
@@ -33,6 +39,10 @@ impl<H: BlockHandler + 'static, S: SyncerSignals + 'static, C: CommitObserver +
         self.syncer.lock().add_blocks(blocks);
     }
 
+    pub async fn add_batches(&self, batches: Vec<Data<Batch>>) {
+        self.syncer.lock().add_batches(batches);
+    }
+
     pub async fn force_new_block(&self, round: RoundNumber) {
         self.syncer.lock().force_new_block(round);
     }
@@ -58,4 +68,36 @@ impl<H: BlockHandler + 'static, S: SyncerSignals + 'static, C: CommitObserver +
             lock.connected_authorities.remove(&authority_index);
         }
     }
+
+    pub async fn snapshot(&self) -> Option<Snapshot> {
+        self.syncer.lock().snapshot()
+    }
+
+    pub async fn install_snapshot(&self, snapshot: Option<Snapshot>) {
+        self.syncer.lock().install_snapshot(snapshot);
+    }
+
+    pub async fn resume_above_own_round(&self, round: RoundNumber) {
+        self.syncer.lock().resume_above_own_round(round);
+    }
+
+    pub async fn sign_address_update(&self, address: PeerAddress) -> io::Result<AddressUpdate> {
+        self.syncer.lock().sign_address_update(address)
+    }
+
+    pub async fn sign_own_round(&self, statement: OwnRoundStatement) -> io::Result<SignedOwnRound> {
+        self.syncer.lock().sign_own_round(statement)
+    }
+
+    pub async fn status(&self) -> NodeStatus {
+        self.syncer.lock().core().status()
+    }
+
+    pub async fn connected_authorities(&self) -> HashSet<AuthorityIndex> {
+        self.syncer.lock().connected_authorities.clone()
+    }
+
+    pub async fn wal_size(&self) -> u64 {
+        self.syncer.lock().core().wal_size()
+    }
 }
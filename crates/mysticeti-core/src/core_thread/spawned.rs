@@ -1,15 +1,21 @@
 // Copyright (c) Mysten Labs, Inc.
 // SPDX-License-Identifier: Apache-2.0
 
-use std::{collections::HashSet, sync::Arc, thread};
+use std::{collections::HashSet, io, sync::Arc, thread};
 
 use tokio::sync::{mpsc, oneshot};
 
 use crate::{
+    address_book::AddressUpdate,
+    admin::NodeStatus,
+    batch::Batch,
     block_handler::BlockHandler,
     data::Data,
     metrics::{Metrics, UtilizationTimerExt},
+    resolver::PeerAddress,
+    snapshot::Snapshot,
     syncer::{CommitObserver, Syncer, SyncerSignals},
+    topology::{OwnRoundStatement, SignedOwnRound},
     types::{AuthorityIndex, BlockReference, RoundNumber, StatementBlock},
 };
 
@@ -26,6 +32,7 @@ pub struct CoreThread<H: BlockHandler, S: SyncerSignals, C: CommitObserver> {
 
 enum CoreThreadCommand {
     AddBlocks(Vec<Data<StatementBlock>>, oneshot::Sender<()>),
+    AddBatches(Vec<Data<Batch>>, oneshot::Sender<()>),
     ForceNewBlock(RoundNumber, oneshot::Sender<()>),
     Cleanup(oneshot::Sender<()>),
     /// Request missing blocks that need to be synched.
@@ -34,6 +41,25 @@ enum CoreThreadCommand {
     ConnectionEstablished(AuthorityIndex, oneshot::Sender<()>),
     /// Indicate that a connection to an authority was dropped.
     ConnectionDropped(AuthorityIndex, oneshot::Sender<()>),
+    /// Request the latest snapshot taken by this validator.
+    GetSnapshot(oneshot::Sender<Option<Snapshot>>),
+    /// Install the snapshot agreed upon by the peers (if any) and start proposing.
+    InstallSnapshot(Option<Snapshot>, oneshot::Sender<()>),
+    /// Resume proposing above the highest round of our own blocks known to the peers.
+    ResumeAboveOwnRound(RoundNumber, oneshot::Sender<()>),
+    /// Sign the announcement of our new address.
+    SignAddressUpdate(PeerAddress, oneshot::Sender<io::Result<AddressUpdate>>),
+    /// Sign our own round request or report, see `topology`.
+    SignOwnRound(
+        OwnRoundStatement,
+        oneshot::Sender<io::Result<SignedOwnRound>>,
+    ),
+    /// Request the consensus status reported to the operators.
+    GetStatus(oneshot::Sender<NodeStatus>),
+    /// Request the authorities we are currently connected to.
+    GetConnected(oneshot::Sender<HashSet<AuthorityIndex>>),
+    /// Request the number of bytes written to the wal.
+    GetWalSize(oneshot::Sender<u64>),
 }
 
 impl<H: BlockHandler + 'static, S: SyncerSignals + 'static, C: CommitObserver + 'static>
@@ -66,6 +92,13 @@ impl<H: BlockHandler + 'static, S: SyncerSignals + 'static, C: CommitObserver +
         receiver.await.expect("core thread is not expected to stop");
     }
 
+    pub async fn add_batches(&self, batches: Vec<Data<Batch>>) {
+        let (sender, receiver) = oneshot::channel();
+        self.send(CoreThreadCommand::AddBatches(batches, sender))
+            .await;
+        receiver.await.expect("core thread is not expected to stop");
+    }
+
     pub async fn force_new_block(&self, round: RoundNumber) {
         let (sender, receiver) = oneshot::channel();
         self.send(CoreThreadCommand::ForceNewBlock(round, sender))
@@ -98,6 +131,58 @@ impl<H: BlockHandler + 'static, S: SyncerSignals + 'static, C: CommitObserver +
         receiver.await.expect("core thread is not expected to stop")
     }
 
+    pub async fn snapshot(&self) -> Option<Snapshot> {
+        let (sender, receiver) = oneshot::channel();
+        self.send(CoreThreadCommand::GetSnapshot(sender)).await;
+        receiver.await.expect("core thread is not expected to stop")
+    }
+
+    pub async fn install_snapshot(&self, snapshot: Option<Snapshot>) {
+        let (sender, receiver) = oneshot::channel();
+        self.send(CoreThreadCommand::InstallSnapshot(snapshot, sender))
+            .await;
+        receiver.await.expect("core thread is not expected to stop")
+    }
+
+    pub async fn resume_above_own_round(&self, round: RoundNumber) {
+        let (sender, receiver) = oneshot::channel();
+        self.send(CoreThreadCommand::ResumeAboveOwnRound(round, sender))
+            .await;
+        receiver.await.expect("core thread is not expected to stop")
+    }
+
+    pub async fn sign_address_update(&self, address: PeerAddress) -> io::Result<AddressUpdate> {
+        let (sender, receiver) = oneshot::channel();
+        self.send(CoreThreadCommand::SignAddressUpdate(address, sender))
+            .await;
+        receiver.await.expect("core thread is not expected to stop")
+    }
+
+    pub async fn sign_own_round(&self, statement: OwnRoundStatement) -> io::Result<SignedOwnRound> {
+        let (sender, receiver) = oneshot::channel();
+        self.send(CoreThreadCommand::SignOwnRound(statement, sender))
+            .await;
+        receiver.await.expect("core thread is not expected to stop")
+    }
+
+    pub async fn status(&self) -> NodeStatus {
+        let (sender, receiver) = oneshot::channel();
+        self.send(CoreThreadCommand::GetStatus(sender)).await;
+        receiver.await.expect("core thread is not expected to stop")
+    }
+
+    pub async fn connected_authorities(&self) -> HashSet<AuthorityIndex> {
+        let (sender, receiver) = oneshot::channel();
+        self.send(CoreThreadCommand::GetConnected(sender)).await;
+        receiver.await.expect("core thread is not expected to stop")
+    }
+
+    pub async fn wal_size(&self) -> u64 {
+        let (sender, receiver) = oneshot::channel();
+        self.send(CoreThreadCommand::GetWalSize(sender)).await;
+        receiver.await.expect("core thread is not expected to stop")
+    }
+
     async fn send(&self, command: CoreThreadCommand) {
         self.metrics.core_lock_enqueued.inc();
         if self.sender.send(command).await.is_err() {
@@ -118,6 +203,10 @@ impl<H: BlockHandler, S: SyncerSignals, C: CommitObserver> CoreThread<H, S, C> {
                     self.syncer.add_blocks(blocks);
                     sender.send(()).ok();
                 }
+                CoreThreadCommand::AddBatches(batches, sender) => {
+                    self.syncer.add_batches(batches);
+                    sender.send(()).ok();
+                }
                 CoreThreadCommand::ForceNewBlock(round, sender) => {
                     self.syncer.force_new_block(round);
                     sender.send(()).ok();
@@ -138,6 +227,32 @@ impl<H: BlockHandler, S: SyncerSignals, C: CommitObserver> CoreThread<H, S, C> {
                     self.syncer.connected_authorities.remove(&authority);
                     sender.send(()).ok();
                 }
+                CoreThreadCommand::GetSnapshot(sender) => {
+                    sender.send(self.syncer.snapshot()).ok();
+                }
+                CoreThreadCommand::InstallSnapshot(snapshot, sender) => {
+                    self.syncer.install_snapshot(snapshot);
+                    sender.send(()).ok();
+                }
+                CoreThreadCommand::ResumeAboveOwnRound(round, sender) => {
+                    self.syncer.resume_above_own_round(round);
+                    sender.send(()).ok();
+                }
+                CoreThreadCommand::SignAddressUpdate(address, sender) => {
+                    sender.send(self.syncer.sign_address_update(address)).ok();
+                }
+                CoreThreadCommand::SignOwnRound(statement, sender) => {
+                    sender.send(self.syncer.sign_own_round(statement)).ok();
+                }
+                CoreThreadCommand::GetStatus(sender) => {
+                    sender.send(self.syncer.core().status()).ok();
+                }
+                CoreThreadCommand::GetConnected(sender) => {
+                    sender.send(self.syncer.connected_authorities.clone()).ok();
+                }
+                CoreThreadCommand::GetWalSize(sender) => {
+                    sender.send(self.syncer.core().wal_size()).ok();
+                }
             }
         }
         self.syncer
@@ -5,15 +5,17 @@ use std::fmt;
 
 use digest::Digest;
 #[cfg(not(test))]
-use ed25519_consensus::Signature;
-use rand::{rngs::StdRng, SeedableRng};
+use ed25519_consensus::{batch, Signature};
+use rand::{rngs::StdRng, CryptoRng, RngCore, SeedableRng};
 use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
-use zeroize::Zeroize;
+use zeroize::{Zeroize, Zeroizing};
 
 #[cfg(not(test))]
 use crate::types::Vote;
 use crate::{
+    resolver::PeerAddress,
     serde::{ByteRepr, BytesVisitor},
+    topology::OwnRoundStatement,
     types::{
         AuthorityIndex,
         BaseStatement,
@@ -22,6 +24,7 @@ use crate::{
         RoundNumber,
         StatementBlock,
         TimestampNs,
+        Transaction,
     },
 };
 
@@ -31,6 +34,18 @@ pub const BLOCK_DIGEST_SIZE: usize = 32;
 #[derive(Clone, Copy, Eq, Ord, PartialOrd, PartialEq, Default, Hash)]
 pub struct BlockDigest([u8; BLOCK_DIGEST_SIZE]);
 
+/// The digest of a block without its signature, this is what the author of the block signs.
+pub type SigningDigest = [u8; BLOCK_DIGEST_SIZE];
+
+/// The digest of a batch of transactions, included in blocks in place of the transactions.
+#[derive(Clone, Copy, Eq, Ord, PartialOrd, PartialEq, Default, Hash, Serialize, Deserialize)]
+pub struct BatchDigest([u8; BLOCK_DIGEST_SIZE]);
+
+/// The digest of an erasure-coded chunk of a block, or of a node of the Merkle tree over the
+/// chunks of a block, see `erasure`.
+#[derive(Clone, Copy, Eq, Ord, PartialOrd, PartialEq, Default, Hash, Serialize, Deserialize)]
+pub struct ChunkDigest([u8; BLOCK_DIGEST_SIZE]);
+
 #[derive(Clone, Eq, PartialEq, Serialize, Deserialize, Debug)]
 pub struct PublicKey(ed25519_consensus::VerificationKey);
 
@@ -82,6 +97,41 @@ impl BlockDigest {
         Default::default()
     }
 
+    /// The digest signed by the author of the block.
+    #[cfg(not(test))]
+    pub fn signing_digest(
+        authority: AuthorityIndex,
+        round: RoundNumber,
+        includes: &[BlockReference],
+        statements: &[BaseStatement],
+        meta_creation_time_ns: TimestampNs,
+        epoch_marker: EpochStatus,
+    ) -> SigningDigest {
+        let mut hasher = BlockHasher::default();
+        Self::digest_without_signature(
+            &mut hasher,
+            authority,
+            round,
+            includes,
+            statements,
+            meta_creation_time_ns,
+            epoch_marker,
+        );
+        hasher.finalize().into()
+    }
+
+    #[cfg(test)]
+    pub fn signing_digest(
+        _authority: AuthorityIndex,
+        _round: RoundNumber,
+        _includes: &[BlockReference],
+        _statements: &[BaseStatement],
+        _meta_creation_time_ns: TimestampNs,
+        _epoch_marker: EpochStatus,
+    ) -> SigningDigest {
+        Default::default()
+    }
+
     /// There is a bit of a complexity around what is considered block digest and what is being signed
     ///
     /// * Block signature covers all the fields in the block, except for signature and reference.digest
@@ -127,6 +177,10 @@ impl BlockDigest {
                     [4].crypto_hash(hasher);
                     range.crypto_hash(hasher);
                 }
+                BaseStatement::Batch(digest) => {
+                    [5].crypto_hash(hasher);
+                    digest.crypto_hash(hasher);
+                }
             }
         }
         meta_creation_time_ns.crypto_hash(hasher);
@@ -134,6 +188,38 @@ impl BlockDigest {
     }
 }
 
+impl BatchDigest {
+    /// Unlike the block digest, this is not stubbed in tests: batches are looked up by digest.
+    pub fn new(author: AuthorityIndex, transactions: &[Transaction]) -> Self {
+        let mut hasher = blake2::Blake2b::<digest::consts::U32>::default();
+        author.crypto_hash(&mut hasher);
+        for transaction in transactions {
+            (transaction.data().len() as u64).crypto_hash(&mut hasher);
+            transaction.crypto_hash(&mut hasher);
+        }
+        Self(hasher.finalize().into())
+    }
+}
+
+impl ChunkDigest {
+    /// Not stubbed in tests either: chunks are checked against the Merkle root.
+    pub fn leaf(index: usize, shard: &[u8]) -> Self {
+        let mut hasher = blake2::Blake2b::<digest::consts::U32>::default();
+        [0u8].crypto_hash(&mut hasher);
+        (index as u64).crypto_hash(&mut hasher);
+        hasher.update(shard);
+        Self(hasher.finalize().into())
+    }
+
+    pub fn node(left: &ChunkDigest, right: &ChunkDigest) -> Self {
+        let mut hasher = blake2::Blake2b::<digest::consts::U32>::default();
+        [1u8].crypto_hash(&mut hasher);
+        left.crypto_hash(&mut hasher);
+        right.crypto_hash(&mut hasher);
+        Self(hasher.finalize().into())
+    }
+}
+
 pub trait AsBytes {
     // This is pretty much same as AsRef<[u8]>
     //
@@ -169,6 +255,55 @@ impl CryptoHash for u128 {
     }
 }
 
+/// The digest signed by a validator announcing the address it accepts connections on, see
+/// `AddressUpdate`. The leading tag tells it apart from the digest of a block, and it is not
+/// stubbed in tests either.
+pub fn address_signing_digest(
+    authority: AuthorityIndex,
+    sequence: u64,
+    address: &PeerAddress,
+) -> SigningDigest {
+    let mut hasher = blake2::Blake2b::<digest::consts::U32>::default();
+    hasher.update(b"address");
+    authority.crypto_hash(&mut hasher);
+    sequence.crypto_hash(&mut hasher);
+    hasher.update(address.to_string());
+    hasher.finalize().into()
+}
+
+/// The digest signed by a validator relaying an own round request or report through the overlay,
+/// see `OwnRoundStatement`.
+pub fn own_round_signing_digest(statement: &OwnRoundStatement) -> SigningDigest {
+    let mut hasher = blake2::Blake2b::<digest::consts::U32>::default();
+    hasher.update(b"own_round");
+    match statement {
+        OwnRoundStatement::Request { requester, nonce } => {
+            hasher.update([0]);
+            requester.crypto_hash(&mut hasher);
+            nonce.crypto_hash(&mut hasher);
+        }
+        OwnRoundStatement::Report {
+            requester,
+            nonce,
+            responder,
+            latest,
+        } => {
+            hasher.update([1]);
+            requester.crypto_hash(&mut hasher);
+            nonce.crypto_hash(&mut hasher);
+            responder.crypto_hash(&mut hasher);
+            match latest {
+                None => hasher.update([0]),
+                Some(latest) => {
+                    hasher.update([1]);
+                    latest.crypto_hash(&mut hasher);
+                }
+            }
+        }
+    }
+    hasher.finalize().into()
+}
+
 impl<T: AsBytes> CryptoHash for T {
     fn crypto_hash(&self, state: &mut impl Digest) {
         state.update(self.as_bytes());
@@ -179,9 +314,7 @@ impl PublicKey {
     #[cfg(not(test))]
     pub fn verify_block(&self, block: &StatementBlock) -> Result<(), ed25519_consensus::Error> {
         let signature = Signature::from(block.signature().0);
-        let mut hasher = BlockHasher::default();
-        BlockDigest::digest_without_signature(
-            &mut hasher,
+        let digest = BlockDigest::signing_digest(
             block.author(),
             block.round(),
             block.includes(),
@@ -189,7 +322,6 @@ impl PublicKey {
             block.meta_creation_time_ns(),
             block.epoch_changed(),
         );
-        let digest: [u8; BLOCK_DIGEST_SIZE] = hasher.finalize().into();
         self.0.verify(&signature, digest.as_ref())
     }
 
@@ -197,6 +329,56 @@ impl PublicKey {
     pub fn verify_block(&self, _block: &StatementBlock) -> Result<(), ed25519_consensus::Error> {
         Ok(())
     }
+
+    /// Verify the signatures of many blocks at once, returning one result per block.
+    /// All signatures are first checked as a single batch, which is considerably cheaper than
+    /// checking them one by one. Only if the batch fails, each signature is checked individually
+    /// to find the culprits.
+    #[cfg(not(test))]
+    pub fn verify_blocks(
+        blocks: &[(&PublicKey, &StatementBlock)],
+    ) -> Vec<Result<(), ed25519_consensus::Error>> {
+        let items: Vec<_> = blocks
+            .iter()
+            .map(|(public_key, block)| {
+                let digest = BlockDigest::signing_digest(
+                    block.author(),
+                    block.round(),
+                    block.includes(),
+                    block.statements(),
+                    block.meta_creation_time_ns(),
+                    block.epoch_changed(),
+                );
+                let signature = Signature::from(block.signature().0);
+                batch::Item::from((public_key.0.into(), signature, &digest))
+            })
+            .collect();
+        let mut verifier = batch::Verifier::new();
+        for item in &items {
+            verifier.queue(item.clone());
+        }
+        if verifier.verify(rand::thread_rng()).is_ok() {
+            return vec![Ok(()); items.len()];
+        }
+        items.into_iter().map(batch::Item::verify_single).collect()
+    }
+
+    #[cfg(test)]
+    pub fn verify_blocks(
+        blocks: &[(&PublicKey, &StatementBlock)],
+    ) -> Vec<Result<(), ed25519_consensus::Error>> {
+        vec![Ok(()); blocks.len()]
+    }
+
+    /// Verify a signature over an arbitrary digest, such as `address_signing_digest`.
+    pub fn verify_digest(
+        &self,
+        digest: &SigningDigest,
+        signature: &SignatureBytes,
+    ) -> Result<(), ed25519_consensus::Error> {
+        let signature = ed25519_consensus::Signature::from(signature.0);
+        self.0.verify(&signature, digest.as_ref())
+    }
 }
 
 impl Signer {
@@ -207,47 +389,30 @@ impl Signer {
             .collect()
     }
 
-    #[cfg(not(test))]
-    pub fn sign_block(
-        &self,
-        authority: AuthorityIndex,
-        round: RoundNumber,
-        includes: &[BlockReference],
-        statements: &[BaseStatement],
-        meta_creation_time_ns: TimestampNs,
-        epoch_marker: EpochStatus,
-    ) -> SignatureBytes {
-        let mut hasher = BlockHasher::default();
-        BlockDigest::digest_without_signature(
-            &mut hasher,
-            authority,
-            round,
-            includes,
-            statements,
-            meta_creation_time_ns,
-            epoch_marker,
-        );
-        let digest: [u8; BLOCK_DIGEST_SIZE] = hasher.finalize().into();
-        let signature = self.0.sign(digest.as_ref());
-        SignatureBytes(signature.to_bytes())
+    /// Generate a fresh keypair. Unlike `new_for_test`, the key can not be derived by anyone else.
+    pub fn generate(rng: &mut (impl RngCore + CryptoRng)) -> Self {
+        Self(Box::new(ed25519_consensus::SigningKey::new(rng)))
     }
 
-    #[cfg(test)]
-    pub fn sign_block(
-        &self,
-        _authority: AuthorityIndex,
-        _round: RoundNumber,
-        _includes: &[BlockReference],
-        _statements: &[BaseStatement],
-        _meta_creation_time_ns: TimestampNs,
-        _epoch_marker: EpochStatus,
-    ) -> SignatureBytes {
-        Default::default()
+    /// Sign the digest covering all the fields of a block except its signature.
+    pub fn sign_digest(&self, digest: &SigningDigest) -> SignatureBytes {
+        SignatureBytes(self.0.sign(digest.as_ref()).to_bytes())
     }
 
     pub fn public_key(&self) -> PublicKey {
         PublicKey(self.0.verification_key())
     }
+
+    /// The raw signing key, only meant to be encrypted at rest.
+    pub fn to_bytes(&self) -> Zeroizing<[u8; 32]> {
+        Zeroizing::new(self.0.to_bytes())
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        ed25519_consensus::SigningKey::try_from(bytes)
+            .ok()
+            .map(|key| Self(Box::new(key)))
+    }
 }
 
 impl AsRef<[u8]> for BlockDigest {
@@ -268,6 +433,18 @@ impl AsBytes for BlockDigest {
     }
 }
 
+impl AsBytes for BatchDigest {
+    fn as_bytes(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+impl AsBytes for ChunkDigest {
+    fn as_bytes(&self) -> &[u8] {
+        &self.0
+    }
+}
+
 impl AsBytes for SignatureBytes {
     fn as_bytes(&self) -> &[u8] {
         &self.0
@@ -286,6 +463,24 @@ impl fmt::Display for BlockDigest {
     }
 }
 
+impl fmt::Debug for BatchDigest {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "#{}", hex::encode(self.0))
+    }
+}
+
+impl fmt::Display for BatchDigest {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "#{}", hex::encode(&self.0[..4]))
+    }
+}
+
+impl fmt::Debug for ChunkDigest {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "%{}", hex::encode(self.0))
+    }
+}
+
 impl fmt::Debug for Signer {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         write!(f, "Signer(public_key={:?})", self.public_key())
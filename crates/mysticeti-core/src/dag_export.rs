@@ -0,0 +1,430 @@
+// Copyright (c) Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Export a range of rounds of the dag to debug the commit rule: as a Graphviz DOT graph, as JSON,
+//! or in the syntax of `Dag::draw` so that a failing case can be pasted into a unit test. Blocks
+//! are the nodes of the graph and their includes its edges, the leader slots are highlighted with
+//! their `LeaderStatus`, and the votes and certificates for the leaders may be annotated.
+
+use std::{
+    collections::HashMap,
+    fmt::Write,
+    fs,
+    ops::RangeInclusive,
+    path::Path,
+    str::FromStr,
+    sync::Arc,
+};
+
+use eyre::{ensure, Context};
+use prometheus::Registry;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    block_store::BlockStore,
+    committee::Committee,
+    config::NodeParameters,
+    consensus::{
+        universal_committer::{UniversalCommitter, UniversalCommitterBuilder},
+        LeaderStatus,
+    },
+    metrics::Metrics,
+    types::{format_authority_round, AuthorityIndex, BlockReference, RoundNumber},
+    wal,
+};
+
+/// The number of rounds exported when no range is specified, ending at the highest round known.
+pub const DEFAULT_EXPORTED_ROUNDS: RoundNumber = 20;
+/// The most rounds exported at once, so that an export does not hold the block store for long.
+pub const MAX_EXPORTED_ROUNDS: RoundNumber = 500;
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, Eq, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum DagFormat {
+    /// A Graphviz graph, to render with `dot -Tsvg`.
+    #[default]
+    Dot,
+    Json,
+    /// The syntax of `Dag::draw`.
+    Draw,
+}
+
+impl FromStr for DagFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "dot" => Ok(Self::Dot),
+            "json" => Ok(Self::Json),
+            "draw" => Ok(Self::Draw),
+            _ => Err(format!(
+                "Unknown dag format {s}, expected dot, json or draw"
+            )),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Eq, PartialEq)]
+pub struct ExportedBlock {
+    /// The name of the block, for instance `B5` for the block of authority 1 at round 5.
+    pub name: String,
+    pub author: AuthorityIndex,
+    pub round: RoundNumber,
+    pub includes: Vec<String>,
+    /// The leaders this block votes for (when annotated).
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub votes_for: Vec<String>,
+    /// The leaders this block certifies (when annotated).
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub certifies: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Eq, PartialEq)]
+pub struct ExportedLeader {
+    /// The leader slot, for instance `B5`.
+    pub slot: String,
+    pub status: ExportedLeaderStatus,
+    /// The block committed for the slot, if any.
+    pub block: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Eq, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum ExportedLeaderStatus {
+    Commit,
+    Skip,
+    Undecided,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Eq, PartialEq)]
+pub struct DagExport {
+    pub from_round: RoundNumber,
+    pub to_round: RoundNumber,
+    /// The blocks of the rounds exported, by round and then by author.
+    pub blocks: Vec<ExportedBlock>,
+    pub leaders: Vec<ExportedLeader>,
+}
+
+/// Exports the dag of a block store, deciding the leaders with the commit rule of the node.
+pub struct DagExporter {
+    block_store: BlockStore,
+    committer: UniversalCommitter,
+}
+
+impl DagExporter {
+    pub(crate) fn new(
+        block_store: BlockStore,
+        committee: Arc<Committee>,
+        parameters: &NodeParameters,
+        metrics: Arc<Metrics>,
+    ) -> Self {
+        // A committer of our own, so that exporting does not interfere with the commit sequence
+        let committer = UniversalCommitterBuilder::new(committee, block_store.clone(), metrics)
+            .with_number_of_leaders(parameters.number_of_leaders)
+            .with_pipeline(parameters.enable_pipelining)
+            .with_wave_length(parameters.wave_length)
+            .build();
+        Self {
+            block_store,
+            committer,
+        }
+    }
+
+    /// The rounds from `from` to `to`, defaulting to the last `DEFAULT_EXPORTED_ROUNDS` rounds.
+    /// Ranges of more than `MAX_EXPORTED_ROUNDS` rounds are rejected.
+    pub fn rounds(
+        &self,
+        from: Option<RoundNumber>,
+        to: Option<RoundNumber>,
+    ) -> eyre::Result<RangeInclusive<RoundNumber>> {
+        let to = to.unwrap_or_else(|| self.block_store.highest_round());
+        let from = from.unwrap_or_else(|| to.saturating_sub(DEFAULT_EXPORTED_ROUNDS - 1));
+        ensure!(from <= to, "No rounds from {from} to {to}");
+        ensure!(
+            to - from < MAX_EXPORTED_ROUNDS,
+            "Can not export more than {MAX_EXPORTED_ROUNDS} rounds at once (rounds {from} to {to})"
+        );
+        Ok(from..=to)
+    }
+
+    /// Export the blocks of `rounds`, annotating the votes and certificates for the leaders if
+    /// `annotate` is set (which is slower).
+    pub fn export(&self, rounds: RangeInclusive<RoundNumber>, annotate: bool) -> DagExport {
+        let mut blocks = Vec::new();
+        for round in rounds.clone() {
+            let mut round_blocks = self.block_store.get_blocks_by_round(round);
+            round_blocks.sort_by_key(|block| *block.reference());
+            blocks.extend(round_blocks);
+        }
+        let mut exported: Vec<_> = blocks
+            .iter()
+            .map(|block| ExportedBlock {
+                name: block.reference().to_string(),
+                author: block.author(),
+                round: block.round(),
+                includes: block.includes().iter().map(ToString::to_string).collect(),
+                votes_for: vec![],
+                certifies: vec![],
+            })
+            .collect();
+
+        let statuses = self.committer.leader_statuses(rounds.clone());
+        if annotate {
+            let index: HashMap<BlockReference, usize> = blocks
+                .iter()
+                .enumerate()
+                .map(|(i, block)| (*block.reference(), i))
+                .collect();
+            for status in &statuses {
+                let leader_blocks = self
+                    .block_store
+                    .get_blocks_at_authority_round(status.authority(), status.round());
+                for leader_block in leader_blocks {
+                    let Some(support) = self.committer.leader_support(&leader_block) else {
+                        continue;
+                    };
+                    let leader = leader_block.reference().to_string();
+                    for vote in support.votes.iter().filter_map(|vote| index.get(vote)) {
+                        exported[*vote].votes_for.push(leader.clone());
+                    }
+                    for certificate in support
+                        .certificates
+                        .iter()
+                        .filter_map(|certificate| index.get(certificate))
+                    {
+                        exported[*certificate].certifies.push(leader.clone());
+                    }
+                }
+            }
+        }
+
+        let leaders = statuses
+            .iter()
+            .map(|leader| {
+                let (status, block) = match leader {
+                    LeaderStatus::Commit(block) => (
+                        ExportedLeaderStatus::Commit,
+                        Some(block.reference().to_string()),
+                    ),
+                    LeaderStatus::Skip(..) => (ExportedLeaderStatus::Skip, None),
+                    LeaderStatus::Undecided(..) => (ExportedLeaderStatus::Undecided, None),
+                };
+                ExportedLeader {
+                    slot: format_authority_round(leader.authority(), leader.round()),
+                    status,
+                    block,
+                }
+            })
+            .collect();
+        DagExport {
+            from_round: *rounds.start(),
+            to_round: *rounds.end(),
+            blocks: exported,
+            leaders,
+        }
+    }
+}
+
+impl DagExport {
+    pub fn render(&self, format: DagFormat) -> String {
+        match format {
+            DagFormat::Dot => self.to_dot(),
+            DagFormat::Json => self.to_json(),
+            DagFormat::Draw => self.to_draw(),
+        }
+    }
+
+    /// Rounds go upwards, the leader slots are filled with the color of their status, and the
+    /// votes (dashed) and certificates (bold) point to the leader they support.
+    pub fn to_dot(&self) -> String {
+        let mut dot = String::new();
+        writeln!(dot, "digraph dag {{").unwrap();
+        writeln!(dot, "    rankdir=BT;").unwrap();
+        writeln!(dot, "    node [shape=box, style=rounded];").unwrap();
+        let mut rounds: Vec<(RoundNumber, Vec<&str>)> = Vec::new();
+        for block in &self.blocks {
+            match rounds.last_mut() {
+                Some((round, names)) if *round == block.round => names.push(&block.name),
+                _ => rounds.push((block.round, vec![&block.name])),
+            }
+        }
+        for (_, names) in rounds {
+            let names: Vec<_> = names.iter().map(|name| format!("\"{name}\"")).collect();
+            writeln!(dot, "    {{ rank=same; {}; }}", names.join("; ")).unwrap();
+        }
+        for leader in &self.leaders {
+            let color = match leader.status {
+                ExportedLeaderStatus::Commit => "palegreen",
+                ExportedLeaderStatus::Skip => "lightcoral",
+                ExportedLeaderStatus::Undecided => "lightyellow",
+            };
+            // Leaders without block (typically skipped) still show up, with a dashed border
+            let style = if self.blocks.iter().any(|block| block.name == leader.slot) {
+                "rounded,filled"
+            } else {
+                "rounded,filled,dashed"
+            };
+            writeln!(
+                dot,
+                "    \"{}\" [style=\"{style}\", fillcolor={color}, label=\"{}\\n{:?}\"];",
+                leader.slot, leader.slot, leader.status
+            )
+            .unwrap();
+        }
+        for block in &self.blocks {
+            for include in &block.includes {
+                writeln!(dot, "    \"{}\" -> \"{include}\";", block.name).unwrap();
+            }
+            for leader in &block.votes_for {
+                writeln!(
+                    dot,
+                    "    \"{}\" -> \"{leader}\" [style=dashed, color=blue, constraint=false];",
+                    block.name
+                )
+                .unwrap();
+            }
+            for leader in &block.certifies {
+                writeln!(
+                    dot,
+                    "    \"{}\" -> \"{leader}\" [style=bold, color=darkgreen, constraint=false];",
+                    block.name
+                )
+                .unwrap();
+            }
+        }
+        writeln!(dot, "}}").unwrap();
+        dot
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).expect("Dag exports are serializable")
+    }
+
+    /// The blocks in the syntax of `Dag::draw`, for instance `A1:[A0, B0]; B1:[A0, B0]`.
+    pub fn to_draw(&self) -> String {
+        self.blocks
+            .iter()
+            .map(|block| format!("{}:[{}]", block.name, block.includes.join(", ")))
+            .collect::<Vec<_>>()
+            .join("; ")
+    }
+}
+
+/// Export the dag stored in the wal file of a validator, typically copied from a validator that
+/// failed to commit. The wal is opened read-only, so it may be in use by a running validator.
+pub fn export_wal(
+    path: impl AsRef<Path>,
+    authority: AuthorityIndex,
+    committee: Arc<Committee>,
+    parameters: &NodeParameters,
+    from: Option<RoundNumber>,
+    to: Option<RoundNumber>,
+    annotate: bool,
+) -> eyre::Result<DagExport> {
+    let path = path.as_ref();
+    ensure!(
+        fs::metadata(path).is_ok(),
+        "Wal file '{}' does not exist",
+        path.display()
+    );
+    let (wal_writer, wal_reader) = wal::open_read_only(path)
+        .wrap_err(format!("Failed to open wal file '{}'", path.display()))?;
+    let (metrics, _reporter) = Metrics::new(&Registry::new(), Some(&committee));
+    let recovered = BlockStore::open(
+        authority,
+        Arc::new(wal_reader),
+        &wal_writer,
+        metrics.clone(),
+        &committee,
+        parameters.block_cache_size,
+    );
+    let exporter = DagExporter::new(recovered.block_store, committee, parameters, metrics);
+    let rounds = exporter.rounds(from, to)?;
+    Ok(exporter.export(rounds, annotate))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{build_dag, committee, test_metrics, TestBlockWriter};
+
+    fn exporter(stop: RoundNumber) -> DagExporter {
+        let committee = committee(4);
+        let mut block_writer = TestBlockWriter::new(&committee);
+        build_dag(&committee, &mut block_writer, None, stop);
+        let parameters = NodeParameters {
+            wave_length: 5,
+            number_of_leaders: 1,
+            enable_pipelining: false,
+            ..Default::default()
+        };
+        DagExporter::new(
+            block_writer.into_block_store(),
+            committee,
+            &parameters,
+            test_metrics(),
+        )
+    }
+
+    #[test]
+    fn export() {
+        let exporter = exporter(11);
+        assert_eq!(exporter.rounds(None, None).unwrap(), 0..=11);
+        assert_eq!(exporter.rounds(Some(3), Some(4)).unwrap(), 3..=4);
+        assert!(exporter.rounds(Some(5), Some(4)).is_err());
+        assert!(exporter.rounds(Some(0), Some(MAX_EXPORTED_ROUNDS)).is_err());
+        assert!(exporter.rounds(Some(1), Some(MAX_EXPORTED_ROUNDS)).is_ok());
+
+        let export = exporter.export(1..=11, true);
+        assert_eq!(export.blocks.len(), 4 * 11);
+        assert_eq!(export.blocks[1].name, "B1");
+        assert_eq!(export.blocks[1].includes, vec!["A0", "B0", "C0", "D0"]);
+        // The leader of round 5 is decided by round 9, not the one of round 10
+        assert_eq!(
+            export.leaders,
+            vec![
+                ExportedLeader {
+                    slot: "B5".to_string(),
+                    status: ExportedLeaderStatus::Commit,
+                    block: Some("B5".to_string()),
+                },
+                ExportedLeader {
+                    slot: "C10".to_string(),
+                    status: ExportedLeaderStatus::Undecided,
+                    block: None,
+                },
+            ]
+        );
+        for block in &export.blocks {
+            let votes: &[&str] = if block.round == 8 { &["B5"] } else { &[] };
+            let certifies: &[&str] = if block.round == 9 { &["B5"] } else { &[] };
+            assert_eq!(block.votes_for, votes, "{}", block.name);
+            assert_eq!(block.certifies, certifies, "{}", block.name);
+        }
+
+        let export = exporter.export(1..=11, false);
+        assert!(export.blocks.iter().all(|block| block.certifies.is_empty()));
+    }
+
+    #[test]
+    fn render() {
+        let export = exporter(11).export(8..=10, true);
+        assert_eq!(
+            &export.to_draw()[..40],
+            "A8:[A7, B7, C7, D7]; B8:[A7, B7, C7, D7]"
+        );
+
+        let dot = export.to_dot();
+        assert!(dot.contains("{ rank=same; \"A9\"; \"B9\"; \"C9\"; \"D9\"; }"));
+        assert!(dot.contains("\"C10\" [style=\"rounded,filled\", fillcolor=lightyellow"));
+        assert!(dot.contains("\"A10\" -> \"A9\";"));
+        // Only the leaders of the rounds exported are annotated
+        assert!(!dot.contains("\"A9\" -> \"B5\""));
+
+        let json: DagExport = serde_json::from_str(&export.to_json()).unwrap();
+        assert_eq!(json, export);
+
+        assert_eq!("draw".parse(), Ok(DagFormat::Draw));
+        assert!("svg".parse::<DagFormat>().is_err());
+    }
+}
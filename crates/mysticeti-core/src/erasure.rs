@@ -0,0 +1,651 @@
+// Copyright (c) Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Erasure-coded dissemination of own blocks. Instead of sending a large block in full to every
+//! peer, its author Reed-Solomon encodes it into one chunk per validator, any f+1 of which are
+//! enough to reconstruct the block. Each validator receives its own chunk from the author and
+//! forwards it to the other validators. Chunks carry a Merkle proof against the root of all the
+//! chunks of the block, so that forwarded chunks can be checked before reconstruction, and the
+//! reconstructed block is checked against the block reference.
+//!
+//! This spreads the upload of a block evenly across the committee, at the price of the expansion
+//! factor n/(f+1) in the total bytes sent. Blocks that can not be reconstructed (for example
+//! because their author crashed half-way) are fetched in full by the `BlockFetcher` once
+//! referenced by other blocks.
+
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap, VecDeque},
+    fmt,
+    sync::Arc,
+};
+
+use eyre::{bail, ensure};
+use parking_lot::Mutex;
+use reed_solomon_erasure::galois_8;
+use serde::{Deserialize, Serialize};
+use tokio::sync::mpsc;
+
+use crate::{
+    committee::Committee,
+    crypto::ChunkDigest,
+    data::Data,
+    network::NetworkMessage,
+    types::{AuthorityIndex, BlockReference, RoundNumber, StatementBlock},
+};
+
+/// The largest number of shards supported by a Reed-Solomon code over GF(2^8).
+pub const MAX_SHARDS: usize = 256;
+/// Blocks received in full are bounded by the network frame size, so are reconstructed ones.
+const MAX_BLOCK_SIZE: usize = 16 * 1024 * 1024;
+/// Older own blocks are streamed to peers catching up, which are better served by full blocks
+/// than by chunks the other validators no longer forward.
+const LIVE_ROUNDS: RoundNumber = 2;
+/// The number of own blocks whose chunks are kept, rather than encoded again for every peer.
+const ENCODED_BLOCKS: usize = 8;
+/// Chunks of blocks this many rounds below the highest known round are dropped.
+const RETAINED_ROUNDS: RoundNumber = 50;
+/// Chunks of blocks this many rounds above the highest known round are dropped.
+const FUTURE_ROUNDS: RoundNumber = 10;
+
+/// Systematic Reed-Solomon code over GF(2^8): the data is split into `data_shards` shards, and
+/// `total_shards - data_shards` parity shards are added, any `data_shards` of the shards are
+/// enough to recover the data.
+pub struct ReedSolomon {
+    data_shards: usize,
+    total_shards: usize,
+    // None without parity shards, the data shards are then all needed and sent as they are
+    coder: Option<galois_8::ReedSolomon>,
+}
+
+impl ReedSolomon {
+    pub fn new(data_shards: usize, total_shards: usize) -> Self {
+        assert!(
+            0 < data_shards && data_shards <= total_shards && total_shards <= MAX_SHARDS,
+            "Invalid Reed-Solomon code with {data_shards} data shards out of {total_shards}"
+        );
+        let coder = (total_shards > data_shards).then(|| {
+            galois_8::ReedSolomon::new(data_shards, total_shards - data_shards)
+                .expect("Shard counts are checked above")
+        });
+        Self {
+            data_shards,
+            total_shards,
+            coder,
+        }
+    }
+
+    /// One shard per validator, any f+1 of them are enough to recover the data.
+    pub fn for_committee(committee: &Committee) -> Self {
+        let total_shards = committee.len();
+        Self::new((total_shards - 1) / 3 + 1, total_shards)
+    }
+
+    pub fn data_shards(&self) -> usize {
+        self.data_shards
+    }
+
+    pub fn total_shards(&self) -> usize {
+        self.total_shards
+    }
+
+    pub fn shard_len(&self, length: usize) -> usize {
+        length.div_ceil(self.data_shards).max(1)
+    }
+
+    pub fn encode(&self, data: &[u8]) -> Vec<Vec<u8>> {
+        let shard_len = self.shard_len(data.len());
+        let mut shards: Vec<Vec<u8>> = (0..self.total_shards)
+            .map(|i| {
+                let start = (i * shard_len).min(data.len());
+                let end = (start + shard_len).min(data.len());
+                let mut shard = data[start..end].to_vec();
+                shard.resize(shard_len, 0);
+                shard
+            })
+            .collect();
+        if let Some(coder) = &self.coder {
+            coder
+                .encode(&mut shards)
+                .expect("Shards have the expected count and length");
+        }
+        shards
+    }
+
+    /// Recover the `length` bytes of data from any `data_shards` distinct shards.
+    pub fn reconstruct(&self, shards: &[(usize, &[u8])], length: usize) -> eyre::Result<Vec<u8>> {
+        ensure!(
+            shards.len() >= self.data_shards,
+            "Need {} shards to reconstruct, got {}",
+            self.data_shards,
+            shards.len()
+        );
+        let shard_len = self.shard_len(length);
+        let mut slots: Vec<Option<Vec<u8>>> = vec![None; self.total_shards];
+        for (index, shard) in &shards[..self.data_shards] {
+            ensure!(
+                *index < self.total_shards,
+                "Shard index {index} out of range"
+            );
+            ensure!(slots[*index].is_none(), "Duplicate shard {index}");
+            ensure!(
+                shard.len() == shard_len,
+                "Shard {index} has length {}, expected {shard_len}",
+                shard.len()
+            );
+            slots[*index] = Some(shard.to_vec());
+        }
+        if let Some(coder) = &self.coder {
+            coder
+                .reconstruct_data(&mut slots)
+                .map_err(|e| eyre::eyre!("Failed to reconstruct: {e:?}"))?;
+        }
+        let mut data: Vec<u8> = slots
+            .into_iter()
+            .take(self.data_shards)
+            .map(|shard| shard.expect("Data shards are reconstructed"))
+            .collect::<Vec<_>>()
+            .concat();
+        data.truncate(length);
+        Ok(data)
+    }
+}
+
+/// Merkle tree over the digests of the chunks of a block, padded to a power of two leaves.
+struct MerkleTree {
+    levels: Vec<Vec<ChunkDigest>>,
+}
+
+impl MerkleTree {
+    fn new(mut leaves: Vec<ChunkDigest>) -> Self {
+        leaves.resize(leaves.len().next_power_of_two(), ChunkDigest::default());
+        let mut levels = vec![leaves];
+        while let Some(level) = levels.last().filter(|level| level.len() > 1) {
+            let next = level
+                .chunks(2)
+                .map(|pair| ChunkDigest::node(&pair[0], &pair[1]))
+                .collect();
+            levels.push(next);
+        }
+        Self { levels }
+    }
+
+    fn root(&self) -> ChunkDigest {
+        self.levels.last().expect("Merkle tree has a root")[0]
+    }
+
+    fn proof(&self, index: usize) -> Vec<ChunkDigest> {
+        self.levels[..self.levels.len() - 1]
+            .iter()
+            .enumerate()
+            .map(|(height, level)| level[(index >> height) ^ 1])
+            .collect()
+    }
+
+    fn verify(root: &ChunkDigest, index: usize, leaf: ChunkDigest, proof: &[ChunkDigest]) -> bool {
+        let (digest, index) = proof
+            .iter()
+            .fold((leaf, index), |(digest, index), sibling| {
+                let parent = if index & 1 == 0 {
+                    ChunkDigest::node(&digest, sibling)
+                } else {
+                    ChunkDigest::node(sibling, &digest)
+                };
+                (parent, index >> 1)
+            });
+        index == 0 && digest == *root
+    }
+}
+
+/// The chunk of a block destined to (and forwarded by) the validator `index`.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct BlockChunk {
+    reference: BlockReference,
+    /// The Merkle root over all the chunks of the block.
+    root: ChunkDigest,
+    /// The length of the serialized block.
+    length: u32,
+    index: AuthorityIndex,
+    shard: Vec<u8>,
+    proof: Vec<ChunkDigest>,
+}
+
+impl BlockChunk {
+    pub fn reference(&self) -> &BlockReference {
+        &self.reference
+    }
+
+    pub fn index(&self) -> AuthorityIndex {
+        self.index
+    }
+}
+
+impl fmt::Debug for BlockChunk {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}[{}]({} bytes)",
+            self.reference,
+            self.index,
+            self.shard.len()
+        )
+    }
+}
+
+/// Encodes our own blocks, forwards the chunks we receive from their authors and reconstructs
+/// the blocks of others from their chunks.
+pub struct ChunkRelay {
+    coder: ReedSolomon,
+    authority: AuthorityIndex,
+    /// Blocks smaller than this are sent in full.
+    threshold: usize,
+    /// Chunks are not authenticated until the block is reconstructed, so the chunks pending from
+    /// each peer are bounded by what an honest peer sends within the window of rounds.
+    max_pending_per_peer: usize,
+    inner: Mutex<ChunkRelayInner>,
+}
+
+#[derive(Default)]
+struct ChunkRelayInner {
+    peers: HashMap<AuthorityIndex, mpsc::Sender<NetworkMessage>>,
+    encoded: VecDeque<(BlockReference, Arc<Vec<BlockChunk>>)>,
+    pending: BTreeMap<BlockReference, PendingChunks>,
+    /// The number of chunks received from each peer still in `pending`.
+    pending_per_peer: HashMap<AuthorityIndex, usize>,
+    /// Blocks we attempted to reconstruct, their remaining chunks are ignored.
+    done: BTreeSet<BlockReference>,
+    /// Blocks of rounds below this were pruned.
+    lowest_round: RoundNumber,
+}
+
+/// The chunks received for a block. At most one chunk is kept for each index, so at most one
+/// chunk per validator is kept for each block.
+#[derive(Default)]
+struct PendingChunks {
+    /// The peer each chunk was received from, by index.
+    senders: HashMap<AuthorityIndex, AuthorityIndex>,
+    /// Chunks grouped by the encoding (root and length) they claim.
+    encodings: HashMap<(ChunkDigest, u32), Vec<BlockChunk>>,
+}
+
+/// What became of a chunk passed to `ChunkRelay::add_chunk`.
+pub enum ChunkOutcome {
+    /// Out of the window of rounds, over the limit of the peer, or already received.
+    Rejected,
+    /// The block was already reconstructed (or failed to).
+    Late,
+    Pending,
+    /// Enough chunks of the same encoding were received; the reconstructed block still has to
+    /// be verified.
+    Reconstructed(eyre::Result<Data<StatementBlock>>),
+}
+
+impl ChunkRelay {
+    pub fn new(committee: &Committee, authority: AuthorityIndex, threshold: usize) -> Self {
+        assert!(
+            committee.len() <= MAX_SHARDS,
+            "Erasure coding supports committees of at most {MAX_SHARDS} validators"
+        );
+        Self {
+            coder: ReedSolomon::for_committee(committee),
+            authority,
+            threshold,
+            max_pending_per_peer: (RETAINED_ROUNDS + FUTURE_ROUNDS + 1) as usize * committee.len(),
+            inner: Default::default(),
+        }
+    }
+
+    pub fn authority(&self) -> AuthorityIndex {
+        self.authority
+    }
+
+    pub fn register_peer(&self, peer: AuthorityIndex, sender: mpsc::Sender<NetworkMessage>) {
+        self.inner.lock().peers.insert(peer, sender);
+    }
+
+    pub fn remove_peer(&self, peer: AuthorityIndex) {
+        self.inner.lock().peers.remove(&peer);
+    }
+
+    /// The peers to forward the chunks received from `author` to.
+    pub fn forward_to(&self, author: AuthorityIndex) -> Vec<mpsc::Sender<NetworkMessage>> {
+        self.inner
+            .lock()
+            .peers
+            .iter()
+            .filter(|(peer, _)| **peer != author)
+            .map(|(_, sender)| sender.clone())
+            .collect()
+    }
+
+    /// The chunk of our own block to send to `peer`, or None if the block is sent in full.
+    pub fn own_chunk(
+        &self,
+        block: &Data<StatementBlock>,
+        peer: AuthorityIndex,
+        last_own_round: RoundNumber,
+    ) -> Option<BlockChunk> {
+        if block.serialized_bytes().len() < self.threshold
+            || block.round() + LIVE_ROUNDS <= last_own_round
+            || peer as usize >= self.coder.total_shards()
+            || peer == self.authority
+        {
+            return None;
+        }
+        let mut inner = self.inner.lock();
+        let chunks = match inner
+            .encoded
+            .iter()
+            .find(|(reference, _)| reference == block.reference())
+        {
+            Some((_, chunks)) => chunks.clone(),
+            None => {
+                let chunks = Arc::new(self.encode(block));
+                if inner.encoded.len() == ENCODED_BLOCKS {
+                    inner.encoded.pop_front();
+                }
+                inner
+                    .encoded
+                    .push_back((*block.reference(), chunks.clone()));
+                chunks
+            }
+        };
+        Some(chunks[peer as usize].clone())
+    }
+
+    pub fn encode(&self, block: &Data<StatementBlock>) -> Vec<BlockChunk> {
+        let bytes = block.serialized_bytes();
+        let shards = self.coder.encode(bytes);
+        let tree = MerkleTree::new(
+            shards
+                .iter()
+                .enumerate()
+                .map(|(index, shard)| ChunkDigest::leaf(index, shard))
+                .collect(),
+        );
+        shards
+            .into_iter()
+            .enumerate()
+            .map(|(index, shard)| BlockChunk {
+                reference: *block.reference(),
+                root: tree.root(),
+                length: bytes.len() as u32,
+                index: index as AuthorityIndex,
+                shard,
+                proof: tree.proof(index),
+            })
+            .collect()
+    }
+
+    /// Check a chunk received from the network is the chunk `index` of the encoding it claims.
+    pub fn verify(&self, chunk: &BlockChunk, index: AuthorityIndex) -> eyre::Result<()> {
+        ensure!(
+            chunk.index == index,
+            "Expected chunk {index}, got {}",
+            chunk.index
+        );
+        ensure!(
+            (chunk.index as usize) < self.coder.total_shards(),
+            "Chunk index {} out of range",
+            chunk.index
+        );
+        ensure!(
+            chunk.length as usize <= MAX_BLOCK_SIZE,
+            "Block length {} too large",
+            chunk.length
+        );
+        let shard_len = self.coder.shard_len(chunk.length as usize);
+        ensure!(
+            chunk.shard.len() == shard_len,
+            "Chunk has length {}, expected {shard_len}",
+            chunk.shard.len()
+        );
+        let leaf = ChunkDigest::leaf(chunk.index as usize, &chunk.shard);
+        ensure!(
+            MerkleTree::verify(&chunk.root, chunk.index as usize, leaf, &chunk.proof),
+            "Invalid Merkle proof"
+        );
+        Ok(())
+    }
+
+    /// Add a verified chunk received from `peer`, the block is reconstructed once enough chunks
+    /// of the same encoding are received. Only chunks of blocks within a window of rounds around
+    /// `highest_round` (the highest round known locally) are kept.
+    pub fn add_chunk(
+        &self,
+        chunk: BlockChunk,
+        peer: AuthorityIndex,
+        highest_round: RoundNumber,
+    ) -> ChunkOutcome {
+        let reference = chunk.reference;
+        let mut inner = self.inner.lock();
+        inner.prune(highest_round.saturating_sub(RETAINED_ROUNDS));
+        if reference.round < inner.lowest_round || reference.round > highest_round + FUTURE_ROUNDS {
+            return ChunkOutcome::Rejected;
+        }
+        if inner.done.contains(&reference) {
+            return ChunkOutcome::Late;
+        }
+        let from_peer = inner.pending_per_peer.entry(peer).or_default();
+        if *from_peer >= self.max_pending_per_peer {
+            tracing::debug!("Too many pending chunks from {peer}, ignoring {chunk:?}");
+            return ChunkOutcome::Rejected;
+        }
+        let pending = inner.pending.entry(reference).or_default();
+        if pending.senders.contains_key(&chunk.index) {
+            return ChunkOutcome::Rejected;
+        }
+        pending.senders.insert(chunk.index, peer);
+        let encoding = (chunk.root, chunk.length);
+        let chunks = pending.encodings.entry(encoding).or_default();
+        chunks.push(chunk);
+        let complete = chunks.len() >= self.coder.data_shards();
+        *inner.pending_per_peer.entry(peer).or_default() += 1;
+        if !complete {
+            return ChunkOutcome::Pending;
+        }
+        let chunks = inner
+            .remove(&reference)
+            .and_then(|mut pending| pending.encodings.remove(&encoding))
+            .expect("Chunks are pending");
+        inner.done.insert(reference);
+        drop(inner);
+        ChunkOutcome::Reconstructed(self.reconstruct(&reference, &chunks))
+    }
+
+    fn reconstruct(
+        &self,
+        reference: &BlockReference,
+        chunks: &[BlockChunk],
+    ) -> eyre::Result<Data<StatementBlock>> {
+        let shards: Vec<_> = chunks
+            .iter()
+            .map(|chunk| (chunk.index as usize, chunk.shard.as_slice()))
+            .collect();
+        let bytes = self.coder.reconstruct(&shards, chunks[0].length as usize)?;
+        let block = Data::<StatementBlock>::from_bytes(bytes.into())?;
+        if block.reference() != reference {
+            bail!(
+                "Reconstructed block {} does not match {}",
+                block.reference(),
+                reference
+            );
+        }
+        Ok(block)
+    }
+}
+
+impl ChunkRelayInner {
+    fn remove(&mut self, reference: &BlockReference) -> Option<PendingChunks> {
+        let pending = self.pending.remove(reference)?;
+        for peer in pending.senders.values() {
+            if let Some(count) = self.pending_per_peer.get_mut(peer) {
+                *count -= 1;
+            }
+        }
+        Some(pending)
+    }
+
+    /// Drop the chunks of blocks below `round`.
+    fn prune(&mut self, round: RoundNumber) {
+        if round <= self.lowest_round {
+            return;
+        }
+        self.lowest_round = round;
+        let threshold = BlockReference {
+            round,
+            ..Default::default()
+        };
+        let pruned: Vec<_> = self.pending.range(..threshold).map(|(r, _)| *r).collect();
+        for reference in pruned {
+            self.remove(&reference);
+        }
+        self.done = self.done.split_off(&threshold);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        test_util::committee,
+        types::{BaseStatement, Transaction},
+    };
+
+    fn block(round: RoundNumber, size: usize) -> Data<StatementBlock> {
+        Data::new(StatementBlock::new(
+            0,
+            round,
+            vec![],
+            vec![BaseStatement::Share(Transaction::new(vec![7; size]))],
+            0,
+            false,
+            Default::default(),
+        ))
+    }
+
+    fn add(relay: &ChunkRelay, chunk: &BlockChunk) -> ChunkOutcome {
+        relay.add_chunk(chunk.clone(), chunk.index, 1)
+    }
+
+    #[test]
+    fn reed_solomon_any_shards() {
+        let coder = ReedSolomon::new(3, 7);
+        let data: Vec<u8> = (0..1000u32).map(|i| (i * 31 % 251) as u8).collect();
+        let shards = coder.encode(&data);
+        assert_eq!(shards.len(), 7);
+        // The code is systematic
+        assert_eq!(&shards[0][..], &data[..coder.shard_len(data.len())]);
+        for first in 0..7 {
+            for second in first + 1..7 {
+                for third in second + 1..7 {
+                    let subset: Vec<_> = [third, first, second]
+                        .into_iter()
+                        .map(|i| (i, shards[i].as_slice()))
+                        .collect();
+                    assert_eq!(coder.reconstruct(&subset, data.len()).unwrap(), data);
+                }
+            }
+        }
+        let too_few: Vec<_> = (0..2).map(|i| (i, shards[i].as_slice())).collect();
+        assert!(coder.reconstruct(&too_few, data.len()).is_err());
+        let duplicate: Vec<_> = [0, 0, 1].map(|i| (i, shards[i].as_slice())).to_vec();
+        assert!(coder.reconstruct(&duplicate, data.len()).is_err());
+    }
+
+    #[test]
+    fn reconstruct_block() {
+        let committee = committee(7);
+        let relay = ChunkRelay::new(&committee, 1, 0);
+        let block = block(1, 10_000);
+        let chunks = relay.encode(&block);
+        assert_eq!(chunks.len(), 7);
+        for (index, chunk) in chunks.iter().enumerate() {
+            relay.verify(chunk, index as AuthorityIndex).unwrap();
+        }
+        // Chunks are only valid at their own index
+        assert!(relay.verify(&chunks[2], 3).is_err());
+        let mut tampered = chunks[2].clone();
+        tampered.shard[0] ^= 1;
+        assert!(relay.verify(&tampered, 2).is_err());
+
+        // f+1 = 3 chunks are enough
+        assert!(matches!(add(&relay, &chunks[6]), ChunkOutcome::Pending));
+        assert!(matches!(add(&relay, &chunks[6]), ChunkOutcome::Rejected));
+        assert!(matches!(add(&relay, &chunks[2]), ChunkOutcome::Pending));
+        let ChunkOutcome::Reconstructed(reconstructed) = add(&relay, &chunks[4]) else {
+            panic!("Block should be reconstructed");
+        };
+        assert_eq!(
+            reconstructed.unwrap().serialized_bytes(),
+            block.serialized_bytes()
+        );
+        // Late chunks are ignored
+        assert!(matches!(add(&relay, &chunks[5]), ChunkOutcome::Late));
+
+        // Chunks of a different encoding (here of another block) are not mixed up
+        let relay = ChunkRelay::new(&committee, 1, 0);
+        let mut other = relay.encode(&self::block(2, 5_000));
+        for chunk in &mut other {
+            chunk.reference = *block.reference();
+        }
+        assert!(matches!(add(&relay, &other[0]), ChunkOutcome::Pending));
+        assert!(matches!(add(&relay, &chunks[1]), ChunkOutcome::Pending));
+        assert!(matches!(add(&relay, &other[3]), ChunkOutcome::Pending));
+        assert!(matches!(add(&relay, &chunks[2]), ChunkOutcome::Pending));
+        // Only one chunk is kept for each index, whatever its encoding
+        assert!(matches!(add(&relay, &chunks[3]), ChunkOutcome::Rejected));
+        assert!(matches!(
+            add(&relay, &other[5]),
+            ChunkOutcome::Reconstructed(Err(_))
+        ));
+    }
+
+    #[test]
+    fn chunk_limits() {
+        let committee = committee(4);
+        let relay = ChunkRelay::new(&committee, 1, 0);
+        let chunk = |round| relay.encode(&block(round, 1_000)).swap_remove(2);
+        // Chunks too far in the future or in the past are rejected
+        assert!(matches!(
+            relay.add_chunk(chunk(100 + FUTURE_ROUNDS + 1), 2, 100),
+            ChunkOutcome::Rejected
+        ));
+        assert!(matches!(
+            relay.add_chunk(chunk(100 - RETAINED_ROUNDS - 1), 2, 100),
+            ChunkOutcome::Rejected
+        ));
+        // A peer can only fill its share of the window
+        for round in 100 - RETAINED_ROUNDS..=100 + FUTURE_ROUNDS {
+            for author in 0..4 {
+                let mut chunk = chunk(round);
+                chunk.reference.authority = author;
+                assert!(matches!(
+                    relay.add_chunk(chunk, 2, 100),
+                    ChunkOutcome::Pending
+                ));
+            }
+        }
+        assert!(matches!(
+            relay.add_chunk(chunk(100), 2, 100),
+            ChunkOutcome::Rejected
+        ));
+        // Moving the window frees the pruned chunks
+        assert!(matches!(
+            relay.add_chunk(chunk(101 + FUTURE_ROUNDS), 2, 101),
+            ChunkOutcome::Pending
+        ));
+    }
+
+    #[test]
+    fn own_chunks() {
+        let committee = committee(4);
+        let relay = ChunkRelay::new(&committee, 0, 1_000);
+        let large = block(1, 10_000);
+        let chunk = relay.own_chunk(&large, 2, 1).unwrap();
+        assert_eq!(chunk.index(), 2);
+        // Small blocks, old blocks, and blocks sent to observers go in full
+        assert!(relay.own_chunk(&block(1, 10), 2, 1).is_none());
+        assert!(relay.own_chunk(&large, 2, 3).is_none());
+        assert!(relay.own_chunk(&large, 4, 1).is_none());
+    }
+}
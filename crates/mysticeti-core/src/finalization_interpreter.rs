@@ -87,6 +87,7 @@ impl<'a> FinalizationInterpreter<'a> {
                     let locator = TransactionLocator::new(*block.reference(), offset as u64);
                     self.vote(block, &locator, block.author());
                 }
+                BaseStatement::Batch(_) => {}
             }
         }
         for parent in block.includes() {
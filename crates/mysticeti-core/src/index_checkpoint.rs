@@ -0,0 +1,259 @@
+// Copyright (c) Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Checkpoints of the index of the block store, so that a restarting validator does not replay its
+//! whole wal. A checkpoint holds what `BlockStore::open` would recover from the wal written before
+//! `IndexCheckpoint::wal_position`: the position of every block and batch, the pending statements,
+//! the last own block and the committed blocks. The state entries are not copied, the checkpoint
+//! only holds their position. The checkpoint is a file next to the wal, replaced atomically every
+//! `index_checkpoint_period` committed rounds (see `Core::handle_committed_subdag`). Checkpoints
+//! are stored by a thread of their own, so that syncing the wal and writing the checkpoint do not
+//! hold the core.
+
+use std::{
+    collections::{HashSet, VecDeque},
+    fs::{self, File},
+    io::{self, Write},
+    path::{Path, PathBuf},
+    sync::mpsc,
+    thread,
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    block_store::{BlockStore, CommitData, OwnBlockData},
+    core::MetaStatement,
+    data::Data,
+    snapshot::Snapshot,
+    state::RecoveredState,
+    types::{BatchDigest, BlockReference, RoundNumber, StatementBlock},
+    wal::{WalPosition, WalSyncer},
+};
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct IndexCheckpoint {
+    /// The checkpoint covers the wal entries written before this position.
+    pub wal_position: WalPosition,
+    /// The crc of the wal right before `wal_position` (see `WalReader::crc_before`), so that a
+    /// checkpoint is not applied to a wal rewritten since.
+    pub wal_crc: u32,
+    /// The position of every block in the wal. The own blocks and the last round seen from every
+    /// authority are derived from them.
+    pub blocks: Vec<(BlockReference, WalPosition)>,
+    pub batches: Vec<(BatchDigest, WalPosition)>,
+    pub pending: Vec<(WalPosition, MetaStatement)>,
+    /// The `next_entry` and the block of the last own block.
+    pub last_own_block: Option<(WalPosition, Data<StatementBlock>)>,
+    /// The position of the last state entry. Checkpoints are taken right after writing the state,
+    /// hence no block was processed since.
+    pub state: Option<WalPosition>,
+    pub last_committed_leader: Option<BlockReference>,
+    pub committed_blocks: Vec<BlockReference>,
+    /// The position of the last commit entry, which holds the committed state.
+    pub committed_state: Option<WalPosition>,
+    pub snapshot: Option<Snapshot>,
+}
+
+impl IndexCheckpoint {
+    pub const DEFAULT_FILENAME: &'static str = "index-checkpoint";
+
+    /// Load the checkpoint stored at `path`, if any.
+    pub fn load(path: impl AsRef<Path>) -> io::Result<Option<Self>> {
+        let bytes = match fs::read(path) {
+            Ok(bytes) => bytes,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e),
+        };
+        if bytes.len() < CRC_LEN {
+            return Err(corrupted("the file is truncated"));
+        }
+        let (crc, serialized) = bytes.split_at(CRC_LEN);
+        let crc = u32::from_le_bytes(crc.try_into().expect("Crc has 4 bytes"));
+        if crc32fast::hash(serialized) != crc {
+            return Err(corrupted("crc mismatch"));
+        }
+        bincode::deserialize(serialized)
+            .map(Some)
+            .map_err(corrupted)
+    }
+
+    /// Replace the checkpoint stored at `path`. The entries covered by the checkpoint must have
+    /// been synced to disk beforehand.
+    pub fn store(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        let path = path.as_ref();
+        let serialized = bincode::serialize(self).expect("Serialization failed");
+        let crc = crc32fast::hash(&serialized);
+        // Write a temporary file and rename it, so that a crash leaves either checkpoint intact
+        let temporary = path.with_extension("tmp");
+        let mut file = File::create(&temporary)?;
+        file.write_all(&crc.to_le_bytes())?;
+        file.write_all(&serialized)?;
+        file.sync_data()?;
+        fs::rename(&temporary, path)
+    }
+
+    #[cfg(test)]
+    pub fn new_test(wal_position: WalPosition) -> Self {
+        Self {
+            wal_position,
+            wal_crc: 0,
+            blocks: vec![(BlockReference::new_test(1, 1), WalPosition::new(0))],
+            batches: vec![],
+            pending: vec![],
+            last_own_block: None,
+            state: None,
+            last_committed_leader: None,
+            committed_blocks: vec![],
+            committed_state: None,
+            snapshot: None,
+        }
+    }
+}
+
+const CRC_LEN: usize = 4;
+
+fn corrupted(reason: impl std::fmt::Display) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("Corrupted index checkpoint: {reason}"),
+    )
+}
+
+/// Takes the checkpoints of a core. Besides the state of the core, checkpoints need the committed
+/// blocks and the snapshot installed (if any), which the core does not keep.
+pub struct IndexCheckpointer {
+    period: RoundNumber,
+    last_committed_leader: Option<BlockReference>,
+    committed_blocks: HashSet<BlockReference>,
+    snapshot: Option<Snapshot>,
+    // Hands the checkpoints over to the thread storing them, see `store`
+    sender: Option<mpsc::SyncSender<IndexCheckpoint>>,
+    writer: Option<thread::JoinHandle<()>>,
+}
+
+impl IndexCheckpointer {
+    pub fn new(
+        path: PathBuf,
+        period: RoundNumber,
+        recovered: &RecoveredState,
+        wal_syncer: WalSyncer,
+    ) -> Self {
+        let (sender, receiver) = mpsc::sync_channel::<IndexCheckpoint>(1);
+        let writer = thread::Builder::new()
+            .name("mysticeti-checkpoint".to_string())
+            .spawn(move || {
+                for checkpoint in receiver {
+                    // The checkpoint must not reference entries lost in a crash
+                    match wal_syncer.sync().and_then(|()| checkpoint.store(&path)) {
+                        Ok(()) => tracing::debug!(
+                            "Stored index checkpoint of {} blocks at position {}",
+                            checkpoint.blocks.len(),
+                            checkpoint.wal_position
+                        ),
+                        Err(e) => tracing::warn!("Failed to store index checkpoint: {e}"),
+                    }
+                }
+            })
+            .expect("Failed to start the index checkpoint thread");
+        Self {
+            period,
+            last_committed_leader: recovered.last_committed_leader,
+            committed_blocks: recovered.committed_blocks.clone(),
+            snapshot: recovered.snapshot.clone(),
+            sender: Some(sender),
+            writer: Some(writer),
+        }
+    }
+
+    pub fn commit_data(&mut self, commits: &[CommitData]) {
+        for commit in commits {
+            self.last_committed_leader = Some(commit.leader);
+            self.committed_blocks.extend(commit.sub_dag.iter().copied());
+        }
+    }
+
+    pub fn snapshot(&mut self, snapshot: Snapshot) {
+        self.snapshot = Some(snapshot);
+    }
+
+    /// Whether a checkpoint is due after committing the rounds `from..to`.
+    pub fn crosses_boundary(&self, from: RoundNumber, to: RoundNumber) -> bool {
+        self.period != 0 && to / self.period > from / self.period
+    }
+
+    pub fn checkpoint(
+        &self,
+        wal_position: WalPosition,
+        block_store: &BlockStore,
+        pending: &VecDeque<(WalPosition, MetaStatement)>,
+        last_own_block: Option<&OwnBlockData>,
+        state: WalPosition,
+        committed_state: WalPosition,
+    ) -> io::Result<IndexCheckpoint> {
+        Ok(IndexCheckpoint {
+            wal_position,
+            wal_crc: block_store.wal_crc_before(wal_position)?,
+            blocks: block_store.block_positions(),
+            batches: block_store.batch_positions(),
+            pending: pending.iter().cloned().collect(),
+            last_own_block: last_own_block.map(|own| (own.next_entry, own.block.clone())),
+            state: Some(state),
+            last_committed_leader: self.last_committed_leader,
+            committed_blocks: self.committed_blocks.iter().copied().collect(),
+            committed_state: Some(committed_state),
+            snapshot: self.snapshot.clone(),
+        })
+    }
+
+    /// Store the checkpoint in the background. The checkpoint is dropped if the previous one is
+    /// still being stored, the next one will cover its entries.
+    pub fn store(&self, checkpoint: IndexCheckpoint) {
+        let sender = self.sender.as_ref().expect("Sender is only taken on drop");
+        if let Err(mpsc::TrySendError::Full(checkpoint)) = sender.try_send(checkpoint) {
+            tracing::debug!(
+                "Skipping index checkpoint at position {}, the previous one is still being stored",
+                checkpoint.wal_position
+            );
+        }
+    }
+}
+
+impl Drop for IndexCheckpointer {
+    fn drop(&mut self) {
+        // Let the thread store the last checkpoint handed over
+        drop(self.sender.take());
+        if let Some(writer) = self.writer.take() {
+            writer.join().ok();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn store_and_load() {
+        let dir = tempdir::TempDir::new("index_checkpoint").unwrap();
+        let path = dir.path().join(IndexCheckpoint::DEFAULT_FILENAME);
+        assert!(IndexCheckpoint::load(&path).unwrap().is_none());
+
+        IndexCheckpoint::new_test(WalPosition::new(10))
+            .store(&path)
+            .unwrap();
+        IndexCheckpoint::new_test(WalPosition::new(20))
+            .store(&path)
+            .unwrap();
+        let loaded = IndexCheckpoint::load(&path).unwrap().unwrap();
+        assert_eq!(loaded.wal_position, WalPosition::new(20));
+        assert_eq!(loaded.blocks.len(), 1);
+
+        let mut bytes = fs::read(&path).unwrap();
+        let last = bytes.len() - 1;
+        bytes[last] ^= 0xff;
+        fs::write(&path, &bytes).unwrap();
+        let error = IndexCheckpoint::load(&path).err().unwrap();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+    }
+}
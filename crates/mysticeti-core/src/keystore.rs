@@ -0,0 +1,338 @@
+// Copyright (c) Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+use std::{
+    env,
+    fs::File,
+    io,
+    io::Read,
+    net::SocketAddr,
+    os::fd::{FromRawFd, RawFd},
+    path::{Path, PathBuf},
+};
+
+use aes_gcm::{aead::AeadInPlace, Aes256Gcm, KeyInit};
+use eyre::{bail, ensure, eyre, Context};
+use rand::{rngs::OsRng, RngCore};
+use serde::{Deserialize, Serialize};
+use zeroize::Zeroizing;
+
+use crate::{
+    config::{print_owner_only, ImportExport, NodePrivateConfig},
+    crypto::Signer,
+    types::AuthorityIndex,
+};
+
+const KEY_SIZE: usize = 32;
+const SALT_SIZE: usize = 32;
+const NONCE_SIZE: usize = 12;
+const TAG_SIZE: usize = 16;
+/// Bounds on the scrypt parameters read from a private config, so that a crafted file can not
+/// make us allocate (128 * r * 2^log_n bytes) or compute (p times as much) arbitrarily.
+const MAX_SCRYPT_LOG_N: u8 = 20;
+const MAX_SCRYPT_R: u32 = 32;
+const MAX_SCRYPT_P: u32 = 16;
+const MAX_SCRYPT_MEMORY: u64 = 1024 * 1024 * 1024;
+
+/// A private config whose signing key is encrypted with a key derived from a password (scrypt)
+/// and authenticated (AES-256-GCM). The authority index and storage path are authenticated as
+/// well, so the key can not be moved to another slot without being detected.
+#[derive(Serialize, Deserialize)]
+pub struct EncryptedNodePrivateConfig {
+    authority: AuthorityIndex,
+    encrypted_keypair: EncryptedKey,
+    pub storage_path: PathBuf,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub signing_guard_path: Option<PathBuf>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub admin_address: Option<SocketAddr>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct EncryptedKey {
+    kdf: ScryptParameters,
+    /// All binary fields are hex-encoded.
+    salt: String,
+    nonce: String,
+    ciphertext: String,
+    tag: String,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy)]
+pub struct ScryptParameters {
+    log_n: u8,
+    r: u32,
+    p: u32,
+}
+
+/// A password used to derive the key encryption key. The buffer is zeroized on drop.
+pub struct Password(Zeroizing<Vec<u8>>);
+
+impl EncryptedNodePrivateConfig {
+    pub fn encrypt(config: &NodePrivateConfig, password: &Password) -> eyre::Result<Self> {
+        Self::encrypt_with_parameters(config, password, ScryptParameters::default())
+    }
+
+    pub fn encrypt_with_parameters(
+        config: &NodePrivateConfig,
+        password: &Password,
+        kdf: ScryptParameters,
+    ) -> eyre::Result<Self> {
+        let mut salt = [0u8; SALT_SIZE];
+        OsRng.fill_bytes(&mut salt);
+        let mut nonce = [0u8; NONCE_SIZE];
+        OsRng.fill_bytes(&mut nonce);
+        let key = kdf.derive(password, &salt)?;
+
+        let mut ciphertext = config
+            .keypair
+            .as_ref()
+            .ok_or_else(|| eyre!("The private config has no keypair"))?
+            .to_bytes()
+            .to_vec();
+        let aad = Self::aad(config.authority(), &config.storage_path);
+        let tag = Aes256Gcm::new(key.as_ref().into())
+            .encrypt_in_place_detached(&nonce.into(), &aad, &mut ciphertext)
+            .map_err(|_| eyre!("Failed to encrypt the keypair"))?;
+
+        Ok(Self {
+            authority: config.authority(),
+            encrypted_keypair: EncryptedKey {
+                kdf,
+                salt: hex::encode(salt),
+                nonce: hex::encode(nonce),
+                ciphertext: hex::encode(ciphertext),
+                tag: hex::encode(tag),
+            },
+            storage_path: config.storage_path.clone(),
+            signing_guard_path: config.signing_guard_path.clone(),
+            admin_address: config.admin_address,
+        })
+    }
+
+    pub fn decrypt(&self, password: &Password) -> eyre::Result<NodePrivateConfig> {
+        let encrypted = &self.encrypted_keypair;
+        let salt = hex::decode(&encrypted.salt).wrap_err("Malformed salt")?;
+        let nonce = hex::decode(&encrypted.nonce).wrap_err("Malformed nonce")?;
+        let ciphertext = hex::decode(&encrypted.ciphertext).wrap_err("Malformed ciphertext")?;
+        let tag = hex::decode(&encrypted.tag).wrap_err("Malformed tag")?;
+        if nonce.len() != NONCE_SIZE || tag.len() != TAG_SIZE {
+            bail!("Malformed encrypted key");
+        }
+        let key = encrypted.kdf.derive(password, &salt)?;
+
+        let aad = Self::aad(self.authority, &self.storage_path);
+        let mut plaintext = Zeroizing::new(ciphertext);
+        Aes256Gcm::new(key.as_ref().into())
+            .decrypt_in_place_detached(
+                nonce.as_slice().into(),
+                &aad,
+                &mut plaintext,
+                tag.as_slice().into(),
+            )
+            .map_err(|_| eyre!("Wrong password or corrupted private config"))?;
+        let keypair =
+            Signer::from_bytes(&plaintext).ok_or_else(|| eyre!("Malformed decrypted key"))?;
+        let mut config = NodePrivateConfig::new(self.authority, keypair, self.storage_path.clone());
+        config
+            .signing_guard_path
+            .clone_from(&self.signing_guard_path);
+        config.admin_address = self.admin_address;
+        Ok(config)
+    }
+
+    fn aad(authority: AuthorityIndex, storage_path: &Path) -> Vec<u8> {
+        bincode::serialize(&(authority, storage_path)).expect("Serialization failed")
+    }
+}
+
+impl ImportExport for EncryptedNodePrivateConfig {
+    fn print<P: AsRef<Path>>(&self, path: P) -> Result<(), io::Error> {
+        print_owner_only(self, path)
+    }
+}
+
+impl ScryptParameters {
+    /// Parameters for tests only, deriving keys with them is fast but insecure.
+    pub fn new_for_tests() -> Self {
+        Self {
+            log_n: 4,
+            r: 8,
+            p: 1,
+        }
+    }
+
+    fn derive(&self, password: &Password, salt: &[u8]) -> eyre::Result<Zeroizing<[u8; KEY_SIZE]>> {
+        ensure!(
+            self.log_n <= MAX_SCRYPT_LOG_N && self.r <= MAX_SCRYPT_R && self.p <= MAX_SCRYPT_P,
+            "Scrypt parameters exceed the limits (log_n {MAX_SCRYPT_LOG_N}, r {MAX_SCRYPT_R}, p {MAX_SCRYPT_P})"
+        );
+        // Scrypt needs 128 * r * (n + p) bytes
+        let memory = (1u64 << self.log_n)
+            .checked_add(self.p as u64)
+            .and_then(|blocks| blocks.checked_mul(128 * self.r as u64));
+        ensure!(
+            matches!(memory, Some(memory) if memory <= MAX_SCRYPT_MEMORY),
+            "Scrypt parameters need more than {MAX_SCRYPT_MEMORY} bytes"
+        );
+        let params = scrypt::Params::new(self.log_n, self.r, self.p, KEY_SIZE)
+            .map_err(|_| eyre!("Invalid scrypt parameters"))?;
+        let mut key = Zeroizing::new([0u8; KEY_SIZE]);
+        scrypt::scrypt(&password.0, salt, &params, key.as_mut())
+            .map_err(|_| eyre!("Failed to derive key from password"))?;
+        Ok(key)
+    }
+}
+
+impl Default for ScryptParameters {
+    fn default() -> Self {
+        Self {
+            log_n: 17,
+            r: 8,
+            p: 1,
+        }
+    }
+}
+
+impl Password {
+    pub fn new(password: Vec<u8>) -> Self {
+        Self(Zeroizing::new(password))
+    }
+
+    /// Read the password from an environment variable.
+    pub fn from_env(variable: &str) -> eyre::Result<Self> {
+        let password = env::var_os(variable)
+            .ok_or_else(|| eyre!("Environment variable '{variable}' is not set"))?;
+        Ok(Self::new(password.into_encoded_bytes()))
+    }
+
+    /// Read the password from a file descriptor (such as a pipe) until end of file. A single
+    /// trailing newline is ignored.
+    pub fn from_fd(fd: RawFd) -> eyre::Result<Self> {
+        // Safety: the descriptor is handed to us by the parent process and not used elsewhere.
+        let mut file = unsafe { File::from_raw_fd(fd) };
+        let mut password = Zeroizing::new(Vec::new());
+        file.read_to_end(&mut password)
+            .wrap_err(format!("Failed to read password from file descriptor {fd}"))?;
+        if password.last() == Some(&b'\n') {
+            password.pop();
+        }
+        Ok(Self(password))
+    }
+}
+
+impl NodePrivateConfig {
+    /// Load a private config, decrypting it with the password if it is encrypted.
+    pub fn load_with_password(
+        path: impl AsRef<Path>,
+        password: Option<&Password>,
+    ) -> eyre::Result<Self> {
+        let path = path.as_ref();
+        let content = std::fs::read_to_string(path)
+            .wrap_err(format!("Failed to read '{}'", path.display()))?;
+        let value: serde_yaml::Value =
+            serde_yaml::from_str(&content).wrap_err("Malformed private config")?;
+        if value.get("encrypted_keypair").is_none() {
+            return serde_yaml::from_value(value).wrap_err("Malformed private config");
+        }
+        let Some(password) = password else {
+            bail!("The private config is encrypted but no password was provided");
+        };
+        let encrypted: EncryptedNodePrivateConfig =
+            serde_yaml::from_value(value).wrap_err("Malformed private config")?;
+        encrypted.decrypt(password)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn encrypted_config(password: &Password) -> EncryptedNodePrivateConfig {
+        let config = NodePrivateConfig::generate(1, "storage-1".into());
+        EncryptedNodePrivateConfig::encrypt_with_parameters(
+            &config,
+            password,
+            ScryptParameters::new_for_tests(),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn encrypt_decrypt() {
+        let dir = tempdir::TempDir::new("encrypt_decrypt").unwrap();
+        let config = NodePrivateConfig::generate(1, "storage-1".into());
+        let password = Password::new(b"correct horse".to_vec());
+        let encrypted = EncryptedNodePrivateConfig::encrypt_with_parameters(
+            &config,
+            &password,
+            ScryptParameters::new_for_tests(),
+        )
+        .unwrap();
+        let path = dir.path().join("private-config.yaml");
+        encrypted.print(&path).unwrap();
+
+        let decrypted = NodePrivateConfig::load_with_password(&path, Some(&password)).unwrap();
+        assert_eq!(decrypted.authority(), 1);
+        assert_eq!(decrypted.storage_path, config.storage_path);
+        assert_eq!(
+            decrypted.public_key().unwrap(),
+            config.public_key().unwrap()
+        );
+
+        assert!(NodePrivateConfig::load_with_password(&path, None).is_err());
+
+        // Plaintext configs are still supported
+        let path = dir.path().join("plaintext.yaml");
+        config.print(&path).unwrap();
+        let loaded = NodePrivateConfig::load_with_password(&path, None).unwrap();
+        assert_eq!(loaded.public_key().unwrap(), config.public_key().unwrap());
+    }
+
+    #[test]
+    fn wrong_password() {
+        let encrypted = encrypted_config(&Password::new(b"correct horse".to_vec()));
+        assert!(encrypted
+            .decrypt(&Password::new(b"battery staple".to_vec()))
+            .is_err());
+    }
+
+    #[test]
+    fn tampered_file() {
+        let password = Password::new(b"correct horse".to_vec());
+
+        let mut encrypted = encrypted_config(&password);
+        let mut ciphertext = hex::decode(&encrypted.encrypted_keypair.ciphertext).unwrap();
+        ciphertext[0] ^= 1;
+        encrypted.encrypted_keypair.ciphertext = hex::encode(ciphertext);
+        assert!(encrypted.decrypt(&password).is_err());
+
+        let mut encrypted = encrypted_config(&password);
+        encrypted.authority = 2;
+        assert!(encrypted.decrypt(&password).is_err());
+
+        let mut encrypted = encrypted_config(&password);
+        encrypted.storage_path = "storage-2".into();
+        assert!(encrypted.decrypt(&password).is_err());
+
+        let mut encrypted = encrypted_config(&password);
+        encrypted.encrypted_keypair.tag = hex::encode([0u8; TAG_SIZE]);
+        assert!(encrypted.decrypt(&password).is_err());
+    }
+
+    #[test]
+    fn excessive_scrypt_parameters() {
+        let password = Password::new(b"correct horse".to_vec());
+        for (log_n, r, p) in [
+            (63, 8, 1),
+            (16, u32::MAX, 1),
+            (16, 8, u32::MAX),
+            (20, 32, 1),
+        ] {
+            let mut encrypted = encrypted_config(&password);
+            encrypted.encrypted_keypair.kdf = ScryptParameters { log_n, r, p };
+            let error = encrypted.decrypt(&password).err().unwrap().to_string();
+            assert!(error.contains("Scrypt parameters"), "{error}");
+        }
+    }
+}
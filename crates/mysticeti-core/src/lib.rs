@@ -1,31 +1,51 @@
 // Copyright (c) Mysten Labs, Inc.
 // SPDX-License-Identifier: Apache-2.0
 
+pub mod address_book;
+pub mod admin;
+pub mod batch;
+mod block_cache;
 pub mod block_handler;
 mod block_manager;
-mod block_store;
+pub mod block_signer;
+pub mod block_verifier;
+pub mod block_store;
 pub mod committee;
+pub mod compression;
 pub mod config;
 pub mod consensus;
 pub mod core;
 mod core_thread;
 mod crypto;
-mod data;
+pub mod dag_export;
+pub mod data;
 mod epoch_close;
+pub mod erasure;
 mod finalization_interpreter;
 #[cfg(test)]
 #[cfg(feature = "simulator")]
 mod future_simulator;
+mod index_checkpoint;
+pub mod keystore;
 #[allow(dead_code)] // todo - delete if unused after a while
 mod lock;
 mod log;
 pub mod metrics;
 pub mod net_sync;
 pub mod network;
+pub mod observer;
+pub mod priority;
 pub mod prometheus;
+pub mod protocol;
+mod quic;
 mod range_map;
+pub mod rate_limit;
+pub mod reload;
+pub mod resolver;
 mod runtime;
 mod serde;
+mod signing_guard;
+mod snapshot;
 #[cfg(test)]
 #[cfg(feature = "simulator")]
 mod simulated_network;
@@ -40,7 +60,9 @@ mod synchronizer;
 #[cfg(test)]
 mod test_util;
 mod threshold_clock;
+pub mod topology;
 mod transactions_generator;
 pub mod types;
 pub mod validator;
-mod wal;
+pub mod wal;
+pub mod wal_inspect;
@@ -2,20 +2,23 @@
 // SPDX-License-Identifier: Apache-2.0
 
 use std::{
-    net::SocketAddr,
+    collections::BTreeMap,
     ops::AddAssign,
     sync::{atomic::Ordering, Arc},
     time::Duration,
 };
 
 use prometheus::{
+    core::Collector,
     register_counter_vec_with_registry,
     register_histogram_vec_with_registry,
+    register_histogram_with_registry,
     register_int_counter_vec_with_registry,
     register_int_counter_with_registry,
     register_int_gauge_vec_with_registry,
     register_int_gauge_with_registry,
     CounterVec,
+    Histogram,
     HistogramVec,
     IntCounter,
     IntCounterVec,
@@ -29,6 +32,8 @@ use tokio::time::Instant;
 use crate::{
     committee::Committee,
     data::{IN_MEMORY_BLOCKS, IN_MEMORY_BLOCKS_BYTES},
+    network::NetworkMessage,
+    resolver::PeerAddress,
     runtime,
     stat::{histogram, DivUsize, HistogramSender, PreciseHistogram},
     types::{format_authority_index, AuthorityIndex},
@@ -38,6 +43,15 @@ const LATENCY_SEC_BUCKETS: &[f64] = &[
     0.1, 0.25, 0.5, 0.75, 1., 1.25, 1.5, 1.75, 2., 2.5, 3.0, 4.0, 5., 10., 20., 30., 60., 90.,
 ];
 
+/// Frames of 64 bytes up to 16 MiB.
+const FRAME_SIZE_BYTES_BUCKETS: &[f64] = &[
+    64., 256., 1024., 4096., 16384., 65536., 262144., 1048576., 4194304., 16777216.,
+];
+
+const SIGNING_LATENCY_SEC_BUCKETS: &[f64] = &[
+    0.00005, 0.0001, 0.00025, 0.0005, 0.001, 0.0025, 0.005, 0.01, 0.025, 0.05, 0.1, 0.5, 1.,
+];
+
 /// Metrics collected by the benchmark.
 pub const BENCHMARK_DURATION: &str = "benchmark_duration";
 pub const LATENCY_S: &str = "latency_s";
@@ -51,9 +65,12 @@ pub struct Metrics {
     pub committed_leaders_total: IntCounterVec,
     pub leader_timeout_total: IntCounter,
     pub inter_block_latency_s: HistogramVec,
+    pub block_signing_latency_s: Histogram,
 
     pub block_store_unloaded_blocks: IntCounter,
     pub block_store_loaded_blocks: IntCounter,
+    pub block_store_cache_hits: IntCounter,
+    pub block_store_cache_bytes: IntGauge,
     pub block_store_entries: IntCounter,
     pub block_store_cleanup_util: IntCounter,
 
@@ -71,6 +88,18 @@ pub struct Metrics {
     pub missing_blocks: IntGaugeVec,
     pub block_sync_requests_sent: IntCounterVec,
     pub block_sync_requests_received: IntCounterVec,
+    pub blocks_verified_total: IntCounterVec,
+    pub batches_total: IntCounterVec,
+    pub erasure_coded_blocks_total: IntCounterVec,
+    pub relayed_blocks_total: IntCounter,
+    pub throttled_messages_total: IntCounterVec,
+    pub banned_peers_total: IntCounterVec,
+    pub connection_queue_depth: IntGaugeVec,
+    pub node_parameters: IntGaugeVec,
+    pub network_sent_bytes_total: IntCounterVec,
+    pub network_received_bytes_total: IntCounterVec,
+    pub network_send_blocked_us: IntCounterVec,
+    pub network_frame_size_bytes: HistogramVec,
 
     pub transaction_certified_latency: HistogramSender<Duration>,
     pub certificate_committed_latency: HistogramSender<Duration>,
@@ -225,6 +254,13 @@ impl Metrics {
                 LATENCY_SEC_BUCKETS.to_vec(),
                 registry,
             ).unwrap(),
+            block_signing_latency_s: register_histogram_with_registry!(
+                "block_signing_latency_s",
+                "Buckets measuring the latency of signing own blocks in seconds",
+                SIGNING_LATENCY_SEC_BUCKETS.to_vec(),
+                registry,
+            )
+            .unwrap(),
             submitted_transactions: register_int_counter_with_registry!(
                 "submitted_transactions",
                 "Total number of submitted transactions",
@@ -240,13 +276,25 @@ impl Metrics {
 
             block_store_loaded_blocks: register_int_counter_with_registry!(
                 "block_store_loaded_blocks",
-                "Blocks loaded from wal position in the block store",
+                "Blocks loaded from wal position in the block store (block cache misses)",
                 registry,
             )
             .unwrap(),
             block_store_unloaded_blocks: register_int_counter_with_registry!(
                 "block_store_unloaded_blocks",
-                "Blocks unloaded from wal position during cleanup",
+                "Blocks evicted from the block cache",
+                registry,
+            )
+            .unwrap(),
+            block_store_cache_hits: register_int_counter_with_registry!(
+                "block_store_cache_hits",
+                "Blocks read from the block cache",
+                registry,
+            )
+            .unwrap(),
+            block_store_cache_bytes: register_int_gauge_with_registry!(
+                "block_store_cache_bytes",
+                "Size of the blocks in the block cache",
                 registry,
             )
             .unwrap(),
@@ -330,6 +378,90 @@ impl Metrics {
                 registry,
             )
             .unwrap(),
+            blocks_verified_total: register_int_counter_vec_with_registry!(
+                "blocks_verified_total",
+                "Number of blocks received from the network and verified, either by checking their signature or by the digest linking them to an already verified block",
+                &["method"],
+                registry,
+            )
+            .unwrap(),
+            batches_total: register_int_counter_vec_with_registry!(
+                "batches_total",
+                "Number of transaction batches stored, created by this validator (own), sent by their author (disseminated) or requested for a block (fetched)",
+                &["origin"],
+                registry,
+            )
+            .unwrap(),
+            erasure_coded_blocks_total: register_int_counter_vec_with_registry!(
+                "erasure_coded_blocks_total",
+                "Number of blocks of others received as chunks, by outcome of their reconstruction",
+                &["outcome"],
+                registry,
+            )
+            .unwrap(),
+            relayed_blocks_total: register_int_counter_with_registry!(
+                "relayed_blocks_total",
+                "Number of blocks received from a neighbour and relayed to the other neighbours",
+                registry,
+            )
+            .unwrap(),
+            throttled_messages_total: register_int_counter_vec_with_registry!(
+                "throttled_messages_total",
+                "Number of messages delayed because the peer exceeded its budget, per authority and budget",
+                &["authority", "budget"],
+                registry,
+            )
+            .unwrap(),
+            banned_peers_total: register_int_counter_vec_with_registry!(
+                "banned_peers_total",
+                "Number of times a peer was banned for exceeding its budget, per authority and budget",
+                &["authority", "budget"],
+                registry,
+            )
+            .unwrap(),
+            connection_queue_depth: register_int_gauge_vec_with_registry!(
+                "connection_queue_depth",
+                "Number of messages queued to be sent to the peers, per priority class",
+                &["class"],
+                registry,
+            )
+            .unwrap(),
+            node_parameters: register_int_gauge_vec_with_registry!(
+                "node_parameters",
+                "Values of the node parameters that can be reloaded at runtime (durations in milliseconds)",
+                &["parameter"],
+                registry,
+            )
+            .unwrap(),
+            network_sent_bytes_total: register_int_counter_vec_with_registry!(
+                "network_sent_bytes_total",
+                "Bytes sent to every peer per message type, including the frame headers",
+                &["peer", "message"],
+                registry,
+            )
+            .unwrap(),
+            network_received_bytes_total: register_int_counter_vec_with_registry!(
+                "network_received_bytes_total",
+                "Bytes received from every peer per message type, including the frame headers",
+                &["peer", "message"],
+                registry,
+            )
+            .unwrap(),
+            network_send_blocked_us: register_int_counter_vec_with_registry!(
+                "network_send_blocked_us",
+                "Time spent waiting for every peer to accept the messages sent to it, in microseconds",
+                &["peer"],
+                registry,
+            )
+            .unwrap(),
+            network_frame_size_bytes: register_histogram_vec_with_registry!(
+                "network_frame_size_bytes",
+                "Buckets measuring the size of the frames sent and received in bytes",
+                &["direction"],
+                FRAME_SIZE_BYTES_BUCKETS.to_vec(),
+                registry,
+            )
+            .unwrap(),
 
             utilization_timer: register_int_counter_vec_with_registry!(
                 "utilization_timer",
@@ -352,6 +484,26 @@ impl Metrics {
 
         (Arc::new(metrics), reporter)
     }
+
+    /// Account for a frame of `size` bytes carrying `message`, sent to `peer`.
+    pub fn frame_sent(&self, peer: AuthorityIndex, message: &NetworkMessage, size: usize) {
+        self.network_sent_bytes_total
+            .with_label_values(&[&format_authority_index(peer).to_string(), message.name()])
+            .inc_by(size as u64);
+        self.network_frame_size_bytes
+            .with_label_values(&["sent"])
+            .observe(size as f64);
+    }
+
+    /// Account for a frame of `size` bytes carrying `message`, received from `peer`.
+    pub fn frame_received(&self, peer: AuthorityIndex, message: &NetworkMessage, size: usize) {
+        self.network_received_bytes_total
+            .with_label_values(&[&format_authority_index(peer).to_string(), message.name()])
+            .inc_by(size as u64);
+        self.network_frame_size_bytes
+            .with_label_values(&["received"])
+            .observe(size as f64);
+    }
 }
 
 pub trait AsPrometheusMetric {
@@ -497,7 +649,7 @@ impl MetricReporter {
     }
 }
 
-pub fn print_network_address_table(addresses: &[SocketAddr]) {
+pub fn print_network_address_table(addresses: &[PeerAddress]) {
     let table: Vec<_> = addresses
         .iter()
         .enumerate()
@@ -509,6 +661,67 @@ pub fn print_network_address_table(addresses: &[SocketAddr]) {
     tracing::info!("Network address table:\n{}", Table::new(table));
 }
 
+/// Log the bytes exchanged with every peer per message type, and the time spent waiting for every
+/// peer to accept our messages.
+pub fn print_network_traffic_table(metrics: &Metrics) {
+    let mut rows: BTreeMap<(String, String), NetworkTrafficTable> = BTreeMap::new();
+    let counters = [
+        &metrics.network_sent_bytes_total,
+        &metrics.network_received_bytes_total,
+    ];
+    for (direction, counter) in counters.into_iter().enumerate() {
+        for metric in counter
+            .collect()
+            .iter()
+            .flat_map(|family| family.get_metric())
+        {
+            let label = |name| {
+                metric
+                    .get_label()
+                    .iter()
+                    .find(|pair| pair.get_name() == name)
+                    .map(|pair| pair.get_value().to_string())
+                    .unwrap_or_default()
+            };
+            let (peer, message) = (label("peer"), label("message"));
+            let row = rows
+                .entry((peer.clone(), message.clone()))
+                .or_insert_with(|| NetworkTrafficTable {
+                    peer,
+                    message,
+                    sent_bytes: 0,
+                    received_bytes: 0,
+                });
+            let bytes = metric.get_counter().get_value() as u64;
+            if direction == 0 {
+                row.sent_bytes = bytes;
+            } else {
+                row.received_bytes = bytes;
+            }
+        }
+    }
+    tracing::info!("Network traffic table:\n{}", Table::new(rows.into_values()));
+
+    let blocked: Vec<_> = metrics
+        .network_send_blocked_us
+        .collect()
+        .iter()
+        .flat_map(|family| family.get_metric())
+        .map(|metric| NetworkBlockedTable {
+            peer: metric
+                .get_label()
+                .first()
+                .map(|pair| pair.get_value().to_string())
+                .unwrap_or_default(),
+            blocked: format!(
+                "{:?}",
+                Duration::from_micros(metric.get_counter().get_value() as u64)
+            ),
+        })
+        .collect();
+    tracing::info!("Network send blocked table:\n{}", Table::new(blocked));
+}
+
 pub trait UtilizationTimerExt {
     fn utilization_timer(&self) -> UtilizationTimer;
     fn owned_utilization_timer(&self) -> OwnedUtilizationTimer;
@@ -567,3 +780,17 @@ struct NetworkAddressTable {
     peer: char,
     address: String,
 }
+
+#[derive(Tabled)]
+struct NetworkTrafficTable {
+    peer: String,
+    message: String,
+    sent_bytes: u64,
+    received_bytes: u64,
+}
+
+#[derive(Tabled)]
+struct NetworkBlockedTable {
+    peer: String,
+    blocked: String,
+}
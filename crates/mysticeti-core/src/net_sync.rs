@@ -3,6 +3,7 @@
 
 use std::{
     collections::HashMap,
+    io,
     sync::{
         atomic::{AtomicU64, Ordering},
         Arc,
@@ -10,30 +11,59 @@ use std::{
     time::Duration,
 };
 
+use axum::Router;
 use futures::future::join_all;
+use parking_lot::Mutex;
 use tokio::{
     select,
-    sync::{mpsc, oneshot, Notify},
+    sync::{mpsc, oneshot, watch, Notify},
 };
 
 use crate::{
+    address_book::AddressBook,
+    admin::{self, MissingBlocks, NodeStatus, PeerStatus},
+    batch::{
+        AwaitBatches,
+        Batch,
+        BatchAvailability,
+        BatchFetcher,
+        BatchQuota,
+        BatchWorker,
+        BATCH_REQUEST_TIMEOUT,
+    },
     block_handler::BlockHandler,
     block_store::BlockStore,
+    block_verifier::BlockVerifier,
     committee::Committee,
-    config::NodePublicConfig,
+    config::{NodeParameters, NodePublicConfig},
     core::Core,
     core_thread::CoreThreadDispatcher,
+    dag_export::{DagExport, DagExporter},
+    data::Data,
+    erasure::{ChunkOutcome, ChunkRelay},
     metrics::Metrics,
     network::{Connection, Network, NetworkMessage},
+    rate_limit::{Admission, BanKey, PeerBans, PeerLimiter, RateLimits},
+    reload::LiveParameters,
+    resolver::PeerAddress,
     runtime::{self, timestamp_utc, Handle, JoinError, JoinHandle},
+    signing_guard::{own_block_round, OwnRoundAggregator},
+    snapshot::SnapshotAggregator,
     syncer::{CommitObserver, Syncer, SyncerSignals},
     synchronizer::{BlockDisseminator, BlockFetcher, SynchronizerParameters},
-    types::{format_authority_index, AuthorityIndex},
+    topology::{BlockRelay, OwnRoundStatement},
+    types::{format_authority_index, AuthorityIndex, RoundNumber, StatementBlock, Transaction},
     wal::WalSyncer,
 };
 
 /// The maximum number of blocks that can be requested in a single message.
 pub const MAXIMUM_BLOCK_REQUEST: usize = 10;
+/// The maximum number of batches that can be requested in a single message.
+pub const MAXIMUM_BATCH_REQUEST: usize = 100;
+/// The delay before asking a peer for its snapshot again when peers do not agree on a snapshot.
+const SNAPSHOT_RETRY_DELAY: Duration = Duration::from_secs(1);
+/// The delay before relaying our own round request again, while less than a quorum answered.
+const OWN_ROUND_TIMEOUT: Duration = Duration::from_secs(2);
 
 pub struct NetworkSyncer<H: BlockHandler, C: CommitObserver> {
     inner: Arc<NetworkSyncerInner<H, C>>,
@@ -46,10 +76,35 @@ pub struct NetworkSyncerInner<H: BlockHandler, C: CommitObserver> {
     pub syncer: CoreThreadDispatcher<H, Arc<Notify>, C>,
     pub block_store: BlockStore,
     pub notify: Arc<Notify>,
+    authority: AuthorityIndex,
     committee: Arc<Committee>,
+    block_verifier: BlockVerifier,
+    // Set when our transactions are disseminated in batches
+    batch_availability: Option<BatchAvailability>,
+    // The blocks waiting for their batches
+    batch_fetcher: BatchFetcher,
+    batch_quota: BatchQuota,
+    // Set when large own blocks are erasure-coded
+    pub chunk_relay: Option<ChunkRelay>,
+    // Set when validators are not all connected to each other, see `topology`
+    block_relay: Option<BlockRelay>,
+    address_book: AddressBook,
+    parameters: LiveParameters,
+    metrics: Arc<Metrics>,
+    rate_limits: RateLimits,
+    peer_bans: PeerBans,
     stop: mpsc::Sender<()>,
     epoch_close_signal: mpsc::Sender<()>,
     pub epoch_closing_time: Arc<AtomicU64>,
+    snapshot_aggregator: Mutex<SnapshotAggregator>,
+    // Set once the validator may subscribe to blocks (after installing a snapshot, if needed)
+    state_synced: watch::Sender<bool>,
+    // Collects the highest round of our own blocks known to the peers, until enough responded
+    own_round_aggregator: Mutex<Option<OwnRoundAggregator>>,
+    // The nonce of our first own round request relayed through the overlay, see `topology`
+    own_round_nonce: u64,
+    // Notified when an operator asks the node to stop, see `admin::SHUTDOWN_ROUTE`
+    shutdown_request: Notify,
 }
 
 impl<H: BlockHandler + 'static, C: CommitObserver + 'static> NetworkSyncer<H, C> {
@@ -68,7 +123,49 @@ impl<H: BlockHandler + 'static, C: CommitObserver + 'static> NetworkSyncer<H, C>
         // todo - ugly, probably need to merge syncer and core
         let (committed, state) = core.take_recovered_committed_blocks();
         commit_observer.recover_committed(committed, state);
+        if let Some(frontier) = core.snapshot_frontier() {
+            commit_observer.recover_snapshot_frontier(frontier);
+        }
+        // Validators joining with an empty store start from the snapshot of their peers
+        let state_sync =
+            public_config.parameters.enable_state_sync && core.block_store().highest_round() == 0;
         let committee = core.committee().clone();
+        // Validators do not propose before learning which of their blocks the peers know, in case
+        // they lost their storage
+        let own_round_aggregator = if core.is_observer() {
+            None
+        } else {
+            Some(OwnRoundAggregator::new(
+                authority_index,
+                core.last_proposed(),
+                &committee,
+            ))
+            .filter(|aggregator| !aggregator.complete())
+        };
+        let (batch_transactions, batch_availability) =
+            match core.block_handler_mut().take_batch_worker() {
+                Some(BatchWorker {
+                    transactions,
+                    available,
+                }) => (
+                    Some(transactions),
+                    Some(BatchAvailability::new(
+                        committee.clone(),
+                        authority_index,
+                        available,
+                    )),
+                ),
+                None => (None, None),
+            };
+        let chunk_relay = public_config
+            .parameters
+            .erasure_coding_threshold
+            .map(|threshold| ChunkRelay::new(&committee, authority_index, threshold));
+        let block_relay = public_config
+            .parameters
+            .topology
+            .relays(committee.len())
+            .then(BlockRelay::default);
         let wal_syncer = core.wal_syncer();
         let block_store = core.block_store().clone();
         let epoch_closing_time = core.epoch_closing_time();
@@ -79,6 +176,12 @@ impl<H: BlockHandler + 'static, C: CommitObserver + 'static> NetworkSyncer<H, C>
             commit_observer,
             metrics.clone(),
         );
+        if state_sync {
+            syncer.await_snapshot();
+        }
+        if own_round_aggregator.is_some() {
+            syncer.await_own_round();
+        }
         syncer.force_new_block(0);
         let syncer = CoreThreadDispatcher::start(syncer);
         let (stop_sender, stop_receiver) = mpsc::channel(1);
@@ -89,16 +192,38 @@ impl<H: BlockHandler + 'static, C: CommitObserver + 'static> NetworkSyncer<H, C>
             notify,
             syncer,
             block_store,
+            authority: authority_index,
+            block_verifier: BlockVerifier::start(
+                committee.clone(),
+                public_config.parameters.block_verification_threads,
+                metrics.clone(),
+            ),
+            batch_availability,
+            batch_fetcher: Default::default(),
+            batch_quota: Default::default(),
+            chunk_relay,
+            block_relay,
+            address_book: network.address_book().clone(),
+            parameters: LiveParameters::new(public_config.parameters.clone(), metrics.clone()),
+            metrics: metrics.clone(),
+            rate_limits: public_config.parameters.rate_limits.clone(),
+            peer_bans: PeerBans::new(&public_config.parameters.rate_limits),
             committee,
             stop: stop_sender.clone(),
             epoch_close_signal: epoch_sender.clone(),
             epoch_closing_time,
+            snapshot_aggregator: Mutex::new(SnapshotAggregator::new()),
+            state_synced: watch::channel(!state_sync).0,
+            own_round_aggregator: Mutex::new(own_round_aggregator),
+            // Nonces only need to grow across restarts
+            own_round_nonce: timestamp_utc().as_millis() as u64,
+            shutdown_request: Notify::new(),
         });
         let block_fetcher = Arc::new(BlockFetcher::start(
             authority_index,
             inner.clone(),
             metrics.clone(),
-            public_config.parameters.enable_synchronizer,
+            inner.parameters.subscribe(),
         ));
         let main_task = handle.spawn(Self::run(
             network,
@@ -107,7 +232,8 @@ impl<H: BlockHandler + 'static, C: CommitObserver + 'static> NetworkSyncer<H, C>
             shutdown_grace_period,
             block_fetcher,
             metrics.clone(),
-            public_config.parameters.leader_timeout,
+            inner.parameters.subscribe(),
+            batch_transactions,
         ));
         let syncer_task = AsyncWalSyncer::start(wal_syncer, stop_sender, epoch_sender);
         Self {
@@ -118,9 +244,35 @@ impl<H: BlockHandler + 'static, C: CommitObserver + 'static> NetworkSyncer<H, C>
         }
     }
 
+    pub fn address_book(&self) -> &AddressBook {
+        &self.inner.address_book
+    }
+
+    /// The parameters applied at runtime, see `reload`.
+    pub fn parameters(&self) -> &LiveParameters {
+        &self.inner.parameters
+    }
+
+    /// The routes reporting the state of the node to the operators, see `admin`.
+    pub fn admin_routes(&self) -> Router {
+        admin::routes(Arc::downgrade(&self.inner))
+    }
+
+    /// Wait until an operator asks the node to stop. The caller is expected to `shutdown` the
+    /// node then.
+    pub async fn await_shutdown_request(&self) {
+        self.inner.shutdown_request.notified().await
+    }
+
+    /// Announce to the peers that we accept connections on `address` from now on. The peers keep
+    /// connecting to the address they know until they receive the announcement, so the validator
+    /// should keep listening on its current address for a little while.
+    pub async fn announce_address(&self, address: PeerAddress) -> io::Result<()> {
+        self.inner.announce_address(address).await
+    }
+
     pub async fn shutdown(self) -> Syncer<H, Arc<Notify>, C> {
         drop(self.stop);
-        // todo - wait for network shutdown as well
         self.main_task.await.ok();
         self.syncer_task.await.ok();
         let Ok(inner) = Arc::try_unwrap(self.inner) else {
@@ -129,6 +281,7 @@ impl<H: BlockHandler + 'static, C: CommitObserver + 'static> NetworkSyncer<H, C>
         inner.syncer.stop()
     }
 
+    #[allow(clippy::too_many_arguments)]
     async fn run(
         mut network: Network,
         inner: Arc<NetworkSyncerInner<H, C>>,
@@ -136,7 +289,8 @@ impl<H: BlockHandler + 'static, C: CommitObserver + 'static> NetworkSyncer<H, C>
         shutdown_grace_period: Duration,
         block_fetcher: Arc<BlockFetcher>,
         metrics: Arc<Metrics>,
-        leader_timeout: Duration,
+        parameters: watch::Receiver<NodeParameters>,
+        batch_transactions: Option<mpsc::Receiver<Vec<Transaction>>>,
     ) {
         let mut connections: HashMap<usize, JoinHandle<Option<()>>> = HashMap::new();
         let handle = Handle::current();
@@ -144,9 +298,18 @@ impl<H: BlockHandler + 'static, C: CommitObserver + 'static> NetworkSyncer<H, C>
             inner.clone(),
             epoch_close_signal,
             shutdown_grace_period,
-            leader_timeout,
+            parameters,
         ));
         let cleanup_task = handle.spawn(Self::cleanup_task(inner.clone()));
+        let batch_fetch_task = handle.spawn(Self::batch_fetch_task(inner.clone()));
+        let own_round_task = handle.spawn(Self::own_round_task(inner.clone()));
+        let batch_task = batch_transactions.map(|transactions| {
+            handle.spawn(Self::batch_task(
+                inner.clone(),
+                transactions,
+                metrics.clone(),
+            ))
+        });
         while let Some(connection) = inner.recv_or_stopped(network.connection_receiver()).await {
             let peer_id = connection.peer_id;
             if let Some(task) = connections.remove(&peer_id) {
@@ -156,7 +319,14 @@ impl<H: BlockHandler + 'static, C: CommitObserver + 'static> NetworkSyncer<H, C>
 
             let sender = connection.sender.clone();
             let authority = peer_id as AuthorityIndex;
-            block_fetcher.register_authority(authority, sender).await;
+            if inner.peer_bans.is_banned(&connection) {
+                tracing::debug!("Dropping connection from banned peer {authority}");
+                continue;
+            }
+            // Observers are not part of the committee and can not serve missing blocks
+            if inner.committee.known_authority(authority) {
+                block_fetcher.register_authority(authority, sender).await;
+            }
 
             let task = handle.spawn(Self::connection_task(
                 connection,
@@ -169,13 +339,20 @@ impl<H: BlockHandler + 'static, C: CommitObserver + 'static> NetworkSyncer<H, C>
         join_all(
             connections
                 .into_values()
-                .chain([leader_timeout_task, cleanup_task].into_iter()),
+                .chain([
+                    leader_timeout_task,
+                    cleanup_task,
+                    batch_fetch_task,
+                    own_round_task,
+                ])
+                .chain(batch_task),
         )
             .await;
         Arc::try_unwrap(block_fetcher)
             .unwrap_or_else(|_| panic!("Failed to drop all connections"))
             .shutdown()
             .await;
+        network.shutdown().await;
     }
 
     async fn connection_task(
@@ -184,34 +361,140 @@ impl<H: BlockHandler + 'static, C: CommitObserver + 'static> NetworkSyncer<H, C>
         block_fetcher: Arc<BlockFetcher>,
         metrics: Arc<Metrics>,
     ) -> Option<()> {
-        let last_seen = inner
-            .block_store
-            .last_seen_by_authority(connection.peer_id as AuthorityIndex);
-        connection
-            .sender
-            .send(NetworkMessage::SubscribeOwnFrom(last_seen))
-            .await
-            .ok()?;
+        let id = connection.peer_id as AuthorityIndex;
+        // Observers only consume blocks: we neither subscribe to them nor accept their blocks
+        let peer_is_observer = !inner.committee.known_authority(id);
+        let mut state_synced = inner.state_synced.subscribe();
+        // We subscribe to the blocks of the peer once our state is synced
+        let mut subscribed = peer_is_observer;
+        if !peer_is_observer && !*state_synced.borrow_and_update() {
+            connection
+                .sender
+                .send(NetworkMessage::RequestSnapshot)
+                .await
+                .ok()?;
+        }
+        if !peer_is_observer && inner.own_round_aggregator.lock().is_some() {
+            connection
+                .sender
+                .send(NetworkMessage::RequestOwnRound)
+                .await
+                .ok()?;
+        }
 
         let mut disseminator = BlockDisseminator::new(
-            connection.sender.clone(),
+            &connection,
             inner.clone(),
             SynchronizerParameters::default(),
             metrics.clone(),
         );
 
-        let id = connection.peer_id as AuthorityIndex;
-        inner.syncer.authority_connection(id, true).await;
-
+        if !peer_is_observer {
+            inner.syncer.authority_connection(id, true).await;
+        }
+        if let (false, Some(batch_availability)) = (peer_is_observer, &inner.batch_availability) {
+            for batch in batch_availability.register_peer(id, connection.sender.clone()) {
+                connection
+                    .sender
+                    .send(NetworkMessage::Batch(batch))
+                    .await
+                    .ok()?;
+            }
+        }
+        if !peer_is_observer {
+            inner
+                .batch_fetcher
+                .register_peer(id, connection.sender.clone());
+        }
+        if let (false, Some(chunk_relay)) = (peer_is_observer, &inner.chunk_relay) {
+            chunk_relay.register_peer(id, connection.sender.clone());
+        }
+        if let (false, Some(block_relay)) = (peer_is_observer, &inner.block_relay) {
+            let request = block_relay.register_peer(id, connection.sender.clone());
+            if let Some(request) = request {
+                connection
+                    .sender
+                    .send(NetworkMessage::RelayedOwnRound(request))
+                    .await
+                    .ok()?;
+            }
+        }
+        for update in inner
+            .address_book
+            .register_peer(id, connection.sender.clone())
+        {
+            connection
+                .sender
+                .send(NetworkMessage::AddressUpdate(update))
+                .await
+                .ok()?;
+        }
         let peer = format_authority_index(id);
-        while let Some(message) = inner.recv_or_stopped(&mut connection.receiver).await {
+        let mut limiter = PeerLimiter::new(&inner.rate_limits, inner.committee.len(), id);
+        loop {
+            if !subscribed && *state_synced.borrow_and_update() {
+                let last_seen = inner.block_store.last_seen_by_authority(id);
+                if connection
+                    .sender
+                    .send(NetworkMessage::SubscribeOwnFrom(last_seen))
+                    .await
+                    .is_err()
+                {
+                    break;
+                }
+                subscribed = true;
+            }
+            let message = select! {
+                message = inner.recv_or_stopped(&mut connection.receiver) => message,
+                _synced = state_synced.changed(), if !subscribed => continue,
+            };
+            let Some(message) = message else {
+                break;
+            };
+            match limiter.admit(&message) {
+                Admission::Accept => {}
+                Admission::Throttle(budget, delay) => {
+                    metrics
+                        .throttled_messages_total
+                        .with_label_values(&[&peer.to_string(), budget.name()])
+                        .inc();
+                    select! {
+                        _throttled = runtime::sleep(delay) => {}
+                        _stopped = inner.stopped() => break,
+                    }
+                }
+                Admission::Ban(budget) => {
+                    let ban = BanKey::of(&connection);
+                    let duration = inner.peer_bans.ban(ban);
+                    tracing::warn!(
+                        "Banned {} ({}) for {:?} after exceeding its budget of {}",
+                        peer,
+                        ban,
+                        duration,
+                        budget.name()
+                    );
+                    metrics
+                        .banned_peers_total
+                        .with_label_values(&[&peer.to_string(), budget.name()])
+                        .inc();
+                    break;
+                }
+            }
             match message {
                 NetworkMessage::SubscribeOwnFrom(round) => {
                     disseminator.disseminate_own_blocks(round).await
                 }
                 NetworkMessage::Block(block) => {
+                    if peer_is_observer {
+                        tracing::warn!(
+                            "Rejected block {} from observer {}",
+                            block.reference(),
+                            peer
+                        );
+                        break;
+                    }
                     tracing::debug!("Received {} from {}", block.reference(), peer);
-                    if let Err(e) = block.verify(&inner.committee) {
+                    if let Err(e) = inner.block_verifier.verify(block.clone()).await {
                         tracing::warn!(
                             "Rejected incorrect block {} from {}: {:?}",
                             block.reference(),
@@ -221,7 +504,27 @@ impl<H: BlockHandler + 'static, C: CommitObserver + 'static> NetworkSyncer<H, C>
                         // Terminate connection upon receiving incorrect block.
                         break;
                     }
-                    inner.syncer.add_blocks(vec![block]).await;
+                    if let Some(block_relay) = &inner.block_relay {
+                        if !inner.block_store.block_exists(*block.reference()) {
+                            let neighbours = block_relay.relay_to(block.reference(), id);
+                            if !neighbours.is_empty() {
+                                metrics.relayed_blocks_total.inc();
+                            }
+                            Self::relay(neighbours, block.clone(), NetworkMessage::Block);
+                        }
+                    }
+                    if Self::request_lost_own_blocks(&inner, &connection.sender, &block)
+                        .await
+                        .is_none()
+                    {
+                        break;
+                    }
+                    if Self::add_verified_block(&inner, &connection.sender, id, block)
+                        .await
+                        .is_none()
+                    {
+                        break;
+                    }
                 }
                 NetworkMessage::RequestBlocks(references) => {
                     if references.len() > MAXIMUM_BLOCK_REQUEST {
@@ -240,22 +543,474 @@ impl<H: BlockHandler + 'static, C: CommitObserver + 'static> NetworkSyncer<H, C>
                 NetworkMessage::BlockNotFound(_references) => {
                     // TODO: leverage this signal to request blocks from other peers
                 }
+                NetworkMessage::RequestSnapshot => {
+                    let snapshot = inner.syncer.snapshot().await;
+                    if connection
+                        .sync_sender
+                        .send(NetworkMessage::Snapshot(snapshot))
+                        .await
+                        .is_err()
+                    {
+                        break;
+                    }
+                }
+                NetworkMessage::Snapshot(snapshot) => {
+                    if peer_is_observer || *inner.state_synced.borrow() {
+                        continue;
+                    }
+                    let agreed = inner
+                        .snapshot_aggregator
+                        .lock()
+                        .add(id, snapshot, &inner.committee);
+                    match agreed {
+                        Err(e) => {
+                            tracing::warn!("Rejected incorrect snapshot from {}: {:?}", peer, e);
+                            // Terminate connection upon receiving incorrect snapshot.
+                            break;
+                        }
+                        Ok(Some(snapshot)) => {
+                            inner.syncer.install_snapshot(snapshot).await;
+                            inner.state_synced.send_replace(true);
+                        }
+                        Ok(None) => {
+                            // Not enough peers agree yet, ask again later
+                            let sender = connection.sender.clone();
+                            Handle::current().spawn(async move {
+                                runtime::sleep(SNAPSHOT_RETRY_DELAY).await;
+                                sender.send(NetworkMessage::RequestSnapshot).await.ok();
+                            });
+                        }
+                    }
+                }
+                NetworkMessage::RequestOwnRound => {
+                    if peer_is_observer {
+                        break;
+                    }
+                    let block = Self::latest_block(&inner, id);
+                    if connection
+                        .sender
+                        .send(NetworkMessage::OwnBlock(block))
+                        .await
+                        .is_err()
+                    {
+                        break;
+                    }
+                }
+                NetworkMessage::OwnBlock(block) => {
+                    if peer_is_observer {
+                        continue;
+                    }
+                    let round = match &block {
+                        Some(block) => {
+                            match own_block_round(inner.authority, block, &inner.committee) {
+                                Ok(round) => round,
+                                Err(e) => {
+                                    tracing::warn!(
+                                        "Rejected incorrect own block {:?} from {}: {:?}",
+                                        block,
+                                        peer,
+                                        e
+                                    );
+                                    break;
+                                }
+                            }
+                        }
+                        // The peer knows none of our blocks
+                        None => 0,
+                    };
+                    Self::own_round_reported(&inner, id, round).await;
+                }
+                NetworkMessage::RelayedOwnRound(signed) => {
+                    if peer_is_observer {
+                        tracing::warn!("Rejected {:?} from observer {}", signed, peer);
+                        break;
+                    }
+                    let Some(block_relay) = &inner.block_relay else {
+                        tracing::warn!("Ignored {:?} from {}", signed, peer);
+                        continue;
+                    };
+                    if let Err(e) = signed.verify(&inner.committee) {
+                        tracing::warn!("Rejected incorrect {:?} from {}: {:?}", signed, peer, e);
+                        // Terminate connection upon receiving incorrect statement.
+                        break;
+                    }
+                    let reported_round = match signed.reported_round(&inner.committee) {
+                        Ok(round) => round,
+                        Err(e) => {
+                            tracing::warn!(
+                                "Rejected incorrect {:?} from {}: {:?}",
+                                signed,
+                                peer,
+                                e
+                            );
+                            break;
+                        }
+                    };
+                    let Some(neighbours) = block_relay.relay_own_round(&signed.statement, id)
+                    else {
+                        continue;
+                    };
+                    Self::relay(neighbours, signed.clone(), NetworkMessage::RelayedOwnRound);
+                    match (signed.statement, reported_round) {
+                        (OwnRoundStatement::Request { requester, nonce }, _)
+                            if requester != inner.authority
+                                && inner.committee.known_authority(inner.authority) =>
+                        {
+                            let block = Self::latest_block(&inner, requester);
+                            let report = OwnRoundStatement::Report {
+                                requester,
+                                nonce,
+                                responder: inner.authority,
+                                latest: block.as_ref().map(|block| *block.reference()),
+                            };
+                            let signed = match inner.syncer.sign_own_round(report).await {
+                                Ok(signed) => signed.with_latest_block(block),
+                                Err(e) => {
+                                    tracing::warn!("Failed to sign {:?}: {}", report, e);
+                                    continue;
+                                }
+                            };
+                            let neighbours = block_relay
+                                .relay_own_round(&report, inner.authority)
+                                .unwrap_or_default();
+                            Self::relay(neighbours, signed, NetworkMessage::RelayedOwnRound);
+                        }
+                        // Reports answering any of our requests since we started are fresh
+                        (
+                            OwnRoundStatement::Report {
+                                requester,
+                                nonce,
+                                responder,
+                                ..
+                            },
+                            Some(round),
+                        ) if requester == inner.authority && nonce >= inner.own_round_nonce => {
+                            Self::own_round_reported(&inner, responder, round).await;
+                        }
+                        _ => {}
+                    }
+                }
+                NetworkMessage::Batch(batch) => {
+                    if peer_is_observer {
+                        tracing::warn!("Rejected batch {:?} from observer {}", batch, peer);
+                        break;
+                    }
+                    let disseminated = batch.author() == id;
+                    let requested = inner.batch_fetcher.is_missing(batch.digest());
+                    if !disseminated && !requested {
+                        tracing::warn!("Ignored unexpected batch {:?} from {}", batch, peer);
+                        continue;
+                    }
+                    if let Err(e) = batch.verify(&inner.committee) {
+                        tracing::warn!(
+                            "Rejected incorrect batch {:?} from {}: {:?}",
+                            batch,
+                            peer,
+                            e
+                        );
+                        // Terminate connection upon receiving incorrect batch.
+                        break;
+                    }
+                    if disseminated
+                        && !requested
+                        && !inner.batch_quota.admit(&batch, timestamp_utc())
+                    {
+                        // Not acknowledged, the author sends it again once we reconnect
+                        tracing::warn!("Ignored batch {:?} exceeding the quota of {}", batch, peer);
+                        continue;
+                    }
+                    let digest = *batch.digest();
+                    inner.syncer.add_batches(vec![batch]).await;
+                    let origin = if disseminated {
+                        "disseminated"
+                    } else {
+                        "fetched"
+                    };
+                    metrics.batches_total.with_label_values(&[origin]).inc();
+                    if disseminated
+                        && connection
+                            .sender
+                            .send(NetworkMessage::BatchAck(digest))
+                            .await
+                            .is_err()
+                    {
+                        break;
+                    }
+                    let ready = inner
+                        .batch_fetcher
+                        .batch_stored(&digest, |digest| inner.block_store.contains_batch(digest));
+                    if !ready.is_empty() {
+                        inner.syncer.add_blocks(ready).await;
+                    }
+                }
+                NetworkMessage::BatchAck(digest) => {
+                    if let (false, Some(batch_availability)) =
+                        (peer_is_observer, &inner.batch_availability)
+                    {
+                        batch_availability.acknowledge(id, digest);
+                    }
+                }
+                NetworkMessage::Chunk(chunk) => {
+                    if peer_is_observer {
+                        tracing::warn!("Rejected chunk {:?} from observer {}", chunk, peer);
+                        break;
+                    }
+                    let Some(chunk_relay) = &inner.chunk_relay else {
+                        tracing::warn!("Ignored chunk {:?} from {}", chunk, peer);
+                        continue;
+                    };
+                    // The author sends us our chunk, the other validators forward their own chunk
+                    let author = chunk.reference().authority;
+                    let index = if author == id {
+                        chunk_relay.authority()
+                    } else {
+                        id
+                    };
+                    if let Err(e) = chunk_relay.verify(&chunk, index) {
+                        tracing::warn!(
+                            "Rejected incorrect chunk {:?} from {}: {:?}",
+                            chunk,
+                            peer,
+                            e
+                        );
+                        // Terminate connection upon receiving incorrect chunk.
+                        break;
+                    }
+                    if author == chunk_relay.authority()
+                        || inner.block_store.block_exists(*chunk.reference())
+                    {
+                        continue;
+                    }
+                    let forward = (author == id).then(|| chunk.clone());
+                    let reference = *chunk.reference();
+                    let outcome =
+                        chunk_relay.add_chunk(chunk, id, inner.block_store.highest_round());
+                    if matches!(outcome, ChunkOutcome::Rejected) {
+                        continue;
+                    }
+                    if let Some(chunk) = forward {
+                        for sender in chunk_relay.forward_to(author) {
+                            sender.send(NetworkMessage::Chunk(chunk.clone())).await.ok();
+                        }
+                    }
+                    let block = match outcome {
+                        ChunkOutcome::Rejected | ChunkOutcome::Late | ChunkOutcome::Pending => {
+                            continue
+                        }
+                        ChunkOutcome::Reconstructed(Ok(block)) => block,
+                        ChunkOutcome::Reconstructed(Err(e)) => {
+                            // The block is fetched in full once referenced by other blocks
+                            tracing::warn!(
+                                "Failed to reconstruct {} from chunks: {:?}",
+                                reference,
+                                e
+                            );
+                            metrics
+                                .erasure_coded_blocks_total
+                                .with_label_values(&["failed"])
+                                .inc();
+                            continue;
+                        }
+                    };
+                    if let Err(e) = inner.block_verifier.verify(block.clone()).await {
+                        // The author is at fault, not the peer completing the block
+                        tracing::warn!(
+                            "Rejected incorrect block {} from chunks: {:?}",
+                            reference,
+                            e
+                        );
+                        metrics
+                            .erasure_coded_blocks_total
+                            .with_label_values(&["failed"])
+                            .inc();
+                        continue;
+                    }
+                    metrics
+                        .erasure_coded_blocks_total
+                        .with_label_values(&["reconstructed"])
+                        .inc();
+                    if Self::add_verified_block(&inner, &connection.sender, id, block)
+                        .await
+                        .is_none()
+                    {
+                        break;
+                    }
+                }
+                NetworkMessage::AddressUpdate(update) => {
+                    if let Err(e) = update.verify(&inner.committee) {
+                        tracing::warn!(
+                            "Rejected incorrect address update {:?} from {}: {:?}",
+                            update,
+                            peer,
+                            e
+                        );
+                        // Terminate connection upon receiving incorrect address update.
+                        break;
+                    }
+                    for sender in inner.address_book.apply(update.clone(), id) {
+                        sender
+                            .send(NetworkMessage::AddressUpdate(update.clone()))
+                            .await
+                            .ok();
+                    }
+                }
+                NetworkMessage::RequestBatches(digests) => {
+                    if digests.len() > MAXIMUM_BATCH_REQUEST {
+                        // Terminate connection on receiving invalid message.
+                        break;
+                    }
+                    let mut sent = true;
+                    for digest in digests {
+                        if let Some(batch) = inner.block_store.get_batch(&digest) {
+                            sent &= connection
+                                .sync_sender
+                                .send(NetworkMessage::Batch(batch))
+                                .await
+                                .is_ok();
+                        }
+                    }
+                    if !sent {
+                        break;
+                    }
+                }
             }
         }
-        inner.syncer.authority_connection(id, false).await;
+        if let Some(batch_availability) = &inner.batch_availability {
+            batch_availability.remove_peer(id);
+        }
+        inner.batch_fetcher.remove_peer(id);
+        if let Some(chunk_relay) = &inner.chunk_relay {
+            chunk_relay.remove_peer(id);
+        }
+        if let Some(block_relay) = &inner.block_relay {
+            block_relay.remove_peer(id);
+        }
+        inner.address_book.remove_peer(id);
+        if !peer_is_observer {
+            inner.syncer.authority_connection(id, false).await;
+        }
         disseminator.shutdown().await;
-        block_fetcher.remove_authority(id).await;
+        if !peer_is_observer {
+            block_fetcher.remove_authority(id).await;
+        }
         None
     }
 
+    /// Count the highest round of our own blocks known to `peer`, and resume proposing once
+    /// enough peers reported it.
+    async fn own_round_reported(
+        inner: &NetworkSyncerInner<H, C>,
+        peer: AuthorityIndex,
+        round: RoundNumber,
+    ) {
+        let highest_round = {
+            let mut aggregator = inner.own_round_aggregator.lock();
+            let highest_round = aggregator
+                .as_mut()
+                .and_then(|aggregator| aggregator.add(peer, round, &inner.committee));
+            if highest_round.is_some() {
+                *aggregator = None;
+            }
+            highest_round
+        };
+        if let Some(highest_round) = highest_round {
+            if let Some(block_relay) = &inner.block_relay {
+                block_relay.set_own_request(None);
+            }
+            inner.syncer.resume_above_own_round(highest_round).await;
+        }
+    }
+
+    /// Add a verified block to the dag, or first request its missing batches from the peer.
+    async fn add_verified_block(
+        inner: &NetworkSyncerInner<H, C>,
+        sender: &mpsc::Sender<NetworkMessage>,
+        peer: AuthorityIndex,
+        block: Data<StatementBlock>,
+    ) -> Option<()> {
+        inner.batch_quota.referenced(&block);
+        let stored = |digest: &_| inner.block_store.contains_batch(digest);
+        let missing = match inner
+            .batch_fetcher
+            .add(block.clone(), peer, timestamp_utc(), stored)
+        {
+            AwaitBatches::Ready => {
+                inner.syncer.add_blocks(vec![block]).await;
+                return Some(());
+            }
+            AwaitBatches::Request(missing) => missing,
+            AwaitBatches::Rejected => {
+                // The block is fetched again once referenced by other blocks
+                tracing::debug!(
+                    "Dropped {}, too many blocks of its author wait for their batches",
+                    block.reference()
+                );
+                return Some(());
+            }
+        };
+        // The peer sent us the block (or completed its chunks), so likely stores its batches
+        for chunk in missing.chunks(MAXIMUM_BATCH_REQUEST) {
+            sender
+                .send(NetworkMessage::RequestBatches(chunk.to_vec()))
+                .await
+                .ok()?;
+        }
+        Some(())
+    }
+
+    /// No peer streams our own blocks back to us: request the ones included by `block` that we
+    /// lost with our wal (e.g. after state sync) from the peer that sent it.
+    async fn request_lost_own_blocks(
+        inner: &NetworkSyncerInner<H, C>,
+        sender: &mpsc::Sender<NetworkMessage>,
+        block: &StatementBlock,
+    ) -> Option<()> {
+        let mut lost: Vec<_> = block
+            .includes()
+            .iter()
+            .filter(|reference| reference.authority == inner.authority)
+            .copied()
+            .collect();
+        if lost.is_empty() {
+            // Observers have no last seen round of their own
+            return Some(());
+        }
+        let last_seen = inner.block_store.last_seen_by_authority(inner.authority);
+        lost.retain(|reference| reference.round > last_seen);
+        if !lost.is_empty() {
+            sender
+                .send(NetworkMessage::RequestBlocks(lost))
+                .await
+                .ok()?;
+        }
+        Some(())
+    }
+
+    /// The latest block of `authority` known to us, none if we only know its genesis block.
+    fn latest_block(
+        inner: &NetworkSyncerInner<H, C>,
+        authority: AuthorityIndex,
+    ) -> Option<Data<StatementBlock>> {
+        let round = inner.block_store.last_seen_by_authority(authority);
+        if round == 0 {
+            return None;
+        }
+        inner
+            .block_store
+            .get_blocks_at_authority_round(authority, round)
+            .into_iter()
+            .next()
+    }
+
     async fn leader_timeout_task(
         inner: Arc<NetworkSyncerInner<H, C>>,
         mut epoch_close_signal: mpsc::Receiver<()>,
         shutdown_grace_period: Duration,
-        leader_timeout: Duration,
+        mut parameters: watch::Receiver<NodeParameters>,
     ) -> Option<()> {
         loop {
             let notified = inner.notify.notified();
+            let leader_timeout = parameters.borrow_and_update().leader_timeout;
             let round = inner
                 .block_store
                 .last_own_block_ref()
@@ -283,6 +1038,9 @@ impl<H: BlockHandler + 'static, C: CommitObserver + 'static> NetworkSyncer<H, C>
                 _notified = notified => {
                     // restart loop
                 }
+                Ok(()) = parameters.changed() => {
+                    // restart loop with the reloaded timeout
+                }
                 _epoch_shutdown = runtime::sleep(shutdown_duration) => {
                     tracing::info!("Shutting down sync after epoch close");
                     epoch_close_signal.close();
@@ -294,6 +1052,116 @@ impl<H: BlockHandler + 'static, C: CommitObserver + 'static> NetworkSyncer<H, C>
         }
     }
 
+    /// Pack our transactions into batches and send them to the peers, see `batch`.
+    async fn batch_task(
+        inner: Arc<NetworkSyncerInner<H, C>>,
+        mut transactions: mpsc::Receiver<Vec<Transaction>>,
+        metrics: Arc<Metrics>,
+    ) -> Option<()> {
+        let batch_availability = inner.batch_availability.as_ref()?;
+        let authority = batch_availability.authority();
+        while let Some(transactions) = inner.recv_or_stopped(&mut transactions).await {
+            for batch in Batch::pack(authority, transactions) {
+                let batch = Data::new(batch);
+                // Store the batch before any of our blocks may reference it
+                inner.syncer.add_batches(vec![batch.clone()]).await;
+                metrics.batches_total.with_label_values(&["own"]).inc();
+                for peer in batch_availability.add_own_batch(batch.clone()) {
+                    // Peers disconnecting in the meantime get the batch when they reconnect
+                    peer.send(NetworkMessage::Batch(batch.clone())).await.ok();
+                }
+            }
+        }
+        None
+    }
+
+    /// Request the batches that were not received in time from other peers, see
+    /// `BatchFetcher::retry`.
+    async fn batch_fetch_task(inner: Arc<NetworkSyncerInner<H, C>>) -> Option<()> {
+        loop {
+            select! {
+                _sleep = runtime::sleep(BATCH_REQUEST_TIMEOUT) => {
+                    let (requests, dropped) = inner.batch_fetcher.retry(timestamp_utc());
+                    if dropped > 0 {
+                        tracing::debug!("Dropped {dropped} blocks still waiting for their batches");
+                    }
+                    for (sender, digests) in requests {
+                        for chunk in digests.chunks(MAXIMUM_BATCH_REQUEST) {
+                            sender
+                                .send(NetworkMessage::RequestBatches(chunk.to_vec()))
+                                .await
+                                .ok();
+                        }
+                    }
+                }
+                _stopped = inner.stopped() => {
+                    return None;
+                }
+            }
+        }
+    }
+
+    /// Send `item` as a `message` to `neighbours` in the background: connection tasks relaying to
+    /// each other would otherwise wait on each other once the queues of their peers are full.
+    fn relay<T: Clone + Send + 'static>(
+        neighbours: Vec<mpsc::Sender<NetworkMessage>>,
+        item: T,
+        message: fn(T) -> NetworkMessage,
+    ) {
+        if neighbours.is_empty() {
+            return;
+        }
+        Handle::current().spawn(async move {
+            for sender in neighbours {
+                sender.send(message(item.clone())).await.ok();
+            }
+        });
+    }
+
+    /// Relay our own round request through the overlay, most validators of the quorum we wait for
+    /// not being our neighbours. Neighbours connecting later get the request when they connect.
+    /// The request is sent again with a new nonce every `OWN_ROUND_TIMEOUT` until a quorum
+    /// answered, in case it or some answers were lost.
+    async fn own_round_task(inner: Arc<NetworkSyncerInner<H, C>>) -> Option<()> {
+        let block_relay = inner.block_relay.as_ref()?;
+        for attempt in 0.. {
+            if inner.own_round_aggregator.lock().is_none() {
+                return None;
+            }
+            if attempt > 0 {
+                tracing::warn!(
+                    "Less than a quorum reported the highest round of our blocks, asking again"
+                );
+            }
+            let request = OwnRoundStatement::Request {
+                requester: inner.authority,
+                nonce: inner.own_round_nonce + attempt,
+            };
+            match inner.syncer.sign_own_round(request).await {
+                Ok(signed) => {
+                    block_relay.set_own_request(Some(signed.clone()));
+                    let neighbours = block_relay
+                        .relay_own_round(&request, inner.authority)
+                        .unwrap_or_default();
+                    for sender in neighbours {
+                        sender
+                            .send(NetworkMessage::RelayedOwnRound(signed.clone()))
+                            .await
+                            .ok();
+                    }
+                }
+                Err(e) => tracing::warn!("Failed to sign {:?}: {}", request, e),
+            }
+            select! {
+                _sleep = runtime::sleep(OWN_ROUND_TIMEOUT) => {}
+                _stopped = inner.stopped() => {
+                    return None;
+                }
+            }
+        }
+        None
+    }
+
     async fn cleanup_task(inner: Arc<NetworkSyncerInner<H, C>>) -> Option<()> {
         let cleanup_interval = Duration::from_secs(10);
         loop {
@@ -342,6 +1210,85 @@ impl<H: BlockHandler + 'static, C: CommitObserver + 'static> NetworkSyncerInner<
             }
         }
     }
+
+    pub async fn status(&self) -> NodeStatus {
+        self.syncer.status().await
+    }
+
+    /// The connection state of the other members of the committee.
+    pub async fn peers(&self) -> Vec<PeerStatus> {
+        let connected = self.syncer.connected_authorities().await;
+        self.committee
+            .authorities()
+            .filter(|authority| *authority != self.authority)
+            .map(|authority| PeerStatus {
+                authority,
+                address: self
+                    .address_book
+                    .address(authority as usize)
+                    .map(|address| address.to_string()),
+                connected: connected.contains(&authority),
+                last_seen_round: self.block_store.last_seen_by_authority(authority),
+            })
+            .collect()
+    }
+
+    /// The blocks we know of but did not receive yet, by author.
+    pub async fn missing_blocks(&self) -> Vec<MissingBlocks> {
+        self.syncer
+            .get_missing_blocks()
+            .await
+            .into_iter()
+            .enumerate()
+            .map(|(authority, missing)| {
+                let mut blocks: Vec<_> = missing.into_iter().collect();
+                blocks.sort();
+                MissingBlocks {
+                    authority: authority as AuthorityIndex,
+                    blocks: blocks.iter().map(ToString::to_string).collect(),
+                }
+            })
+            .collect()
+    }
+
+    pub async fn wal_size(&self) -> u64 {
+        self.syncer.wal_size().await
+    }
+
+    /// Export the rounds from `from` to `to` of the dag, see `DagExporter::rounds`. The export
+    /// reads the block store and may be slow, so it should not run on the async runtime.
+    pub fn export_dag(
+        &self,
+        from: Option<RoundNumber>,
+        to: Option<RoundNumber>,
+        annotate: bool,
+    ) -> eyre::Result<DagExport> {
+        let exporter = DagExporter::new(
+            self.block_store.clone(),
+            self.committee.clone(),
+            &self.parameters.current(),
+            self.metrics.clone(),
+        );
+        Ok(exporter.export(exporter.rounds(from, to)?, annotate))
+    }
+
+    pub fn request_shutdown(&self) {
+        // Stores a permit if nobody awaits the request yet
+        self.shutdown_request.notify_one();
+    }
+
+    /// See `NetworkSyncer::announce_address`.
+    pub async fn announce_address(&self, address: PeerAddress) -> io::Result<()> {
+        let update = self.syncer.sign_address_update(address).await?;
+        let authority = update.authority;
+        for sender in self.address_book.apply(update.clone(), authority) {
+            sender
+                .send(NetworkMessage::AddressUpdate(update.clone()))
+                .await
+                .ok();
+        }
+        Ok(())
+    }
 }
 
 impl SyncerSignals for Arc<Notify> {
@@ -418,9 +1365,30 @@ impl AsyncWalSyncer {
 
 #[cfg(test)]
 mod tests {
-    use std::time::Duration;
+    use std::{
+        fs,
+        net::{Ipv4Addr, SocketAddr},
+        time::Duration,
+    };
 
-    use crate::test_util::{check_commits, network_syncers};
+    use tempdir::TempDir;
+
+    use crate::{
+        compression::Compression,
+        config::NodePublicConfig,
+        protocol::Capabilities,
+        resolver::{PeerAddress, Resolver},
+        test_util::{
+            check_commits,
+            network_syncer_at,
+            network_syncers,
+            network_syncers_with_capabilities,
+            network_syncers_with_observer,
+            network_syncers_with_public_config,
+        },
+        topology::Topology,
+        types::AuthorityIndex,
+    };
 
     #[tokio::test]
     async fn test_network_sync() {
@@ -436,6 +1404,271 @@ mod tests {
 
         check_commits(&syncers);
     }
+
+    #[tokio::test]
+    async fn test_erasure_coded_sync() {
+        let mut public_config = NodePublicConfig::new_for_tests(7);
+        public_config.parameters.erasure_coding_threshold = Some(0);
+        let network_syncers = network_syncers_with_public_config(5501, &public_config).await;
+        tokio::time::sleep(Duration::from_secs(3)).await;
+        let mut syncers = vec![];
+        for network_syncer in network_syncers {
+            let syncer = network_syncer.shutdown().await;
+            syncers.push(syncer);
+        }
+
+        // Blocks of others were reconstructed from their chunks
+        for syncer in &syncers {
+            let reconstructed = syncer
+                .core()
+                .metrics
+                .erasure_coded_blocks_total
+                .with_label_values(&["reconstructed"])
+                .get();
+            assert!(reconstructed > 0);
+        }
+        check_commits(&syncers);
+    }
+
+    #[tokio::test]
+    async fn test_gossip_sync() {
+        // Every validator only connects to the two validators next to it on a ring
+        let mut public_config = NodePublicConfig::new_for_tests(7);
+        public_config.parameters.topology = Topology::Regular {
+            degree: 2,
+            seed: Some(1),
+        };
+        let network_syncers = network_syncers_with_public_config(5601, &public_config).await;
+        tokio::time::sleep(Duration::from_secs(3)).await;
+        let mut syncers = vec![];
+        for network_syncer in network_syncers {
+            let syncer = network_syncer.shutdown().await;
+            syncers.push(syncer);
+        }
+
+        for syncer in &syncers {
+            assert!(syncer.core().metrics.relayed_blocks_total.get() > 0);
+            assert!(!syncer.commit_observer().committed_leaders().is_empty());
+        }
+        check_commits(&syncers);
+    }
+
+    #[tokio::test]
+    async fn test_mixed_protocol_versions() {
+        // Half of the committee runs an older release, speaking only the first version of the
+        // protocol without compression; the others speak with each other the latest version
+        let public_config = NodePublicConfig::new_for_tests(4);
+        let old = Capabilities::new(Compression::None).with_versions(1, 1);
+        let new = Capabilities::new(Compression::Deflate);
+        let capabilities = [old, new, old, new];
+        let network_syncers =
+            network_syncers_with_capabilities(5801, &public_config, &capabilities).await;
+        tokio::time::sleep(Duration::from_secs(3)).await;
+        let mut syncers = vec![];
+        for network_syncer in network_syncers {
+            let syncer = network_syncer.shutdown().await;
+            syncers.push(syncer);
+        }
+
+        for syncer in &syncers {
+            assert!(!syncer.commit_observer().committed_leaders().is_empty());
+        }
+        check_commits(&syncers);
+    }
+
+    #[tokio::test]
+    async fn test_address_update() {
+        const FIRST_PORT: u16 = 5901;
+        let dir = TempDir::new("test_address_update").unwrap();
+        let public_config = NodePublicConfig::new_for_tests(4);
+        let mut addresses: Vec<_> = (0..4)
+            .map(|i| PeerAddress::from(SocketAddr::new(Ipv4Addr::LOCALHOST.into(), FIRST_PORT + i)))
+            .collect();
+        let resolver = Resolver::default();
+        // Every validator keeps its store in its own directory, to restart from it
+        let paths: Vec<_> = (0..4).map(|i| dir.path().join(i.to_string())).collect();
+        let mut network_syncers = vec![];
+        for (authority, path) in paths.iter().enumerate() {
+            fs::create_dir_all(path).unwrap();
+            let network_syncer = network_syncer_at(
+                authority as AuthorityIndex,
+                &addresses,
+                &resolver,
+                &public_config,
+                Some(path),
+            )
+            .await;
+            network_syncers.push(network_syncer);
+        }
+        tokio::time::sleep(Duration::from_secs(1)).await;
+
+        // The last validator announces its new address before moving there
+        addresses[3].port = FIRST_PORT + 4;
+        network_syncers[3]
+            .announce_address(addresses[3].clone())
+            .await
+            .unwrap();
+        tokio::time::sleep(Duration::from_millis(500)).await;
+        for network_syncer in &network_syncers[..3] {
+            assert_eq!(
+                network_syncer.address_book().address(3),
+                Some(addresses[3].clone())
+            );
+        }
+
+        // The other validators reconnect to it at its new address
+        let moved = network_syncers.pop().unwrap().shutdown().await;
+        let last_proposed = moved.core().last_proposed();
+        drop(moved);
+        let moved =
+            network_syncer_at(3, &addresses, &resolver, &public_config, Some(&paths[3])).await;
+        tokio::time::sleep(Duration::from_secs(3)).await;
+        let mut syncers = vec![];
+        for network_syncer in network_syncers.into_iter().chain([moved]) {
+            let syncer = network_syncer.shutdown().await;
+            syncers.push(syncer);
+        }
+
+        // The moved validator kept proposing blocks, and its commits since the restart continue the
+        // commit sequence of the other validators
+        let moved = syncers.pop().unwrap();
+        assert!(moved.core().last_proposed() > last_proposed);
+        let committed = moved.commit_observer().committed_leaders();
+        assert!(!committed.is_empty());
+        let reference = syncers[0].commit_observer().committed_leaders();
+        let start = reference
+            .iter()
+            .position(|leader| leader == &committed[0])
+            .expect("Commit sequences diverged");
+        let end = (start + committed.len()).min(reference.len());
+        assert_eq!(&reference[start..end], &committed[..end - start]);
+        check_commits(&syncers);
+    }
+
+    #[tokio::test]
+    async fn test_hostnames_ipv6() {
+        // The validators are known by name only, and their names resolve to IPv6 addresses only.
+        // Their ports are past the range of the ports validators used to dial from.
+        const FIRST_PORT: u16 = 7001;
+        let public_config = NodePublicConfig::new_for_tests(4);
+        let addresses: Vec<PeerAddress> = (0..4)
+            .map(|i| format!("validator-{i}:{}", FIRST_PORT + i).parse().unwrap())
+            .collect();
+        let resolver =
+            Resolver::from_hosts("::1 validator-0 validator-1 validator-2 validator-3").unwrap();
+        let mut network_syncers = vec![];
+        for authority in 0..4 {
+            let network_syncer =
+                network_syncer_at(authority, &addresses, &resolver, &public_config, None).await;
+            network_syncers.push(network_syncer);
+        }
+        tokio::time::sleep(Duration::from_secs(3)).await;
+        let mut syncers = vec![];
+        for network_syncer in network_syncers {
+            let syncer = network_syncer.shutdown().await;
+            syncers.push(syncer);
+        }
+
+        for syncer in &syncers {
+            assert!(!syncer.commit_observer().committed_leaders().is_empty());
+        }
+        check_commits(&syncers);
+    }
+
+    #[tokio::test]
+    async fn test_observer_sync() {
+        let (network_syncers, observer) = network_syncers_with_observer(5201, 4).await;
+        tokio::time::sleep(Duration::from_secs(3)).await;
+        let mut syncers = vec![];
+        for network_syncer in network_syncers {
+            let syncer = network_syncer.shutdown().await;
+            syncers.push(syncer);
+        }
+        let observer = observer.shutdown().await;
+        assert_eq!(observer.core().last_proposed(), 0);
+        assert!(!observer.commit_observer().committed_leaders().is_empty());
+
+        // The commit sequence of the observer is a prefix of (or equal to) the validators' one
+        syncers.push(observer);
+        check_commits(&syncers);
+    }
+
+    #[tokio::test]
+    async fn test_state_sync() {
+        const FIRST_PORT: u16 = 6001;
+        const SNAPSHOT_PERIOD: u64 = 10;
+        let dir = TempDir::new("test_state_sync").unwrap();
+        let mut public_config = NodePublicConfig::new_for_tests(4);
+        public_config.parameters.snapshot_period = SNAPSHOT_PERIOD;
+        public_config.parameters.enable_state_sync = true;
+        let addresses: Vec<_> = (0..4)
+            .map(|i| PeerAddress::from(SocketAddr::new(Ipv4Addr::LOCALHOST.into(), FIRST_PORT + i)))
+            .collect();
+        let resolver = Resolver::default();
+        let paths: Vec<_> = (0..4).map(|i| dir.path().join(i.to_string())).collect();
+        let mut network_syncers = vec![];
+        for (authority, path) in paths.iter().enumerate() {
+            fs::create_dir_all(path).unwrap();
+            let network_syncer = network_syncer_at(
+                authority as AuthorityIndex,
+                &addresses,
+                &resolver,
+                &public_config,
+                Some(path),
+            )
+            .await;
+            network_syncers.push(network_syncer);
+        }
+        tokio::time::sleep(Duration::from_secs(1)).await;
+
+        // The last validator loses its wal once the others have taken a few snapshots, but keeps
+        // its signing guard
+        let wiped = network_syncers.pop().unwrap().shutdown().await;
+        let last_proposed = wiped.core().last_proposed();
+        drop(wiped);
+        fs::remove_file(paths[3].join("003.wal")).unwrap();
+        let restarted =
+            network_syncer_at(3, &addresses, &resolver, &public_config, Some(&paths[3])).await;
+        tokio::time::sleep(Duration::from_secs(2)).await;
+        let mut syncers = vec![];
+        for network_syncer in network_syncers {
+            let syncer = network_syncer.shutdown().await;
+            syncers.push(syncer);
+        }
+        let restarted = restarted.shutdown().await;
+
+        // The restarted validator started from a snapshot and then proposed blocks, never signing
+        // a second block for a round it signed before losing its wal
+        let frontier = restarted.core().snapshot_frontier().unwrap();
+        assert!(frontier
+            .iter()
+            .any(|reference| reference.round > SNAPSHOT_PERIOD));
+        assert!(restarted.core().last_proposed() > last_proposed);
+        for syncer in &syncers {
+            let block_store = syncer.core().block_store();
+            for round in 1..=restarted.core().last_proposed() {
+                assert!(block_store.get_blocks_at_authority_round(3, round).len() <= 1);
+            }
+        }
+
+        // Its commit sequence continues the one of the other validators after the snapshot
+        let committed = restarted.commit_observer().committed_leaders();
+        assert!(!committed.is_empty());
+        // The others are stopped one by one and may keep committing meanwhile, so compare with
+        // the longest sequence, see `check_commits`
+        let reference = syncers
+            .iter()
+            .map(|syncer| syncer.commit_observer().committed_leaders())
+            .max_by_key(|leaders| leaders.len())
+            .unwrap();
+        let start = reference
+            .iter()
+            .position(|leader| leader == &committed[0])
+            .expect("Commit sequences diverged");
+        let end = (start + committed.len()).min(reference.len());
+        assert_eq!(&reference[start..end], &committed[..end - start]);
+        check_commits(&syncers);
+    }
 }
 
 #[cfg(test)]
@@ -453,17 +1686,32 @@ mod sim_tests {
         block_handler::{TestBlockHandler, TestCommitHandler},
         config,
         config::NodePublicConfig,
+        data::Data,
         finalization_interpreter::FinalizationInterpreter,
         future_simulator::SimulatedExecutorState,
+        metrics::print_network_traffic_table,
+        network::{Network, NetworkMessage},
         runtime,
         simulator_tracing::setup_simulator_tracing,
         syncer::Syncer,
         test_util::{
             check_commits,
+            committee_and_cores,
             print_stats,
             rng_at_seed,
+            simulated_network_some_syncers,
             simulated_network_syncers,
+            simulated_network_syncers_with_cores,
             simulated_network_syncers_with_epoch_duration,
+            simulated_network_syncers_with_public_config,
+        },
+        topology::Topology,
+        types::{
+            format_authority_index,
+            AuthorityIndex,
+            BaseStatement,
+            StatementBlock,
+            Transaction,
         },
     };
 
@@ -571,6 +1819,22 @@ mod sim_tests {
 
         check_commits(&syncers);
         print_stats(&syncers, &mut reporters);
+
+        // The blocks sent by A to B are received by B, except for those still in flight
+        let sent = syncers[0]
+            .core()
+            .metrics
+            .network_sent_bytes_total
+            .with_label_values(&["B", "block"])
+            .get();
+        let received = syncers[1]
+            .core()
+            .metrics
+            .network_received_bytes_total
+            .with_label_values(&["A", "block"])
+            .get();
+        assert!(received > 0 && received <= sent);
+        print_network_traffic_table(&syncers[0].core().metrics);
     }
 
     #[test]
@@ -625,4 +1889,202 @@ mod sim_tests {
         check_commits(&syncers);
         print_stats(&syncers, &mut reporters);
     }
+
+    #[test]
+    fn test_erasure_coding_bandwidth() {
+        setup_simulator_tracing();
+        SimulatedExecutorState::run(rng_at_seed(0), test_erasure_coding_bandwidth_async());
+    }
+
+    // Compare the bytes sent by a committee of 10 validators with and without erasure coding, the
+    // first validator proposing much larger blocks than the others
+    async fn test_erasure_coding_bandwidth_async() {
+        let n = 10;
+        let mut max_bytes_sent = vec![];
+        for threshold in [None, Some(16 * 1024)] {
+            let mut public_config = NodePublicConfig::new_for_tests(n);
+            public_config.parameters.erasure_coding_threshold = threshold;
+            let (committee, mut cores, mut reporters) = committee_and_cores(n);
+            cores[0].block_handler_mut().set_transaction_size(2 * 1024);
+            let (simulated_network, network_syncers, _) =
+                simulated_network_syncers_with_cores(&committee, cores, n, &public_config);
+            simulated_network.connect_all().await;
+            runtime::sleep(Duration::from_secs(20)).await;
+            let bytes_sent = simulated_network.bytes_sent();
+            let mut syncers = vec![];
+            for network_syncer in network_syncers {
+                let syncer = network_syncer.shutdown().await;
+                syncers.push(syncer);
+            }
+
+            check_commits(&syncers);
+            print_stats(&syncers, &mut reporters);
+            let total: u64 = bytes_sent.iter().sum();
+            let max = *bytes_sent.iter().max().unwrap();
+            println!(
+                "Erasure coding threshold {threshold:?}: {} KiB sent per validator on average, {} KiB at most",
+                total / n as u64 / 1024,
+                max / 1024
+            );
+            max_bytes_sent.push(max);
+        }
+        // The upload of the large blocks is spread across the committee rather than left to their
+        // author
+        assert!(max_bytes_sent[1] < max_bytes_sent[0]);
+    }
+
+    #[test]
+    fn test_gossip_topology() {
+        setup_simulator_tracing();
+        SimulatedExecutorState::run(rng_at_seed(0), test_gossip_topology_async());
+    }
+
+    // Compare the commit latency and the messages sent by a committee of 16 validators connected
+    // by a full mesh and by a 4-regular overlay
+    async fn test_gossip_topology_async() {
+        let n = 16;
+        let degree = 4;
+        let mut latencies = vec![];
+        let mut messages = vec![];
+        for topology in [
+            Topology::Mesh,
+            Topology::Regular {
+                degree,
+                seed: Some(0),
+            },
+        ] {
+            let mut public_config = NodePublicConfig::new_for_tests(n);
+            public_config.parameters.topology = topology.clone();
+            let (simulated_network, network_syncers, mut reporters) =
+                simulated_network_syncers_with_public_config(n, &public_config);
+            simulated_network
+                .connect_some(|a, b| {
+                    topology
+                        .neighbours(n, a as AuthorityIndex)
+                        .contains(&(b as AuthorityIndex))
+                })
+                .await;
+            runtime::sleep(Duration::from_secs(20)).await;
+            let bytes_sent = simulated_network.bytes_sent();
+            let messages_sent = simulated_network.messages_sent();
+            let mut syncers = vec![];
+            for network_syncer in network_syncers {
+                let syncer = network_syncer.shutdown().await;
+                syncers.push(syncer);
+            }
+
+            check_commits(&syncers);
+            print_stats(&syncers, &mut reporters);
+            let latency = reporters
+                .iter()
+                .map(|r| r.transaction_committed_latency.histogram.avg().unwrap())
+                .sum::<Duration>()
+                / reporters.len() as u32;
+            // Per proposed round, the overlay proposing fewer of them
+            let rounds = syncers
+                .iter()
+                .map(|syncer| syncer.core().last_proposed())
+                .min()
+                .unwrap();
+            let total: u64 = messages_sent.iter().sum();
+            let per_round = total / n as u64 / rounds;
+            println!(
+                "Topology {topology:?}: commit latency {latency:?}, {} messages per validator per round, {} KiB sent per validator",
+                per_round,
+                bytes_sent.iter().sum::<u64>() / n as u64 / 1024
+            );
+            latencies.push(latency);
+            messages.push(per_round);
+        }
+        // Blocks take a few more hops through the overlay, queueing behind the relayed ones on the
+        // simulated links, which deliver one message at a time
+        assert!(latencies[0] < latencies[1] && latencies[1] < latencies[0] * 20);
+        // Every validator relays a block at most once to each of its neighbours
+        assert!(messages[0] < messages[1] && messages[1] <= (n * degree) as u64);
+    }
+
+    #[test]
+    fn test_block_flood() {
+        setup_simulator_tracing();
+        SimulatedExecutorState::run(rng_at_seed(0), test_block_flood_async());
+    }
+
+    // The last of 10 validators floods the others with its first block. The honest validators
+    // should commit as fast as when the last validator is down.
+    async fn test_block_flood_async() {
+        let n = 10;
+        let flooder = n - 1;
+        let mut latencies = vec![];
+        for flood in [false, true] {
+            let public_config = NodePublicConfig::new_for_tests(n);
+            let (simulated_network, network_syncers, mut reporters, mut networks) =
+                simulated_network_some_syncers(n, flooder, &public_config);
+            if flood {
+                simulated_network.connect_all().await;
+                let network = networks.pop().unwrap();
+                runtime::Handle::current().spawn(flood_blocks(network, flooder, n));
+            } else {
+                simulated_network
+                    .connect_some(|a, b| a != flooder && b != flooder)
+                    .await;
+            }
+            runtime::sleep(Duration::from_secs(20)).await;
+            let mut syncers = vec![];
+            for network_syncer in network_syncers {
+                let syncer = network_syncer.shutdown().await;
+                syncers.push(syncer);
+            }
+
+            check_commits(&syncers);
+            print_stats(&syncers, &mut reporters);
+            if flood {
+                let flooder = format_authority_index(flooder as AuthorityIndex).to_string();
+                for syncer in &syncers {
+                    let bans = &syncer.core().metrics.banned_peers_total;
+                    assert_eq!(bans.with_label_values(&[&flooder, "blocks"]).get(), 1);
+                }
+            }
+            let latency = reporters
+                .iter()
+                .map(|r| r.transaction_committed_latency.histogram.avg().unwrap())
+                .sum::<Duration>()
+                / reporters.len() as u32;
+            latencies.push(latency);
+        }
+        println!(
+            "Commit latency {:?} when down, {:?} when flooding",
+            latencies[0], latencies[1]
+        );
+        assert!(latencies[1] <= latencies[0] * 11 / 10);
+    }
+
+    // Send copies of the same block to every peer, as fast as they accept them
+    async fn flood_blocks(mut network: Network, authority: usize, n: usize) {
+        let includes = (0..n as AuthorityIndex)
+            .map(|authority| *StatementBlock::new_genesis(authority).reference())
+            .collect();
+        let block = Data::new(StatementBlock::new(
+            authority as AuthorityIndex,
+            1,
+            includes,
+            vec![BaseStatement::Share(Transaction::new(vec![0; 1024]))],
+            0,
+            false,
+            Default::default(),
+        ));
+        while let Some(mut connection) = network.connection_receiver().recv().await {
+            let block = block.clone();
+            runtime::Handle::current().spawn(async move {
+                while connection
+                    .sender
+                    .send(NetworkMessage::Block(block.clone()))
+                    .await
+                    .is_ok()
+                {
+                    // Drain what the peer sends us
+                    while connection.receiver.try_recv().is_ok() {}
+                }
+            });
+        }
+    }
 }
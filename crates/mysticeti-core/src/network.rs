@@ -1,10 +1,22 @@
 // Copyright (c) Mysten Labs, Inc.
 // SPDX-License-Identifier: Apache-2.0
 
-use std::{collections::HashMap, io, net::SocketAddr, ops::Range, sync::Arc, time::Duration};
+use std::{
+    borrow::Cow,
+    collections::HashMap,
+    io,
+    mem,
+    net::{IpAddr, SocketAddr},
+    ops::Range,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
 
 use futures::{
-    future::{select, select_all, Either},
+    future::{join_all, select_all},
     FutureExt,
 };
 use rand::{prelude::ThreadRng, Rng};
@@ -14,25 +26,46 @@ use tokio::{
     net::{
         tcp::{OwnedReadHalf, OwnedWriteHalf},
         TcpListener,
-        TcpSocket,
         TcpStream,
     },
     runtime::Handle,
     select,
-    sync::mpsc,
-    time::Instant,
+    sync::{mpsc, watch},
+    task::JoinHandle,
+    time::{timeout, Instant},
 };
 
 use crate::{
+    address_book::{AddressBook, AddressUpdate},
+    batch::Batch,
+    compression::Compression,
     config::NodePublicConfig,
     data::Data,
-    metrics::{print_network_address_table, Metrics},
+    erasure::BlockChunk,
+    metrics::{print_network_address_table, Metrics, UtilizationTimerVecExt},
+    priority::{priority_channel, PriorityReceiver},
+    protocol::{self, Capabilities, Protocol},
+    quic::QuicTransport,
+    resolver::{PeerAddress, Resolver},
     runtime,
+    snapshot::Snapshot,
     stat::HistogramSender,
-    types::{AuthorityIndex, BlockReference, RoundNumber, StatementBlock},
+    topology::{SignedOwnRound, Topology},
+    types::{
+        format_authority_index,
+        AuthorityIndex,
+        BatchDigest,
+        BlockReference,
+        RoundNumber,
+        StatementBlock,
+    },
 };
 
-const PING_INTERVAL: Duration = Duration::from_secs(30);
+pub(crate) const PING_INTERVAL: Duration = Duration::from_secs(30);
+/// The largest frame accepted from a peer.
+pub(crate) const MAX_FRAME_SIZE: u32 = 16 * 1024 * 1024;
+/// How long a peer connecting to us has to identify itself.
+pub(crate) const IDENTIFY_TIMEOUT: Duration = Duration::from_secs(5);
 
 #[derive(Debug, Serialize, Deserialize)]
 pub enum NetworkMessage {
@@ -42,15 +75,108 @@ pub enum NetworkMessage {
     RequestBlocks(Vec<BlockReference>),
     /// Indicate that a requested block is not found.
     BlockNotFound(Vec<BlockReference>),
+    /// Request the latest snapshot of the peer (sent by validators joining with an empty store).
+    RequestSnapshot,
+    /// The latest snapshot of the peer, if any.
+    Snapshot(Option<Snapshot>),
+    /// Request the latest of the requester's own blocks known to the peer (sent by validators on
+    /// startup, to avoid signing twice for the same round after losing storage).
+    RequestOwnRound,
+    /// The latest of the requester's own blocks known to the peer, if any.
+    OwnBlock(Option<Data<StatementBlock>>),
+    /// A batch of transactions, sent by its author or in response to `RequestBatches`.
+    Batch(Data<Batch>),
+    /// Acknowledge that a batch received from its author is stored.
+    BatchAck(BatchDigest),
+    /// Request the batches referenced by a block received from the peer.
+    RequestBatches(Vec<BatchDigest>),
+    /// The chunk of an erasure-coded block, sent by its author or forwarded by the validator the
+    /// chunk is destined to.
+    Chunk(BlockChunk),
+    /// A validator announcing its new address, relayed to the peers, see `address_book`.
+    AddressUpdate(AddressUpdate),
+    /// A request for the highest round of the requester's own blocks, or a report answering it,
+    /// relayed through the overlay when validators are not all connected, see `topology`.
+    RelayedOwnRound(SignedOwnRound),
+}
+
+impl NetworkMessage {
+    /// The name of the message type, as reported in the metrics.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::SubscribeOwnFrom(_) => "subscribe_own_from",
+            Self::Block(_) => "block",
+            Self::RequestBlocks(_) => "request_blocks",
+            Self::BlockNotFound(_) => "block_not_found",
+            Self::RequestSnapshot => "request_snapshot",
+            Self::Snapshot(_) => "snapshot",
+            Self::RequestOwnRound => "request_own_round",
+            Self::OwnBlock(_) => "own_block",
+            Self::Batch(_) => "batch",
+            Self::BatchAck(_) => "batch_ack",
+            Self::RequestBatches(_) => "request_batches",
+            Self::Chunk(_) => "chunk",
+            Self::AddressUpdate(_) => "address_update",
+            Self::RelayedOwnRound(_) => "relayed_own_round",
+        }
+    }
+
+    /// The number of kinds of messages, see `kind`.
+    pub const KINDS: u16 = 14;
+
+    /// The kind of the message in the envelope, see `protocol`. The kinds never change, new kinds
+    /// of messages get the next number.
+    pub fn kind(&self) -> u16 {
+        match self {
+            Self::SubscribeOwnFrom(_) => 0,
+            Self::Block(_) => 1,
+            Self::RequestBlocks(_) => 2,
+            Self::BlockNotFound(_) => 3,
+            Self::RequestSnapshot => 4,
+            Self::Snapshot(_) => 5,
+            Self::RequestOwnRound => 6,
+            Self::OwnBlock(_) => 7,
+            Self::Batch(_) => 8,
+            Self::BatchAck(_) => 9,
+            Self::RequestBatches(_) => 10,
+            Self::Chunk(_) => 11,
+            Self::RelayedOwnRound(_) => 12,
+            Self::AddressUpdate(_) => 13,
+        }
+    }
+}
+
+/// How the connections to the peers are established and framed.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, Eq, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum TransportProtocol {
+    /// Our own protocol over TCP, all the messages to a peer share a single stream.
+    #[default]
+    Tcp,
+    /// QUIC, with a stream per priority class so that sync traffic does not block fresh blocks,
+    /// see `quic`.
+    Quic,
 }
 
 pub struct Network {
     connection_receiver: mpsc::Receiver<Connection>,
+    address_book: AddressBook,
+    // The server and worker tasks, stopped when the network is dropped
+    tasks: Vec<JoinHandle<()>>,
 }
 
+/// The messages sent to the peer are queued by priority, see `priority`.
 pub struct Connection {
     pub peer_id: usize,
+    /// The address of the peer if it dialed us, in which case the peer id is only declared by
+    /// the peer. None if we dialed the peer at its known address.
+    pub incoming_from: Option<IpAddr>,
+    /// Fresh blocks and protocol messages.
     pub sender: mpsc::Sender<NetworkMessage>,
+    /// Responses to the requests of the peer.
+    pub sync_sender: mpsc::Sender<NetworkMessage>,
+    /// Blocks streamed to the peer to catch up.
+    pub bulk_sender: mpsc::Sender<NetworkMessage>,
     pub receiver: mpsc::Receiver<NetworkMessage>,
 }
 
@@ -59,6 +185,8 @@ impl Network {
     pub(crate) fn new_from_raw(connection_receiver: mpsc::Receiver<Connection>) -> Self {
         Self {
             connection_receiver,
+            address_book: AddressBook::default(),
+            tasks: vec![],
         }
     }
 
@@ -70,18 +198,108 @@ impl Network {
     ) -> Self {
         let addresses = parameters.all_network_addresses().collect::<Vec<_>>();
         print_network_address_table(&addresses);
-        Self::from_socket_addresses(&addresses, our_id as usize, local_addr, metrics).await
+        let observers = parameters.observers_following(our_id).collect::<Vec<_>>();
+        Self::from_addresses(
+            &addresses,
+            &observers,
+            our_id as usize,
+            local_addr,
+            metrics,
+            Capabilities::from_parameters(&parameters.parameters),
+            parameters.parameters.network_transport,
+            &parameters.parameters.topology,
+            Resolver::default(),
+        )
+        .await
+    }
+
+    /// Load the network of an observer. Observers only connect to the validators they follow
+    /// and never accept connections from other nodes.
+    pub async fn load_observer(
+        parameters: &NodePublicConfig,
+        observer: usize,
+        local_addr: SocketAddr,
+        metrics: Arc<Metrics>,
+    ) -> Self {
+        let addresses = parameters.all_network_addresses().collect::<Vec<_>>();
+        print_network_address_table(&addresses);
+        let follow = &parameters
+            .observer(observer)
+            .expect("Unknown observer")
+            .follow;
+        Self::observer_from_addresses(
+            &addresses,
+            follow,
+            parameters.observer_peer_id(observer),
+            local_addr,
+            metrics,
+            Capabilities::from_parameters(&parameters.parameters),
+            parameters.parameters.network_transport,
+            Resolver::default(),
+        )
+        .await
     }
 
     pub fn connection_receiver(&mut self) -> &mut mpsc::Receiver<Connection> {
         &mut self.connection_receiver
     }
 
+    /// The current addresses of the peers, updating them makes the network reconnect.
+    pub fn address_book(&self) -> &AddressBook {
+        &self.address_book
+    }
+
+    /// Stop the server and worker tasks, and wait until the listener is closed so that the local
+    /// address can be bound again, e.g. by a restarted validator.
+    pub async fn shutdown(mut self) {
+        let tasks = mem::take(&mut self.tasks);
+        for task in &tasks {
+            task.abort();
+        }
+        join_all(tasks).await;
+    }
+
+    #[allow(clippy::too_many_arguments)]
     pub async fn from_socket_addresses(
         addresses: &[SocketAddr],
+        observers: &[(usize, SocketAddr)],
+        our_id: usize,
+        local_addr: SocketAddr,
+        metrics: Arc<Metrics>,
+        capabilities: Capabilities,
+        transport: TransportProtocol,
+        topology: &Topology,
+    ) -> Self {
+        let addresses: Vec<PeerAddress> =
+            addresses.iter().map(|address| (*address).into()).collect();
+        Self::from_addresses(
+            &addresses,
+            observers,
+            our_id,
+            local_addr,
+            metrics,
+            capabilities,
+            transport,
+            topology,
+            Resolver::default(),
+        )
+        .await
+    }
+
+    /// Connect to our neighbours in the topology, all other validators by default, resolving
+    /// their hostnames with `resolver`. The observers following us are specified by their peer
+    /// id and address; we accept their connections but never dial them.
+    #[allow(clippy::too_many_arguments)]
+    pub async fn from_addresses(
+        addresses: &[PeerAddress],
+        observers: &[(usize, SocketAddr)],
         our_id: usize,
         local_addr: SocketAddr,
         metrics: Arc<Metrics>,
+        capabilities: Capabilities,
+        transport: TransportProtocol,
+        topology: &Topology,
+        resolver: Resolver,
     ) -> Self {
         if our_id >= addresses.len() {
             panic!(
@@ -89,200 +307,489 @@ impl Network {
                 addresses.len()
             );
         }
+        let neighbours = topology.neighbours(addresses.len(), our_id as AuthorityIndex);
+        let peers = addresses
+            .iter()
+            .enumerate()
+            .filter(|(id, _)| neighbours.contains(&(*id as AuthorityIndex)))
+            .map(|(id, address)| Peer {
+                id,
+                address: address.clone(),
+                dial: true,
+                active_immediately: id < our_id,
+            })
+            .chain(observers.iter().map(|(id, address)| Peer {
+                id: *id,
+                address: (*address).into(),
+                dial: false,
+                active_immediately: false,
+            }));
+        let our_id = our_id as u64;
+        Self::from_peers(
+            peers,
+            our_id,
+            local_addr,
+            metrics,
+            capabilities,
+            transport,
+            resolver,
+        )
+        .await
+    }
+
+    /// The network of the observer with peer id `our_id`, see `NodePublicConfig::observer_peer_id`.
+    #[allow(clippy::too_many_arguments)]
+    pub async fn observer_from_addresses(
+        addresses: &[PeerAddress],
+        follow: &[AuthorityIndex],
+        our_id: usize,
+        local_addr: SocketAddr,
+        metrics: Arc<Metrics>,
+        capabilities: Capabilities,
+        transport: TransportProtocol,
+        resolver: Resolver,
+    ) -> Self {
+        let peers = follow.iter().map(|authority| Peer {
+            id: *authority as usize,
+            address: addresses
+                .get(*authority as usize)
+                .expect("Observer follows an unknown authority")
+                .clone(),
+            dial: true,
+            active_immediately: true,
+        });
+        let our_id = our_id as u64;
+        Self::from_peers(
+            peers,
+            our_id,
+            local_addr,
+            metrics,
+            capabilities,
+            transport,
+            resolver,
+        )
+        .await
+    }
+
+    async fn from_peers(
+        peers: impl Iterator<Item = Peer>,
+        our_id: u64,
+        local_addr: SocketAddr,
+        metrics: Arc<Metrics>,
+        capabilities: Capabilities,
+        transport: TransportProtocol,
+        resolver: Resolver,
+    ) -> Self {
+        let peers: Vec<_> = peers.collect();
+        let address_book =
+            AddressBook::new(peers.iter().map(|peer| (peer.id, peer.address.clone())));
+        let (connection_sender, connection_receiver) = mpsc::channel(16);
+        let tasks = match transport {
+            TransportProtocol::Tcp => {
+                TcpTransport {
+                    local_addr,
+                    our_id,
+                    capabilities,
+                    resolver,
+                }
+                .start(peers, address_book.clone(), connection_sender, metrics)
+                .await
+            }
+            TransportProtocol::Quic => {
+                QuicTransport {
+                    local_addr,
+                    our_id,
+                    capabilities,
+                    resolver,
+                }
+                .start(peers, address_book.clone(), connection_sender, metrics)
+                .await
+            }
+        };
+        Self {
+            connection_receiver,
+            address_book,
+            tasks,
+        }
+    }
+}
+
+impl Drop for Network {
+    fn drop(&mut self) {
+        for task in &self.tasks {
+            task.abort();
+        }
+    }
+}
+
+/// How `Network` connects to its peers, see `TransportProtocol`.
+pub(crate) trait Transport {
+    /// Start connecting to the peers, handing every connection established to
+    /// `connection_sender`. The peers are dialed at their address in `address_book` and
+    /// identify themselves by their peer id when they dial us. Returns the tasks to abort when
+    /// the network is dropped.
+    async fn start(
+        self,
+        peers: Vec<Peer>,
+        address_book: AddressBook,
+        connection_sender: mpsc::Sender<Connection>,
+        metrics: Arc<Metrics>,
+    ) -> Vec<JoinHandle<()>>;
+}
+
+/// Our own protocol over TCP: both ends dial each other (peers with a higher index after a
+/// random delay) and the connection accepted last replaces the current one, unless we dialed
+/// it, see `OutboundConnection`. The dialer sends its peer id after the active handshake.
+struct TcpTransport {
+    local_addr: SocketAddr,
+    our_id: u64,
+    /// What we offer to the peers in the handshake.
+    capabilities: Capabilities,
+    resolver: Resolver,
+}
+
+impl Transport for TcpTransport {
+    async fn start(
+        self,
+        peers: Vec<Peer>,
+        address_book: AddressBook,
+        connection_sender: mpsc::Sender<Connection>,
+        metrics: Arc<Metrics>,
+    ) -> Vec<JoinHandle<()>> {
+        let local_addr = self.local_addr;
         let server = TcpListener::bind(local_addr)
             .await
             .expect("Failed to bind to local socket");
-        let mut worker_senders: HashMap<SocketAddr, mpsc::UnboundedSender<TcpStream>> =
+        let mut worker_senders: HashMap<usize, mpsc::UnboundedSender<TcpStream>> =
             HashMap::default();
         let handle = Handle::current();
-        let (connection_sender, connection_receiver) = mpsc::channel(16);
-        for (id, address) in addresses.iter().enumerate() {
-            if id == our_id {
-                continue;
-            }
+        let mut tasks = vec![];
+        for peer in peers {
             let (sender, receiver) = mpsc::unbounded_channel();
             assert!(
-                worker_senders.insert(*address, sender).is_none(),
-                "Duplicated address {} in list",
-                address
+                worker_senders.insert(peer.id, sender).is_none(),
+                "Duplicated peer {} in list",
+                peer.id
             );
-            handle.spawn(
+            tasks.push(handle.spawn(
                 Worker {
-                    peer: *address,
-                    peer_id: id,
+                    peer_id: peer.id,
+                    our_id: self.our_id,
                     connection_sender: connection_sender.clone(),
-                    bind_addr: bind_addr(local_addr),
-                    active_immediately: id < our_id,
-                    latency_sender: metrics.connection_latency_sender.get(id).expect("Can not locate connection_latency_sender metric - did you initialize metrics with correct committee?").clone()
+                    resolver: self.resolver.clone(),
+                    dial: peer.dial,
+                    active_immediately: peer.active_immediately,
+                    outbound: OutboundConnection::default(),
+                    // Observers are not part of the committee and have no latency metric.
+                    latency_sender: metrics.connection_latency_sender.get(peer.id).cloned(),
+                    metrics: metrics.clone(),
+                    capabilities: self.capabilities,
                 }
-                .run(receiver),
-            );
+                .run(receiver, address_book.subscribe(peer.id))
+                .map(drop),
+            ));
         }
 
-        handle.spawn(
+        tasks.push(handle.spawn(
             Server {
                 server,
-                worker_senders,
+                worker_senders: Arc::new(worker_senders),
+                address_book,
+                resolver: self.resolver,
             }
             .run(),
-        );
-        Self {
-            connection_receiver,
+        ));
+        tasks
+    }
+}
+
+pub(crate) struct Peer {
+    pub id: usize,
+    pub address: PeerAddress,
+    /// Whether we initiate connections to this peer. Validators do not dial observers.
+    pub dial: bool,
+    /// Whether we dial the peer as soon as we start. Validators dial the peers of lower index
+    /// immediately; with QUIC, only those are dialed.
+    pub active_immediately: bool,
+}
+
+/// Whether a peer that dialed us from `remote` is at the address we know for `peer`. The dialer
+/// only declares its peer id, so this keeps any host from taking the place of a peer.
+pub(crate) async fn dialed_from_peer_address(
+    address_book: &AddressBook,
+    resolver: &Resolver,
+    peer: usize,
+    remote: IpAddr,
+) -> bool {
+    let Some(address) = address_book.address(peer) else {
+        return false;
+    };
+    match resolver.resolve(&address).await {
+        Ok(addresses) => addresses
+            .iter()
+            .any(|address| address.ip().to_canonical() == remote.to_canonical()),
+        Err(err) => {
+            tracing::debug!("Failed to resolve peer {peer}: {err}");
+            false
         }
     }
 }
 
+/// Whether a connection we dialed to a peer is established. A connection dialed by the peer is
+/// only identified by the peer id it declares, so it never replaces one we dialed.
+#[derive(Default)]
+pub(crate) struct OutboundConnection(AtomicBool);
+
+/// Clears `OutboundConnection` when dropped, along with the connection.
+pub(crate) struct OutboundConnectionGuard<'a>(&'a AtomicBool);
+
+impl OutboundConnection {
+    pub fn is_established(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+
+    /// Mark the connection we dialed established until the returned guard is dropped.
+    pub fn establish(&self) -> OutboundConnectionGuard<'_> {
+        self.0.store(true, Ordering::Relaxed);
+        OutboundConnectionGuard(&self.0)
+    }
+}
+
+impl Drop for OutboundConnectionGuard<'_> {
+    fn drop(&mut self) {
+        self.0.store(false, Ordering::Relaxed);
+    }
+}
+
 struct Server {
     server: TcpListener,
-    worker_senders: HashMap<SocketAddr, mpsc::UnboundedSender<TcpStream>>,
+    worker_senders: Arc<HashMap<usize, mpsc::UnboundedSender<TcpStream>>>,
+    address_book: AddressBook,
+    resolver: Resolver,
 }
 
 impl Server {
     async fn run(self) {
         loop {
             let (socket, remote_peer) = self.server.accept().await.expect("Accept failed");
-            let remote_peer = remote_to_local_port(remote_peer);
-            if let Some(sender) = self.worker_senders.get(&remote_peer) {
-                sender.send(socket).ok();
-            } else {
-                tracing::warn!("Dropping connection from unknown peer {remote_peer}");
-            }
-        }
-    }
-}
-
-// just ignore these two functions for now :)
-fn remote_to_local_port(mut remote_peer: SocketAddr) -> SocketAddr {
-    match &mut remote_peer {
-        SocketAddr::V4(v4) => {
-            v4.set_port(v4.port() / 10);
-        }
-        SocketAddr::V6(v6) => {
-            v6.set_port(v6.port() / 10);
+            let worker_senders = self.worker_senders.clone();
+            let address_book = self.address_book.clone();
+            let resolver = self.resolver.clone();
+            // Identify the peer off the accept loop, so that a slow peer does not hold it up
+            tokio::spawn(async move {
+                match timeout(IDENTIFY_TIMEOUT, Self::identify(socket)).await {
+                    Ok(Ok((socket, peer))) => {
+                        if !dialed_from_peer_address(
+                            &address_book,
+                            &resolver,
+                            peer,
+                            remote_peer.ip(),
+                        )
+                        .await
+                        {
+                            tracing::warn!(
+                                "Dropping connection from peer {peer} at {remote_peer}, \
+                                not the address of the peer"
+                            );
+                        } else if let Some(sender) = worker_senders.get(&peer) {
+                            sender.send(socket).ok();
+                        } else {
+                            tracing::warn!(
+                                "Dropping connection from unknown peer {peer} at {remote_peer}"
+                            );
+                        }
+                    }
+                    Ok(Err(err)) => {
+                        tracing::warn!("Failed to identify peer at {remote_peer}: {err}");
+                    }
+                    Err(_) => tracing::warn!("Peer at {remote_peer} did not identify itself"),
+                }
+            });
         }
     }
-    remote_peer
-}
 
-fn bind_addr(mut local_peer: SocketAddr) -> SocketAddr {
-    match &mut local_peer {
-        SocketAddr::V4(v4) => {
-            v4.set_port(v4.port() * 10);
-        }
-        SocketAddr::V6(v6) => {
-            v6.set_port(v6.port() * 10);
+    /// Read the active handshake and the peer id of the dialer.
+    async fn identify(mut socket: TcpStream) -> io::Result<(TcpStream, usize)> {
+        let handshake = socket.read_u64().await?;
+        if handshake != Worker::ACTIVE_HANDSHAKE {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("Invalid active handshake: {handshake}"),
+            ));
         }
+        let peer = socket.read_u64().await?;
+        Ok((socket, peer as usize))
     }
-    local_peer
 }
 
 struct Worker {
-    peer: SocketAddr,
     peer_id: usize,
+    our_id: u64,
     connection_sender: mpsc::Sender<Connection>,
-    bind_addr: SocketAddr,
+    resolver: Resolver,
+    dial: bool,
     active_immediately: bool,
-    latency_sender: HistogramSender<Duration>,
+    outbound: OutboundConnection,
+    latency_sender: Option<HistogramSender<Duration>>,
+    metrics: Arc<Metrics>,
+    /// What we offer to the peer in the handshake.
+    capabilities: Capabilities,
 }
 
 struct WorkerConnection {
     sender: mpsc::Sender<NetworkMessage>,
-    receiver: mpsc::Receiver<NetworkMessage>,
+    receiver: PriorityReceiver,
     peer_id: usize,
-    latency_sender: HistogramSender<Duration>,
+    latency_sender: Option<HistogramSender<Duration>>,
+    metrics: Arc<Metrics>,
 }
 
 impl Worker {
     const ACTIVE_HANDSHAKE: u64 = 0xFEFE0000;
     const PASSIVE_HANDSHAKE: u64 = 0x0000AEAE;
-    const MAX_SIZE: u32 = 16 * 1024 * 1024;
 
-    async fn run(self, mut receiver: mpsc::UnboundedReceiver<TcpStream>) -> Option<()> {
+    /// Keep a connection to the peer at `address`, reconnecting as the address changes.
+    async fn run(
+        self,
+        mut receiver: mpsc::UnboundedReceiver<TcpStream>,
+        mut address: watch::Receiver<PeerAddress>,
+    ) -> Option<()> {
         let initial_delay = if self.active_immediately {
             Duration::ZERO
         } else {
             sample_delay(Duration::from_secs(1)..Duration::from_secs(5))
         };
-        let mut work = self.connect_and_handle(initial_delay, self.peer).boxed();
+        let peer = address.borrow_and_update().clone();
+        let mut work = self.connect_and_handle(initial_delay, peer).boxed();
         loop {
-            match select(work, receiver.recv().boxed()).await {
-                Either::Left((_work, _receiver)) => {
+            select! {
+                _work = &mut work => {
                     let delay = sample_delay(Duration::from_secs(1)..Duration::from_secs(5));
-                    work = self.connect_and_handle(delay, self.peer).boxed();
+                    work = self.connect_and_handle(delay, address.borrow().clone()).boxed();
                 }
-                Either::Right((received, _work)) => {
+                received = receiver.recv() => {
                     if let Some(received) = received {
-                        tracing::debug!("Replaced connection for {}", self.peer_id);
-                        work = self.handle_passive_stream(received).boxed();
+                        if self.outbound.is_established() {
+                            tracing::debug!("Kept the connection dialed to {}", self.peer_id);
+                        } else {
+                            tracing::debug!("Replaced connection for {}", self.peer_id);
+                            work = self.handle_passive_stream(received).boxed();
+                        }
                     } else {
                         // Channel closed, server is terminated
                         return None;
                     }
                 }
+                Ok(()) = address.changed() => {
+                    let peer = address.borrow_and_update().clone();
+                    tracing::info!("Reconnecting to {} at {peer}", self.peer_id);
+                    let delay = sample_delay(Duration::from_secs(1)..Duration::from_secs(5));
+                    work = self.connect_and_handle(delay, peer).boxed();
+                }
             }
         }
     }
 
-    async fn connect_and_handle(&self, delay: Duration, peer: SocketAddr) -> io::Result<()> {
+    async fn connect_and_handle(&self, delay: Duration, peer: PeerAddress) -> io::Result<()> {
+        if !self.dial {
+            // Wait for the peer to connect to us.
+            return futures::future::pending().await;
+        }
         // this is critical to avoid race between active and passive connections
         runtime::sleep(delay).await;
         let mut stream = loop {
-            let socket = if self.bind_addr.is_ipv4() {
-                TcpSocket::new_v4().unwrap()
-            } else {
-                TcpSocket::new_v6().unwrap()
-            };
-            socket.set_reuseport(true).unwrap();
-            socket.bind(self.bind_addr).unwrap();
-            match socket.connect(peer).await {
-                Ok(stream) => break stream,
-                Err(_err) => {
-                    tokio::time::sleep(Duration::from_secs(1)).await;
-                }
+            // Resolve the peer again on every attempt, its name may point elsewhere by now
+            if let Some(stream) = self.connect(&peer).await {
+                break stream;
             }
+            tokio::time::sleep(Duration::from_secs(1)).await;
         };
         stream.set_nodelay(true)?;
         stream.write_u64(Self::ACTIVE_HANDSHAKE).await?;
+        stream.write_u64(self.our_id).await?;
         let handshake = stream.read_u64().await?;
         if handshake != Self::PASSIVE_HANDSHAKE {
             tracing::warn!("Invalid passive handshake: {handshake}");
             return Ok(());
         }
-        let Some(connection) = self.make_connection().await else {
+        let (mut reader, mut writer) = stream.split();
+        let negotiation =
+            protocol::handshake(&self.capabilities, &mut writer, &mut reader, self.peer_id);
+        let Some(protocol) = negotiation.await? else {
+            return Ok(());
+        };
+        let Some(connection) = self.make_connection(None).await else {
             // todo - pass signal to break the main loop
             return Ok(());
         };
-        Self::handle_stream(stream, connection).await
+        let _outbound = self.outbound.establish();
+        Self::handle_stream(stream, connection, protocol).await
+    }
+
+    /// Dial the addresses the peer resolves to in turn.
+    async fn connect(&self, peer: &PeerAddress) -> Option<TcpStream> {
+        let addresses = match self.resolver.resolve(peer).await {
+            Ok(addresses) => addresses,
+            Err(err) => {
+                tracing::debug!("Failed to resolve peer {}: {err}", self.peer_id);
+                return None;
+            }
+        };
+        for address in addresses {
+            if let Ok(stream) = TcpStream::connect(address).await {
+                return Some(stream);
+            }
+        }
+        None
     }
 
+    /// The server already read the active handshake and the peer id, see `Server::identify`.
     async fn handle_passive_stream(&self, mut stream: TcpStream) -> io::Result<()> {
         stream.set_nodelay(true)?;
+        let incoming_from = stream.peer_addr()?.ip();
         stream.write_u64(Self::PASSIVE_HANDSHAKE).await?;
-        let handshake = stream.read_u64().await?;
-        if handshake != Self::ACTIVE_HANDSHAKE {
-            tracing::warn!("Invalid active handshake: {handshake}");
+        let (mut reader, mut writer) = stream.split();
+        let negotiation =
+            protocol::handshake(&self.capabilities, &mut writer, &mut reader, self.peer_id);
+        let Some(protocol) = negotiation.await? else {
             return Ok(());
-        }
-        let Some(connection) = self.make_connection().await else {
+        };
+        let Some(connection) = self.make_connection(Some(incoming_from)).await else {
             // todo - pass signal to break the main loop
             return Ok(());
         };
-        Self::handle_stream(stream, connection).await
+        Self::handle_stream(stream, connection, protocol).await
     }
 
-    async fn handle_stream(stream: TcpStream, connection: WorkerConnection) -> io::Result<()> {
+    async fn handle_stream(
+        stream: TcpStream,
+        connection: WorkerConnection,
+        protocol: Protocol,
+    ) -> io::Result<()> {
         let WorkerConnection {
             sender,
             receiver,
             peer_id,
             latency_sender,
+            metrics,
         } = connection;
         tracing::debug!("Connected to {}", peer_id);
         let (reader, writer) = stream.into_split();
         let (pong_sender, pong_receiver) = mpsc::channel(16);
-        let write_fut =
-            Self::handle_write_stream(writer, receiver, pong_receiver, latency_sender).boxed();
-        let read_fut = Self::handle_read_stream(reader, sender, pong_sender).boxed();
+        let peer = peer_id as AuthorityIndex;
+        let write_fut = Self::handle_write_stream(
+            writer,
+            receiver,
+            pong_receiver,
+            latency_sender,
+            protocol,
+            peer,
+            metrics.clone(),
+        )
+        .boxed();
+        let read_fut =
+            Self::handle_read_stream(reader, sender, pong_sender, protocol, peer, metrics)
+                .boxed();
         let (r, _, _) = select_all([write_fut, read_fut]).await;
         tracing::debug!("Disconnected from {}", peer_id);
         r
@@ -290,10 +797,14 @@ impl Worker {
 
     async fn handle_write_stream(
         mut writer: OwnedWriteHalf,
-        mut receiver: mpsc::Receiver<NetworkMessage>,
+        mut receiver: PriorityReceiver,
         mut pong_receiver: mpsc::Receiver<i64>,
-        latency_sender: HistogramSender<Duration>,
+        latency_sender: Option<HistogramSender<Duration>>,
+        protocol: Protocol,
+        peer: AuthorityIndex,
+        metrics: Arc<Metrics>,
     ) -> io::Result<()> {
+        let peer_label = format_authority_index(peer).to_string();
         let start = Instant::now();
         let mut ping_deadline = start + PING_INTERVAL;
         loop {
@@ -336,7 +847,9 @@ impl Worker {
                                 let time = start.elapsed().as_micros() as u64;
                                 match time.checked_sub(our_ping) {
                                     Some(delay) => {
-                                        latency_sender.observe(Duration::from_micros(delay));
+                                        if let Some(latency_sender) = &latency_sender {
+                                            latency_sender.observe(Duration::from_micros(delay));
+                                        }
                                     },
                                     None => {
                                         tracing::warn!("Invalid ping: {ping}, greater then current time {time}");
@@ -355,9 +868,12 @@ impl Worker {
                 received = receiver.recv() => {
                     // todo - pass signal to break main loop
                     let Some(message) = received else {return Ok(())};
-                    let serialized = bincode::serialize(&message).expect("Serialization should not fail");
-                    writer.write_u32(serialized.len() as u32).await?;
-                    writer.write_all(&serialized).await?;
+                    let frame = encode_frame(protocol.compression(), protocol.encode(&message));
+                    metrics.frame_sent(peer, &message, frame.len());
+                    // The time spent in the write is the time the peer (or the kernel buffers)
+                    // kept us waiting
+                    let _blocked = metrics.network_send_blocked_us.utilization_timer(&peer_label);
+                    writer.write_all(&frame).await?;
                 }
             }
         }
@@ -367,14 +883,17 @@ impl Worker {
         mut stream: OwnedReadHalf,
         sender: mpsc::Sender<NetworkMessage>,
         pong_sender: mpsc::Sender<i64>,
+        protocol: Protocol,
+        peer: AuthorityIndex,
+        metrics: Arc<Metrics>,
     ) -> io::Result<()> {
         // stdlib has a special fast implementation for generating n-size byte vectors,
         // see impl SpecFromElem for u8
-        // Note that Box::new([0u8; Self::MAX_SIZE as usize]); does not work with large MAX_SIZE
-        let mut buf = vec![0u8; Self::MAX_SIZE as usize].into_boxed_slice();
+        // Note that Box::new([0u8; MAX_FRAME_SIZE as usize]); does not work with large MAX_FRAME_SIZE
+        let mut buf = vec![0u8; MAX_FRAME_SIZE as usize].into_boxed_slice();
         loop {
             let size = stream.read_u32().await?;
-            if size > Self::MAX_SIZE {
+            if size > MAX_FRAME_SIZE {
                 tracing::warn!("Invalid size: {size}");
                 return Ok(());
             }
@@ -392,8 +911,17 @@ impl Worker {
             let buf = &mut buf[..size as usize];
             let read = stream.read_exact(buf).await?;
             assert_eq!(read, buf.len());
-            match bincode::deserialize::<NetworkMessage>(buf) {
-                Ok(message) => {
+            let buf = match decode_frame(protocol.compression(), buf) {
+                Ok(buf) => buf,
+                Err(err) => {
+                    tracing::warn!("Invalid frame: {}", err);
+                    return Ok(());
+                }
+            };
+            match protocol.decode(&buf) {
+                Ok(None) => tracing::debug!("Skipping message of unknown kind from {peer}"),
+                Ok(Some(message)) => {
+                    metrics.frame_received(peer, &message, size as usize + 4);
                     if sender.send(message).await.is_err() {
                         // todo - pass signal to break main loop
                         return Ok(());
@@ -407,12 +935,16 @@ impl Worker {
         }
     }
 
-    async fn make_connection(&self) -> Option<WorkerConnection> {
+    async fn make_connection(&self, incoming_from: Option<IpAddr>) -> Option<WorkerConnection> {
         let (network_in_sender, network_in_receiver) = mpsc::channel(16);
-        let (network_out_sender, network_out_receiver) = mpsc::channel(16);
+        let ([sender, sync_sender, bulk_sender], network_out_receiver) =
+            priority_channel(Some(self.metrics.connection_queue_depth.clone()));
         let connection = Connection {
             peer_id: self.peer_id,
-            sender: network_out_sender,
+            incoming_from,
+            sender,
+            sync_sender,
+            bulk_sender,
             receiver: network_in_receiver,
         };
         self.connection_sender.send(connection).await.ok()?;
@@ -421,10 +953,46 @@ impl Worker {
             receiver: network_out_receiver,
             peer_id: self.peer_id,
             latency_sender: self.latency_sender.clone(),
+            metrics: self.metrics.clone(),
         })
     }
 }
 
+/// Frame the serialized message: its size, then the compression of the frame on compressed
+/// connections, then the (possibly compressed) message.
+pub(crate) fn encode_frame(compression: Compression, serialized: Vec<u8>) -> Vec<u8> {
+    if compression == Compression::None {
+        return [&(serialized.len() as u32).to_be_bytes()[..], &serialized].concat();
+    }
+    let (id, payload) = match compression.maybe_compress(&serialized) {
+        Some(compressed) => (compression.id(), compressed),
+        None => (Compression::None.id(), serialized),
+    };
+    [
+        &(payload.len() as u32 + 1).to_be_bytes()[..],
+        &[id],
+        &payload,
+    ]
+    .concat()
+}
+
+/// Strip the compression header of the frame and decompress it if needed.
+pub(crate) fn decode_frame(compression: Compression, frame: &[u8]) -> io::Result<Cow<'_, [u8]>> {
+    if compression == Compression::None {
+        return Ok(Cow::Borrowed(frame));
+    }
+    let invalid = |message: &str| io::Error::new(io::ErrorKind::InvalidData, message);
+    let (&id, payload) = frame.split_first().ok_or_else(|| invalid("Empty frame"))?;
+    if id == Compression::None.id() {
+        Ok(Cow::Borrowed(payload))
+    } else if id == compression.id() {
+        let decompressed = compression.decompress(payload, MAX_FRAME_SIZE as usize)?;
+        Ok(Cow::Owned(decompressed))
+    } else {
+        Err(invalid("Unexpected frame compression"))
+    }
+}
+
 fn sample_delay(range: Range<Duration>) -> Duration {
     ThreadRng::default().gen_range(range)
 }
@@ -444,11 +1012,134 @@ fn decode_ping(message: &[u8]) -> i64 {
 
 #[cfg(test)]
 mod test {
-    use std::collections::HashSet;
+    use std::{
+        collections::HashSet,
+        net::{Ipv4Addr, SocketAddrV4},
+    };
 
     use prometheus::Registry;
 
-    use crate::{committee::Committee, metrics::Metrics, test_util::networks_and_addresses};
+    use super::*;
+    use crate::{
+        committee::Committee,
+        metrics::Metrics,
+        test_util::networks_and_addresses,
+        types::{BaseStatement, Transaction},
+    };
+
+    #[test]
+    fn decode_frame_test() {
+        let message = vec![7u8; 4096];
+        let compressed = Compression::Deflate.compress(&message).unwrap();
+        let frame = [&[Compression::Deflate.id()], &compressed[..]].concat();
+        assert_eq!(
+            decode_frame(Compression::Deflate, &frame).unwrap().as_ref(),
+            &message
+        );
+        let frame = [&[Compression::None.id()], &message[..]].concat();
+        assert_eq!(
+            decode_frame(Compression::Deflate, &frame).unwrap().as_ref(),
+            &message
+        );
+        // Uncompressed connections have no frame header
+        assert_eq!(
+            decode_frame(Compression::None, &message).unwrap().as_ref(),
+            &message
+        );
+        assert!(decode_frame(Compression::Deflate, &[]).is_err());
+        assert!(decode_frame(Compression::Deflate, &[9, 1, 2]).is_err());
+    }
+
+    #[tokio::test]
+    async fn dialed_from_peer_address_test() {
+        let address = SocketAddr::V4(SocketAddrV4::new(Ipv4Addr::LOCALHOST, 5400));
+        let address_book = AddressBook::new([(0, address.into())]);
+        let resolver = Resolver::default();
+        let dialed_from = |peer, remote: &str| {
+            dialed_from_peer_address(&address_book, &resolver, peer, remote.parse().unwrap())
+        };
+        assert!(dialed_from(0, "127.0.0.1").await);
+        assert!(dialed_from(0, "::ffff:127.0.0.1").await);
+        // Another host declaring the peer id of the peer
+        assert!(!dialed_from(0, "10.0.0.1").await);
+        assert!(!dialed_from(1, "127.0.0.1").await);
+
+        let outbound = OutboundConnection::default();
+        let guard = outbound.establish();
+        assert!(outbound.is_established());
+        drop(guard);
+        assert!(!outbound.is_established());
+    }
+
+    #[tokio::test]
+    async fn network_compression_test() {
+        let committee = Committee::new_test(vec![1, 1]);
+        let addresses: Vec<_> = (0..2)
+            .map(|i| SocketAddr::V4(SocketAddrV4::new(Ipv4Addr::LOCALHOST, 5401 + i)))
+            .collect();
+        let metrics: Vec<_> = committee
+            .authorities()
+            .map(|_| Metrics::new(&Registry::default(), Some(&committee)).0)
+            .collect();
+        let networks = addresses.iter().enumerate().map(|(i, address)| {
+            Network::from_socket_addresses(
+                &addresses,
+                &[],
+                i,
+                *address,
+                metrics[i].clone(),
+                Capabilities::new(Compression::Deflate),
+                TransportProtocol::Tcp,
+                &Topology::Mesh,
+            )
+        });
+        let mut networks = futures::future::join_all(networks).await;
+        let mut connections = Vec::new();
+        for network in &mut networks {
+            connections.push(network.connection_receiver().recv().await.unwrap());
+        }
+
+        let transactions = (0..64)
+            .map(|_| BaseStatement::Share(Transaction::new(vec![0u8; 512])))
+            .collect();
+        let block = Data::new(StatementBlock::new(
+            0,
+            1,
+            vec![],
+            transactions,
+            0,
+            false,
+            Default::default(),
+        ));
+        connections[0]
+            .sender
+            .send(NetworkMessage::Block(block.clone()))
+            .await
+            .unwrap();
+        let Some(NetworkMessage::Block(received)) = connections[1].receiver.recv().await else {
+            panic!("Expected a block");
+        };
+        assert_eq!(received.serialized_bytes(), block.serialized_bytes());
+
+        // Both ends account for the compressed frame
+        let sent = metrics[0]
+            .network_sent_bytes_total
+            .with_label_values(&["B", "block"])
+            .get();
+        let received = metrics[1]
+            .network_received_bytes_total
+            .with_label_values(&["A", "block"])
+            .get();
+        assert_eq!(sent, received);
+        assert!(sent > 0 && sent < block.serialized_bytes().len() as u64);
+        assert_eq!(
+            metrics[1]
+                .network_frame_size_bytes
+                .with_label_values(&["received"])
+                .get_sample_count(),
+            1
+        );
+    }
 
     #[ignore]
     #[tokio::test]
@@ -0,0 +1,139 @@
+// Copyright (c) Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+use std::{path::Path, sync::Arc};
+
+use ::prometheus::Registry;
+use eyre::{eyre, Context, Result};
+
+use crate::{
+    block_handler::{TestBlockHandler, TestCommitHandler},
+    block_store::BlockStore,
+    committee::Committee,
+    config::NodePublicConfig,
+    core::{Core, CoreOptions},
+    log::TransactionLog,
+    metrics::Metrics,
+    net_sync::NetworkSyncer,
+    network::Network,
+    prometheus,
+    resolver::PeerAddress,
+    runtime::{JoinError, JoinHandle},
+    types::AuthorityIndex,
+    wal::{self, walf},
+};
+
+/// A read-only node following consensus. The observer subscribes to the blocks of the validators
+/// it follows, stores them, and runs the commit rule locally to reconstruct the commit sequence.
+/// It never proposes blocks and does not hold any stake.
+pub struct Observer {
+    network_synchronizer: NetworkSyncer<TestBlockHandler, TestCommitHandler<TransactionLog>>,
+    metrics_handle: JoinHandle<Result<(), hyper::Error>>,
+}
+
+impl Observer {
+    pub async fn start(
+        observer: usize,
+        committee: Arc<Committee>,
+        public_config: NodePublicConfig,
+        storage_path: &Path,
+    ) -> Result<Self> {
+        let identifier = public_config
+            .observer(observer)
+            .ok_or(eyre!("No configuration for observer {observer}"))
+            .wrap_err("Unknown observer")?;
+        let network_address = identifier.network_address;
+        let binding_network_address = PeerAddress::from(network_address).default_listen_address();
+
+        let metrics_address = identifier.metrics_address;
+        let binding_metrics_address = PeerAddress::from(metrics_address).default_listen_address();
+
+        // The observer is identified by a peer id that does not collide with any authority.
+        let observer_id = public_config.observer_peer_id(observer) as AuthorityIndex;
+
+        // Boot the prometheus server.
+        let registry = Registry::new();
+        let (metrics, reporter) = Metrics::new(&registry, Some(&committee));
+        reporter.start();
+
+        let metrics_handle =
+            prometheus::start_prometheus_server(binding_metrics_address, &registry);
+
+        // Open the block store.
+        let wal_file =
+            wal::open_file_for_wal(storage_path.join("wal")).expect("Failed to open wal file");
+        let (mut wal_writer, wal_reader) = walf(wal_file).expect("Failed to open wal");
+        wal_writer.set_compression(public_config.parameters.wal_compression);
+        let recovered = BlockStore::open(
+            observer_id,
+            Arc::new(wal_reader),
+            &wal_writer,
+            metrics.clone(),
+            &committee,
+            public_config.parameters.block_cache_size,
+        );
+
+        // The block handler is never invoked since the observer does not propose blocks.
+        let block_handler =
+            TestBlockHandler::new(0, committee.clone(), observer_id, metrics.clone());
+        let committed_transaction_log = TransactionLog::start(storage_path.join("committed.txt"))
+            .expect("Failed to open committed transaction log for write");
+        let commit_handler = TestCommitHandler::new_with_handler(
+            committee.clone(),
+            block_handler.transaction_time.clone(),
+            metrics.clone(),
+            committed_transaction_log,
+            observer_id,
+        );
+        let core = Core::open_observer(
+            block_handler,
+            observer_id,
+            committee.clone(),
+            &public_config,
+            metrics.clone(),
+            recovered,
+            wal_writer,
+            CoreOptions::default(),
+        );
+        let network = Network::load_observer(
+            &public_config,
+            observer,
+            binding_network_address,
+            metrics.clone(),
+        )
+        .await;
+        let network_synchronizer = NetworkSyncer::start(
+            network,
+            core,
+            public_config.parameters.wave_length,
+            commit_handler,
+            public_config.parameters.shutdown_grace_period,
+            metrics,
+            &public_config,
+        );
+
+        tracing::info!("Observer {observer} listening on {network_address}");
+        tracing::info!("Observer {observer} exposing metrics on {metrics_address}");
+
+        Ok(Self {
+            network_synchronizer,
+            metrics_handle,
+        })
+    }
+
+    pub async fn await_completion(
+        self,
+    ) -> (
+        Result<(), JoinError>,
+        Result<Result<(), hyper::Error>, JoinError>,
+    ) {
+        tokio::join!(
+            self.network_synchronizer.await_completion(),
+            self.metrics_handle
+        )
+    }
+
+    pub async fn stop(self) {
+        self.network_synchronizer.shutdown().await;
+    }
+}
@@ -0,0 +1,201 @@
+// Copyright (c) Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! The messages sent to a peer are queued by priority class, so that a large sync response or a
+//! peer catching up does not delay our fresh blocks. The classes share the connection by weighted
+//! fair queueing: every class gets a share of the bytes sent proportional to its weight, as long
+//! as it has messages queued, and the unused share of idle classes goes to the others.
+
+use std::{collections::VecDeque, future::poll_fn, task::Poll};
+
+use prometheus::IntGaugeVec;
+use tokio::sync::mpsc;
+
+use crate::network::NetworkMessage;
+
+/// The number of messages queued per class before senders wait.
+const QUEUE_CAPACITY: usize = 16;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Priority {
+    /// Fresh blocks, chunks and batches, and the protocol messages.
+    Fresh,
+    /// The responses to the requests of the peer.
+    Sync,
+    /// The blocks streamed to a peer catching up.
+    Bulk,
+}
+
+impl Priority {
+    pub const ALL: [Priority; 3] = [Priority::Fresh, Priority::Sync, Priority::Bulk];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Fresh => "fresh",
+            Self::Sync => "sync",
+            Self::Bulk => "bulk",
+        }
+    }
+
+    fn weight(&self) -> u64 {
+        match self {
+            Self::Fresh => 8,
+            Self::Sync => 2,
+            Self::Bulk => 1,
+        }
+    }
+}
+
+/// The senders of every class, in the order of `Priority::ALL`, and the receiver scheduling them.
+/// The queue depth of every class is reported to `depth`, if any.
+pub fn priority_channel(
+    depth: Option<IntGaugeVec>,
+) -> ([mpsc::Sender<NetworkMessage>; 3], PriorityReceiver) {
+    let (fresh_sender, fresh_receiver) = mpsc::channel(1);
+    let (sync_sender, sync_receiver) = mpsc::channel(1);
+    let (bulk_sender, bulk_receiver) = mpsc::channel(1);
+    let receiver = PriorityReceiver {
+        receivers: [
+            Some(fresh_receiver),
+            Some(sync_receiver),
+            Some(bulk_receiver),
+        ],
+        queues: Default::default(),
+        tags: [0; 3],
+        virtual_time: 0,
+        depth,
+    };
+    ([fresh_sender, sync_sender, bulk_sender], receiver)
+}
+
+pub struct PriorityReceiver {
+    // None once all senders of the class are dropped
+    receivers: [Option<mpsc::Receiver<NetworkMessage>>; 3],
+    queues: [VecDeque<(NetworkMessage, u64)>; 3],
+    // The virtual time at which the first queued message of each class starts, see `recv`
+    tags: [u64; 3],
+    virtual_time: u64,
+    depth: Option<IntGaugeVec>,
+}
+
+impl PriorityReceiver {
+    /// The next message to send, None once the senders of all classes are dropped.
+    ///
+    /// This is start-time fair queueing: sending a message of a class moves the virtual time of
+    /// the class forward by the size of the message divided by the weight of the class, and the
+    /// class furthest behind in virtual time goes next. A class that had nothing queued starts
+    /// again from the current virtual time, so that it can not save up for a burst.
+    pub async fn recv(&mut self) -> Option<NetworkMessage> {
+        loop {
+            self.fill();
+            let next = (0..Priority::ALL.len())
+                .filter(|class| !self.queues[*class].is_empty())
+                .min_by_key(|class| self.tags[*class]);
+            if let Some(class) = next {
+                let (message, size) = self.queues[class].pop_front().expect("Queue is not empty");
+                self.virtual_time = self.tags[class];
+                self.tags[class] += size * Priority::Fresh.weight() / Priority::ALL[class].weight();
+                self.report_depth(class, -1);
+                return Some(message);
+            }
+            if self.receivers.iter().all(Option::is_none) {
+                return None;
+            }
+            let (class, message) = poll_fn(|cx| {
+                for (class, receiver) in self.receivers.iter_mut().enumerate() {
+                    if let Some(receiver) = receiver {
+                        if let Poll::Ready(message) = receiver.poll_recv(cx) {
+                            return Poll::Ready((class, message));
+                        }
+                    }
+                }
+                Poll::Pending
+            })
+            .await;
+            match message {
+                Some(message) => self.push(class, message),
+                None => self.receivers[class] = None,
+            }
+        }
+    }
+
+    /// Move the messages waiting in the channels to the queues.
+    fn fill(&mut self) {
+        for class in 0..Priority::ALL.len() {
+            while self.queues[class].len() < QUEUE_CAPACITY {
+                let Some(receiver) = &mut self.receivers[class] else {
+                    break;
+                };
+                match receiver.try_recv() {
+                    Ok(message) => self.push(class, message),
+                    Err(mpsc::error::TryRecvError::Empty) => break,
+                    Err(mpsc::error::TryRecvError::Disconnected) => self.receivers[class] = None,
+                }
+            }
+        }
+    }
+
+    fn push(&mut self, class: usize, message: NetworkMessage) {
+        if self.queues[class].is_empty() {
+            self.tags[class] = self.tags[class].max(self.virtual_time);
+        }
+        let size = bincode::serialized_size(&message).expect("Serialization should not fail");
+        self.queues[class].push_back((message, size));
+        self.report_depth(class, 1);
+    }
+
+    fn report_depth(&self, class: usize, delta: i64) {
+        if let Some(depth) = &self.depth {
+            depth
+                .with_label_values(&[Priority::ALL[class].name()])
+                .add(delta);
+        }
+    }
+}
+
+impl Drop for PriorityReceiver {
+    fn drop(&mut self) {
+        for class in 0..Priority::ALL.len() {
+            self.report_depth(class, -(self.queues[class].len() as i64));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn weighted_fair_queueing() {
+        let ([fresh, sync, bulk], mut receiver) = priority_channel(None);
+        let message = NetworkMessage::SubscribeOwnFrom;
+        // Fill the sync and bulk classes first
+        for round in 0..QUEUE_CAPACITY as u64 {
+            sync.send(message(round)).await.unwrap();
+            bulk.send(message(100 + round)).await.unwrap();
+            receiver.fill();
+        }
+        fresh.send(message(200)).await.unwrap();
+        let Some(NetworkMessage::SubscribeOwnFrom(first)) = receiver.recv().await else {
+            panic!("Expected a message");
+        };
+        // All classes start at the same virtual time, the first class goes first
+        assert_eq!(first, 200);
+        let mut received = vec![];
+        for _ in 0..10 {
+            let Some(NetworkMessage::SubscribeOwnFrom(round)) = receiver.recv().await else {
+                panic!("Expected a message");
+            };
+            received.push(round);
+        }
+        // Sync messages are sent twice as often as bulk messages, in order
+        assert_eq!(received, vec![0, 100, 1, 2, 101, 3, 4, 102, 5, 6]);
+
+        drop((fresh, sync, bulk));
+        let mut remaining = 0;
+        while receiver.recv().await.is_some() {
+            remaining += 1;
+        }
+        assert_eq!(remaining, 2 * QUEUE_CAPACITY - 10);
+    }
+}
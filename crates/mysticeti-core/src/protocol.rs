@@ -0,0 +1,319 @@
+// Copyright (c) Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Versioning of the wire protocol. Right after connecting, both ends send a `Hello` with the
+//! range of protocol versions they speak and the features they support. The connection then uses
+//! the newest version both speak and the features both support, so that peers running different
+//! releases keep talking to each other. Peers sharing no version with us, or lacking a feature we
+//! require, are rejected.
+//!
+//! Versions:
+//! 1. Frames carry bare messages.
+//! 2. Frames carry messages in an envelope tagged with the kind of the message, so that peers skip
+//!    the kinds of messages they do not know rather than dropping the connection. New kinds of
+//!    messages can thus be added without a new version.
+
+use std::{
+    fmt,
+    io,
+    ops::{BitAnd, BitOr},
+};
+
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+use crate::{
+    batch::Dissemination,
+    compression::Compression,
+    config::NodeParameters,
+    network::NetworkMessage,
+};
+
+pub type ProtocolVersion = u16;
+
+/// The newest protocol version.
+pub const PROTOCOL_VERSION: ProtocolVersion = 2;
+/// The oldest protocol version we still speak.
+pub const MIN_PROTOCOL_VERSION: ProtocolVersion = 1;
+/// The first version sending messages in an envelope.
+const ENVELOPE_VERSION: ProtocolVersion = 2;
+/// Every hello starts with these bytes, to tell apart peers that do not speak this protocol.
+const HELLO_MAGIC: u32 = 0x4D59_5354;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Features(u64);
+
+impl Features {
+    pub const NONE: Self = Self(0);
+    /// Frames may be compressed with deflate.
+    pub const DEFLATE: Self = Self(1);
+    /// Batches of transactions may be disseminated and requested, see `Dissemination::Batches`.
+    pub const BATCH_SYNC: Self = Self(1 << 1);
+
+    pub fn contains(&self, other: Self) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    /// The features of `self` missing from `other`.
+    pub fn missing_from(&self, other: Self) -> Self {
+        Self(self.0 & !other.0)
+    }
+}
+
+impl BitOr for Features {
+    type Output = Self;
+
+    fn bitor(self, other: Self) -> Self {
+        Self(self.0 | other.0)
+    }
+}
+
+impl BitAnd for Features {
+    type Output = Self;
+
+    fn bitand(self, other: Self) -> Self {
+        Self(self.0 & other.0)
+    }
+}
+
+/// What we offer to our peers in the handshake.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Capabilities {
+    pub min_version: ProtocolVersion,
+    pub max_version: ProtocolVersion,
+    pub features: Features,
+    /// Peers lacking any of these features are rejected.
+    pub required: Features,
+}
+
+impl Capabilities {
+    /// Speak all the versions we know, offering the given compression and requiring nothing.
+    pub fn new(compression: Compression) -> Self {
+        let compression = match compression {
+            Compression::None => Features::NONE,
+            Compression::Deflate => Features::DEFLATE,
+        };
+        Self {
+            min_version: MIN_PROTOCOL_VERSION,
+            max_version: PROTOCOL_VERSION,
+            features: compression | Features::BATCH_SYNC,
+            required: Features::NONE,
+        }
+    }
+
+    pub fn from_parameters(parameters: &NodeParameters) -> Self {
+        let mut capabilities = Self::new(parameters.network_compression);
+        // Our blocks reference batches that the peers must be able to fetch from us
+        if parameters.dissemination == Dissemination::Batches {
+            capabilities.required = Features::BATCH_SYNC;
+        }
+        capabilities
+    }
+
+    pub fn with_versions(mut self, min_version: ProtocolVersion, max: ProtocolVersion) -> Self {
+        self.min_version = min_version;
+        self.max_version = max;
+        self
+    }
+
+    pub fn hello(&self) -> Hello {
+        Hello {
+            min_version: self.min_version,
+            max_version: self.max_version,
+            features: self.features,
+        }
+    }
+
+    /// The protocol to speak with the peer that sent `hello`, or why the peer is rejected.
+    pub fn negotiate(&self, hello: &Hello) -> Result<Protocol, Rejection> {
+        let version = self.max_version.min(hello.max_version);
+        if version < self.min_version.max(hello.min_version) {
+            return Err(Rejection::NoCommonVersion {
+                ours: (self.min_version, self.max_version),
+                theirs: (hello.min_version, hello.max_version),
+            });
+        }
+        if !hello.features.contains(self.required) {
+            return Err(Rejection::MissingFeatures(
+                self.required.missing_from(hello.features),
+            ));
+        }
+        Ok(Protocol {
+            version,
+            features: self.features & hello.features,
+        })
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Hello {
+    pub min_version: ProtocolVersion,
+    pub max_version: ProtocolVersion,
+    pub features: Features,
+}
+
+impl Hello {
+    pub async fn write(&self, writer: &mut (impl AsyncWrite + Unpin)) -> io::Result<()> {
+        let mut hello = Vec::with_capacity(16);
+        hello.extend_from_slice(&HELLO_MAGIC.to_be_bytes());
+        hello.extend_from_slice(&self.min_version.to_be_bytes());
+        hello.extend_from_slice(&self.max_version.to_be_bytes());
+        hello.extend_from_slice(&self.features.0.to_be_bytes());
+        writer.write_all(&hello).await
+    }
+
+    /// Read the hello of the peer, None if the peer does not speak this protocol.
+    pub async fn read(reader: &mut (impl AsyncRead + Unpin)) -> io::Result<Option<Self>> {
+        if reader.read_u32().await? != HELLO_MAGIC {
+            return Ok(None);
+        }
+        Ok(Some(Self {
+            min_version: reader.read_u16().await?,
+            max_version: reader.read_u16().await?,
+            features: Features(reader.read_u64().await?),
+        }))
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum Rejection {
+    NoCommonVersion {
+        ours: (ProtocolVersion, ProtocolVersion),
+        theirs: (ProtocolVersion, ProtocolVersion),
+    },
+    MissingFeatures(Features),
+}
+
+impl fmt::Display for Rejection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NoCommonVersion { ours, theirs } => write!(
+                f,
+                "no common protocol version, we speak {}..={} and the peer {}..={}",
+                ours.0, ours.1, theirs.0, theirs.1
+            ),
+            Self::MissingFeatures(missing) => {
+                write!(f, "the peer lacks the required features {:#x}", missing.0)
+            }
+        }
+    }
+}
+
+/// The protocol spoken on a connection.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Protocol {
+    pub version: ProtocolVersion,
+    pub features: Features,
+}
+
+impl Protocol {
+    pub fn compression(&self) -> Compression {
+        if self.features.contains(Features::DEFLATE) {
+            Compression::Deflate
+        } else {
+            Compression::None
+        }
+    }
+
+    pub fn encode(&self, message: &NetworkMessage) -> Vec<u8> {
+        let mut encoded = vec![];
+        if self.version >= ENVELOPE_VERSION {
+            encoded.extend_from_slice(&message.kind().to_le_bytes());
+        }
+        bincode::serialize_into(&mut encoded, message).expect("Serialization should not fail");
+        encoded
+    }
+
+    /// Decode a message, None if the message is of a kind we do not know.
+    pub fn decode(&self, encoded: &[u8]) -> Result<Option<NetworkMessage>, bincode::Error> {
+        if self.version < ENVELOPE_VERSION {
+            return bincode::deserialize(encoded).map(Some);
+        }
+        let Some((kind, message)) = encoded.split_first_chunk::<2>() else {
+            return Err(bincode::ErrorKind::Custom("Truncated envelope".to_string()).into());
+        };
+        if u16::from_le_bytes(*kind) >= NetworkMessage::KINDS {
+            return Ok(None);
+        }
+        bincode::deserialize(message).map(Some)
+    }
+}
+
+/// Exchange hellos with the peer, returns the protocol to speak or None if the peer is rejected.
+pub async fn handshake(
+    capabilities: &Capabilities,
+    writer: &mut (impl AsyncWrite + Unpin),
+    reader: &mut (impl AsyncRead + Unpin),
+    peer_id: usize,
+) -> io::Result<Option<Protocol>> {
+    capabilities.hello().write(writer).await?;
+    let Some(hello) = Hello::read(reader).await? else {
+        tracing::warn!("Rejecting peer {peer_id}: the peer does not speak our protocol");
+        return Ok(None);
+    };
+    match capabilities.negotiate(&hello) {
+        Ok(protocol) => {
+            tracing::debug!("Using {protocol:?} with {peer_id}");
+            Ok(Some(protocol))
+        }
+        Err(rejection) => {
+            tracing::warn!("Rejecting peer {peer_id}: {rejection}");
+            Ok(None)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn negotiate() {
+        let ours = Capabilities::new(Compression::Deflate);
+        // Peers speaking an older version are downgraded to it
+        let old = Capabilities::new(Compression::None).with_versions(1, 1);
+        let protocol = ours.negotiate(&old.hello()).unwrap();
+        assert_eq!(protocol.version, 1);
+        assert_eq!(protocol.compression(), Compression::None);
+        assert_eq!(old.negotiate(&ours.hello()), Ok(protocol));
+
+        let protocol = ours.negotiate(&ours.hello()).unwrap();
+        assert_eq!(protocol.version, PROTOCOL_VERSION);
+        assert_eq!(protocol.compression(), Compression::Deflate);
+
+        // Peers sharing no version, or lacking a required feature, are rejected
+        let new = ours.with_versions(PROTOCOL_VERSION + 1, PROTOCOL_VERSION + 2);
+        assert!(matches!(
+            ours.negotiate(&new.hello()),
+            Err(Rejection::NoCommonVersion { .. })
+        ));
+        let mut requiring = ours;
+        requiring.required = Features::DEFLATE | Features::BATCH_SYNC;
+        assert_eq!(
+            requiring.negotiate(&old.hello()),
+            Err(Rejection::MissingFeatures(Features::DEFLATE))
+        );
+    }
+
+    #[test]
+    fn envelope() {
+        let message = NetworkMessage::SubscribeOwnFrom(7);
+        for version in [1, 2] {
+            let protocol = Protocol {
+                version,
+                features: Features::NONE,
+            };
+            let encoded = protocol.encode(&message);
+            let Ok(Some(NetworkMessage::SubscribeOwnFrom(7))) = protocol.decode(&encoded) else {
+                panic!("Failed to decode message of version {version}");
+            };
+        }
+        // Messages of unknown kinds are skipped
+        let protocol = Protocol {
+            version: 2,
+            features: Features::NONE,
+        };
+        let mut encoded = protocol.encode(&message);
+        encoded[..2].copy_from_slice(&NetworkMessage::KINDS.to_le_bytes());
+        assert!(matches!(protocol.decode(&encoded), Ok(None)));
+    }
+}
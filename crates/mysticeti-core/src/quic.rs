@@ -0,0 +1,525 @@
+// Copyright (c) Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! The QUIC transport, see `TransportProtocol::Quic`. Every pair of peers shares a single QUIC
+//! connection, dialed by one end only: the validator of higher index, or the observer. The
+//! messages of every priority class are sent on their own stream, so that a large sync response
+//! only delays the messages queued behind it in its class, and QUIC sends the streams of higher
+//! classes first. As with TCP, the dialer identifies itself by sending its peer id on the first
+//! stream, only accepted from the address of that peer, and the messages are authenticated by
+//! their signatures: the certificates are self-signed and not verified.
+
+use std::{
+    collections::HashMap,
+    io,
+    net::{IpAddr, SocketAddr},
+    sync::Arc,
+    time::{Duration, SystemTime},
+};
+
+use futures::{future::select_all, FutureExt};
+use rustls::{
+    client::{ServerCertVerified, ServerCertVerifier},
+    Certificate,
+    PrivateKey,
+    ServerName,
+};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    runtime::Handle,
+    select,
+    sync::{mpsc, watch},
+    task::JoinHandle,
+    time::timeout,
+};
+
+use crate::{
+    address_book::AddressBook,
+    metrics::{Metrics, UtilizationTimerVecExt},
+    network::{
+        decode_frame,
+        dialed_from_peer_address,
+        encode_frame,
+        Connection,
+        NetworkMessage,
+        OutboundConnection,
+        Peer,
+        Transport,
+        IDENTIFY_TIMEOUT,
+        MAX_FRAME_SIZE,
+        PING_INTERVAL,
+    },
+    priority::Priority,
+    protocol::{handshake, Capabilities, Protocol},
+    resolver::{PeerAddress, Resolver},
+    runtime,
+    stat::HistogramSender,
+    types::{format_authority_index, AuthorityIndex},
+};
+
+/// The name the certificates are issued for.
+const SERVER_NAME: &str = "mysticeti";
+const RECONNECT_DELAY: Duration = Duration::from_secs(1);
+const KEEP_ALIVE_INTERVAL: Duration = Duration::from_secs(5);
+/// The number of messages queued per class before senders wait.
+const QUEUE_CAPACITY: usize = 16;
+
+/// A connection accepted from a peer, with the stream it identified itself on.
+type Incoming = (quinn::Connection, quinn::SendStream, quinn::RecvStream);
+
+pub(crate) struct QuicTransport {
+    pub local_addr: SocketAddr,
+    pub our_id: u64,
+    /// What we offer to the peers in the handshake.
+    pub capabilities: Capabilities,
+    pub resolver: Resolver,
+}
+
+impl Transport for QuicTransport {
+    async fn start(
+        self,
+        peers: Vec<Peer>,
+        address_book: AddressBook,
+        connection_sender: mpsc::Sender<Connection>,
+        metrics: Arc<Metrics>,
+    ) -> Vec<JoinHandle<()>> {
+        let endpoint = endpoint(self.local_addr);
+        let mut worker_senders: HashMap<usize, mpsc::UnboundedSender<Incoming>> =
+            HashMap::default();
+        let handle = Handle::current();
+        let mut tasks = vec![];
+        for peer in peers {
+            let (sender, receiver) = mpsc::unbounded_channel();
+            assert!(
+                worker_senders.insert(peer.id, sender).is_none(),
+                "Duplicated peer {} in list",
+                peer.id
+            );
+            tasks.push(handle.spawn(
+                Worker {
+                    endpoint: endpoint.clone(),
+                    peer_id: peer.id,
+                    our_id: self.our_id,
+                    resolver: self.resolver.clone(),
+                    dial: peer.dial && peer.active_immediately,
+                    outbound: OutboundConnection::default(),
+                    connection_sender: connection_sender.clone(),
+                    // Observers are not part of the committee and have no latency metric.
+                    latency_sender: metrics.connection_latency_sender.get(peer.id).cloned(),
+                    metrics: metrics.clone(),
+                    capabilities: self.capabilities,
+                }
+                .run(receiver, address_book.subscribe(peer.id)),
+            ));
+        }
+        tasks.push(handle.spawn(accept(
+            endpoint,
+            Arc::new(worker_senders),
+            address_book,
+            self.resolver,
+        )));
+        tasks
+    }
+}
+
+/// An endpoint accepting connections on `local_addr` with a fresh self-signed certificate, and
+/// accepting any certificate from the peers it dials.
+fn endpoint(local_addr: SocketAddr) -> quinn::Endpoint {
+    let certificate = rcgen::generate_simple_self_signed(vec![SERVER_NAME.to_string()])
+        .expect("Failed to generate certificate");
+    let certificate_der = certificate
+        .serialize_der()
+        .expect("Failed to serialize certificate");
+    let key = PrivateKey(certificate.serialize_private_key_der());
+
+    let mut transport = quinn::TransportConfig::default();
+    transport.keep_alive_interval(Some(KEEP_ALIVE_INTERVAL));
+    let transport = Arc::new(transport);
+
+    let mut server_config =
+        quinn::ServerConfig::with_single_cert(vec![Certificate(certificate_der)], key)
+            .expect("Invalid certificate");
+    server_config.transport_config(transport.clone());
+    let crypto = rustls::ClientConfig::builder()
+        .with_safe_defaults()
+        .with_custom_certificate_verifier(Arc::new(SkipServerVerification))
+        .with_no_client_auth();
+    let mut client_config = quinn::ClientConfig::new(Arc::new(crypto));
+    client_config.transport_config(transport);
+
+    let mut endpoint =
+        quinn::Endpoint::server(server_config, local_addr).expect("Failed to bind to local socket");
+    endpoint.set_default_client_config(client_config);
+    endpoint
+}
+
+async fn accept(
+    endpoint: quinn::Endpoint,
+    worker_senders: Arc<HashMap<usize, mpsc::UnboundedSender<Incoming>>>,
+    address_book: AddressBook,
+    resolver: Resolver,
+) {
+    while let Some(connecting) = endpoint.accept().await {
+        let remote_peer = connecting.remote_address();
+        let worker_senders = worker_senders.clone();
+        let address_book = address_book.clone();
+        let resolver = resolver.clone();
+        // Identify the peer off the accept loop, so that a slow peer does not hold it up
+        tokio::spawn(async move {
+            match timeout(IDENTIFY_TIMEOUT, identify(connecting)).await {
+                Ok(Ok((peer, incoming))) => {
+                    if !dialed_from_peer_address(&address_book, &resolver, peer, remote_peer.ip())
+                        .await
+                    {
+                        tracing::warn!(
+                            "Dropping connection from peer {peer} at {remote_peer}, \
+                            not the address of the peer"
+                        );
+                    } else if let Some(sender) = worker_senders.get(&peer) {
+                        sender.send(incoming).ok();
+                    } else {
+                        tracing::warn!(
+                            "Dropping connection from unknown peer {peer} at {remote_peer}"
+                        );
+                    }
+                }
+                Ok(Err(err)) => tracing::warn!("Failed to identify peer at {remote_peer}: {err}"),
+                Err(_) => tracing::warn!("Peer at {remote_peer} did not identify itself"),
+            }
+        });
+    }
+}
+
+/// Accept the first stream of the connection and read the peer id of the dialer from it.
+async fn identify(connecting: quinn::Connecting) -> io::Result<(usize, Incoming)> {
+    let connection = connecting.await.map_err(io::Error::other)?;
+    let (send, mut receive) = connection.accept_bi().await.map_err(io::Error::other)?;
+    let peer = receive.read_u64().await?;
+    Ok((peer as usize, (connection, send, receive)))
+}
+
+struct Worker {
+    endpoint: quinn::Endpoint,
+    peer_id: usize,
+    our_id: u64,
+    resolver: Resolver,
+    /// Whether we dial the peer, otherwise we wait for its connections.
+    dial: bool,
+    outbound: OutboundConnection,
+    connection_sender: mpsc::Sender<Connection>,
+    latency_sender: Option<HistogramSender<Duration>>,
+    metrics: Arc<Metrics>,
+    capabilities: Capabilities,
+}
+
+impl Worker {
+    /// Keep a connection to the peer at `address`, reconnecting as the address changes.
+    async fn run(
+        self,
+        mut receiver: mpsc::UnboundedReceiver<Incoming>,
+        mut address: watch::Receiver<PeerAddress>,
+    ) {
+        let peer = address.borrow_and_update().clone();
+        let mut work = self.connect_and_handle(Duration::ZERO, peer).boxed();
+        loop {
+            select! {
+                result = &mut work => {
+                    if let Err(err) = result {
+                        tracing::debug!("Connection to {} failed: {err}", self.peer_id);
+                    }
+                    let peer = address.borrow().clone();
+                    work = self.connect_and_handle(RECONNECT_DELAY, peer).boxed();
+                }
+                received = receiver.recv() => {
+                    if let Some(received) = received {
+                        if self.outbound.is_established() {
+                            tracing::debug!("Kept the connection dialed to {}", self.peer_id);
+                        } else {
+                            tracing::debug!("Replaced connection for {}", self.peer_id);
+                            work = self.handle_incoming(received).boxed();
+                        }
+                    } else {
+                        // Channel closed, the endpoint is closed
+                        return;
+                    }
+                }
+                Ok(()) = address.changed() => {
+                    let peer = address.borrow_and_update().clone();
+                    tracing::info!("Reconnecting to {} at {peer}", self.peer_id);
+                    work = self.connect_and_handle(Duration::ZERO, peer).boxed();
+                }
+            }
+        }
+    }
+
+    async fn connect_and_handle(&self, delay: Duration, peer: PeerAddress) -> io::Result<()> {
+        if !self.dial {
+            // Wait for the peer to connect to us.
+            return futures::future::pending().await;
+        }
+        runtime::sleep(delay).await;
+        let connection = loop {
+            // Resolve the peer again on every attempt, its name may point elsewhere by now
+            if let Some(connection) = self.connect(&peer).await {
+                break connection;
+            }
+            runtime::sleep(RECONNECT_DELAY).await;
+        };
+        let (mut send, mut receive) = connection.open_bi().await.map_err(io::Error::other)?;
+        send.write_u64(self.our_id).await?;
+        let negotiation = handshake(&self.capabilities, &mut send, &mut receive, self.peer_id);
+        let Some(protocol) = negotiation.await? else {
+            return Ok(());
+        };
+        let _outbound = self.outbound.establish();
+        self.handle_connection(connection, protocol, None).await
+    }
+
+    /// Dial the addresses the peer resolves to in turn.
+    async fn connect(&self, peer: &PeerAddress) -> Option<quinn::Connection> {
+        let addresses = match self.resolver.resolve(peer).await {
+            Ok(addresses) => addresses,
+            Err(err) => {
+                tracing::debug!("Failed to resolve peer {}: {err}", self.peer_id);
+                return None;
+            }
+        };
+        for address in addresses {
+            // Addresses of a family the endpoint is not bound to are refused right away
+            let Ok(connecting) = self.endpoint.connect(address, SERVER_NAME) else {
+                continue;
+            };
+            if let Ok(connection) = connecting.await {
+                return Some(connection);
+            }
+        }
+        None
+    }
+
+    async fn handle_incoming(&self, incoming: Incoming) -> io::Result<()> {
+        let (connection, mut send, mut receive) = incoming;
+        let negotiation = handshake(&self.capabilities, &mut send, &mut receive, self.peer_id);
+        let Some(protocol) = negotiation.await? else {
+            return Ok(());
+        };
+        let incoming_from = connection.remote_address().ip();
+        self.handle_connection(connection, protocol, Some(incoming_from))
+            .await
+    }
+
+    async fn handle_connection(
+        &self,
+        connection: quinn::Connection,
+        protocol: Protocol,
+        incoming_from: Option<IpAddr>,
+    ) -> io::Result<()> {
+        let (network_in_sender, network_in_receiver) = mpsc::channel(16);
+        let (sender, fresh_receiver) = mpsc::channel(QUEUE_CAPACITY);
+        let (sync_sender, sync_receiver) = mpsc::channel(QUEUE_CAPACITY);
+        let (bulk_sender, bulk_receiver) = mpsc::channel(QUEUE_CAPACITY);
+        let receivers = [fresh_receiver, sync_receiver, bulk_receiver];
+
+        let mut streams = vec![];
+        for (priority, receiver) in Priority::ALL.into_iter().zip(receivers) {
+            let mut stream = connection.open_uni().await.map_err(io::Error::other)?;
+            stream
+                .set_priority(stream_priority(priority))
+                .map_err(io::Error::other)?;
+            // Streams are only visible to the peer once written to
+            stream.write_u8(priority as u8).await?;
+            let send = self.handle_send_stream(stream, receiver, protocol);
+            streams.push(send.boxed());
+        }
+        for _ in Priority::ALL {
+            let mut stream = connection.accept_uni().await.map_err(io::Error::other)?;
+            stream.read_u8().await?;
+            let receive = self.handle_receive_stream(stream, network_in_sender.clone(), protocol);
+            streams.push(receive.boxed());
+        }
+        streams.push(self.report_latency(&connection).boxed());
+
+        let connection = Connection {
+            peer_id: self.peer_id,
+            incoming_from,
+            sender,
+            sync_sender,
+            bulk_sender,
+            receiver: network_in_receiver,
+        };
+        if self.connection_sender.send(connection).await.is_err() {
+            // todo - pass signal to break the main loop
+            return Ok(());
+        }
+        tracing::debug!("Connected to {}", self.peer_id);
+        let (r, _, _) = select_all(streams).await;
+        tracing::debug!("Disconnected from {}", self.peer_id);
+        r
+    }
+
+    async fn handle_send_stream(
+        &self,
+        mut stream: quinn::SendStream,
+        mut receiver: mpsc::Receiver<NetworkMessage>,
+        protocol: Protocol,
+    ) -> io::Result<()> {
+        let peer = self.peer_id as AuthorityIndex;
+        let peer_label = format_authority_index(peer).to_string();
+        while let Some(message) = receiver.recv().await {
+            let frame = encode_frame(protocol.compression(), protocol.encode(&message));
+            self.metrics.frame_sent(peer, &message, frame.len());
+            // The time spent in the write is the time the flow control of the stream kept us waiting
+            let _blocked = self
+                .metrics
+                .network_send_blocked_us
+                .utilization_timer(&peer_label);
+            stream.write_all(&frame).await?;
+        }
+        Ok(())
+    }
+
+    async fn handle_receive_stream(
+        &self,
+        mut stream: quinn::RecvStream,
+        sender: mpsc::Sender<NetworkMessage>,
+        protocol: Protocol,
+    ) -> io::Result<()> {
+        let peer = self.peer_id as AuthorityIndex;
+        loop {
+            let size = stream.read_u32().await?;
+            if size == 0 || size > MAX_FRAME_SIZE {
+                tracing::warn!("Invalid size: {size}");
+                return Ok(());
+            }
+            let mut frame = vec![0u8; size as usize];
+            stream
+                .read_exact(&mut frame)
+                .await
+                .map_err(io::Error::other)?;
+            let serialized = match decode_frame(protocol.compression(), &frame) {
+                Ok(serialized) => serialized,
+                Err(err) => {
+                    tracing::warn!("Invalid frame: {}", err);
+                    return Ok(());
+                }
+            };
+            match protocol.decode(&serialized) {
+                Ok(None) => tracing::debug!("Skipping message of unknown kind from {peer}"),
+                Ok(Some(message)) => {
+                    self.metrics
+                        .frame_received(peer, &message, size as usize + 4);
+                    if sender.send(message).await.is_err() {
+                        // todo - pass signal to break main loop
+                        return Ok(());
+                    }
+                }
+                Err(err) => {
+                    tracing::warn!("Failed to deserialize: {}", err);
+                    return Ok(());
+                }
+            }
+        }
+    }
+
+    /// Report the round-trip time estimated by QUIC, as TCP connections report their pings.
+    async fn report_latency(&self, connection: &quinn::Connection) -> io::Result<()> {
+        loop {
+            runtime::sleep(PING_INTERVAL).await;
+            if let Some(latency_sender) = &self.latency_sender {
+                latency_sender.observe(connection.rtt());
+            }
+        }
+    }
+}
+
+/// QUIC sends the streams of higher priority first.
+fn stream_priority(priority: Priority) -> i32 {
+    match priority {
+        Priority::Fresh => 2,
+        Priority::Sync => 1,
+        Priority::Bulk => 0,
+    }
+}
+
+/// Peers are authenticated by the signatures of their messages rather than by their certificates.
+struct SkipServerVerification;
+
+impl ServerCertVerifier for SkipServerVerification {
+    fn verify_server_cert(
+        &self,
+        _end_entity: &Certificate,
+        _intermediates: &[Certificate],
+        _server_name: &ServerName,
+        _scts: &mut dyn Iterator<Item = &[u8]>,
+        _ocsp_response: &[u8],
+        _now: SystemTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        Ok(ServerCertVerified::assertion())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::{Ipv4Addr, SocketAddr, SocketAddrV4};
+
+    use prometheus::Registry;
+
+    use crate::{
+        committee::Committee,
+        compression::Compression,
+        metrics::Metrics,
+        network::{Network, NetworkMessage, TransportProtocol},
+        protocol::Capabilities,
+        topology::Topology,
+        types::BlockReference,
+    };
+
+    #[tokio::test]
+    async fn fresh_messages_overtake_bulk() {
+        let committee = Committee::new_test(vec![1, 1]);
+        let addresses: Vec<_> = (0..2)
+            .map(|i| SocketAddr::V4(SocketAddrV4::new(Ipv4Addr::LOCALHOST, 5701 + i)))
+            .collect();
+        let networks = addresses.iter().enumerate().map(|(i, address)| {
+            Network::from_socket_addresses(
+                &addresses,
+                &[],
+                i,
+                *address,
+                Metrics::new(&Registry::default(), Some(&committee)).0,
+                Capabilities::new(Compression::None),
+                TransportProtocol::Quic,
+                &Topology::Mesh,
+            )
+        });
+        let mut networks = futures::future::join_all(networks).await;
+        let mut connections = Vec::new();
+        for network in &mut networks {
+            connections.push(network.connection_receiver().recv().await.unwrap());
+        }
+
+        // Queue a few large messages to stream to the peer, then a fresh one
+        let references = vec![BlockReference::default(); 100_000];
+        for _ in 0..8 {
+            let bulk = NetworkMessage::BlockNotFound(references.clone());
+            connections[1].bulk_sender.send(bulk).await.unwrap();
+        }
+        connections[1]
+            .sender
+            .send(NetworkMessage::SubscribeOwnFrom(1))
+            .await
+            .unwrap();
+
+        let mut bulk_received = 0;
+        loop {
+            match connections[0].receiver.recv().await.unwrap() {
+                NetworkMessage::BlockNotFound(_) => bulk_received += 1,
+                NetworkMessage::SubscribeOwnFrom(round) => {
+                    assert_eq!(round, 1);
+                    break;
+                }
+                _ => panic!("Unexpected message"),
+            }
+        }
+        assert!(bulk_received < 8);
+    }
+}
@@ -0,0 +1,438 @@
+// Copyright (c) Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Per-peer budgets protecting validators from peers flooding them. Every connection has a budget
+//! of bytes per second, blocks per round and sync requests per second. A peer exceeding its
+//! budget of bytes or sync requests is throttled: we stop reading from it until the budget allows
+//! the message. Peers throttled for too long in a row, or sending more of their own blocks of a
+//! round than any honest peer would, are disconnected and banned for a while, twice as long every
+//! time. Blocks of other authors relayed or served by a peer only count toward its bytes: an
+//! equivocating author must not get the honest peers forwarding its blocks banned.
+//!
+//! Peers dialing us merely declare their authority index, so they are banned by their address:
+//! banning the authority they claim would let anyone get an honest validator banned. Only the
+//! peers we dial at their known address are banned by authority.
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt,
+    net::IpAddr,
+    time::Duration,
+};
+
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    network::{Connection, NetworkMessage},
+    runtime::timestamp_utc,
+    types::{AuthorityIndex, RoundNumber},
+};
+
+/// The number of rounds for which blocks are counted.
+const RETAINED_ROUNDS: usize = 64;
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(default)]
+pub struct RateLimits {
+    /// The bytes per second a peer may send us, in bursts of up to a second.
+    pub bytes_per_second: u64,
+    /// The blocks of its own a peer may send us for a single round, twice the committee size by
+    /// default.
+    pub blocks_per_round: Option<usize>,
+    /// The block, batch and snapshot requests per second a peer may send us.
+    pub sync_requests_per_second: u64,
+    /// Peers throttled for this long in a row are banned.
+    pub max_throttled: Duration,
+    /// How long a peer is banned the first time. Every further ban lasts twice as long.
+    pub ban_duration: Duration,
+    pub max_ban_duration: Duration,
+}
+
+impl Default for RateLimits {
+    fn default() -> Self {
+        Self {
+            bytes_per_second: 128 * 1024 * 1024,
+            blocks_per_round: None,
+            sync_requests_per_second: 1000,
+            max_throttled: Duration::from_secs(10),
+            ban_duration: Duration::from_secs(10),
+            max_ban_duration: Duration::from_secs(3600),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Budget {
+    Bytes,
+    Blocks,
+    SyncRequests,
+}
+
+impl Budget {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Bytes => "bytes",
+            Self::Blocks => "blocks",
+            Self::SyncRequests => "sync_requests",
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum Admission {
+    Accept,
+    /// The message may only be processed after this delay.
+    Throttle(Budget, Duration),
+    /// The peer should be disconnected and banned.
+    Ban(Budget),
+}
+
+/// The budgets of a single connection.
+pub struct PeerLimiter {
+    peer: AuthorityIndex,
+    bytes: TokenBucket,
+    sync_requests: TokenBucket,
+    blocks_per_round: usize,
+    blocks: BTreeMap<RoundNumber, usize>,
+    max_throttled: Duration,
+    throttled_since: Option<Duration>,
+}
+
+impl PeerLimiter {
+    pub fn new(limits: &RateLimits, committee_size: usize, peer: AuthorityIndex) -> Self {
+        Self {
+            peer,
+            bytes: TokenBucket::new(limits.bytes_per_second as f64),
+            sync_requests: TokenBucket::new(limits.sync_requests_per_second as f64),
+            blocks_per_round: limits.blocks_per_round.unwrap_or(2 * committee_size),
+            blocks: BTreeMap::new(),
+            max_throttled: limits.max_throttled,
+            throttled_since: None,
+        }
+    }
+
+    pub fn admit(&mut self, message: &NetworkMessage) -> Admission {
+        let size = bincode::serialized_size(message).expect("Serialization should not fail");
+        self.admit_at(message, size, timestamp_utc())
+    }
+
+    fn admit_at(&mut self, message: &NetworkMessage, size: u64, now: Duration) -> Admission {
+        match message {
+            NetworkMessage::Block(block) if block.author() == self.peer => {
+                let count = self.blocks.entry(block.round()).or_default();
+                *count += 1;
+                if *count > self.blocks_per_round {
+                    return Admission::Ban(Budget::Blocks);
+                }
+                if self.blocks.len() > RETAINED_ROUNDS {
+                    self.blocks.pop_first();
+                }
+            }
+            _ => {}
+        }
+        let mut admission = (Budget::Bytes, self.bytes.take(size as f64, now));
+        if matches!(
+            message,
+            NetworkMessage::RequestBlocks(_)
+                | NetworkMessage::RequestBatches(_)
+                | NetworkMessage::RequestSnapshot
+                | NetworkMessage::RequestOwnRound
+        ) {
+            let delay = self.sync_requests.take(1., now);
+            if delay > admission.1 {
+                admission = (Budget::SyncRequests, delay);
+            }
+        }
+        match admission {
+            (_, Duration::ZERO) => {
+                self.throttled_since = None;
+                Admission::Accept
+            }
+            (budget, delay) => {
+                let since = *self.throttled_since.get_or_insert(now);
+                if now + delay - since > self.max_throttled {
+                    Admission::Ban(budget)
+                } else {
+                    Admission::Throttle(budget, delay)
+                }
+            }
+        }
+    }
+}
+
+/// A token bucket refilled at `rate` tokens per second, holding up to a second worth of tokens.
+/// Taking more tokens than available leaves the bucket in debt, which has to be repaid before the
+/// next message is admitted.
+struct TokenBucket {
+    rate: f64,
+    tokens: f64,
+    updated: Duration,
+}
+
+impl TokenBucket {
+    fn new(rate: f64) -> Self {
+        Self {
+            rate,
+            tokens: rate,
+            updated: Duration::ZERO,
+        }
+    }
+
+    /// Take `amount` tokens, returns how long to wait for the bucket to be out of debt.
+    fn take(&mut self, amount: f64, now: Duration) -> Duration {
+        let elapsed = now.saturating_sub(self.updated).as_secs_f64();
+        self.updated = now;
+        self.tokens = (self.tokens + elapsed * self.rate).min(self.rate);
+        self.tokens -= amount;
+        if self.tokens >= 0. {
+            Duration::ZERO
+        } else {
+            Duration::from_secs_f64(-self.tokens / self.rate)
+        }
+    }
+}
+
+/// The peers banned by this validator, shared by all connections.
+pub struct PeerBans {
+    ban_duration: Duration,
+    max_ban_duration: Duration,
+    bans: Mutex<HashMap<BanKey, Ban>>,
+}
+
+/// What a ban applies to, see the module documentation.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum BanKey {
+    Authority(AuthorityIndex),
+    Address(IpAddr),
+}
+
+impl BanKey {
+    pub fn of(connection: &Connection) -> Self {
+        match connection.incoming_from {
+            Some(address) => Self::Address(address),
+            None => Self::Authority(connection.peer_id as AuthorityIndex),
+        }
+    }
+}
+
+impl fmt::Display for BanKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Authority(authority) => write!(f, "authority {authority}"),
+            Self::Address(address) => write!(f, "address {address}"),
+        }
+    }
+}
+
+struct Ban {
+    until: Duration,
+    count: u32,
+}
+
+impl PeerBans {
+    pub fn new(limits: &RateLimits) -> Self {
+        Self {
+            ban_duration: limits.ban_duration,
+            max_ban_duration: limits.max_ban_duration,
+            bans: Default::default(),
+        }
+    }
+
+    /// Ban the peer, returns how long it is banned for.
+    pub fn ban(&self, peer: BanKey) -> Duration {
+        self.ban_at(peer, timestamp_utc())
+    }
+
+    /// Whether the peer of the connection is banned, by authority or by address.
+    pub fn is_banned(&self, connection: &Connection) -> bool {
+        let now = timestamp_utc();
+        let authority = BanKey::Authority(connection.peer_id as AuthorityIndex);
+        self.is_banned_at(authority, now) || self.is_banned_at(BanKey::of(connection), now)
+    }
+
+    fn ban_at(&self, peer: BanKey, now: Duration) -> Duration {
+        let mut bans = self.bans.lock();
+        let ban = bans.entry(peer).or_insert(Ban {
+            until: now,
+            count: 0,
+        });
+        let duration = self
+            .ban_duration
+            .saturating_mul(1 << ban.count.min(31))
+            .min(self.max_ban_duration);
+        ban.until = now + duration;
+        ban.count += 1;
+        duration
+    }
+
+    fn is_banned_at(&self, peer: BanKey, now: Duration) -> bool {
+        self.bans
+            .lock()
+            .get(&peer)
+            .is_some_and(|ban| ban.until > now)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::sync::mpsc;
+
+    use super::*;
+    use crate::{data::Data, types::StatementBlock};
+
+    fn limiter() -> PeerLimiter {
+        let limits = RateLimits {
+            bytes_per_second: 1000,
+            sync_requests_per_second: 10,
+            max_throttled: Duration::from_secs(2),
+            ..Default::default()
+        };
+        PeerLimiter::new(&limits, 4, 1)
+    }
+
+    #[test]
+    fn throttle_bytes() {
+        let mut limiter = limiter();
+        let message = NetworkMessage::BlockNotFound(vec![]);
+        let at = Duration::from_millis;
+        assert_eq!(limiter.admit_at(&message, 600, at(0)), Admission::Accept);
+        assert_eq!(
+            limiter.admit_at(&message, 600, at(0)),
+            Admission::Throttle(Budget::Bytes, at(200))
+        );
+        // The peer waited for the throttle
+        assert_eq!(limiter.admit_at(&message, 100, at(300)), Admission::Accept);
+        // A peer throttled for more than two seconds in a row is banned
+        assert_eq!(
+            limiter.admit_at(&message, 1500, at(300)),
+            Admission::Throttle(Budget::Bytes, at(1500))
+        );
+        assert_eq!(
+            limiter.admit_at(&message, 1000, at(1800)),
+            Admission::Ban(Budget::Bytes)
+        );
+    }
+
+    #[test]
+    fn throttle_sync_requests() {
+        let mut limiter = limiter();
+        let request = NetworkMessage::RequestBlocks(vec![]);
+        let at = Duration::from_millis;
+        for _ in 0..10 {
+            assert_eq!(limiter.admit_at(&request, 1, at(0)), Admission::Accept);
+        }
+        assert_eq!(
+            limiter.admit_at(&request, 1, at(0)),
+            Admission::Throttle(Budget::SyncRequests, at(100))
+        );
+    }
+
+    #[test]
+    fn ban_block_flood() {
+        let mut limiter = limiter();
+        let block = |author, round| {
+            NetworkMessage::Block(Data::new(StatementBlock::new(
+                author,
+                round,
+                vec![],
+                vec![],
+                0,
+                false,
+                Default::default(),
+            )))
+        };
+        for round in 1..=3 {
+            for _ in 0..8 {
+                assert_eq!(
+                    limiter.admit_at(&block(1, round), 1, Duration::ZERO),
+                    Admission::Accept
+                );
+            }
+        }
+        assert_eq!(
+            limiter.admit_at(&block(1, 2), 1, Duration::ZERO),
+            Admission::Ban(Budget::Blocks)
+        );
+    }
+
+    #[test]
+    fn relayed_blocks_not_counted() {
+        let mut limiter = limiter();
+        let block = |author| {
+            NetworkMessage::Block(Data::new(StatementBlock::new(
+                author,
+                1,
+                vec![],
+                vec![],
+                0,
+                false,
+                Default::default(),
+            )))
+        };
+        // The peer relays the blocks of an author equivocating many times in the round
+        for _ in 0..100 {
+            assert_eq!(
+                limiter.admit_at(&block(2), 1, Duration::ZERO),
+                Admission::Accept
+            );
+        }
+        for _ in 0..8 {
+            assert_eq!(
+                limiter.admit_at(&block(1), 1, Duration::ZERO),
+                Admission::Accept
+            );
+        }
+        assert_eq!(
+            limiter.admit_at(&block(1), 1, Duration::ZERO),
+            Admission::Ban(Budget::Blocks)
+        );
+    }
+
+    #[test]
+    fn exponential_bans() {
+        let limits = RateLimits {
+            ban_duration: Duration::from_secs(10),
+            max_ban_duration: Duration::from_secs(30),
+            ..Default::default()
+        };
+        let bans = PeerBans::new(&limits);
+        let at = Duration::from_secs;
+        let peer = BanKey::Authority(1);
+        assert!(!bans.is_banned_at(peer, at(100)));
+        assert_eq!(bans.ban_at(peer, at(100)), at(10));
+        assert!(bans.is_banned_at(peer, at(109)));
+        assert!(!bans.is_banned_at(BanKey::Authority(2), at(109)));
+        assert!(!bans.is_banned_at(peer, at(110)));
+        assert_eq!(bans.ban_at(peer, at(200)), at(20));
+        assert!(bans.is_banned_at(peer, at(219)));
+        assert_eq!(bans.ban_at(peer, at(300)), at(30));
+    }
+
+    #[test]
+    fn incoming_connections_banned_by_address() {
+        let bans = PeerBans::new(&RateLimits::default());
+        let connection = |incoming_from| {
+            let (sender, _) = mpsc::channel(1);
+            let (_, receiver) = mpsc::channel(1);
+            Connection {
+                peer_id: 1,
+                incoming_from,
+                sender: sender.clone(),
+                sync_sender: sender.clone(),
+                bulk_sender: sender,
+                receiver,
+            }
+        };
+        let address = IpAddr::from([10, 0, 0, 1]);
+        let incoming = connection(Some(address));
+        bans.ban(BanKey::of(&incoming));
+        assert!(bans.is_banned(&incoming));
+        // A peer claiming to be authority 1 does not get the authority banned
+        assert!(!bans.is_banned(&connection(None)));
+        assert!(!bans.is_banned(&connection(Some(IpAddr::from([10, 0, 0, 2])))));
+
+        bans.ban(BanKey::of(&connection(None)));
+        assert!(bans.is_banned(&connection(Some(IpAddr::from([10, 0, 0, 2])))));
+    }
+}
@@ -0,0 +1,270 @@
+// Copyright (c) Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Some of the `NodeParameters` can be changed while the node runs, to tune validators during a
+//! long benchmark without restarting them: on SIGHUP (from the public config) or through the
+//! `PARAMETERS_ROUTE` of the admin server (see `admin`). The other parameters are only read on
+//! startup, and some of them, such as the wave length or the number of leaders, must be the same
+//! across the committee for the commit rule to be safe: a reload changing any of them is rejected.
+
+use std::sync::Arc;
+
+use axum::{http::StatusCode, routing::get, Extension, Router};
+use eyre::{ensure, eyre};
+use serde_yaml::{Mapping, Value};
+use tokio::sync::watch;
+
+use crate::{config::NodeParameters, metrics::Metrics};
+
+/// Returns the current parameters as YAML, and applies the (partial) parameters posted as YAML.
+pub const PARAMETERS_ROUTE: &str = "/parameters";
+
+/// The parameters currently applied, watched by the tasks using them.
+#[derive(Clone)]
+pub struct LiveParameters {
+    sender: Arc<watch::Sender<NodeParameters>>,
+    metrics: Arc<Metrics>,
+}
+
+impl LiveParameters {
+    pub fn new(parameters: NodeParameters, metrics: Arc<Metrics>) -> Self {
+        let live = Self {
+            sender: Arc::new(watch::channel(parameters).0),
+            metrics,
+        };
+        live.report();
+        live
+    }
+
+    pub fn current(&self) -> NodeParameters {
+        self.sender.borrow().clone()
+    }
+
+    pub fn subscribe(&self) -> watch::Receiver<NodeParameters> {
+        self.sender.subscribe()
+    }
+
+    /// Apply `parameters`, returning the names of the parameters changed. Nothing is applied if
+    /// any parameter that is not reloadable differs from its current value.
+    pub fn reload(&self, parameters: NodeParameters) -> eyre::Result<Vec<String>> {
+        let current = self.current();
+        let changed = changed_parameters(&current, &parameters);
+        let names = reloadable(&current).map(|(name, ..)| name);
+        let rejected: Vec<_> = changed
+            .iter()
+            .map(String::as_str)
+            .filter(|name| !names.contains(name))
+            .collect();
+        ensure!(
+            rejected.is_empty(),
+            "Parameters {} can not be changed at runtime",
+            rejected.join(", ")
+        );
+        for (name, value, _) in reloadable(&parameters) {
+            if changed.iter().any(|changed| changed == name) {
+                tracing::info!("Reloaded parameter {name}: {value}");
+            }
+        }
+        self.sender.send_replace(parameters);
+        self.report();
+        Ok(changed)
+    }
+
+    /// Apply the parameters listed in `yaml`, keeping the current value of the others.
+    pub fn update(&self, yaml: &str) -> eyre::Result<Vec<String>> {
+        let changes: Mapping = serde_yaml::from_str(yaml)?;
+        let mut parameters = serde_yaml::to_value(self.current())?;
+        let mapping = parameters
+            .as_mapping_mut()
+            .expect("Parameters are serialized as a mapping");
+        for (name, value) in changes {
+            ensure!(
+                mapping.contains_key(&name),
+                "Unknown parameter {}",
+                name.as_str().unwrap_or_default()
+            );
+            mapping.insert(name, value);
+        }
+        let parameters =
+            serde_yaml::from_value(parameters).map_err(|e| eyre!("Invalid parameters: {e}"))?;
+        self.reload(parameters)
+    }
+
+    fn report(&self) {
+        for (name, _, value) in reloadable(&self.sender.borrow()) {
+            self.metrics
+                .node_parameters
+                .with_label_values(&[name])
+                .set(value);
+        }
+    }
+}
+
+/// The parameters that can be changed at runtime, with their value as logged and as reported by
+/// the `node_parameters` gauge (durations in milliseconds).
+fn reloadable(parameters: &NodeParameters) -> [(&'static str, String, i64); 3] {
+    [
+        (
+            "leader_timeout",
+            format!("{:?}", parameters.leader_timeout),
+            parameters.leader_timeout.as_millis() as i64,
+        ),
+        (
+            "max_block_size",
+            parameters.max_block_size.to_string(),
+            parameters.max_block_size as i64,
+        ),
+        (
+            "enable_synchronizer",
+            parameters.enable_synchronizer.to_string(),
+            parameters.enable_synchronizer as i64,
+        ),
+    ]
+}
+
+/// The names of the parameters whose value differs.
+fn changed_parameters(current: &NodeParameters, parameters: &NodeParameters) -> Vec<String> {
+    let to_mapping = |parameters| match serde_yaml::to_value(parameters) {
+        Ok(Value::Mapping(mapping)) => mapping,
+        _ => panic!("Parameters are serialized as a mapping"),
+    };
+    let current = to_mapping(current);
+    to_mapping(parameters)
+        .into_iter()
+        .filter(|(name, value)| current.get(name) != Some(value))
+        .filter_map(|(name, _)| name.as_str().map(str::to_string))
+        .collect()
+}
+
+/// The routes to read and reload the parameters, served by the admin server.
+pub fn routes(parameters: LiveParameters) -> Router {
+    Router::new()
+        .route(PARAMETERS_ROUTE, get(get_parameters).post(post_parameters))
+        .layer(Extension(parameters))
+}
+
+async fn get_parameters(parameters: Extension<LiveParameters>) -> (StatusCode, String) {
+    match serde_yaml::to_string(&parameters.current()) {
+        Ok(yaml) => (StatusCode::OK, yaml),
+        Err(error) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            format!("Unable to encode parameters: {error}"),
+        ),
+    }
+}
+
+async fn post_parameters(
+    parameters: Extension<LiveParameters>,
+    body: String,
+) -> (StatusCode, String) {
+    match parameters.update(&body) {
+        Ok(changed) => (
+            StatusCode::OK,
+            format!("Reloaded [{}]\n", changed.join(", ")),
+        ),
+        Err(error) => (StatusCode::BAD_REQUEST, format!("{error}\n")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{net::SocketAddr, time::Duration};
+
+    use prometheus::Registry;
+
+    use super::*;
+    use crate::admin::start_admin_server;
+
+    fn live_parameters() -> LiveParameters {
+        let (metrics, _) = Metrics::new(&Registry::new(), None);
+        LiveParameters::new(NodeParameters::default(), metrics)
+    }
+
+    fn gauge(parameters: &LiveParameters, name: &str) -> i64 {
+        parameters
+            .metrics
+            .node_parameters
+            .with_label_values(&[name])
+            .get()
+    }
+
+    #[test]
+    fn reload() {
+        let live = live_parameters();
+        let mut receiver = live.subscribe();
+        let mut parameters = live.current();
+        parameters.leader_timeout = Duration::from_secs(1);
+        parameters.max_block_size = 1024;
+        assert_eq!(
+            live.reload(parameters.clone()).unwrap(),
+            vec!["leader_timeout", "max_block_size"]
+        );
+        assert!(receiver.has_changed().unwrap());
+        assert_eq!(
+            receiver.borrow_and_update().leader_timeout,
+            Duration::from_secs(1)
+        );
+        assert_eq!(gauge(&live, "leader_timeout"), 1000);
+        assert_eq!(gauge(&live, "max_block_size"), 1024);
+
+        // Changes to the commit rule are rejected, along with the rest of the reload
+        parameters.wave_length += 1;
+        parameters.enable_synchronizer = !parameters.enable_synchronizer;
+        let error = live.reload(parameters).unwrap_err();
+        assert!(error.to_string().contains("wave_length"));
+        assert_eq!(
+            live.current().enable_synchronizer,
+            NodeParameters::default().enable_synchronizer
+        );
+    }
+
+    #[test]
+    fn update() {
+        let live = live_parameters();
+        let changed = live.update("max_block_size: 2048\n").unwrap();
+        assert_eq!(changed, vec!["max_block_size"]);
+        assert_eq!(live.current().max_block_size, 2048);
+        // Reloading the same value changes nothing
+        assert!(live.update("max_block_size: 2048\n").unwrap().is_empty());
+
+        assert!(live.update("number_of_leaders: 1\n").is_err());
+        assert!(live.update("max_blocksize: 1\n").is_err());
+        assert!(live.update("max_block_size: large\n").is_err());
+        assert_eq!(live.current().max_block_size, 2048);
+    }
+
+    #[tokio::test]
+    async fn route() {
+        let address: SocketAddr = "127.0.0.1:7101".parse().unwrap();
+        let live = live_parameters();
+        let _server = start_admin_server(address, routes(live.clone()));
+        tokio::time::sleep(Duration::from_millis(100)).await;
+
+        let url = format!("http://{address}{PARAMETERS_ROUTE}");
+        let client = reqwest::Client::new();
+        let response = client
+            .post(&url)
+            .body("leader_timeout:\n  secs: 2\n  nanos: 0\n")
+            .send()
+            .await
+            .unwrap();
+        assert!(response.status().is_success());
+        assert_eq!(live.current().leader_timeout, Duration::from_secs(2));
+
+        let response = client
+            .post(&url)
+            .body("wave_length: 3\n")
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), reqwest::StatusCode::BAD_REQUEST);
+
+        let yaml = reqwest::get(&url).await.unwrap().text().await.unwrap();
+        let parameters: NodeParameters = serde_yaml::from_str(&yaml).unwrap();
+        assert_eq!(parameters.leader_timeout, Duration::from_secs(2));
+        assert_eq!(
+            parameters.wave_length,
+            NodeParameters::default().wave_length
+        );
+    }
+}
@@ -0,0 +1,289 @@
+// Copyright (c) Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Peers are reached at a `PeerAddress`: an IP address or a hostname, and a port. Hostnames are
+//! resolved every time we dial the peer, so that a validator may change its IP address behind a
+//! stable name without its peers restarting. Resolved IPv6 addresses are dialed before IPv4 ones.
+
+use std::{
+    collections::HashMap,
+    fmt,
+    fs,
+    io,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr},
+    path::Path,
+    str::FromStr,
+    sync::Arc,
+};
+
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Host {
+    Ip(IpAddr),
+    Name(String),
+}
+
+impl FromStr for Host {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let ip = s
+            .strip_prefix('[')
+            .and_then(|s| s.strip_suffix(']'))
+            .unwrap_or(s);
+        if let Ok(ip) = ip.parse() {
+            return Ok(Self::Ip(ip));
+        }
+        let valid = |label: &str| {
+            !label.is_empty()
+                && !label.starts_with('-')
+                && label.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+        };
+        if s.len() > 253 || !s.split('.').all(valid) {
+            return Err(format!("Invalid hostname '{s}'"));
+        }
+        Ok(Self::Name(s.to_ascii_lowercase()))
+    }
+}
+
+impl fmt::Display for Host {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Ip(ip) => write!(f, "{ip}"),
+            Self::Name(name) => write!(f, "{name}"),
+        }
+    }
+}
+
+impl From<IpAddr> for Host {
+    fn from(ip: IpAddr) -> Self {
+        Self::Ip(ip)
+    }
+}
+
+/// The address of a peer, written `host:port` (IPv6 addresses in brackets, as in `[::1]:1500`).
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct PeerAddress {
+    pub host: Host,
+    pub port: u16,
+}
+
+impl PeerAddress {
+    pub fn new(host: Host, port: u16) -> Self {
+        Self { host, port }
+    }
+
+    /// The address, if the host is an IP address.
+    pub fn socket_addr(&self) -> Option<SocketAddr> {
+        match self.host {
+            Host::Ip(ip) => Some(SocketAddr::new(ip, self.port)),
+            Host::Name(_) => None,
+        }
+    }
+
+    /// The address to accept connections on when no other is specified: all the interfaces of
+    /// the family of the host, on our port. Hostnames may resolve to IPv6 addresses, so we listen
+    /// on the IPv6 interfaces (which also accept IPv4 connections on dual-stack hosts).
+    pub fn default_listen_address(&self) -> SocketAddr {
+        let ip = match self.host {
+            Host::Ip(IpAddr::V4(_)) => IpAddr::V4(Ipv4Addr::UNSPECIFIED),
+            Host::Ip(IpAddr::V6(_)) | Host::Name(_) => IpAddr::V6(Ipv6Addr::UNSPECIFIED),
+        };
+        SocketAddr::new(ip, self.port)
+    }
+}
+
+impl From<SocketAddr> for PeerAddress {
+    fn from(address: SocketAddr) -> Self {
+        Self::new(Host::Ip(address.ip()), address.port())
+    }
+}
+
+impl FromStr for PeerAddress {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Ok(address) = s.parse::<SocketAddr>() {
+            return Ok(address.into());
+        }
+        let Some((host, port)) = s.rsplit_once(':') else {
+            return Err(format!("Missing port in address '{s}'"));
+        };
+        if host.contains(':') {
+            return Err(format!(
+                "IPv6 address '{host}' should be written in brackets"
+            ));
+        }
+        let port = port
+            .parse()
+            .map_err(|_| format!("Invalid port in address '{s}'"))?;
+        Ok(Self::new(host.parse()?, port))
+    }
+}
+
+impl fmt::Display for PeerAddress {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.host {
+            Host::Ip(ip) => write!(f, "{}", SocketAddr::new(*ip, self.port)),
+            Host::Name(name) => write!(f, "{name}:{}", self.port),
+        }
+    }
+}
+
+// Written as a string, as socket addresses are, so that existing configs remain valid
+impl Serialize for PeerAddress {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for PeerAddress {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer)?
+            .parse()
+            .map_err(de::Error::custom)
+    }
+}
+
+/// Resolves the hostnames of the peers.
+#[derive(Clone, Default)]
+pub enum Resolver {
+    /// The resolver of the operating system.
+    #[default]
+    System,
+    /// The names listed in a hosts table, see `Resolver::from_hosts`.
+    Hosts(Arc<HashMap<String, Vec<IpAddr>>>),
+}
+
+impl Resolver {
+    /// Resolve names through a table in the format of `/etc/hosts`: every line lists an IP
+    /// address followed by the names resolving to it, and `#` starts a comment.
+    pub fn from_hosts(table: &str) -> io::Result<Self> {
+        let mut hosts: HashMap<String, Vec<IpAddr>> = HashMap::new();
+        for line in table.lines() {
+            let line = line.split('#').next().unwrap_or_default();
+            let mut fields = line.split_whitespace();
+            let Some(ip) = fields.next() else {
+                continue;
+            };
+            let ip = ip.parse().map_err(|_| {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("Invalid IP address '{ip}' in hosts table"),
+                )
+            })?;
+            for name in fields {
+                hosts.entry(name.to_ascii_lowercase()).or_default().push(ip);
+            }
+        }
+        Ok(Self::Hosts(Arc::new(hosts)))
+    }
+
+    pub fn load_hosts<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        Self::from_hosts(&fs::read_to_string(path)?)
+    }
+
+    /// The addresses to dial the peer at, IPv6 addresses first.
+    pub async fn resolve(&self, address: &PeerAddress) -> io::Result<Vec<SocketAddr>> {
+        let port = address.port;
+        let mut resolved: Vec<_> = match (&address.host, self) {
+            (Host::Ip(ip), _) => return Ok(vec![SocketAddr::new(*ip, port)]),
+            (Host::Name(name), Self::System) => tokio::net::lookup_host((name.as_str(), port))
+                .await?
+                .collect(),
+            (Host::Name(name), Self::Hosts(hosts)) => hosts
+                .get(name)
+                .into_iter()
+                .flatten()
+                .map(|ip| SocketAddr::new(*ip, port))
+                .collect(),
+        };
+        if resolved.is_empty() {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("Failed to resolve {address}"),
+            ));
+        }
+        // The sort is stable: the resolver's order is kept within each family
+        resolved.sort_by_key(|address| address.is_ipv4());
+        Ok(resolved)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse() {
+        for address in [
+            "127.0.0.1:1500",
+            "[::1]:1500",
+            "validator-0.example.com:65000",
+        ] {
+            let parsed: PeerAddress = address.parse().unwrap();
+            assert_eq!(parsed.to_string(), address);
+        }
+        let parsed: PeerAddress = "[2001:db8::1]:80".parse().unwrap();
+        assert_eq!(
+            parsed.socket_addr(),
+            Some(SocketAddr::new("2001:db8::1".parse().unwrap(), 80))
+        );
+        assert_eq!(parsed.default_listen_address().to_string(), "[::]:80");
+        let parsed: PeerAddress = "10.0.0.1:80".parse().unwrap();
+        assert_eq!(parsed.default_listen_address().to_string(), "0.0.0.0:80");
+
+        for address in [
+            "validator",
+            "validator:port",
+            "::1:80",
+            "-validator:80",
+            "a..b:80",
+        ] {
+            assert!(address.parse::<PeerAddress>().is_err(), "{address}");
+        }
+    }
+
+    #[test]
+    fn serde() {
+        // Configs written when addresses were socket addresses remain valid
+        let address: SocketAddr = "127.0.0.1:1500".parse().unwrap();
+        let yaml = serde_yaml::to_string(&address).unwrap();
+        let parsed: PeerAddress = serde_yaml::from_str(&yaml).unwrap();
+        assert_eq!(parsed, address.into());
+        let address: PeerAddress = "validator-0:1500".parse().unwrap();
+        let yaml = serde_yaml::to_string(&address).unwrap();
+        assert_eq!(serde_yaml::from_str::<PeerAddress>(&yaml).unwrap(), address);
+    }
+
+    #[tokio::test]
+    async fn resolve() {
+        let resolver = Resolver::from_hosts(
+            "# Dual-stack validator\n\
+             10.0.0.1 validator-0 v0\n\
+             fd00::1  validator-0\n\
+             \n\
+             fd00::2  validator-1 # IPv6 only\n",
+        )
+        .unwrap();
+        let address: PeerAddress = "Validator-0:80".parse().unwrap();
+        let resolved = resolver.resolve(&address).await.unwrap();
+        assert_eq!(
+            resolved,
+            vec![
+                "[fd00::1]:80".parse().unwrap(),
+                "10.0.0.1:80".parse().unwrap()
+            ]
+        );
+        let address: PeerAddress = "v0:80".parse().unwrap();
+        assert_eq!(resolver.resolve(&address).await.unwrap().len(), 1);
+        let address: PeerAddress = "validator-2:80".parse().unwrap();
+        assert!(resolver.resolve(&address).await.is_err());
+        // IP addresses are not looked up
+        let address: PeerAddress = "10.0.0.2:80".parse().unwrap();
+        assert_eq!(resolver.resolve(&address).await.unwrap().len(), 1);
+
+        assert!(Resolver::from_hosts("localhost 127.0.0.1").is_err());
+    }
+}
@@ -0,0 +1,222 @@
+// Copyright (c) Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+use std::{
+    fs::{File, OpenOptions},
+    io,
+    os::unix::fs::FileExt,
+    path::Path,
+};
+
+use blake2::Digest;
+use eyre::ensure;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    committee::{Committee, QuorumThreshold, StakeAggregator},
+    data::Data,
+    types::{AuthorityIndex, RoundNumber, StatementBlock},
+};
+
+type CommitteeHasher = blake2::Blake2b<digest::consts::U32>;
+
+pub type CommitteeDigest = [u8; 32];
+
+/// Persists the highest round signed by this validator, independently of the wal. The guard is
+/// updated (and fsynced) before signing every block, so that a validator whose wal was wiped or
+/// restored from an old backup never signs a second block for a round it already signed.
+pub struct SigningGuard {
+    file: Option<File>,
+    record: SigningGuardRecord,
+}
+
+/// The guard is only valid for the committee (epoch) it was created for; it is reset when the
+/// committee changes.
+#[derive(Serialize, Deserialize, Default, Clone, Copy, PartialEq, Debug)]
+struct SigningGuardRecord {
+    committee: CommitteeDigest,
+    highest_signed_round: RoundNumber,
+}
+
+impl SigningGuard {
+    pub fn open(path: impl AsRef<Path>, committee: &Committee) -> io::Result<Self> {
+        let file = OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .read(true)
+            .write(true)
+            .open(path)?;
+        let committee = committee_digest(committee);
+        let mut buf = vec![0u8; file.metadata()?.len() as usize];
+        file.read_exact_at(&mut buf, 0)?;
+        let record = match bincode::deserialize::<SigningGuardRecord>(&buf) {
+            Ok(record) if record.committee == committee => record,
+            Ok(_) => {
+                tracing::info!("Signing guard belongs to a different committee, resetting it");
+                SigningGuardRecord::new(committee)
+            }
+            Err(_) if buf.is_empty() => SigningGuardRecord::new(committee),
+            Err(e) => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("Corrupted signing guard: {e}"),
+                ))
+            }
+        };
+        Ok(Self {
+            file: Some(file),
+            record,
+        })
+    }
+
+    /// A guard that is not persisted, only suitable for tests.
+    pub fn new_in_memory(committee: &Committee) -> Self {
+        Self {
+            file: None,
+            record: SigningGuardRecord::new(committee_digest(committee)),
+        }
+    }
+
+    pub fn highest_signed_round(&self) -> RoundNumber {
+        self.record.highest_signed_round
+    }
+
+    /// Whether a block for the specified round can be signed without risking equivocation.
+    pub fn may_sign(&self, round: RoundNumber) -> bool {
+        round > self.record.highest_signed_round
+    }
+
+    /// Durably record that a block for the specified round is about to be signed. Must be called
+    /// (and succeed) before signing the block.
+    pub fn record(&mut self, round: RoundNumber) -> io::Result<()> {
+        assert!(
+            self.may_sign(round),
+            "Round {round} is not above the highest signed round {}",
+            self.record.highest_signed_round
+        );
+        self.record.highest_signed_round = round;
+        if let Some(file) = &self.file {
+            // The record is a few dozen bytes, well within a single sector, so overwriting it in
+            // place is atomic.
+            let serialized = bincode::serialize(&self.record).expect("Serialization failed");
+            file.write_all_at(&serialized, 0)?;
+            file.sync_data()?;
+        }
+        Ok(())
+    }
+}
+
+impl SigningGuardRecord {
+    fn new(committee: CommitteeDigest) -> Self {
+        Self {
+            committee,
+            highest_signed_round: 0,
+        }
+    }
+}
+
+fn committee_digest(committee: &Committee) -> CommitteeDigest {
+    let mut hasher = CommitteeHasher::default();
+    hasher.update(bincode::serialize(committee).expect("Serialization failed"));
+    hasher.finalize().into()
+}
+
+/// Collects the highest round of our own blocks seen by the peers. Once peers holding a quorum of
+/// stake (including ourselves) responded, any block we signed that may have been used by others
+/// is known, and we may resume proposing above the highest reported round. The rounds are taken
+/// from the blocks the peers sent back, once checked that we signed them, so that a peer cannot
+/// report a round we never reached.
+pub struct OwnRoundAggregator {
+    stake: StakeAggregator<QuorumThreshold>,
+    highest_round: RoundNumber,
+    complete: bool,
+}
+
+impl OwnRoundAggregator {
+    pub fn new(authority: AuthorityIndex, last_proposed: RoundNumber, committee: &Committee) -> Self {
+        let mut stake = StakeAggregator::new();
+        let complete = stake.add(authority, committee);
+        Self {
+            stake,
+            highest_round: last_proposed,
+            complete,
+        }
+    }
+
+    /// Register the response of a peer. Returns the highest round of our blocks seen by the peers
+    /// once enough of them responded.
+    pub fn add(
+        &mut self,
+        peer: AuthorityIndex,
+        round: RoundNumber,
+        committee: &Committee,
+    ) -> Option<RoundNumber> {
+        self.highest_round = self.highest_round.max(round);
+        self.complete = self.stake.add(peer, committee);
+        self.complete.then_some(self.highest_round)
+    }
+
+    /// Whether enough peers responded (or our own stake is already a quorum).
+    pub fn complete(&self) -> bool {
+        self.complete
+    }
+}
+
+/// The round of a block, once checked that it was authored and signed by `authority`.
+pub fn own_block_round(
+    authority: AuthorityIndex,
+    block: &Data<StatementBlock>,
+    committee: &Committee,
+) -> eyre::Result<RoundNumber> {
+    ensure!(
+        block.author() == authority,
+        "Block {} is not authored by {authority}",
+        block.reference()
+    );
+    block.verify(committee)?;
+    Ok(block.round())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::committee;
+
+    #[test]
+    fn signing_guard_persists() {
+        let dir = tempdir::TempDir::new("signing_guard_persists").unwrap();
+        let path = dir.path().join("guard");
+        let committee = committee(4);
+
+        let mut guard = SigningGuard::open(&path, &committee).unwrap();
+        assert_eq!(guard.highest_signed_round(), 0);
+        guard.record(1).unwrap();
+        guard.record(5).unwrap();
+        assert!(!guard.may_sign(5));
+        drop(guard);
+
+        let guard = SigningGuard::open(&path, &committee).unwrap();
+        assert_eq!(guard.highest_signed_round(), 5);
+        assert!(guard.may_sign(6));
+        drop(guard);
+
+        // A new committee starts from scratch
+        let guard = SigningGuard::open(&path, &crate::test_util::committee(7)).unwrap();
+        assert_eq!(guard.highest_signed_round(), 0);
+    }
+
+    #[test]
+    fn own_round_aggregator() {
+        let committee = committee(4);
+        let mut aggregator = OwnRoundAggregator::new(0, 3, &committee);
+        assert!(!aggregator.complete());
+        assert_eq!(aggregator.add(1, 7, &committee), None);
+        // Duplicate responses are not counted twice
+        assert_eq!(aggregator.add(1, 7, &committee), None);
+        assert_eq!(aggregator.add(2, 5, &committee), Some(7));
+        assert!(aggregator.complete());
+
+        // A single validator does not need to query anyone
+        assert!(OwnRoundAggregator::new(0, 0, &crate::test_util::committee(1)).complete());
+    }
+}
@@ -1,7 +1,14 @@
 // Copyright (c) Mysten Labs, Inc.
 // SPDX-License-Identifier: Apache-2.0
 
-use std::{fmt::Debug, ops::Range, time::Duration};
+use std::{
+    ops::Range,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
 
 use rand::Rng;
 use tokio::sync::mpsc;
@@ -9,19 +16,32 @@ use tokio::sync::mpsc;
 use crate::{
     committee::Committee,
     future_simulator::SimulatorContext,
-    network::{Connection, Network},
+    metrics::{Metrics, UtilizationTimerVecExt},
+    network::{Connection, Network, NetworkMessage},
+    priority::priority_channel,
     runtime,
+    types::{format_authority_index, AuthorityIndex},
 };
 
 pub struct SimulatedNetwork {
     senders: Vec<mpsc::Sender<Connection>>,
+    // The bytes sent by each authority, as they would be serialized on the wire
+    bytes_sent: Arc<Vec<AtomicU64>>,
+    // The messages sent by each authority
+    messages_sent: Arc<Vec<AtomicU64>>,
+    // The metrics of each authority, accounting for the traffic as `Network` does
+    metrics: Vec<Arc<Metrics>>,
 }
 
 impl SimulatedNetwork {
     // This is one way latency distribution, e.g. 1/2 RTT
     const LATENCY_RANGE: Range<Duration> = Duration::from_millis(50)..Duration::from_millis(100);
 
-    pub fn new(committee: &Committee) -> (SimulatedNetwork, Vec<Network>) {
+    pub fn new(
+        committee: &Committee,
+        metrics: Vec<Arc<Metrics>>,
+    ) -> (SimulatedNetwork, Vec<Network>) {
+        assert_eq!(metrics.len(), committee.len());
         let (networks, senders): (Vec<_>, Vec<_>) = committee
             .authorities()
             .map(|_| {
@@ -32,7 +52,33 @@ impl SimulatedNetwork {
                 )
             })
             .unzip();
-        (Self { senders }, networks)
+        let bytes_sent = Arc::new(senders.iter().map(|_| AtomicU64::new(0)).collect());
+        let messages_sent = Arc::new(senders.iter().map(|_| AtomicU64::new(0)).collect());
+        (
+            Self {
+                senders,
+                bytes_sent,
+                messages_sent,
+                metrics,
+            },
+            networks,
+        )
+    }
+
+    /// The bytes sent by each authority so far.
+    pub fn bytes_sent(&self) -> Vec<u64> {
+        self.bytes_sent
+            .iter()
+            .map(|bytes| bytes.load(Ordering::Relaxed))
+            .collect()
+    }
+
+    /// The messages sent by each authority so far.
+    pub fn messages_sent(&self) -> Vec<u64> {
+        self.messages_sent
+            .iter()
+            .map(|messages| messages.load(Ordering::Relaxed))
+            .collect()
     }
 
     pub async fn connect_all(&self) {
@@ -55,16 +101,23 @@ impl SimulatedNetwork {
     }
 
     pub async fn connect(&self, a: usize, b: usize) {
-        let (a_sender, a_receiver) = Self::latency_channel();
-        let (b_sender, b_receiver) = Self::latency_channel();
+        // Messages to a are sent by b and vice versa
+        let ([a_sender, a_sync_sender, a_bulk_sender], a_receiver) = self.latency_channel(b, a);
+        let ([b_sender, b_sync_sender, b_bulk_sender], b_receiver) = self.latency_channel(a, b);
         let a_connection = Connection {
             peer_id: b,
+            incoming_from: None,
             sender: b_sender,
+            sync_sender: b_sync_sender,
+            bulk_sender: b_bulk_sender,
             receiver: a_receiver,
         };
         let b_connection = Connection {
             peer_id: a,
+            incoming_from: None,
             sender: a_sender,
+            sync_sender: a_sync_sender,
+            bulk_sender: a_bulk_sender,
             receiver: b_receiver,
         };
         let a = &self.senders[a];
@@ -73,20 +126,42 @@ impl SimulatedNetwork {
         b.send(b_connection).await.ok();
     }
 
-    fn latency_channel<T: Send + 'static + Debug>() -> (mpsc::Sender<T>, mpsc::Receiver<T>) {
-        let (buf_sender, mut buf_receiver) = mpsc::channel(16);
+    fn latency_channel(
+        &self,
+        from: usize,
+        to: usize,
+    ) -> (
+        [mpsc::Sender<NetworkMessage>; 3],
+        mpsc::Receiver<NetworkMessage>,
+    ) {
+        let (buf_senders, mut buf_receiver) = priority_channel(None);
         let (sender, receiver) = mpsc::channel(16);
+        let bytes_sent = self.bytes_sent.clone();
+        let messages_sent = self.messages_sent.clone();
+        let sender_metrics = self.metrics[from].clone();
+        let receiver_metrics = self.metrics[to].clone();
+        let peer_label = format_authority_index(to as AuthorityIndex).to_string();
         runtime::Handle::current().spawn(async move {
             while let Some(message) = buf_receiver.recv().await {
+                let size = bincode::serialized_size(&message).expect("Serialization failed");
+                bytes_sent[from].fetch_add(size, Ordering::Relaxed);
+                messages_sent[from].fetch_add(1, Ordering::Relaxed);
+                // Frames carry the size of the message, as on the wire
+                let frame_size = size as usize + 4;
+                sender_metrics.frame_sent(to as AuthorityIndex, &message, frame_size);
                 let latency = SimulatorContext::with_rng(|rng| rng.gen_range(Self::LATENCY_RANGE));
                 // println!("{} {:?} lat {latency:?}", SimulatorContext::time().as_millis(), message);
                 runtime::sleep(latency).await;
                 // println!("{} snd {:?} lat {latency:?}", SimulatorContext::time().as_millis(), message);
+                receiver_metrics.frame_received(from as AuthorityIndex, &message, frame_size);
+                let _blocked = sender_metrics
+                    .network_send_blocked_us
+                    .utilization_timer(&peer_label);
                 if sender.send(message).await.is_err() {
                     return;
                 }
             }
         });
-        (buf_sender, receiver)
+        (buf_senders, receiver)
     }
 }
@@ -0,0 +1,224 @@
+// Copyright (c) Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+use std::collections::HashMap;
+
+use blake2::Digest;
+use eyre::{bail, ensure};
+use minibytes::Bytes;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    committee::Committee,
+    data::Data,
+    types::{AuthorityIndex, BlockReference, RoundNumber, StatementBlock},
+};
+
+type SnapshotHasher = blake2::Blake2b<digest::consts::U32>;
+
+pub type SnapshotDigest = [u8; 32];
+
+/// A snapshot of the committed state, allowing new (or wiped) validators to join consensus without
+/// replaying the whole DAG. Snapshots are taken right after committing the first leader of every
+/// snapshot period, so that all honest validators produce identical snapshots.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Snapshot {
+    /// The last committed leader when the snapshot was taken.
+    pub last_committed_leader: BlockReference,
+    /// The highest committed block of each authority (indexed by authority). All the blocks of an
+    /// authority at or below its frontier block are committed, and none above it.
+    pub frontier: Vec<Data<StatementBlock>>,
+    /// The state of the commit observer (see `CommitObserver::aggregator_state`).
+    pub committed_state: Bytes,
+}
+
+impl Snapshot {
+    pub fn digest(&self) -> SnapshotDigest {
+        let mut hasher = SnapshotHasher::default();
+        hasher
+            .update(bincode::serialize(&self.last_committed_leader).expect("Serialization failed"));
+        for block in &self.frontier {
+            hasher.update(bincode::serialize(block.reference()).expect("Serialization failed"));
+        }
+        hasher.update(&self.committed_state);
+        hasher.finalize().into()
+    }
+
+    pub fn frontier_references(&self) -> Vec<BlockReference> {
+        self.frontier
+            .iter()
+            .map(|block| *block.reference())
+            .collect()
+    }
+
+    pub fn round(&self) -> RoundNumber {
+        self.last_committed_leader.round()
+    }
+
+    /// Check the snapshot is well-formed and that all frontier blocks are correctly signed.
+    pub fn verify(&self, committee: &Committee) -> eyre::Result<()> {
+        ensure!(
+            self.frontier.len() == committee.len(),
+            "Snapshot frontier has {} blocks, expected {}",
+            self.frontier.len(),
+            committee.len()
+        );
+        for (authority, block) in committee.authorities().zip(self.frontier.iter()) {
+            ensure!(
+                block.author() == authority,
+                "Frontier block {} is not authored by {authority}",
+                block.reference()
+            );
+            ensure!(
+                block.round() <= self.round(),
+                "Frontier block {} is above the last committed leader {}",
+                block.reference(),
+                self.last_committed_leader
+            );
+            if block.round() == 0 {
+                ensure!(
+                    block.reference() == StatementBlock::new_genesis(authority).reference(),
+                    "Invalid genesis block {} in frontier",
+                    block.reference()
+                );
+            } else {
+                block.verify(committee)?;
+            }
+        }
+        let leader = self.last_committed_leader;
+        if self.frontier[leader.authority as usize].reference() != &leader {
+            bail!("Last committed leader {leader} is not part of the frontier");
+        }
+        Ok(())
+    }
+}
+
+/// Collects the snapshots returned by peers until f+1 of them agree. Peers that do not have a
+/// snapshot yet respond with `None`; if f+1 peers agree on `None` there is nothing to sync.
+#[derive(Default)]
+pub struct SnapshotAggregator {
+    responses: HashMap<AuthorityIndex, Option<SnapshotDigest>>,
+    snapshots: HashMap<SnapshotDigest, Snapshot>,
+}
+
+impl SnapshotAggregator {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Register the response of a peer, replacing its previous response (if any). Snapshots no
+    /// peer responds with anymore are dropped. Returns the agreed upon snapshot once f+1 peers
+    /// agree.
+    pub fn add(
+        &mut self,
+        peer: AuthorityIndex,
+        snapshot: Option<Snapshot>,
+        committee: &Committee,
+    ) -> eyre::Result<Option<Option<Snapshot>>> {
+        let digest = match snapshot {
+            Some(snapshot) => {
+                snapshot.verify(committee)?;
+                let digest = snapshot.digest();
+                self.snapshots.entry(digest).or_insert(snapshot);
+                Some(digest)
+            }
+            None => None,
+        };
+        if let Some(Some(previous)) = self.responses.insert(peer, digest) {
+            if !self
+                .responses
+                .values()
+                .any(|response| *response == Some(previous))
+            {
+                self.snapshots.remove(&previous);
+            }
+        }
+
+        let stake = self
+            .responses
+            .iter()
+            .filter(|(_, response)| **response == digest)
+            .filter_map(|(peer, _)| committee.get_stake(*peer))
+            .sum();
+        if !committee.is_valid(stake) {
+            return Ok(None);
+        }
+        Ok(Some(digest.map(|digest| {
+            self.snapshots
+                .remove(&digest)
+                .expect("Snapshot should be registered")
+        })))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::committee;
+
+    fn snapshot(round: RoundNumber, committed_state: &[u8]) -> Snapshot {
+        let frontier: Vec<_> = (0..4).map(StatementBlock::new_genesis).collect();
+        let mut last_committed_leader = *frontier[0].reference();
+        last_committed_leader.round = round;
+        Snapshot {
+            last_committed_leader,
+            frontier,
+            committed_state: committed_state.to_vec().into(),
+        }
+    }
+
+    #[test]
+    fn snapshot_verify() {
+        let committee = committee(4);
+        assert!(snapshot(0, &[]).verify(&committee).is_ok());
+        // The leader is not part of the frontier
+        assert!(snapshot(5, &[]).verify(&committee).is_err());
+        let mut truncated = snapshot(0, &[]);
+        truncated.frontier.pop();
+        assert!(truncated.verify(&committee).is_err());
+    }
+
+    #[test]
+    fn snapshot_aggregator() {
+        let committee = committee(4);
+        let mut aggregator = SnapshotAggregator::new();
+        let first = snapshot(0, &[1]);
+        let second = snapshot(0, &[2]);
+        assert!(aggregator
+            .add(0, Some(first.clone()), &committee)
+            .unwrap()
+            .is_none());
+        assert!(aggregator
+            .add(1, Some(second.clone()), &committee)
+            .unwrap()
+            .is_none());
+        // Peers may change their response, e.g. after a new snapshot was taken
+        assert!(aggregator
+            .add(1, Some(first.clone()), &committee)
+            .unwrap()
+            .is_some());
+        // Replaced responses do not keep their snapshot around
+        assert!(aggregator.snapshots.is_empty());
+
+        let mut aggregator = SnapshotAggregator::new();
+        for snapshot in [&first, &second, &first] {
+            assert!(aggregator
+                .add(0, Some(snapshot.clone()), &committee)
+                .unwrap()
+                .is_none());
+        }
+        assert_eq!(aggregator.snapshots.len(), 1);
+        assert!(aggregator.snapshots.contains_key(&first.digest()));
+
+        let mut aggregator = SnapshotAggregator::new();
+        assert!(aggregator.add(0, None, &committee).unwrap().is_none());
+        assert!(aggregator
+            .add(1, Some(second), &committee)
+            .unwrap()
+            .is_none());
+        assert!(matches!(
+            aggregator.add(2, None, &committee).unwrap(),
+            Some(None)
+        ));
+    }
+}
@@ -1,7 +1,10 @@
 // Copyright (c) Mysten Labs, Inc.
 // SPDX-License-Identifier: Apache-2.0
 
-use std::collections::{BTreeMap, HashSet, VecDeque};
+use std::{
+    collections::{BTreeMap, HashSet, VecDeque},
+    io,
+};
 
 use minibytes::Bytes;
 
@@ -9,8 +12,10 @@ use crate::{
     block_store::{BlockStore, CommitData, OwnBlockData},
     core::MetaStatement,
     data::Data,
+    index_checkpoint::IndexCheckpoint,
+    snapshot::Snapshot,
     types::{BlockReference, StatementBlock},
-    wal::WalPosition,
+    wal::{WalPosition, WalReader},
 };
 
 pub struct RecoveredState {
@@ -23,6 +28,7 @@ pub struct RecoveredState {
     pub last_committed_leader: Option<BlockReference>,
     pub committed_blocks: HashSet<BlockReference>,
     pub committed_state: Option<Bytes>,
+    pub snapshot: Option<Snapshot>,
 }
 
 #[derive(Default)]
@@ -35,6 +41,7 @@ pub struct RecoveredStateBuilder {
     last_committed_leader: Option<BlockReference>,
     committed_blocks: HashSet<BlockReference>,
     committed_state: Option<Bytes>,
+    snapshot: Option<Snapshot>,
 }
 
 impl RecoveredStateBuilder {
@@ -42,6 +49,46 @@ impl RecoveredStateBuilder {
         Self::default()
     }
 
+    /// Resume the replay after the entries covered by an index checkpoint, reading the state
+    /// entries it references from the wal.
+    pub fn from_checkpoint(checkpoint: &IndexCheckpoint, reader: &WalReader) -> io::Result<Self> {
+        let pending = checkpoint
+            .pending
+            .iter()
+            .map(|(position, statement)| (*position, RawMetaStatement::from(statement)))
+            .collect();
+        let last_own_block = checkpoint
+            .last_own_block
+            .as_ref()
+            .map(|(next_entry, block)| OwnBlockData {
+                next_entry: *next_entry,
+                block: block.clone(),
+            });
+        let state = checkpoint
+            .state
+            .map(|position| reader.read(position).map(|(_, state)| state))
+            .transpose()?;
+        let committed_state = match checkpoint.committed_state {
+            Some(position) => {
+                let (_, commits) = reader.read(position)?;
+                let (_, committed_state): (Vec<CommitData>, Bytes) = bincode::deserialize(&commits)
+                    .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+                Some(committed_state)
+            }
+            None => None,
+        };
+        Ok(Self {
+            pending,
+            last_own_block,
+            state,
+            unprocessed_blocks: vec![],
+            last_committed_leader: checkpoint.last_committed_leader,
+            committed_blocks: checkpoint.committed_blocks.iter().copied().collect(),
+            committed_state,
+            snapshot: checkpoint.snapshot.clone(),
+        })
+    }
+
     pub fn block(&mut self, pos: WalPosition, block: &Data<StatementBlock>) {
         self.pending
             .insert(pos, RawMetaStatement::Include(*block.reference()));
@@ -73,6 +120,10 @@ impl RecoveredStateBuilder {
         self.committed_state = Some(committed_state);
     }
 
+    pub fn snapshot(&mut self, snapshot: Snapshot) {
+        self.snapshot = Some(snapshot);
+    }
+
     pub fn build(self, block_store: BlockStore) -> RecoveredState {
         let pending = self
             .pending
@@ -88,6 +139,7 @@ impl RecoveredStateBuilder {
             last_committed_leader: self.last_committed_leader,
             committed_blocks: self.committed_blocks,
             committed_state: self.committed_state,
+            snapshot: self.snapshot,
         }
     }
 }
@@ -97,6 +149,19 @@ enum RawMetaStatement {
     Payload(Bytes),
 }
 
+impl From<&MetaStatement> for RawMetaStatement {
+    fn from(statement: &MetaStatement) -> Self {
+        match statement {
+            MetaStatement::Include(include) => RawMetaStatement::Include(*include),
+            MetaStatement::Payload(payload) => RawMetaStatement::Payload(
+                bincode::serialize(payload)
+                    .expect("Payload serialization failed")
+                    .into(),
+            ),
+        }
+    }
+}
+
 impl RawMetaStatement {
     fn into_meta_statement(self) -> MetaStatement {
         match self {
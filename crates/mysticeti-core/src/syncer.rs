@@ -1,24 +1,33 @@
 // Copyright (c) Mysten Labs, Inc.
 // SPDX-License-Identifier: Apache-2.0
 
-use std::{collections::HashSet, sync::Arc};
+use std::{collections::HashSet, io, sync::Arc};
 
 use minibytes::Bytes;
 
 use crate::{
+    address_book::AddressUpdate,
+    batch::Batch,
     block_handler::BlockHandler,
     block_store::BlockStore,
     consensus::linearizer::CommittedSubDag,
     core::Core,
     data::Data,
     metrics::{Metrics, UtilizationTimerVecExt},
+    resolver::PeerAddress,
     runtime::timestamp_utc,
+    snapshot::Snapshot,
+    topology::{OwnRoundStatement, SignedOwnRound},
     types::{AuthorityIndex, BlockReference, RoundNumber, StatementBlock},
 };
 
 pub struct Syncer<H: BlockHandler, S: SyncerSignals, C: CommitObserver> {
     core: Core<H>,
     force_new_block: bool,
+    // Set while a new validator waits for a snapshot before proposing
+    awaiting_snapshot: bool,
+    // Set while waiting for the peers to report the highest round of our own blocks they know
+    awaiting_own_round: bool,
     commit_period: u64,
     signals: S,
     commit_observer: C,
@@ -40,6 +49,9 @@ pub trait CommitObserver: Send + Sync {
     fn aggregator_state(&self) -> Bytes;
 
     fn recover_committed(&mut self, committed: HashSet<BlockReference>, state: Option<Bytes>);
+
+    /// Blocks at or below the snapshot frontier are committed but not available locally.
+    fn recover_snapshot_frontier(&mut self, frontier: &[BlockReference]);
 }
 
 impl<H: BlockHandler, S: SyncerSignals, C: CommitObserver> Syncer<H, S, C> {
@@ -54,6 +66,8 @@ impl<H: BlockHandler, S: SyncerSignals, C: CommitObserver> Syncer<H, S, C> {
         Self {
             core,
             force_new_block: false,
+            awaiting_snapshot: false,
+            awaiting_own_round: false,
             commit_period,
             signals,
             commit_observer,
@@ -68,10 +82,77 @@ impl<H: BlockHandler, S: SyncerSignals, C: CommitObserver> Syncer<H, S, C> {
             .utilization_timer
             .utilization_timer("Syncer::add_blocks");
         self.core.add_blocks(blocks);
+        if self.core.is_observer() {
+            // Observers never propose, they only try to commit the blocks they receive
+            self.try_commit();
+        } else {
+            self.try_new_block();
+        }
+    }
+
+    pub fn add_batches(&mut self, batches: Vec<Data<Batch>>) {
+        self.core.add_batches(batches);
+    }
+
+    /// Do not propose blocks until `install_snapshot` is called.
+    pub fn await_snapshot(&mut self) {
+        self.awaiting_snapshot = true;
+    }
+
+    /// Start from the snapshot agreed upon by the peers (if any) and resume proposing blocks.
+    pub fn install_snapshot(&mut self, snapshot: Option<Snapshot>) {
+        if !self.awaiting_snapshot {
+            return;
+        }
+        if let Some(snapshot) = snapshot {
+            let state = snapshot.committed_state.clone();
+            let frontier = snapshot.frontier_references();
+            self.core.install_snapshot(snapshot);
+            self.commit_observer
+                .recover_committed(HashSet::new(), Some(state));
+            self.commit_observer.recover_snapshot_frontier(&frontier);
+        }
+        self.awaiting_snapshot = false;
+        self.force_new_block = true;
         self.try_new_block();
     }
 
+    pub fn snapshot(&self) -> Option<Snapshot> {
+        self.core.snapshot().cloned()
+    }
+
+    pub fn sign_address_update(&mut self, address: PeerAddress) -> io::Result<AddressUpdate> {
+        self.core.sign_address_update(address)
+    }
+
+    pub fn sign_own_round(&mut self, statement: OwnRoundStatement) -> io::Result<SignedOwnRound> {
+        self.core.sign_own_round(statement)
+    }
+
+    /// Do not propose blocks until `resume_above_own_round` is called.
+    pub fn await_own_round(&mut self) {
+        self.awaiting_own_round = true;
+    }
+
+    /// Resume proposing blocks, above the highest round of our own blocks known to the peers.
+    pub fn resume_above_own_round(&mut self, round: RoundNumber) {
+        if !self.awaiting_own_round {
+            return;
+        }
+        self.core.set_peers_own_round(round);
+        self.awaiting_own_round = false;
+        self.force_new_block = true;
+        self.try_new_block();
+    }
+
+    fn awaiting_peers(&self) -> bool {
+        self.awaiting_snapshot || self.awaiting_own_round
+    }
+
     pub fn force_new_block(&mut self, round: RoundNumber) -> bool {
+        if self.core.is_observer() || self.awaiting_peers() {
+            return false;
+        }
         if self.core.last_proposed() == round {
             self.metrics.leader_timeout_total.inc();
             self.force_new_block = true;
@@ -88,6 +169,9 @@ impl<H: BlockHandler, S: SyncerSignals, C: CommitObserver> Syncer<H, S, C> {
             .utilization_timer
             .utilization_timer("Syncer::try_new_block");
 
+        if self.awaiting_peers() {
+            return;
+        }
         if self.force_new_block
             || self
             .core
@@ -103,20 +187,34 @@ impl<H: BlockHandler, S: SyncerSignals, C: CommitObserver> Syncer<H, S, C> {
                 return;
             }; // No need to commit after epoch is safe to close
 
-            let newly_committed = self.core.try_commit();
-            let utc_now = timestamp_utc();
-            if !newly_committed.is_empty() {
-                let committed_refs: Vec<_> = newly_committed
-                    .iter()
-                    .map(|block| {
-                        let age = utc_now
-                            .checked_sub(block.meta_creation_time())
-                            .unwrap_or_default();
-                        format!("{}({}ms)", block.reference(), age.as_millis())
-                    })
-                    .collect();
-                tracing::debug!("Committed {:?}", committed_refs);
-            }
+            self.try_commit();
+        }
+    }
+
+    fn try_commit(&mut self) {
+        let mut newly_committed = self.core.try_commit();
+        let utc_now = timestamp_utc();
+        if !newly_committed.is_empty() {
+            let committed_refs: Vec<_> = newly_committed
+                .iter()
+                .map(|block| {
+                    let age = utc_now
+                        .checked_sub(block.meta_creation_time())
+                        .unwrap_or_default();
+                    format!("{}({}ms)", block.reference(), age.as_millis())
+                })
+                .collect();
+            tracing::debug!("Committed {:?}", committed_refs);
+        }
+        // Leaders are handed to the commit observer in chunks ending at snapshot boundaries, so
+        // that snapshots capture the same committed state on all validators.
+        loop {
+            let remaining = match self.core.snapshot_boundary(&newly_committed) {
+                Some(index) if index + 1 < newly_committed.len() => {
+                    newly_committed.split_off(index + 1)
+                }
+                _ => vec![],
+            };
             let committed_subdag = self
                 .commit_observer
                 .handle_commit(self.core.block_store(), newly_committed);
@@ -124,6 +222,10 @@ impl<H: BlockHandler, S: SyncerSignals, C: CommitObserver> Syncer<H, S, C> {
                 committed_subdag,
                 &self.commit_observer.aggregator_state(),
             );
+            if remaining.is_empty() {
+                break;
+            }
+            newly_committed = remaining;
         }
     }
 
@@ -5,13 +5,14 @@ use std::{collections::HashMap, sync::Arc, time::Duration};
 
 use futures::future::join_all;
 use rand::{seq::SliceRandom, thread_rng};
-use tokio::sync::mpsc;
+use tokio::sync::{mpsc, watch};
 
 use crate::{
     block_handler::BlockHandler,
+    config::NodeParameters,
     metrics::Metrics,
     net_sync::{self, NetworkSyncerInner},
-    network::NetworkMessage,
+    network::{Connection, NetworkMessage},
     runtime::{sleep, timestamp_utc, Handle, JoinHandle},
     syncer::CommitObserver,
     types::{AuthorityIndex, BlockReference, RoundNumber},
@@ -50,8 +51,14 @@ impl Default for SynchronizerParameters {
 }
 
 pub struct BlockDisseminator<H: BlockHandler, C: CommitObserver> {
-    /// The sender to the network.
+    /// The peer to send the blocks to.
+    peer: AuthorityIndex,
+    /// The sender of our fresh blocks to the network.
     sender: mpsc::Sender<NetworkMessage>,
+    /// The sender of the responses to the requests of the peer.
+    sync_sender: mpsc::Sender<NetworkMessage>,
+    /// The sender of the blocks streamed to the peer to catch up.
+    bulk_sender: mpsc::Sender<NetworkMessage>,
     /// The inner state of the network syncer.
     inner: Arc<NetworkSyncerInner<H, C>>,
     /// The handle of the task disseminating our own blocks.
@@ -70,13 +77,16 @@ where
     C: CommitObserver + 'static,
 {
     pub fn new(
-        sender: mpsc::Sender<NetworkMessage>,
+        connection: &Connection,
         inner: Arc<NetworkSyncerInner<H, C>>,
         parameters: SynchronizerParameters,
         metrics: Arc<Metrics>,
     ) -> Self {
         Self {
-            sender,
+            peer: connection.peer_id as AuthorityIndex,
+            sender: connection.sender.clone(),
+            sync_sender: connection.sync_sender.clone(),
+            bulk_sender: connection.bulk_sender.clone(),
             inner,
             own_blocks: None,
             other_blocks: Vec::new(),
@@ -109,7 +119,11 @@ where
             let found = stored_block.is_some();
             match stored_block {
                 // TODO: Should we be able to send more than one block in a single network message?
-                Some(block) => self.sender.send(NetworkMessage::Block(block)).await.ok()?,
+                Some(block) => self
+                    .sync_sender
+                    .send(NetworkMessage::Block(block))
+                    .await
+                    .ok()?,
                 None => missing.push(reference),
             }
             self.metrics
@@ -117,7 +131,7 @@ where
                 .with_label_values(&[&peer.to_string(), &found.to_string()])
                 .inc();
         }
-        self.sender
+        self.sync_sender
             .send(NetworkMessage::BlockNotFound(missing))
             .await
             .ok()
@@ -130,6 +144,7 @@ where
         }
 
         let handle = Handle::current().spawn(Self::stream_own_blocks(
+            self.peer,
             self.sender.clone(),
             self.inner.clone(),
             round,
@@ -139,6 +154,7 @@ where
     }
 
     async fn stream_own_blocks(
+        peer: AuthorityIndex,
         to: mpsc::Sender<NetworkMessage>,
         inner: Arc<NetworkSyncerInner<H, C>>,
         mut round: RoundNumber,
@@ -147,11 +163,29 @@ where
         loop {
             let notified = inner.notify.notified();
             let blocks = inner.block_store.get_own_blocks(round, batch_size);
+            // A full batch means the peer is behind, e.g. after a restart, so keep sending
+            let caught_up = blocks.len() < batch_size;
+            let last_own_round = inner
+                .block_store
+                .last_own_block_ref()
+                .map(|reference| reference.round())
+                .unwrap_or_default();
             for block in blocks {
                 round = block.round();
-                to.send(NetworkMessage::Block(block)).await.ok()?;
+                // Large blocks are erasure-coded, the peer only gets its chunk, see `erasure`
+                let chunk = inner
+                    .chunk_relay
+                    .as_ref()
+                    .and_then(|relay| relay.own_chunk(&block, peer, last_own_round));
+                let message = match chunk {
+                    Some(chunk) => NetworkMessage::Chunk(chunk),
+                    None => NetworkMessage::Block(block),
+                };
+                to.send(message).await.ok()?;
+            }
+            if caught_up {
+                notified.await
             }
-            notified.await
         }
     }
 
@@ -165,7 +199,7 @@ where
         }
 
         let handle = Handle::current().spawn(Self::stream_others_blocks(
-            self.sender.clone(),
+            self.bulk_sender.clone(),
             self.inner.clone(),
             round,
             author,
@@ -211,14 +245,14 @@ impl BlockFetcher {
         id: AuthorityIndex,
         inner: Arc<NetworkSyncerInner<B, C>>,
         metrics: Arc<Metrics>,
-        enable: bool,
+        node_parameters: watch::Receiver<NodeParameters>,
     ) -> Self
     where
         B: BlockHandler + 'static,
         C: CommitObserver + 'static,
     {
         let (sender, receiver) = mpsc::channel(100);
-        let worker = BlockFetcherWorker::new(id, inner, receiver, metrics, enable);
+        let worker = BlockFetcherWorker::new(id, inner, receiver, metrics, node_parameters);
         let handle = Handle::current().spawn(worker.run());
         Self { sender, handle }
     }
@@ -256,7 +290,8 @@ struct BlockFetcherWorker<B: BlockHandler, C: CommitObserver> {
     metrics: Arc<Metrics>,
     /// Hold a timestamp of when blocks were first considered missing.
     missing: HashMap<BlockReference, Duration>,
-    enable: bool,
+    /// Whether the synchronizer is enabled may be reloaded at runtime, see `reload`.
+    node_parameters: watch::Receiver<NodeParameters>,
 }
 
 impl<B, C> BlockFetcherWorker<B, C>
@@ -269,7 +304,7 @@ where
         inner: Arc<NetworkSyncerInner<B, C>>,
         receiver: mpsc::Receiver<BlockFetcherMessage>,
         metrics: Arc<Metrics>,
-        enable: bool,
+        node_parameters: watch::Receiver<NodeParameters>,
     ) -> Self {
         Self {
             id,
@@ -279,7 +314,7 @@ where
             parameters: Default::default(),
             metrics,
             missing: Default::default(),
-            enable,
+            node_parameters,
         }
     }
 
@@ -304,7 +339,7 @@ where
 
     /// A simple and naive strategy that requests missing blocks from random peers.
     async fn sync_strategy(&mut self) {
-        if self.enable {
+        if self.node_parameters.borrow().enable_synchronizer {
             return;
         }
 
@@ -3,7 +3,7 @@
 
 use std::{
     net::{Ipv4Addr, SocketAddr, SocketAddrV4},
-    path::Path,
+    path::{Path, PathBuf},
     sync::Arc,
 };
 
@@ -17,15 +17,22 @@ use crate::future_simulator::OverrideNodeContext;
 use crate::simulated_network::SimulatedNetwork;
 use crate::{
     block_handler::{BlockHandler, TestBlockHandler, TestCommitHandler},
+    block_signer::BlockSigner,
     block_store::{BlockStore, BlockWriter, OwnBlockData, WAL_ENTRY_BLOCK},
     committee::Committee,
+    compression::Compression,
     config::{self, NodePrivateConfig, NodePublicConfig},
     core::{Core, CoreOptions},
     data::Data,
+    index_checkpoint::IndexCheckpoint,
     metrics::{MetricReporter, Metrics},
     net_sync::NetworkSyncer,
-    network::Network,
+    network::{Network, TransportProtocol},
+    protocol::Capabilities,
+    resolver::{PeerAddress, Resolver},
+    signing_guard::SigningGuard,
     syncer::{Syncer, SyncerSignals},
+    topology::Topology,
     types::{format_authority_index, AuthorityIndex, BlockReference, RoundNumber, StatementBlock},
     wal::{open_file_for_wal, walf, WalPosition, WalWriter},
 };
@@ -80,6 +87,55 @@ pub fn committee_and_cores_persisted_epoch_duration(
     Arc<Committee>,
     Vec<Core<TestBlockHandler>>,
     Vec<MetricReporter>,
+) {
+    open_cores(n, path, public_config, false, |authority| {
+        NodePrivateConfig::new_for_tests(authority)
+            .into_block_signer()
+            .unwrap()
+    })
+}
+
+/// Same as `committee_and_cores_persisted`, storing and loading index checkpoints next to the wal
+/// files every `index_checkpoint_period` committed rounds.
+pub fn committee_and_cores_persisted_with_index_checkpoint(
+    n: usize,
+    path: &Path,
+    index_checkpoint_period: RoundNumber,
+) -> (
+    Arc<Committee>,
+    Vec<Core<TestBlockHandler>>,
+    Vec<MetricReporter>,
+) {
+    let mut public_config = NodePublicConfig::new_for_tests(n);
+    public_config.parameters.index_checkpoint_period = index_checkpoint_period;
+    open_cores(n, Some(path), &public_config, true, |authority| {
+        NodePrivateConfig::new_for_tests(authority)
+            .into_block_signer()
+            .unwrap()
+    })
+}
+
+pub fn committee_and_cores_with_signer(
+    n: usize,
+    signer: impl FnMut(AuthorityIndex) -> Box<dyn BlockSigner>,
+) -> (
+    Arc<Committee>,
+    Vec<Core<TestBlockHandler>>,
+    Vec<MetricReporter>,
+) {
+    open_cores(n, None, &NodePublicConfig::new_for_tests(n), false, signer)
+}
+
+fn open_cores(
+    n: usize,
+    path: Option<&Path>,
+    public_config: &NodePublicConfig,
+    index_checkpoint: bool,
+    mut signer: impl FnMut(AuthorityIndex) -> Box<dyn BlockSigner>,
+) -> (
+    Arc<Committee>,
+    Vec<Core<TestBlockHandler>>,
+    Vec<MetricReporter>,
 ) {
     let committee = committee(n);
     let cores: Vec<_> = committee
@@ -100,27 +156,47 @@ pub fn committee_and_cores_persisted_epoch_duration(
                 tempfile::tempfile().unwrap()
             };
             let (wal_writer, wal_reader) = walf(wal_file).expect("Failed to open wal");
-            let recovered = BlockStore::open(
+            let index_checkpoint_path = match path {
+                Some(path) if index_checkpoint => Some(index_checkpoint_path(path, authority)),
+                _ => None,
+            };
+            let checkpoint = index_checkpoint_path
+                .as_ref()
+                .and_then(|path| IndexCheckpoint::load(path).unwrap());
+            let recovered = BlockStore::open_with_checkpoint(
                 authority,
                 Arc::new(wal_reader),
                 &wal_writer,
                 metrics.clone(),
                 &committee,
+                public_config.parameters.block_cache_size,
+                checkpoint,
             );
+            let mut options = CoreOptions::test();
+            if let Some(path) = index_checkpoint_path {
+                options = options.with_index_checkpoint(path);
+            }
 
-            let private_config = NodePrivateConfig::new_for_tests(authority);
+            let block_signer = signer(authority);
+            let signing_guard = if let Some(path) = path {
+                let guard_path = path.join(format!("{:03}.guard", authority));
+                SigningGuard::open(guard_path, &committee).unwrap()
+            } else {
+                SigningGuard::new_in_memory(&committee)
+            };
 
             println!("Opening core {authority}");
             let core = Core::open(
                 block_handler,
                 authority,
                 committee.clone(),
-                private_config,
+                block_signer,
+                signing_guard,
                 public_config,
                 metrics,
                 recovered,
                 wal_writer,
-                CoreOptions::test(),
+                options,
             );
             (core, reporter)
         })
@@ -129,6 +205,10 @@ pub fn committee_and_cores_persisted_epoch_duration(
     (committee, cores, reporters)
 }
 
+pub fn index_checkpoint_path(path: &Path, authority: AuthorityIndex) -> PathBuf {
+    path.join(format!("{:03}.index", authority))
+}
+
 fn first_transaction_for_authority(authority: AuthorityIndex) -> u64 {
     authority * 1_000_000
 }
@@ -149,6 +229,7 @@ pub fn committee_and_syncers(
                     committee.clone(),
                     core.block_handler().transaction_time.clone(),
                     test_metrics(),
+                    core.authority(),
                 );
                 Syncer::new(core, 3, Default::default(), commit_handler, test_metrics())
             })
@@ -157,9 +238,23 @@ pub fn committee_and_syncers(
 }
 
 pub async fn networks_and_addresses(metrics: &[Arc<Metrics>]) -> (Vec<Network>, Vec<SocketAddr>) {
+    let capabilities = vec![Capabilities::new(Compression::None); metrics.len()];
+    networks_and_addresses_with_observers(metrics, 5001, &[], &Topology::Mesh, &capabilities).await
+}
+
+/// Create the networks of the validators, listening on consecutive ports starting from
+/// `first_port` and connected in the given topology. Every validator accepts connections from
+/// all the specified observers, and offers the capabilities at its index in the handshake.
+pub async fn networks_and_addresses_with_observers(
+    metrics: &[Arc<Metrics>],
+    first_port: u16,
+    observers: &[(usize, SocketAddr)],
+    topology: &Topology,
+    capabilities: &[Capabilities],
+) -> (Vec<Network>, Vec<SocketAddr>) {
     let host = Ipv4Addr::LOCALHOST;
     let addresses: Vec<_> = (0..metrics.len())
-        .map(|i| SocketAddr::V4(SocketAddrV4::new(host, 5001 + i as u16)))
+        .map(|i| SocketAddr::V4(SocketAddrV4::new(host, first_port + i as u16)))
         .collect();
     let networks =
         addresses
@@ -167,7 +262,16 @@ pub async fn networks_and_addresses(metrics: &[Arc<Metrics>]) -> (Vec<Network>,
             .zip(metrics.iter())
             .enumerate()
             .map(|(i, (address, metrics))| {
-                Network::from_socket_addresses(&addresses, i, *address, metrics.clone())
+                Network::from_socket_addresses(
+                    &addresses,
+                    observers,
+                    i,
+                    *address,
+                    metrics.clone(),
+                    capabilities[i],
+                    TransportProtocol::Tcp,
+                    topology,
+                )
             });
     let networks = join_all(networks).await;
     (networks, addresses)
@@ -197,13 +301,15 @@ pub fn simulated_network_syncers_with_epoch_duration(
     Vec<MetricReporter>,
 ) {
     let (committee, cores, reporters) = committee_and_cores_epoch_duration(n, rounds_in_epoch);
-    let (simulated_network, networks) = SimulatedNetwork::new(&committee);
+    let metrics = cores.iter().map(|core| core.metrics.clone()).collect();
+    let (simulated_network, networks) = SimulatedNetwork::new(&committee, metrics);
     let mut network_syncers = vec![];
     for (network, core) in networks.into_iter().zip(cores.into_iter()) {
         let commit_handler = TestCommitHandler::new(
             committee.clone(),
             core.block_handler().transaction_time.clone(),
             core.metrics.clone(),
+            core.authority(),
         );
         let node_context = OverrideNodeContext::enter(Some(core.authority()));
         let network_syncer = NetworkSyncer::start(
@@ -213,6 +319,7 @@ pub fn simulated_network_syncers_with_epoch_duration(
             commit_handler,
             config::node_defaults::default_shutdown_grace_period(),
             test_metrics(),
+            &NodePublicConfig::new_for_tests(n),
         );
         drop(node_context);
         network_syncers.push(network_syncer);
@@ -220,6 +327,81 @@ pub fn simulated_network_syncers_with_epoch_duration(
     (simulated_network, network_syncers, reporters)
 }
 
+#[cfg(feature = "simulator")]
+pub fn simulated_network_syncers_with_public_config(
+    n: usize,
+    public_config: &NodePublicConfig,
+) -> (
+    SimulatedNetwork,
+    Vec<NetworkSyncer<TestBlockHandler, TestCommitHandler>>,
+    Vec<MetricReporter>,
+) {
+    let (simulated_network, network_syncers, reporters, _) =
+        simulated_network_some_syncers(n, n, public_config);
+    (simulated_network, network_syncers, reporters)
+}
+
+/// Starts the first `started` validators of a committee of `n`, and returns the networks of the
+/// other validators, for the test to play their part.
+#[cfg(feature = "simulator")]
+pub fn simulated_network_some_syncers(
+    n: usize,
+    started: usize,
+    public_config: &NodePublicConfig,
+) -> (
+    SimulatedNetwork,
+    Vec<NetworkSyncer<TestBlockHandler, TestCommitHandler>>,
+    Vec<MetricReporter>,
+    Vec<Network>,
+) {
+    let (committee, cores, mut reporters) = committee_and_cores(n);
+    reporters.truncate(started);
+    let (simulated_network, network_syncers, others) =
+        simulated_network_syncers_with_cores(&committee, cores, started, public_config);
+    (simulated_network, network_syncers, reporters, others)
+}
+
+/// Starts the first `started` of the given cores on a simulated network, and returns the networks
+/// of the other validators.
+#[cfg(feature = "simulator")]
+pub fn simulated_network_syncers_with_cores(
+    committee: &Arc<Committee>,
+    cores: Vec<Core<TestBlockHandler>>,
+    started: usize,
+    public_config: &NodePublicConfig,
+) -> (
+    SimulatedNetwork,
+    Vec<NetworkSyncer<TestBlockHandler, TestCommitHandler>>,
+    Vec<Network>,
+) {
+    let metrics = cores.iter().map(|core| core.metrics.clone()).collect();
+    let (simulated_network, mut networks) = SimulatedNetwork::new(committee, metrics);
+    let others = networks.split_off(started);
+    let mut network_syncers = vec![];
+    for (network, core) in networks.into_iter().zip(cores) {
+        let commit_handler = TestCommitHandler::new(
+            committee.clone(),
+            core.block_handler().transaction_time.clone(),
+            core.metrics.clone(),
+            core.authority(),
+        );
+        let node_context = OverrideNodeContext::enter(Some(core.authority()));
+        let metrics = core.metrics.clone();
+        let network_syncer = NetworkSyncer::start(
+            network,
+            core,
+            3,
+            commit_handler,
+            config::node_defaults::default_shutdown_grace_period(),
+            metrics,
+            public_config,
+        );
+        drop(node_context);
+        network_syncers.push(network_syncer);
+    }
+    (simulated_network, network_syncers, others)
+}
+
 pub async fn network_syncers(n: usize) -> Vec<NetworkSyncer<TestBlockHandler, TestCommitHandler>> {
     network_syncers_with_epoch_duration(n, config::node_defaults::default_rounds_in_epoch()).await
 }
@@ -237,6 +419,7 @@ pub async fn network_syncers_with_epoch_duration(
             committee.clone(),
             core.block_handler().transaction_time.clone(),
             test_metrics(),
+            core.authority(),
         );
         let network_syncer = NetworkSyncer::start(
             network,
@@ -252,6 +435,195 @@ pub async fn network_syncers_with_epoch_duration(
     network_syncers
 }
 
+/// Start a network syncer for every core, on the network at the same index. The network syncers
+/// report to the metrics of their core.
+pub fn start_network_syncers(
+    networks: Vec<Network>,
+    cores: Vec<Core<TestBlockHandler>>,
+    committee: &Arc<Committee>,
+    public_config: &NodePublicConfig,
+) -> Vec<NetworkSyncer<TestBlockHandler, TestCommitHandler>> {
+    networks
+        .into_iter()
+        .zip(cores)
+        .map(|(network, core)| {
+            let commit_handler = TestCommitHandler::new(
+                committee.clone(),
+                core.block_handler().transaction_time.clone(),
+                test_metrics(),
+                core.authority(),
+            );
+            let metrics = core.metrics.clone();
+            NetworkSyncer::start(
+                network,
+                core,
+                3,
+                commit_handler,
+                config::node_defaults::default_shutdown_grace_period(),
+                metrics,
+                public_config,
+            )
+        })
+        .collect()
+}
+
+/// Start a committee of validators with the given parameters, listening on consecutive ports
+/// starting from `first_port`. The network syncers report to the metrics of their core.
+pub async fn network_syncers_with_public_config(
+    first_port: u16,
+    public_config: &NodePublicConfig,
+) -> Vec<NetworkSyncer<TestBlockHandler, TestCommitHandler>> {
+    let capabilities = Capabilities::from_parameters(&public_config.parameters);
+    let capabilities = vec![capabilities; public_config.identifiers.len()];
+    network_syncers_with_capabilities(first_port, public_config, &capabilities).await
+}
+
+/// Like `network_syncers_with_public_config`, every validator offering the capabilities at its
+/// index in the handshake, to run several versions of the protocol side by side.
+pub async fn network_syncers_with_capabilities(
+    first_port: u16,
+    public_config: &NodePublicConfig,
+    capabilities: &[Capabilities],
+) -> Vec<NetworkSyncer<TestBlockHandler, TestCommitHandler>> {
+    let (committee, cores, _) = committee_and_cores(public_config.identifiers.len());
+    let metrics: Vec<_> = cores.iter().map(|c| c.metrics.clone()).collect();
+    let (networks, _) = networks_and_addresses_with_observers(
+        &metrics,
+        first_port,
+        &[],
+        &public_config.parameters.topology,
+        capabilities,
+    )
+    .await;
+    start_network_syncers(networks, cores, &committee, public_config)
+}
+
+/// Start validator `authority`, listening on its address in `addresses` and connecting to the
+/// other validators at theirs, resolving their names with `resolver`. The validator recovers its
+/// store from `path` if given, and starts with an empty store otherwise.
+pub async fn network_syncer_at(
+    authority: AuthorityIndex,
+    addresses: &[PeerAddress],
+    resolver: &Resolver,
+    public_config: &NodePublicConfig,
+    path: Option<&Path>,
+) -> NetworkSyncer<TestBlockHandler, TestCommitHandler> {
+    let (committee, mut cores, _) =
+        committee_and_cores_persisted_epoch_duration(addresses.len(), path, public_config);
+    let core = cores.swap_remove(authority as usize);
+    // Listen on the first address our own name resolves to
+    let local_addr = resolver
+        .resolve(&addresses[authority as usize])
+        .await
+        .expect("Failed to resolve our own address")[0];
+    let network = Network::from_addresses(
+        addresses,
+        &[],
+        authority as usize,
+        local_addr,
+        core.metrics.clone(),
+        Capabilities::from_parameters(&public_config.parameters),
+        TransportProtocol::Tcp,
+        &Topology::Mesh,
+        resolver.clone(),
+    )
+    .await;
+    let commit_handler = TestCommitHandler::new(
+        committee,
+        core.block_handler().transaction_time.clone(),
+        test_metrics(),
+        authority,
+    );
+    NetworkSyncer::start(
+        network,
+        core,
+        3,
+        commit_handler,
+        config::node_defaults::default_shutdown_grace_period(),
+        test_metrics(),
+        public_config,
+    )
+}
+
+/// Start a committee of `n` validators and one observer following all of them, listening on
+/// consecutive ports starting from `first_port`.
+pub async fn network_syncers_with_observer(
+    first_port: u16,
+    n: usize,
+) -> (
+    Vec<NetworkSyncer<TestBlockHandler, TestCommitHandler>>,
+    NetworkSyncer<TestBlockHandler, TestCommitHandler>,
+) {
+    let public_config = NodePublicConfig::new_for_tests(n);
+    let (committee, cores, _) = committee_and_cores(n);
+    let observer_id = n as AuthorityIndex;
+    let observer_address = SocketAddr::V4(SocketAddrV4::new(
+        Ipv4Addr::LOCALHOST,
+        first_port + n as u16,
+    ));
+    let metrics: Vec<_> = cores.iter().map(|c| c.metrics.clone()).collect();
+    let (networks, addresses) = networks_and_addresses_with_observers(
+        &metrics,
+        first_port,
+        &[(observer_id as usize, observer_address)],
+        &Topology::Mesh,
+        &vec![Capabilities::from_parameters(&public_config.parameters); n],
+    )
+    .await;
+    let network_syncers = start_network_syncers(networks, cores, &committee, &public_config);
+
+    let (metrics, _) = Metrics::new(&Registry::new(), Some(&committee));
+    let block_handler = TestBlockHandler::new(0, committee.clone(), observer_id, metrics.clone());
+    let (wal_writer, wal_reader) = walf(tempfile::tempfile().unwrap()).expect("Failed to open wal");
+    let recovered = BlockStore::open(
+        observer_id,
+        Arc::new(wal_reader),
+        &wal_writer,
+        metrics.clone(),
+        &committee,
+        public_config.parameters.block_cache_size,
+    );
+    let core = Core::open_observer(
+        block_handler,
+        observer_id,
+        committee.clone(),
+        &public_config,
+        metrics.clone(),
+        recovered,
+        wal_writer,
+        CoreOptions::test(),
+    );
+    let follow: Vec<_> = committee.authorities().collect();
+    let addresses: Vec<_> = addresses.into_iter().map(PeerAddress::from).collect();
+    let network = Network::observer_from_addresses(
+        &addresses,
+        &follow,
+        observer_id as usize,
+        observer_address,
+        metrics,
+        Capabilities::from_parameters(&public_config.parameters),
+        TransportProtocol::Tcp,
+        Resolver::default(),
+    )
+    .await;
+    let commit_handler = TestCommitHandler::new(
+        committee.clone(),
+        core.block_handler().transaction_time.clone(),
+        test_metrics(),
+        observer_id,
+    );
+    let observer = NetworkSyncer::start(
+        network,
+        core,
+        3,
+        commit_handler,
+        config::node_defaults::default_shutdown_grace_period(),
+        test_metrics(),
+        &public_config,
+    );
+    (network_syncers, observer)
+}
+
 pub fn rng_at_seed(seed: u64) -> StdRng {
     let bytes = seed.to_le_bytes();
     let mut seed = [0u8; 32];
@@ -355,6 +727,7 @@ impl TestBlockWriter {
             &wal_writer,
             test_metrics(),
             committee,
+            config::node_defaults::default_block_cache_size(),
         );
         let block_store = state.block_store;
         Self {
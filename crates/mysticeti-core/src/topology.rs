@@ -0,0 +1,491 @@
+// Copyright (c) Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! The overlay connecting validators. By default every validator connects to every other one.
+//! In large committees validators can instead connect to a few neighbours in a regular graph and
+//! relay the blocks they receive to their other neighbours, so that blocks reach every validator
+//! in a few hops. Missing blocks are fetched from the neighbours, which serve the blocks of any
+//! author.
+//!
+//! A restarting validator learns the highest round of its own blocks known to the others (see
+//! `OwnRoundAggregator`) from a quorum of validators, most of which are not its neighbours. The
+//! request is signed by the restarting validator and relayed like blocks, the signed answers travel
+//! back along the path the request came in.
+
+use std::{
+    collections::{BTreeSet, HashMap, HashSet},
+    fmt,
+    io,
+};
+
+use eyre::{bail, ensure};
+use parking_lot::Mutex;
+use rand::{rngs::StdRng, seq::SliceRandom, SeedableRng};
+use serde::{Deserialize, Serialize};
+use tokio::sync::mpsc;
+
+use crate::{
+    block_signer::BlockSigner,
+    committee::Committee,
+    crypto::{own_round_signing_digest, SignatureBytes},
+    data::Data,
+    network::NetworkMessage,
+    signing_guard::own_block_round,
+    types::{AuthorityIndex, BlockReference, RoundNumber, StatementBlock},
+};
+
+/// Blocks this many rounds below the latest relayed block are forgotten.
+const RETAINED_ROUNDS: RoundNumber = 50;
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default, Eq, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Topology {
+    /// Every validator connects to every other validator.
+    #[default]
+    Mesh,
+    /// Every validator connects to `degree` neighbours, the validators placed next to it on a
+    /// ring (rounded down to an even degree in committees of odd size). The ring follows the
+    /// authority indices, or a permutation of them drawn from `seed`.
+    Regular {
+        degree: usize,
+        #[serde(default)]
+        seed: Option<u64>,
+    },
+}
+
+impl Topology {
+    /// The validators `authority` connects to, in a committee of `committee_size` validators.
+    pub fn neighbours(
+        &self,
+        committee_size: usize,
+        authority: AuthorityIndex,
+    ) -> Vec<AuthorityIndex> {
+        let others = (0..committee_size as AuthorityIndex).filter(|peer| *peer != authority);
+        let (degree, seed) = match self {
+            Self::Regular { degree, seed } if *degree + 1 < committee_size => (*degree, *seed),
+            _ => return others.collect(),
+        };
+        let mut ring: Vec<AuthorityIndex> = (0..committee_size as AuthorityIndex).collect();
+        if let Some(seed) = seed {
+            ring.shuffle(&mut StdRng::seed_from_u64(seed));
+        }
+        let position = ring
+            .iter()
+            .position(|peer| *peer == authority)
+            .expect("Authority is part of the committee");
+        let at = |offset: usize| ring[(position + offset) % committee_size];
+        let mut neighbours = BTreeSet::new();
+        for offset in 1..=degree / 2 {
+            neighbours.insert(at(offset));
+            neighbours.insert(at(committee_size - offset));
+        }
+        // The opposite validator on the ring completes an odd degree
+        if !degree.is_multiple_of(2) && committee_size.is_multiple_of(2) {
+            neighbours.insert(at(committee_size / 2));
+        }
+        neighbours.into_iter().collect()
+    }
+
+    /// Whether blocks are relayed, rather than sent directly to every validator by their author.
+    pub fn relays(&self, committee_size: usize) -> bool {
+        matches!(self, Self::Regular { degree, .. } if *degree + 1 < committee_size)
+    }
+}
+
+#[derive(Clone, Copy, Serialize, Deserialize, Debug, Eq, PartialEq)]
+pub enum OwnRoundStatement {
+    /// `requester` asks for the highest round of its blocks known to the other validators. The
+    /// nonce grows across restarts, so that an old request relayed again is ignored.
+    Request {
+        requester: AuthorityIndex,
+        nonce: u64,
+    },
+    /// The latest block of `requester` known to `responder`, answering the request with the same
+    /// nonce. The block itself comes along with the signed report, see `SignedOwnRound`.
+    Report {
+        requester: AuthorityIndex,
+        nonce: u64,
+        responder: AuthorityIndex,
+        latest: Option<BlockReference>,
+    },
+}
+
+impl OwnRoundStatement {
+    pub fn requester(&self) -> AuthorityIndex {
+        match self {
+            Self::Request { requester, .. } | Self::Report { requester, .. } => *requester,
+        }
+    }
+
+    pub fn nonce(&self) -> u64 {
+        match self {
+            Self::Request { nonce, .. } | Self::Report { nonce, .. } => *nonce,
+        }
+    }
+
+    /// The validator signing the statement.
+    pub fn author(&self) -> AuthorityIndex {
+        match self {
+            Self::Request { requester, .. } => *requester,
+            Self::Report { responder, .. } => *responder,
+        }
+    }
+}
+
+/// An `OwnRoundStatement` signed by its author.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct SignedOwnRound {
+    pub statement: OwnRoundStatement,
+    signature: SignatureBytes,
+    // The block referenced by a report, signed by the requester rather than the responder
+    latest_block: Option<Data<StatementBlock>>,
+}
+
+impl SignedOwnRound {
+    pub fn new(statement: OwnRoundStatement, signer: &mut dyn BlockSigner) -> io::Result<Self> {
+        let signature = signer.sign_own_round(&statement)?;
+        Ok(Self {
+            statement,
+            signature,
+            latest_block: None,
+        })
+    }
+
+    /// Attach the block referenced by a report.
+    pub fn with_latest_block(mut self, latest_block: Option<Data<StatementBlock>>) -> Self {
+        self.latest_block = latest_block;
+        self
+    }
+
+    /// The round of the latest block of the requester known to the responder of a report (None
+    /// for a request), once checked that the attached block is the one reported, authored and
+    /// signed by the requester. A responder may thus only report a round the requester signed.
+    pub fn reported_round(&self, committee: &Committee) -> eyre::Result<Option<RoundNumber>> {
+        let OwnRoundStatement::Report {
+            requester, latest, ..
+        } = self.statement
+        else {
+            return Ok(None);
+        };
+        match (latest, &self.latest_block) {
+            // The responder knows none of the blocks of the requester
+            (None, None) => Ok(Some(0)),
+            (Some(latest), Some(block)) => {
+                ensure!(
+                    *block.reference() == latest,
+                    "Attached block {} is not the reported block {latest}",
+                    block.reference()
+                );
+                own_block_round(requester, block, committee).map(Some)
+            }
+            _ => bail!("Attached block does not match the reported block {latest:?}"),
+        }
+    }
+
+    /// Check that the statement is signed by its author.
+    pub fn verify(&self, committee: &Committee) -> Result<(), ed25519_consensus::Error> {
+        let public_key = committee
+            .get_public_key(self.statement.author())
+            .ok_or(ed25519_consensus::Error::InvalidSignature)?;
+        let digest = own_round_signing_digest(&self.statement);
+        public_key.verify_digest(&digest, &self.signature)
+    }
+}
+
+impl fmt::Debug for SignedOwnRound {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?}", self.statement)
+    }
+}
+
+/// Relays the blocks received from a neighbour to the other neighbours, once per block, and
+/// likewise the own round statements.
+#[derive(Default)]
+pub struct BlockRelay {
+    inner: Mutex<BlockRelayInner>,
+}
+
+#[derive(Default)]
+struct BlockRelayInner {
+    peers: HashMap<AuthorityIndex, mpsc::Sender<NetworkMessage>>,
+    relayed: BTreeSet<BlockReference>,
+    // The latest request nonce of every requester, the neighbour it came from and the authors of
+    // the statements relayed for it
+    own_rounds: HashMap<AuthorityIndex, (u64, Option<AuthorityIndex>, HashSet<AuthorityIndex>)>,
+    // Our own round request while less than a quorum answered it
+    own_request: Option<SignedOwnRound>,
+}
+
+impl BlockRelay {
+    /// Register a neighbour, and return our pending own round request for it to relay.
+    pub fn register_peer(
+        &self,
+        peer: AuthorityIndex,
+        sender: mpsc::Sender<NetworkMessage>,
+    ) -> Option<SignedOwnRound> {
+        let mut inner = self.inner.lock();
+        inner.peers.insert(peer, sender);
+        inner.own_request.clone()
+    }
+
+    /// Set our pending own round request, None once a quorum answered it.
+    pub fn set_own_request(&self, request: Option<SignedOwnRound>) {
+        self.inner.lock().own_request = request;
+    }
+
+    pub fn remove_peer(&self, peer: AuthorityIndex) {
+        self.inner.lock().peers.remove(&peer);
+    }
+
+    /// The neighbours to relay a block received from `from` to, none if it was relayed already.
+    pub fn relay_to(
+        &self,
+        reference: &BlockReference,
+        from: AuthorityIndex,
+    ) -> Vec<mpsc::Sender<NetworkMessage>> {
+        let mut inner = self.inner.lock();
+        if !inner.relayed.insert(*reference) {
+            return vec![];
+        }
+        let threshold = BlockReference {
+            round: reference.round.saturating_sub(RETAINED_ROUNDS),
+            ..Default::default()
+        };
+        inner.relayed = inner.relayed.split_off(&threshold);
+        inner
+            .peers
+            .iter()
+            .filter(|(peer, _)| **peer != from && **peer != reference.authority)
+            .map(|(_, sender)| sender.clone())
+            .collect()
+    }
+
+    /// The neighbours to relay an own round statement received from `from` to, or None if the
+    /// statement was relayed already or belongs to an older request. A request goes to all the
+    /// other neighbours, a report only back to the neighbour the request came from.
+    pub fn relay_own_round(
+        &self,
+        statement: &OwnRoundStatement,
+        from: AuthorityIndex,
+    ) -> Option<Vec<mpsc::Sender<NetworkMessage>>> {
+        let mut inner = self.inner.lock();
+        let inner = &mut *inner;
+        let (nonce, came_from, authors) = inner
+            .own_rounds
+            .entry(statement.requester())
+            .or_insert_with(|| (statement.nonce(), None, HashSet::new()));
+        if statement.nonce() < *nonce {
+            return None;
+        }
+        if statement.nonce() > *nonce {
+            *nonce = statement.nonce();
+            *came_from = None;
+            authors.clear();
+        }
+        if !authors.insert(statement.author()) {
+            return None;
+        }
+        let neighbours = match statement {
+            OwnRoundStatement::Request { .. } => {
+                *came_from = Some(from);
+                inner
+                    .peers
+                    .iter()
+                    .filter(|(peer, _)| **peer != from)
+                    .map(|(_, sender)| sender.clone())
+                    .collect()
+            }
+            // Reports to our own requests stop here, we are not one of our peers
+            OwnRoundStatement::Report { .. } => came_from
+                .filter(|peer| *peer != from)
+                .and_then(|peer| inner.peers.get(&peer))
+                .cloned()
+                .into_iter()
+                .collect(),
+        };
+        Some(neighbours)
+    }
+
+    /// All the neighbours, to send our own statements to.
+    pub fn peers(&self) -> Vec<mpsc::Sender<NetworkMessage>> {
+        self.inner.lock().peers.values().cloned().collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{committee::Authority, crypto::Signer};
+
+    /// Every validator reaches every other one through the neighbours.
+    fn connected(topology: &Topology, committee_size: usize) -> bool {
+        let mut reached = BTreeSet::from([0]);
+        let mut frontier = vec![0];
+        while let Some(authority) = frontier.pop() {
+            for neighbour in topology.neighbours(committee_size, authority) {
+                if reached.insert(neighbour) {
+                    frontier.push(neighbour);
+                }
+            }
+        }
+        reached.len() == committee_size
+    }
+
+    #[test]
+    fn regular_topology() {
+        for (committee_size, degree, expected) in [(10, 4, 4), (10, 3, 3), (11, 3, 2), (5, 8, 4)] {
+            for seed in [None, Some(7)] {
+                let topology = Topology::Regular { degree, seed };
+                for authority in 0..committee_size as AuthorityIndex {
+                    let neighbours = topology.neighbours(committee_size, authority);
+                    assert_eq!(neighbours.len(), expected);
+                    assert!(!neighbours.contains(&authority));
+                    // Neighbours are mutual, so that only one of them needs to dial the other
+                    for neighbour in neighbours {
+                        assert!(topology
+                            .neighbours(committee_size, neighbour)
+                            .contains(&authority));
+                    }
+                }
+                assert!(connected(&topology, committee_size));
+            }
+        }
+        assert_eq!(Topology::Mesh.neighbours(4, 1), vec![0, 2, 3]);
+        assert!(!Topology::Regular {
+            degree: 3,
+            seed: None
+        }
+        .relays(4));
+        assert!(Topology::Regular {
+            degree: 3,
+            seed: None
+        }
+        .relays(5));
+    }
+
+    #[test]
+    fn relay_once() {
+        let relay = BlockRelay::default();
+        let (sender, _receiver) = mpsc::channel(1);
+        for peer in 0..4 {
+            assert!(relay.register_peer(peer, sender.clone()).is_none());
+        }
+        let reference = BlockReference {
+            authority: 1,
+            round: 3,
+            ..Default::default()
+        };
+        // Neither back to the neighbour we got the block from, nor to its author
+        assert_eq!(relay.relay_to(&reference, 2).len(), 2);
+        assert!(relay.relay_to(&reference, 3).is_empty());
+        relay.remove_peer(0);
+        let reference = BlockReference {
+            round: 4,
+            ..reference
+        };
+        assert_eq!(relay.relay_to(&reference, 2).len(), 1);
+    }
+
+    #[test]
+    fn relay_own_round_once() {
+        let relay = BlockRelay::default();
+        let (sender, _receiver) = mpsc::channel(1);
+        for peer in 0..4 {
+            relay.register_peer(peer, sender.clone());
+        }
+        let request = |nonce| OwnRoundStatement::Request {
+            requester: 1,
+            nonce,
+        };
+        let report = |nonce, responder| OwnRoundStatement::Report {
+            requester: 1,
+            nonce,
+            responder,
+            latest: None,
+        };
+        assert_eq!(relay.relay_own_round(&request(10), 1).unwrap().len(), 3);
+        assert!(relay.relay_own_round(&request(10), 2).is_none());
+        // Reports go back to the neighbour the request came from
+        assert_eq!(relay.relay_own_round(&report(10, 2), 2).unwrap().len(), 1);
+        assert!(relay.relay_own_round(&report(10, 2), 3).is_none());
+        assert!(relay.relay_own_round(&report(10, 0), 1).unwrap().is_empty());
+        // A new request replaces the older one
+        assert!(relay.relay_own_round(&request(11), 1).is_some());
+        assert!(relay.relay_own_round(&report(11, 2), 2).is_some());
+        assert!(relay.relay_own_round(&request(10), 1).is_none());
+        assert!(relay.relay_own_round(&report(10, 3), 3).is_none());
+    }
+
+    #[test]
+    fn pending_own_request() {
+        let relay = BlockRelay::default();
+        let (sender, _receiver) = mpsc::channel(1);
+        let mut signers = Signer::new_for_test(1);
+        let request = OwnRoundStatement::Request {
+            requester: 0,
+            nonce: 1,
+        };
+        relay.set_own_request(Some(SignedOwnRound::new(request, &mut signers[0]).unwrap()));
+        // Neighbours connecting after we sent the request relay it too
+        let pending = relay.register_peer(1, sender.clone()).unwrap();
+        assert_eq!(pending.statement, request);
+        relay.set_own_request(None);
+        assert!(relay.register_peer(2, sender).is_none());
+    }
+
+    #[test]
+    fn signed_own_round() {
+        let mut signers = Signer::new_for_test(2);
+        let committee = Committee::new(
+            signers
+                .iter()
+                .map(|signer| Authority::new(1, signer.public_key()))
+                .collect(),
+        );
+        let block = |author, round: RoundNumber, signer: &mut Signer| {
+            let includes = (0..2)
+                .map(|authority| BlockReference {
+                    authority,
+                    round: round - 1,
+                    digest: Default::default(),
+                })
+                .collect();
+            Data::new(
+                StatementBlock::new_with_signer(author, round, includes, vec![], 0, false, signer)
+                    .unwrap(),
+            )
+        };
+        let own_block = block(0, 12, &mut signers[0]);
+        let report = |latest| OwnRoundStatement::Report {
+            requester: 0,
+            nonce: 7,
+            responder: 1,
+            latest,
+        };
+        let signed = SignedOwnRound::new(report(Some(*own_block.reference())), &mut signers[1])
+            .unwrap()
+            .with_latest_block(Some(own_block.clone()));
+        signed.verify(&committee).unwrap();
+        assert_eq!(signed.reported_round(&committee).unwrap(), Some(12));
+        // Only the responder can report what it knows
+        let forged = SignedOwnRound::new(report(None), &mut signers[0]).unwrap();
+        assert!(forged.verify(&committee).is_err());
+        let none = SignedOwnRound::new(report(None), &mut signers[1]).unwrap();
+        assert_eq!(none.reported_round(&committee).unwrap(), Some(0));
+
+        // The reported round must come from the attached block, authored by the requester
+        let higher = block(0, 1000, &mut signers[0]);
+        let inflated = SignedOwnRound::new(report(Some(*higher.reference())), &mut signers[1])
+            .unwrap()
+            .with_latest_block(Some(own_block.clone()));
+        assert!(inflated.reported_round(&committee).is_err());
+        let missing = SignedOwnRound::new(report(Some(*higher.reference())), &mut signers[1])
+            .unwrap();
+        assert!(missing.reported_round(&committee).is_err());
+        let foreign = block(1, 1000, &mut signers[1]);
+        let foreign = SignedOwnRound::new(report(Some(*foreign.reference())), &mut signers[1])
+            .unwrap()
+            .with_latest_block(Some(foreign));
+        assert!(foreign.reported_round(&committee).is_err());
+    }
+}
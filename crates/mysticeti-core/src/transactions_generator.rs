@@ -4,10 +4,10 @@
 use std::{cmp::min, sync::Arc, time::Duration};
 
 use rand::{rngs::StdRng, Rng, SeedableRng};
-use tokio::sync::mpsc;
+use tokio::sync::{mpsc, watch};
 
 use crate::{
-    config::{ClientParameters, NodePublicConfig},
+    config::{ClientParameters, NodeParameters},
     crypto::AsBytes,
     metrics::Metrics,
     runtime::{self, timestamp_utc},
@@ -18,7 +18,8 @@ pub struct TransactionGenerator {
     sender: mpsc::Sender<Vec<Transaction>>,
     rng: StdRng,
     client_parameters: ClientParameters,
-    node_public_config: NodePublicConfig,
+    /// The maximum block size may be reloaded at runtime, see `reload`.
+    node_parameters: watch::Receiver<NodeParameters>,
     metrics: Arc<Metrics>,
 }
 
@@ -29,7 +30,7 @@ impl TransactionGenerator {
         sender: mpsc::Sender<Vec<Transaction>>,
         seed: AuthorityIndex,
         client_parameters: ClientParameters,
-        node_public_config: NodePublicConfig,
+        node_parameters: watch::Receiver<NodeParameters>,
         metrics: Arc<Metrics>,
     ) {
         assert!(client_parameters.transaction_size > 8 + 8); // 8 bytes timestamp + 8 bytes random
@@ -43,7 +44,7 @@ impl TransactionGenerator {
                 sender,
                 rng: StdRng::seed_from_u64(seed),
                 client_parameters,
-                node_public_config,
+                node_parameters,
                 metrics,
             }
             .run(),
@@ -57,9 +58,6 @@ impl TransactionGenerator {
             "Generating {transactions_per_block_interval} transactions per {} ms",
             Self::TARGET_BLOCK_INTERVAL.as_millis()
         );
-        let max_block_size = self.node_public_config.parameters.max_block_size;
-        let target_block_size = min(max_block_size, transactions_per_block_interval);
-
         let mut counter = 0;
         let mut tx_to_report = 0;
         let mut random: u64 = self.rng.gen(); // 8 bytes
@@ -69,6 +67,8 @@ impl TransactionGenerator {
         runtime::sleep(self.client_parameters.initial_delay).await;
         loop {
             interval.tick().await;
+            let max_block_size = self.node_parameters.borrow().max_block_size;
+            let target_block_size = min(max_block_size, transactions_per_block_interval);
             let timestamp = (timestamp_utc().as_millis() as u64).to_le_bytes();
 
             let mut block = Vec::with_capacity(target_block_size);
@@ -10,6 +10,7 @@ pub struct Transaction {
 
 pub type RoundNumber = u64;
 pub type BlockDigest = crate::crypto::BlockDigest;
+pub type BatchDigest = crate::crypto::BatchDigest;
 pub type Stake = u64;
 pub type KeyPair = u64;
 pub type PublicKey = crate::crypto::PublicKey;
@@ -17,6 +18,7 @@ pub type PublicKey = crate::crypto::PublicKey;
 use std::{
     fmt,
     hash::{Hash, Hasher},
+    io,
     ops::Range,
     time::Duration,
 };
@@ -28,8 +30,9 @@ use serde::{Deserialize, Serialize};
 pub use test::Dag;
 
 use crate::{
+    block_signer::{BlockSigner, UnsignedBlock},
     committee::{Committee, VoteRangeBuilder},
-    crypto::{AsBytes, CryptoHash, SignatureBytes, Signer},
+    crypto::{AsBytes, CryptoHash, SignatureBytes},
     data::Data,
     threshold_clock::threshold_clock_valid_non_genesis,
 };
@@ -67,6 +70,8 @@ pub enum BaseStatement {
     Vote(TransactionLocator, Vote),
     // For now only accept votes are batched
     VoteRange(TransactionLocatorRange),
+    /// Authority shares the transactions of a batch disseminated beforehand, see `batch`.
+    Batch(BatchDigest),
 }
 
 impl Hash for BlockReference {
@@ -137,25 +142,26 @@ impl StatementBlock {
         statements: Vec<BaseStatement>,
         meta_creation_time_ns: TimestampNs,
         epoch_marker: EpochStatus,
-        signer: &Signer,
-    ) -> Self {
-        let signature = signer.sign_block(
-            authority,
-            round,
-            &includes,
-            &statements,
-            meta_creation_time_ns,
-            epoch_marker,
-        );
-        Self::new(
-            authority,
+        signer: &mut dyn BlockSigner,
+    ) -> io::Result<Self> {
+        let block = UnsignedBlock {
+            author: authority,
             round,
             includes,
             statements,
             meta_creation_time_ns,
             epoch_marker,
+        };
+        let signature = signer.sign(&block)?;
+        Ok(Self::new(
+            block.author,
+            block.round,
+            block.includes,
+            block.statements,
+            block.meta_creation_time_ns,
+            block.epoch_marker,
             signature,
-        )
+        ))
     }
 
     pub fn new(
@@ -236,6 +242,16 @@ impl StatementBlock {
             })
     }
 
+    /// The digests of the batches shared by this block.
+    pub fn batches(&self) -> impl Iterator<Item = &BatchDigest> {
+        self.statements
+            .iter()
+            .filter_map(|statement| match statement {
+                BaseStatement::Batch(digest) => Some(digest),
+                _ => None,
+            })
+    }
+
     pub fn author(&self) -> AuthorityIndex {
         self.reference.authority
     }
@@ -272,6 +288,20 @@ impl StatementBlock {
     }
 
     pub fn verify(&self, committee: &Committee) -> eyre::Result<()> {
+        let pub_key = self.verify_without_signature(committee)?;
+        if let Err(e) = pub_key.verify_block(self) {
+            bail!("Block signature verification has failed: {:?}", e);
+        }
+        Ok(())
+    }
+
+    /// Run all the checks of `verify` except for the (expensive) signature check, which is left
+    /// to the caller, for example to check many signatures in a batch. Returns the public key of
+    /// the author the signature should be checked against.
+    pub fn verify_without_signature<'a>(
+        &self,
+        committee: &'a Committee,
+    ) -> eyre::Result<&'a PublicKey> {
         let round = self.round();
         let digest = BlockDigest::new(
             self.author(),
@@ -295,9 +325,6 @@ impl StatementBlock {
         if round == GENESIS_ROUND {
             bail!("Genesis block should not go through verification");
         }
-        if let Err(e) = pub_key.verify_block(self) {
-            bail!("Block signature verification has failed: {:?}", e);
-        }
         for include in &self.includes {
             // Also check duplicate includes?
             ensure!(
@@ -318,13 +345,14 @@ impl StatementBlock {
                 BaseStatement::Share(_) => {}
                 BaseStatement::Vote(_, _) => {}
                 BaseStatement::VoteRange(range) => range.verify()?,
+                BaseStatement::Batch(_) => {}
             }
         }
         ensure!(
             threshold_clock_valid_non_genesis(self, committee),
             "Threshold clock is not valid"
         );
-        Ok(())
+        Ok(pub_key)
     }
 
     pub fn detailed(&self) -> Detailed {
@@ -609,6 +637,7 @@ impl fmt::Display for BaseStatement {
                 "+{}:{}:{}",
                 range.block, range.offset_start_inclusive, range.offset_end_exclusive
             ),
+            BaseStatement::Batch(digest) => write!(f, "{digest}"),
         }
     }
 }
@@ -1,26 +1,29 @@
 // Copyright (c) Mysten Labs, Inc.
 // SPDX-License-Identifier: Apache-2.0
 
-use std::{
-    net::{IpAddr, Ipv4Addr},
-    sync::Arc,
-};
+use std::{io, sync::Arc};
 
 use ::prometheus::Registry;
 use eyre::{eyre, Context, Result};
 
 use crate::{
+    address_book::AddressBook,
+    admin,
     block_handler::{RealBlockHandler, TestCommitHandler},
     block_store::BlockStore,
     committee::Committee,
     config::{ClientParameters, NodePrivateConfig, NodePublicConfig},
     core::{Core, CoreOptions},
+    index_checkpoint::IndexCheckpoint,
     log::TransactionLog,
-    metrics::Metrics,
+    metrics::{print_network_traffic_table, Metrics},
     net_sync::NetworkSyncer,
     network::Network,
     prometheus,
+    reload::{self, LiveParameters},
+    resolver::PeerAddress,
     runtime::{JoinError, JoinHandle},
+    signing_guard::SigningGuard,
     transactions_generator::TransactionGenerator,
     types::AuthorityIndex,
     wal::{self, walf},
@@ -29,6 +32,7 @@ use crate::{
 pub struct Validator {
     network_synchronizer: NetworkSyncer<RealBlockHandler, TestCommitHandler<TransactionLog>>,
     metrics_handle: JoinHandle<Result<(), hyper::Error>>,
+    admin_handle: JoinHandle<Result<(), hyper::Error>>,
 }
 
 impl Validator {
@@ -43,34 +47,40 @@ impl Validator {
             .network_address(authority)
             .ok_or(eyre!("No network address for authority {authority}"))
             .wrap_err("Unknown authority")?;
-        let mut binding_network_address = network_address;
-        binding_network_address.set_ip(IpAddr::V4(Ipv4Addr::UNSPECIFIED));
+        let binding_network_address = public_config
+            .listen_address(authority)
+            .expect("Authorities with a network address have a listen address");
 
         let metrics_address = public_config
             .metrics_address(authority)
             .ok_or(eyre!("No metrics address for authority {authority}"))
             .wrap_err("Unknown authority")?;
-        let mut binding_metrics_address = metrics_address;
-        binding_metrics_address.set_ip(IpAddr::V4(Ipv4Addr::UNSPECIFIED));
+        let binding_metrics_address = metrics_address.default_listen_address();
+        let admin_address = private_config.admin_address(&metrics_address);
 
         // Boot the prometheus server.
         let registry = Registry::new();
         let (metrics, reporter) = Metrics::new(&registry, Some(&committee));
         reporter.start();
 
-        let metrics_handle =
-            prometheus::start_prometheus_server(binding_metrics_address, &registry);
-
         // Open the block store.
         let wal_file =
             wal::open_file_for_wal(private_config.wal()).expect("Failed to open wal file");
-        let (wal_writer, wal_reader) = walf(wal_file).expect("Failed to open wal");
-        let recovered = BlockStore::open(
+        let (mut wal_writer, wal_reader) = walf(wal_file).expect("Failed to open wal");
+        wal_writer.set_compression(public_config.parameters.wal_compression);
+        let index_checkpoint_path = private_config.index_checkpoint();
+        let index_checkpoint = IndexCheckpoint::load(&index_checkpoint_path).unwrap_or_else(|e| {
+            tracing::warn!("Ignoring index checkpoint: {e}");
+            None
+        });
+        let recovered = BlockStore::open_with_checkpoint(
             authority,
             Arc::new(wal_reader),
             &wal_writer,
             metrics.clone(),
             &committee,
+            public_config.parameters.block_cache_size,
+            index_checkpoint,
         );
 
         // Boot the validator node.
@@ -81,15 +91,9 @@ impl Validator {
             recovered.block_store.clone(),
             metrics.clone(),
             public_config.parameters.consensus_only,
+            public_config.parameters.dissemination,
         );
 
-        TransactionGenerator::start(
-            block_sender,
-            authority,
-            client_parameters,
-            public_config.clone(),
-            metrics.clone(),
-        );
         let committed_transaction_log =
             TransactionLog::start(private_config.committed_transactions_log())
                 .expect("Failed to open committed transaction log for write");
@@ -100,16 +104,25 @@ impl Validator {
             committed_transaction_log,
             authority
         );
+        let signing_guard = SigningGuard::open(private_config.signing_guard(), &committee)
+            .wrap_err("Failed to open signing guard")?;
+        let address_sequence_path = private_config.address_sequence();
+        let block_signer = private_config
+            .into_block_signer()
+            .wrap_err("Failed to load block signer")?;
         let core = Core::open(
             block_handler,
             authority,
             committee.clone(),
-            private_config,
+            block_signer,
+            signing_guard,
             &public_config,
             metrics.clone(),
             recovered,
             wal_writer,
-            CoreOptions::default(),
+            CoreOptions::default()
+                .with_index_checkpoint(index_checkpoint_path)
+                .with_address_sequence(address_sequence_path),
         );
         let network = Network::load(
             &public_config,
@@ -124,53 +137,103 @@ impl Validator {
             public_config.parameters.wave_length,
             commit_handler,
             public_config.parameters.shutdown_grace_period,
-            metrics,
+            metrics.clone(),
             &public_config,
         );
+        let parameters = network_synchronizer.parameters().clone();
 
-        tracing::info!("Validator {authority} listening on {network_address}");
+        TransactionGenerator::start(
+            block_sender,
+            authority,
+            client_parameters,
+            parameters.subscribe(),
+            metrics,
+        );
+        let metrics_handle =
+            prometheus::start_prometheus_server(binding_metrics_address, &registry);
+        let admin_handle = admin::start_admin_server(
+            admin_address,
+            reload::routes(parameters).merge(network_synchronizer.admin_routes()),
+        );
+
+        tracing::info!(
+            "Validator {authority} listening on {binding_network_address}, reachable at {network_address}"
+        );
         tracing::info!("Validator {authority} exposing metrics on {metrics_address}");
+        tracing::info!("Validator {authority} serving admin routes on {admin_address}");
 
         Ok(Self {
             network_synchronizer,
             metrics_handle,
+            admin_handle,
         })
     }
 
+    /// The current addresses of the peers, see `AddressBook::reload`.
+    pub fn address_book(&self) -> AddressBook {
+        self.network_synchronizer.address_book().clone()
+    }
+
+    /// Announce to the peers that the validator moves to `address`, see
+    /// `NetworkSyncer::announce_address`.
+    pub async fn announce_address(&self, address: PeerAddress) -> io::Result<()> {
+        self.network_synchronizer.announce_address(address).await
+    }
+
+    /// The parameters currently applied, see `LiveParameters::reload`.
+    pub fn parameters(&self) -> LiveParameters {
+        self.network_synchronizer.parameters().clone()
+    }
+
+    /// Wait until an operator asks the validator to stop through `admin::SHUTDOWN_ROUTE`, after
+    /// which the validator should be stopped.
+    pub async fn await_shutdown_request(&self) {
+        self.network_synchronizer.await_shutdown_request().await
+    }
+
     pub async fn await_completion(
         self,
     ) -> (
         Result<(), JoinError>,
         Result<Result<(), hyper::Error>, JoinError>,
+        Result<Result<(), hyper::Error>, JoinError>,
     ) {
         tokio::join!(
             self.network_synchronizer.await_completion(),
-            self.metrics_handle
+            self.metrics_handle,
+            self.admin_handle
         )
     }
 
     pub async fn stop(self) {
-        self.network_synchronizer.shutdown().await;
+        let syncer = self.network_synchronizer.shutdown().await;
+        print_network_traffic_table(&syncer.core().metrics);
     }
 }
 
 #[cfg(test)]
 mod smoke_tests {
-    use std::{collections::VecDeque, fs, net::SocketAddr, time::Duration};
+    use std::{collections::VecDeque, fs, time::Duration};
 
     use tempdir::TempDir;
     use tokio::time;
 
     use super::Validator;
     use crate::{
+        admin::{self, EpochStatus, MissingBlocks, NodeStatus, PeerStatus, WalStatus},
+        batch::Dissemination,
         committee::Committee,
         config::{self, ClientParameters, NodePrivateConfig, NodePublicConfig},
+        dag_export::{self, DagExport, ExportedLeaderStatus},
+        network::TransportProtocol,
         prometheus,
+        reload,
+        resolver::PeerAddress,
         types::AuthorityIndex,
     };
 
     /// Check whether the validator specified by its metrics address has committed at least once.
-    async fn check_commit(address: &SocketAddr) -> Result<bool, reqwest::Error> {
+    async fn check_commit(address: &PeerAddress) -> Result<bool, reqwest::Error> {
         let route = prometheus::METRICS_ROUTE;
         let res = reqwest::get(format! {"http://{address}{route}"}).await?;
         let string = res.text().await?;
@@ -178,8 +241,19 @@ mod smoke_tests {
         Ok(commit)
     }
 
+    /// Check whether the validator specified by its metrics address has committed transactions
+    /// disseminated in batches by its peers.
+    async fn check_batches(address: &PeerAddress) -> Result<bool, reqwest::Error> {
+        let route = prometheus::METRICS_ROUTE;
+        let res = reqwest::get(format! {"http://{address}{route}"}).await?;
+        let string = res.text().await?;
+        let batches = string.contains("batches_total{origin=\"disseminated\"}");
+        let committed = string.contains("latency_s_count{workload=\"shared\"}");
+        Ok(batches && committed)
+    }
+
     /// Await for all the validators specified by their metrics addresses to commit.
-    async fn await_for_commits(addresses: Vec<SocketAddr>) {
+    async fn await_for_commits(addresses: Vec<PeerAddress>) {
         let mut queue = VecDeque::from(addresses);
         while let Some(address) = queue.pop_front() {
             time::sleep(Duration::from_millis(100)).await;
@@ -232,6 +306,49 @@ mod smoke_tests {
         }
     }
 
+    /// Ensure that a committee of honest validators connected over QUIC commits.
+    #[tokio::test]
+    async fn validator_commit_quic() {
+        let committee_size = 4;
+        let committee = Committee::new_for_benchmarks(committee_size);
+        let mut public_config =
+            NodePublicConfig::new_for_tests(committee_size).with_port_offset(400);
+        public_config.parameters.network_transport = TransportProtocol::Quic;
+        let client_parameters = ClientParameters::default();
+
+        let mut handles = Vec::new();
+        let dir = TempDir::new("validator_commit_quic").unwrap();
+        let private_configs = NodePrivateConfig::new_for_benchmarks(dir.as_ref(), committee_size);
+        private_configs.iter().for_each(|private_config| {
+            fs::create_dir_all(&private_config.storage_path).unwrap();
+        });
+
+        for (i, private_config) in private_configs.into_iter().enumerate() {
+            let authority = i as AuthorityIndex;
+            let validator = Validator::start(
+                authority,
+                committee.clone(),
+                public_config.clone(),
+                private_config,
+                client_parameters.clone(),
+            )
+            .await
+            .unwrap();
+            handles.push(validator.await_completion());
+        }
+
+        let addresses = public_config
+            .all_metric_addresses()
+            .map(|address| address.to_owned())
+            .collect();
+        let timeout = config::node_defaults::default_leader_timeout() * 40;
+
+        tokio::select! {
+            _ = await_for_commits(addresses) => (),
+            _ = time::sleep(timeout) => panic!("Failed to gather commits within a few timeouts"),
+        }
+    }
+
     /// Ensure validators can sync missing blocks
     #[tokio::test]
     async fn validator_sync() {
@@ -305,6 +422,59 @@ mod smoke_tests {
         }
     }
 
+    /// Ensure that validators commit the transactions they disseminate in batches.
+    #[tokio::test]
+    async fn validator_batches() {
+        let committee_size = 4;
+        let committee = Committee::new_for_benchmarks(committee_size);
+        let mut public_config =
+            NodePublicConfig::new_for_tests(committee_size).with_port_offset(300);
+        public_config.parameters.dissemination = Dissemination::Batches;
+        let client_parameters = ClientParameters {
+            initial_delay: Duration::from_millis(100),
+            ..Default::default()
+        };
+
+        let mut handles = Vec::new();
+        let dir = TempDir::new("validator_batches").unwrap();
+        let private_configs = NodePrivateConfig::new_for_benchmarks(dir.as_ref(), committee_size);
+        private_configs.iter().for_each(|private_config| {
+            fs::create_dir_all(&private_config.storage_path).unwrap();
+        });
+
+        for (i, private_config) in private_configs.into_iter().enumerate() {
+            let authority = i as AuthorityIndex;
+            let validator = Validator::start(
+                authority,
+                committee.clone(),
+                public_config.clone(),
+                private_config,
+                client_parameters.clone(),
+            )
+            .await
+            .unwrap();
+            handles.push(validator.await_completion());
+        }
+
+        let addresses: Vec<_> = public_config
+            .all_metric_addresses()
+            .map(|address| address.to_owned())
+            .collect();
+        let timeout = config::node_defaults::default_leader_timeout() * 40;
+        let await_for_batches = async {
+            for address in addresses {
+                while !matches!(check_batches(&address).await, Ok(true)) {
+                    time::sleep(Duration::from_millis(100)).await;
+                }
+            }
+        };
+
+        tokio::select! {
+            _ = await_for_batches => (),
+            _ = time::sleep(timeout) => panic!("Failed to commit batches within a few timeouts"),
+        }
+    }
+
     // Ensure that honest validators commit despite the presence of a crash fault.
     #[tokio::test]
     async fn validator_crash_faults() {
@@ -350,4 +520,162 @@ mod smoke_tests {
             _ = time::sleep(timeout) => panic!("Failed to gather commits within a few timeouts"),
         }
     }
+
+    /// Query the admin routes of a validator of a local committee, then stop it through them.
+    #[tokio::test]
+    async fn validator_admin() {
+        let committee_size = 4;
+        let committee = Committee::new_for_benchmarks(committee_size);
+        let public_config = NodePublicConfig::new_for_tests(committee_size).with_port_offset(500);
+        let client_parameters = ClientParameters::default();
+
+        let mut validators = Vec::new();
+        let dir = TempDir::new("validator_admin").unwrap();
+        let private_configs = NodePrivateConfig::new_for_benchmarks(dir.as_ref(), committee_size);
+        private_configs.iter().for_each(|private_config| {
+            fs::create_dir_all(&private_config.storage_path).unwrap();
+        });
+        let wal_path = private_configs[0].wal();
+        let admin_address =
+            private_configs[0].admin_address(&public_config.metrics_address(0).unwrap());
+
+        for (i, private_config) in private_configs.into_iter().enumerate() {
+            let authority = i as AuthorityIndex;
+            let validator = Validator::start(
+                authority,
+                committee.clone(),
+                public_config.clone(),
+                private_config,
+                client_parameters.clone(),
+            )
+            .await
+            .unwrap();
+            validators.push(validator);
+        }
+
+        let addresses: Vec<_> = public_config
+            .all_metric_addresses()
+            .map(|address| address.to_owned())
+            .collect();
+        let timeout = config::node_defaults::default_leader_timeout() * 15;
+
+        tokio::select! {
+            _ = await_for_commits(addresses.clone()) => (),
+            _ = time::sleep(timeout) => panic!("Failed to gather commits within a few timeouts"),
+        }
+
+        // The admin routes are not served next to the metrics
+        for route in [admin::STATUS_ROUTE, reload::PARAMETERS_ROUTE] {
+            let response = reqwest::get(format!("http://{}{route}", addresses[0]))
+                .await
+                .unwrap();
+            assert_eq!(response.status(), reqwest::StatusCode::NOT_FOUND);
+        }
+
+        let address = &admin_address;
+        let get = |route: &str| reqwest::get(format!("http://{address}{route}"));
+
+        let status: NodeStatus = get(admin::STATUS_ROUTE)
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+        assert_eq!(status.authority, 0);
+        assert!(status.threshold_clock_round > 0);
+        assert!(status.last_proposed_round > 0);
+        assert!(status.last_committed_leader.is_some());
+        assert_eq!(status.epoch, EpochStatus::Open);
+
+        let response = get(reload::PARAMETERS_ROUTE).await.unwrap();
+        assert!(response.status().is_success());
+
+        let peers: Vec<PeerStatus> = get(admin::PEERS_ROUTE).await.unwrap().json().await.unwrap();
+        let authorities: Vec<_> = peers.iter().map(|peer| peer.authority).collect();
+        assert_eq!(authorities, vec![1, 2, 3]);
+        assert!(peers
+            .iter()
+            .all(|peer| peer.connected && peer.address.is_some()));
+        assert!(peers.iter().all(|peer| peer.last_seen_round > 0));
+
+        let missing: Vec<MissingBlocks> = get(admin::MISSING_BLOCKS_ROUTE)
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+        assert_eq!(missing.len(), committee_size);
+
+        let wal: WalStatus = get(admin::WAL_ROUTE).await.unwrap().json().await.unwrap();
+        assert!(wal.size_bytes > 0);
+
+        let export: DagExport = get(&format!("{}?format=json", admin::DAG_ROUTE))
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+        assert!(!export.blocks.is_empty());
+        let draw = get(&format!("{}?format=draw&from=1&to=1", admin::DAG_ROUTE))
+            .await
+            .unwrap()
+            .text()
+            .await
+            .unwrap();
+        assert!(draw.starts_with("A1:["));
+        let response = get(&format!(
+            "{}?from=1&to={}",
+            admin::DAG_ROUTE,
+            dag_export::MAX_EXPORTED_ROUNDS + 1
+        ))
+        .await
+        .unwrap();
+        assert_eq!(response.status(), reqwest::StatusCode::BAD_REQUEST);
+
+        // Announce the address the first validator already listens on
+        let client = reqwest::Client::new();
+        let response = client
+            .post(format!("http://{address}{}", admin::ADDRESS_ROUTE))
+            .query(&[(
+                "address",
+                public_config.identifiers[0].network_address.to_string(),
+            )])
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), reqwest::StatusCode::ACCEPTED);
+
+        // Stop the first validator through its admin routes
+        let mut validators = validators.into_iter();
+        let validator = validators.next().unwrap();
+        let response = client
+            .post(format!("http://{address}{}", admin::SHUTDOWN_ROUTE))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), reqwest::StatusCode::ACCEPTED);
+        time::timeout(Duration::from_secs(1), validator.await_shutdown_request())
+            .await
+            .expect("Shutdown was not requested");
+        validator.stop().await;
+
+        let response = get(admin::STATUS_ROUTE).await.unwrap();
+        assert_eq!(response.status(), reqwest::StatusCode::SERVICE_UNAVAILABLE);
+
+        // The dag of the stopped validator can be exported from its wal
+        let export = dag_export::export_wal(
+            &wal_path,
+            0,
+            committee.clone(),
+            &public_config.parameters,
+            None,
+            None,
+            true,
+        )
+        .unwrap();
+        assert!(export
+            .leaders
+            .iter()
+            .any(|leader| leader.status == ExportedLeaderStatus::Commit));
+    }
 }
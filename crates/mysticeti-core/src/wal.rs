@@ -7,7 +7,11 @@ use std::{
     fs::{File, OpenOptions},
     io,
     io::{IoSlice, Seek, SeekFrom, Write},
-    os::fd::{AsRawFd, RawFd},
+    mem::ManuallyDrop,
+    os::{
+        fd::{AsRawFd, FromRawFd, RawFd},
+        unix::fs::FileExt,
+    },
     path::Path,
 };
 
@@ -16,9 +20,12 @@ use minibytes::Bytes;
 use parking_lot::Mutex;
 use serde::{Deserialize, Serialize};
 
+use crate::compression::Compression;
+
 pub struct WalWriter {
     file: File,
     pos: u64,
+    compression: Compression,
 }
 
 pub struct WalReader {
@@ -44,13 +51,46 @@ pub fn walf(mut file: File) -> io::Result<(WalWriter, WalReader)> {
     make_wal(file)
 }
 
-/// Opens file with mode suitable for walf
+/// Opens file with mode suitable for walf. The file is locked (shared) until it is closed, so
+/// that the wal can not be truncated while in use (see `lock_exclusive`).
 pub fn open_file_for_wal(p: impl AsRef<Path>) -> io::Result<File> {
-    OpenOptions::new()
+    let file = OpenOptions::new()
         .read(true)
         .write(true)
         .create(true)
-        .open(p)
+        .open(p)?;
+    flock(&file, libc::LOCK_SH)?;
+    Ok(file)
+}
+
+/// Opens the wal read-only, to inspect the wal of a validator that may be running. The writer
+/// only reports the size of the wal, writing to it fails.
+pub fn open_read_only(path: impl AsRef<Path>) -> io::Result<(WalWriter, WalReader)> {
+    let file = OpenOptions::new().read(true).open(path)?;
+    make_wal(file)
+}
+
+/// Lock the wal file for exclusive use, failing if it is opened by a validator (see
+/// `open_file_for_wal`). The lock is released when the returned file is closed.
+pub fn lock_exclusive(path: impl AsRef<Path>) -> io::Result<File> {
+    let file = OpenOptions::new().write(true).open(path)?;
+    flock(&file, libc::LOCK_EX)?;
+    Ok(file)
+}
+
+fn flock(file: &File, operation: libc::c_int) -> io::Result<()> {
+    if unsafe { libc::flock(file.as_raw_fd(), operation | libc::LOCK_NB) } == 0 {
+        return Ok(());
+    }
+    let error = io::Error::last_os_error();
+    if error.kind() == io::ErrorKind::WouldBlock {
+        Err(io::Error::new(
+            io::ErrorKind::WouldBlock,
+            "The wal is in use by another process",
+        ))
+    } else {
+        Err(error)
+    }
 }
 
 /// Creates wal reader and wal writer on the file.
@@ -85,6 +125,12 @@ pub fn wal(path: impl AsRef<Path>) -> io::Result<(WalWriter, WalReader)> {
     make_wal(file)
 }
 
+/// Cut the wal file (locked with `lock_exclusive`) at `position`, dropping the entry written at
+/// this position and all the entries after it.
+pub fn truncate(file: &File, position: WalPosition) -> io::Result<()> {
+    file.set_len(position.start)
+}
+
 fn make_wal(file: File) -> io::Result<(WalWriter, WalReader)> {
     // todo - replace dup with File::try_clone()
     let fd = unsafe { libc::dup(file.as_raw_fd()) };
@@ -98,6 +144,7 @@ fn make_wal(file: File) -> io::Result<(WalWriter, WalReader)> {
     let writer = WalWriter {
         pos: file.metadata()?.len(),
         file,
+        compression: Compression::None,
     };
     Ok((writer, reader))
 }
@@ -120,6 +167,12 @@ const HEADER_LEN_BYTES: u64 = 8 + 8;
 // CRC and length
 const HEADER_LEN_BYTES_USIZE: usize = HEADER_LEN_BYTES as usize;
 
+// The highest byte of the tag in the header holds the compression of the entry. The crc covers
+// the entry as stored, so a compressed block can be checked without decompressing it, and the
+// block itself (and thus its digest) is the same whether it was compressed or not.
+const COMPRESSION_SHIFT: u32 = 24;
+const MAX_TAG: Tag = (1 << COMPRESSION_SHIFT) - 1;
+
 #[allow(dead_code)]
 const fn assert_constants() {
     if u64::MAX - MAP_MASK != MAP_SIZE - 1 {
@@ -156,7 +209,33 @@ impl WalWriter {
         self.writev(tag, &[IoSlice::new(b)])
     }
 
+    /// Compress the entries written from now on (entries that do not shrink are left alone).
+    /// Compressed and uncompressed entries can be mixed in the same wal.
+    pub fn set_compression(&mut self, compression: Compression) {
+        self.compression = compression;
+    }
+
     pub fn writev(&mut self, tag: Tag, v: &[IoSlice]) -> io::Result<WalPosition> {
+        assert!(tag <= MAX_TAG, "Wal tag {tag} is too large");
+        let v_len = v.iter().map(|s| s.len()).sum::<usize>();
+        assert!(
+            v_len <= MAX_ENTRY_SIZE,
+            "Wal entry too big, {v_len} > {MAX_ENTRY_SIZE}"
+        );
+        if self.compression != Compression::None {
+            let mut entry = Vec::with_capacity(v_len);
+            for slice in v {
+                entry.extend_from_slice(slice);
+            }
+            if let Some(compressed) = self.compression.maybe_compress(&entry) {
+                let tag = tag | ((self.compression.id() as Tag) << COMPRESSION_SHIFT);
+                return self.writev_raw(tag, &[IoSlice::new(&compressed)]);
+            }
+        }
+        self.writev_raw(tag, v)
+    }
+
+    fn writev_raw(&mut self, tag: Tag, v: &[IoSlice]) -> io::Result<WalPosition> {
         let v_len = v.iter().map(|s| s.len()).sum::<usize>();
         let len = v_len as u64 + HEADER_LEN_BYTES;
         assert!(len <= MAP_SIZE, "Wal entry too big, {len} < {MAP_SIZE}");
@@ -200,6 +279,11 @@ impl WalWriter {
         self.file.sync_data()
     }
 
+    /// The number of bytes written to the wal, including the entries written before opening it.
+    pub fn size(&self) -> u64 {
+        self.pos
+    }
+
     /// Allow to retrieve a 'syncer' instance that allows
     /// to fsync wal to disk without acquiring a lock on wal itself.
     ///
@@ -232,6 +316,20 @@ fn split_header(combined: u128) -> (u64, u64, Tag) {
 }
 
 impl WalReader {
+    /// The number of bytes of wal covered by `crc_before`.
+    pub const CRC_BEFORE_LEN: u64 = 4096;
+
+    /// The crc of the last `CRC_BEFORE_LEN` bytes of wal written before `position`, to detect a
+    /// wal rewritten since `position` was recorded (see `IndexCheckpoint`).
+    pub fn crc_before(&self, position: WalPosition) -> io::Result<u32> {
+        let start = position.start.saturating_sub(Self::CRC_BEFORE_LEN);
+        let mut buf = vec![0u8; (position.start - start) as usize];
+        // The file is owned by the reader, which closes it on drop
+        let file = ManuallyDrop::new(unsafe { File::from_raw_fd(self.fd) });
+        file.read_exact_at(&mut buf, start)?;
+        Ok(crc32fast::hash(&buf))
+    }
+
     pub fn read(&self, position: WalPosition) -> io::Result<(Tag, Bytes)> {
         match self.try_read(position)? {
             Some(entry) => Ok(entry),
@@ -240,6 +338,17 @@ impl WalReader {
     }
 
     fn try_read(&self, position: WalPosition) -> io::Result<Option<(Tag, Bytes)>> {
+        self.try_read_raw(position, u64::MAX)?
+            .map(|(tag, bytes)| Self::decompress(tag, bytes))
+            .transpose()
+    }
+
+    /// Read the entry as stored, possibly compressed. Entries that do not end before `end` (the
+    /// size of the wal) or whose crc does not match are reported as invalid data.
+    fn try_read_raw(&self, position: WalPosition, end: u64) -> io::Result<Option<(Tag, Bytes)>> {
+        if position.start + HEADER_LEN_BYTES > end {
+            return Err(invalid_entry(position, "the header is truncated"));
+        }
         let offset = offset(position.start);
         let bytes = self.map_offset(offset)?;
         let buf_offset = (position.start - offset) as usize;
@@ -248,23 +357,47 @@ impl WalReader {
             if crc == 0 {
                 return Ok(None);
             }
-            panic!(
-                "Non-zero crc at len 0, crc: {crc}, position:{}",
-                position.start
-            );
+            return Err(invalid_entry(
+                position,
+                format!("non-zero crc {crc} at len 0"),
+            ));
+        }
+        if len < HEADER_LEN_BYTES || buf_offset as u64 + len > MAP_SIZE {
+            return Err(invalid_entry(position, format!("invalid length {len}")));
+        }
+        if position.start + len > end {
+            return Err(invalid_entry(
+                position,
+                format!("length {len} is truncated"),
+            ));
         }
         let bytes = bytes.slice(buf_offset + HEADER_LEN_BYTES_USIZE..buf_offset + (len as usize));
         let actual_crc = crc32fast::hash(bytes.as_ref()) as u64;
         if actual_crc != crc {
-            // todo - return error
-            panic!(
-                "Crc mismatch, expected {}, found {} at position {}:{}",
-                crc, actual_crc, position.start, len
-            );
+            return Err(invalid_entry(
+                position,
+                format!("crc mismatch, expected {crc}, found {actual_crc}"),
+            ));
         }
         Ok(Some((tag, bytes)))
     }
 
+    fn decompress(tag: Tag, bytes: Bytes) -> io::Result<(Tag, Bytes)> {
+        let compression = (tag >> COMPRESSION_SHIFT) as u8;
+        let tag = tag & MAX_TAG;
+        if compression == 0 {
+            return Ok((tag, bytes));
+        }
+        let compression = Compression::from_id(compression).ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("Unknown wal entry compression {compression}"),
+            )
+        })?;
+        let decompressed = compression.decompress(&bytes, MAX_ENTRY_SIZE)?;
+        Ok((tag, decompressed.into()))
+    }
+
     // Attempts cleaning internal mem maps, returning number of retained maps
     // Map can be freed when all buffers linked to this portion of a file are dropped
     pub fn cleanup(&self) -> usize {
@@ -275,9 +408,15 @@ impl WalReader {
 
     // Iter all entries up to writer position at the time iter_until(...) is called
     pub fn iter_until(&self, w: &WalWriter) -> WalIterator {
+        self.iter_from(WalPosition { start: 0 }, w)
+    }
+
+    // Same as iter_until(...), starting at position (which must be the position of an entry,
+    // or the end of an entry)
+    pub fn iter_from(&self, position: WalPosition, w: &WalWriter) -> WalIterator<'_> {
         WalIterator {
             wal_reader: self,
-            position: Some(WalPosition { start: 0 }),
+            position: Some(position),
             end_position: w.pos,
         }
     }
@@ -308,49 +447,84 @@ impl WalReader {
     }
 }
 
+fn invalid_entry(position: WalPosition, reason: impl fmt::Display) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("Invalid wal entry at position {position}: {reason}"),
+    )
+}
+
 pub struct WalIterator<'a> {
     wal_reader: &'a WalReader,
     position: Option<WalPosition>,
     end_position: u64,
 }
 
+/// Yields an error for a corrupted entry (see `try_next`), the iteration stops after it.
 impl<'a> Iterator for WalIterator<'a> {
-    type Item = (WalPosition, (Tag, Bytes));
+    type Item = io::Result<(WalPosition, (Tag, Bytes))>;
 
     fn next(&mut self) -> Option<Self::Item> {
-        let position = self.position.take()?;
+        self.try_next().transpose()
+    }
+}
+
+impl<'a> WalIterator<'a> {
+    /// The position of the next entry to read (or of the padding before it), None once the
+    /// iteration is over.
+    pub fn next_position(&self) -> Option<WalPosition> {
+        self.position
+    }
+
+    /// Read the next entry, returns an error on a corrupted entry (such as a compressed entry that
+    /// fails to decompress). The iteration stops after the first error.
+    pub fn try_next(&mut self) -> io::Result<Option<(WalPosition, (Tag, Bytes))>> {
+        let Some(position) = self.position.take() else {
+            return Ok(None);
+        };
         tracing::trace!("Iter read {}", position.start);
         // Either read from current position, or try next mapping, but only once
-        if let Some(item) = self.try_position(position) {
-            return Some(item);
+        if let Some(item) = self.try_position(position)? {
+            return Ok(Some(item));
         }
         if position.first_in_map() {
-            return None;
+            return Ok(None);
         }
         tracing::trace!("Iter fallback read {}", position.next_start_offset().start);
         // todo - need to consider crash recovery here
         // Either need to reset writer position, or read all offsets until writer position
         self.try_position(position.next_start_offset())
     }
-}
 
-impl<'a> WalIterator<'a> {
-    fn try_position(&mut self, position: WalPosition) -> Option<(WalPosition, (Tag, Bytes))> {
+    fn try_position(
+        &mut self,
+        position: WalPosition,
+    ) -> io::Result<Option<(WalPosition, (Tag, Bytes))>> {
         if position.start >= self.end_position {
-            return None;
+            return Ok(None);
         }
-        let (tag, data) = self
-            .wal_reader
-            .try_read(position)
-            .expect("Failed to read wal")?;
-        self.position = Some(position.add(data.len() as u64 + HEADER_LEN_BYTES));
-        Some((position, (tag, data)))
+        let Some((tag, data)) = self.wal_reader.try_read_raw(position, self.end_position)? else {
+            return Ok(None);
+        };
+        let next_position = position.add(data.len() as u64 + HEADER_LEN_BYTES);
+        let entry = WalReader::decompress(tag, data)?;
+        self.position = Some(next_position);
+        Ok(Some((position, entry)))
     }
 }
 
 impl WalPosition {
     pub const MAX: WalPosition = WalPosition { start: u64::MAX };
 
+    pub const fn new(start: u64) -> Self {
+        Self { start }
+    }
+
+    /// The offset of the entry in the wal file.
+    pub fn start(&self) -> u64 {
+        self.start
+    }
+
     pub fn add(&self, len: u64) -> Self {
         Self {
             start: self.start + len,
@@ -389,6 +563,10 @@ impl fmt::Display for WalPosition {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::{
+        data::Data,
+        types::{BaseStatement, BlockReference, StatementBlock, Transaction},
+    };
 
     #[test]
     fn test_wal() {
@@ -467,7 +645,7 @@ mod tests {
     #[track_caller]
     // Read from iterator, assert tag and position
     fn rd_it(iter: &mut WalIterator, tag: Tag, pos: WalPosition) -> Bytes {
-        let (read_pos, (read_tag, data)) = iter.next().unwrap();
+        let (read_pos, (read_tag, data)) = iter.next().unwrap().unwrap();
         assert_eq!(read_tag, tag);
         assert_eq!(read_pos, pos);
         data
@@ -494,6 +672,64 @@ mod tests {
         assert_eq!(1, reader.cleanup()); // assert only one mapping was created (therefore one and two share same mapping)
     }
 
+    #[test]
+    fn test_wal_compression() {
+        let temp = tempdir::TempDir::new("test_wal_compression").unwrap();
+        let file = temp.path().join("wal");
+        let (mut writer, reader) = wal(file).unwrap();
+        let block = Data::new(StatementBlock::new(
+            1,
+            2,
+            vec![BlockReference::new_test(0, 1)],
+            (0..64)
+                .map(|i| BaseStatement::Share(Transaction::new(vec![i; 512])))
+                .collect(),
+            0,
+            false,
+            Default::default(),
+        ));
+        let plain_pos = writer.write(3, block.serialized_bytes()).unwrap();
+        writer.set_compression(Compression::Deflate);
+        let compressed_pos = writer.write(3, block.serialized_bytes()).unwrap();
+        let small = [1u8; 15];
+        let small_pos = writer.write(4, &small).unwrap();
+        let end = writer.write(5, &small).unwrap();
+        // The compressed entry takes less space
+        assert!(end.start - compressed_pos.start < compressed_pos.start - plain_pos.start);
+
+        let read = Data::<StatementBlock>::from_bytes(rd(&reader, compressed_pos, 3)).unwrap();
+        assert_eq!(read.serialized_bytes(), block.serialized_bytes());
+        assert_eq!(read.reference(), block.reference());
+        assert_eq!(&small, rd(&reader, small_pos, 4).as_ref());
+
+        let mut iter = reader.iter_until(&writer);
+        let plain = rd_it(&mut iter, 3, plain_pos);
+        let compressed = rd_it(&mut iter, 3, compressed_pos);
+        assert_eq!(plain, compressed);
+        assert_eq!(&small, rd_it(&mut iter, 4, small_pos).as_ref());
+        assert_eq!(&small, rd_it(&mut iter, 5, end).as_ref());
+        assert!(iter.next().is_none());
+    }
+
+    #[test]
+    fn test_wal_corrupted_compressed_entry() {
+        let temp = tempdir::TempDir::new("test_wal_corrupted_compressed_entry").unwrap();
+        let file = temp.path().join("wal");
+        let (mut writer, reader) = wal(file).unwrap();
+        let valid = [1u8; 15];
+        let valid_pos = writer.write(3, &valid).unwrap();
+        // A valid crc over bytes that are not a deflate stream
+        let tag = 3 | ((Compression::Deflate.id() as Tag) << COMPRESSION_SHIFT);
+        let corrupted_pos = writer.writev_raw(tag, &[IoSlice::new(&[7u8; 32])]).unwrap();
+        writer.write(4, &valid).unwrap();
+
+        assert!(reader.read(corrupted_pos).is_err());
+        let mut iter = reader.iter_until(&writer);
+        assert_eq!(&valid, rd_it(&mut iter, 3, valid_pos).as_ref());
+        assert!(iter.next().unwrap().is_err());
+        assert!(iter.next().is_none());
+    }
+
     #[test]
     fn test_header_combine_split() {
         for crc in [0, 1, 12, u64::MAX] {
@@ -0,0 +1,399 @@
+// Copyright (c) Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Offline inspection and repair of the wal of a validator. The entries are read with
+//! `WalReader::iter_until` and decoded as in `BlockStore::open`, but corrupted entries are
+//! reported rather than crashing, so that the wal can be cut right before the first of them. The
+//! wal is opened read-only for inspection, and `truncate` refuses to cut the wal of a running
+//! validator.
+
+use std::{collections::BTreeMap, fmt, fs, path::Path};
+
+use eyre::{bail, ensure, Context};
+use minibytes::Bytes;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    block_store::{OwnBlockData, WalEntry},
+    index_checkpoint::IndexCheckpoint,
+    types::BaseStatement,
+    wal::{self, Tag, WalPosition, WalReader, WalWriter},
+};
+
+/// The number of entries and the (uncompressed) bytes they hold, per tag.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, Eq)]
+pub struct WalStats {
+    pub size_bytes: u64,
+    pub entries: BTreeMap<String, TagStats>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct TagStats {
+    pub count: u64,
+    pub bytes: u64,
+}
+
+/// A decoded entry, see `dump`.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct DumpedEntry {
+    pub position: u64,
+    pub tag: String,
+    pub bytes: usize,
+    pub description: String,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct WalVerification {
+    pub size_bytes: u64,
+    /// The number of valid entries before the first invalid one (if any).
+    pub valid_entries: u64,
+    /// The position of the first invalid entry, where the wal should be truncated to recover it.
+    pub invalid_position: Option<u64>,
+    pub error: Option<String>,
+}
+
+impl WalVerification {
+    pub fn is_valid(&self) -> bool {
+        self.error.is_none()
+    }
+}
+
+/// Count the entries of the wal per tag. Fails on the first invalid entry (see `verify`).
+pub fn stats(path: impl AsRef<Path>) -> eyre::Result<WalStats> {
+    let (writer, reader) = open(path.as_ref())?;
+    let mut stats = WalStats {
+        size_bytes: writer.size(),
+        ..Default::default()
+    };
+    for_each_entry(&writer, &reader, u64::MAX, |_, tag, data| {
+        let entry = stats.entries.entry(tag_name(tag)).or_default();
+        entry.count += 1;
+        entry.bytes += data.len() as u64;
+    })?;
+    Ok(stats)
+}
+
+/// Decode the entries written at positions in `from..to`.
+pub fn dump(
+    path: impl AsRef<Path>,
+    from: Option<u64>,
+    to: Option<u64>,
+) -> eyre::Result<Vec<DumpedEntry>> {
+    let from = from.unwrap_or(0);
+    let to = to.unwrap_or(u64::MAX);
+    let (writer, reader) = open(path.as_ref())?;
+    let mut entries = Vec::new();
+    for_each_entry(&writer, &reader, to, |position, tag, data| {
+        if position.start() >= from {
+            let bytes = data.len();
+            let description = match WalEntry::decode(tag, data) {
+                Ok(entry) => describe(&entry),
+                Err(e) => format!("Failed to deserialize: {e}"),
+            };
+            entries.push(DumpedEntry {
+                position: position.start(),
+                tag: tag_name(tag),
+                bytes,
+                description,
+            });
+        }
+    })?;
+    Ok(entries)
+}
+
+/// Check the crc of all the entries and that they can be deserialized, stopping at the first
+/// invalid entry.
+pub fn verify(path: impl AsRef<Path>) -> eyre::Result<WalVerification> {
+    let (writer, reader) = open(path.as_ref())?;
+    let mut verification = WalVerification {
+        size_bytes: writer.size(),
+        valid_entries: 0,
+        invalid_position: None,
+        error: None,
+    };
+    let mut iter = reader.iter_until(&writer);
+    while let Some(next_position) = iter.next_position() {
+        let (position, (tag, data)) = match iter.try_next() {
+            Ok(Some(entry)) => entry,
+            Ok(None) => break,
+            Err(e) => {
+                verification.invalid_position = Some(next_position.start());
+                verification.error = Some(e.to_string());
+                break;
+            }
+        };
+        if let Err(e) = check_entry(position, tag, data) {
+            verification.invalid_position = Some(position.start());
+            verification.error = Some(format!("Invalid wal entry at position {position}: {e}"));
+            break;
+        }
+        verification.valid_entries += 1;
+    }
+    Ok(verification)
+}
+
+/// Cut the wal at `position`, which must be the position of an entry or the end of a valid entry
+/// (such as the `invalid_position` reported by `verify`). Returns the number of bytes removed.
+/// The index checkpoint next to the wal is removed if it covers entries after `position`.
+pub fn truncate(path: impl AsRef<Path>, position: u64) -> eyre::Result<u64> {
+    let path = path.as_ref();
+    let (writer, reader) = open(path)?;
+    let locked = wal::lock_exclusive(path)
+        .wrap_err(format!("Failed to lock wal file '{}'", path.display()))?;
+    let size = writer.size();
+    let mut found = position == size;
+    if !found {
+        ensure!(
+            position < size,
+            "Position {position} is beyond the end of the wal ({size} bytes)"
+        );
+        let mut iter = reader.iter_until(&writer);
+        while let Some(next_position) = iter.next_position() {
+            if next_position.start() >= position {
+                found = next_position.start() == position;
+                break;
+            }
+            let next = iter
+                .try_next()
+                .wrap_err(format!("Position {position} is after an invalid entry"))?;
+            if next.is_none() {
+                break;
+            }
+        }
+    }
+    ensure!(found, "There is no wal entry at position {position}");
+    drop(reader);
+    drop(writer);
+    wal::truncate(&locked, WalPosition::new(position))
+        .wrap_err(format!("Failed to truncate wal file '{}'", path.display()))?;
+    let checkpoint = path.with_file_name(IndexCheckpoint::DEFAULT_FILENAME);
+    if let Ok(Some(loaded)) = IndexCheckpoint::load(&checkpoint) {
+        if loaded.wal_position.start() > position {
+            fs::remove_file(&checkpoint).wrap_err(format!(
+                "Failed to remove index checkpoint '{}'",
+                checkpoint.display()
+            ))?;
+        }
+    }
+    Ok(size - position)
+}
+
+fn open(path: &Path) -> eyre::Result<(WalWriter, WalReader)> {
+    ensure!(
+        fs::metadata(path).is_ok(),
+        "Wal file '{}' does not exist",
+        path.display()
+    );
+    wal::open_read_only(path).wrap_err(format!("Failed to open wal file '{}'", path.display()))
+}
+
+/// Call `f` on the entries written before position `to`, the entries after it are not read.
+fn for_each_entry(
+    writer: &WalWriter,
+    reader: &WalReader,
+    to: u64,
+    mut f: impl FnMut(WalPosition, Tag, Bytes),
+) -> eyre::Result<()> {
+    let mut iter = reader.iter_until(writer);
+    while iter.next_position().is_some_and(|next| next.start() < to) {
+        match iter.try_next().wrap_err("Invalid wal, see `verify`")? {
+            Some((position, (tag, data))) if position.start() < to => f(position, tag, data),
+            _ => break,
+        }
+    }
+    Ok(())
+}
+
+fn check_entry(position: WalPosition, tag: Tag, data: Bytes) -> eyre::Result<()> {
+    match WalEntry::decode(tag, data)? {
+        // Payloads are only deserialized once the wal is replayed (see `RecoveredStateBuilder`)
+        WalEntry::Payload(payload) => {
+            bincode::deserialize::<Vec<BaseStatement>>(&payload)?;
+        }
+        WalEntry::OwnBlock(OwnBlockData { next_entry, .. })
+            if next_entry != WalPosition::MAX && next_entry > position =>
+        {
+            bail!("The next entry {next_entry} of the own block is after the block");
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+fn tag_name(tag: Tag) -> String {
+    WalEntry::tag_name(tag)
+        .map(|name| name.trim_start_matches("WAL_ENTRY_").to_string())
+        .unwrap_or_else(|| format!("UNKNOWN_{tag}"))
+}
+
+fn describe(entry: &WalEntry) -> String {
+    match entry {
+        WalEntry::Block(block) => format!(
+            "block {} with {} includes and {} statements",
+            block.reference(),
+            block.includes().len(),
+            block.statements().len()
+        ),
+        WalEntry::Payload(payload) => match bincode::deserialize::<Vec<BaseStatement>>(payload) {
+            Ok(statements) => format!("payload of {} statements", statements.len()),
+            Err(e) => format!("payload that fails to deserialize: {e}"),
+        },
+        WalEntry::OwnBlock(own_block) => format!(
+            "own block {} with {} includes and {} statements, pending entries from {}",
+            own_block.block.reference(),
+            own_block.block.includes().len(),
+            own_block.block.statements().len(),
+            own_block.next_entry
+        ),
+        WalEntry::State(state) => format!("state of {} bytes", state.len()),
+        WalEntry::Commit(commits, _) => {
+            let leaders: Vec<_> = commits
+                .iter()
+                .map(|commit| commit.leader.to_string())
+                .collect();
+            let blocks: usize = commits.iter().map(|commit| commit.sub_dag.len()).sum();
+            format!(
+                "commit of leaders [{}] with {blocks} blocks",
+                leaders.join(", ")
+            )
+        }
+        WalEntry::Snapshot(snapshot) => format!(
+            "snapshot at leader {} with {} frontier blocks",
+            snapshot.last_committed_leader,
+            snapshot.frontier.len()
+        ),
+        WalEntry::Batch(batch) => format!("batch {:?}", **batch),
+    }
+}
+
+impl fmt::Display for DumpedEntry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} {} ({} bytes): {}",
+            self.position, self.tag, self.bytes, self.description
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        block_store::{WAL_ENTRY_BLOCK, WAL_ENTRY_PAYLOAD, WAL_ENTRY_STATE},
+        data::Data,
+        types::{BlockReference, StatementBlock, Transaction},
+    };
+
+    fn write_wal(path: &Path) -> Vec<WalPosition> {
+        let (mut writer, _reader) = wal::wal(path).unwrap();
+        let statements = vec![BaseStatement::Share(Transaction::new(vec![1; 8]))];
+        let block = Data::new(StatementBlock::new(
+            1,
+            2,
+            vec![BlockReference::new_test(0, 1)],
+            statements.clone(),
+            0,
+            false,
+            Default::default(),
+        ));
+        vec![
+            writer
+                .write(WAL_ENTRY_BLOCK, block.serialized_bytes())
+                .unwrap(),
+            writer
+                .write(WAL_ENTRY_PAYLOAD, &bincode::serialize(&statements).unwrap())
+                .unwrap(),
+            writer.write(WAL_ENTRY_STATE, &[1, 2, 3]).unwrap(),
+            writer
+                .write(WAL_ENTRY_BLOCK, block.serialized_bytes())
+                .unwrap(),
+        ]
+    }
+
+    #[test]
+    fn inspect() {
+        let dir = tempdir::TempDir::new("wal_inspect").unwrap();
+        let path = dir.path().join("wal");
+        let positions = write_wal(&path);
+
+        let stats = stats(&path).unwrap();
+        assert_eq!(stats.entries["BLOCK"].count, 2);
+        assert_eq!(stats.entries["PAYLOAD"].count, 1);
+        assert_eq!(stats.entries["STATE"], TagStats { count: 1, bytes: 3 });
+
+        let dumped = dump(
+            &path,
+            Some(positions[1].start()),
+            Some(positions[3].start()),
+        )
+        .unwrap();
+        assert_eq!(dumped.len(), 2);
+        assert_eq!(dumped[0].tag, "PAYLOAD");
+        assert_eq!(dumped[0].description, "payload of 1 statements");
+        assert_eq!(dumped[1].description, "state of 3 bytes");
+        assert!(verify(&path).unwrap().is_valid());
+    }
+
+    #[test]
+    fn verify_and_truncate() {
+        let dir = tempdir::TempDir::new("wal_inspect").unwrap();
+        let path = dir.path().join("wal");
+        let positions = write_wal(&path);
+        let size = fs::metadata(&path).unwrap().len();
+
+        // Corrupt the payload of the third entry
+        let mut bytes = fs::read(&path).unwrap();
+        let corrupted = positions[2].start() as usize + 16;
+        bytes[corrupted] ^= 0xff;
+        fs::write(&path, bytes).unwrap();
+
+        let verification = verify(&path).unwrap();
+        assert!(!verification.is_valid());
+        assert_eq!(verification.valid_entries, 2);
+        assert_eq!(verification.invalid_position, Some(positions[2].start()));
+        assert!(stats(&path).is_err());
+        // The entries before the corrupted one can still be dumped
+        assert_eq!(
+            dump(&path, None, Some(positions[2].start())).unwrap().len(),
+            2
+        );
+
+        // An index checkpoint covering the removed entries is removed with them
+        let checkpoint = path.with_file_name(IndexCheckpoint::DEFAULT_FILENAME);
+        IndexCheckpoint::new_test(positions[3])
+            .store(&checkpoint)
+            .unwrap();
+
+        assert!(truncate(&path, positions[2].start() + 1).is_err());
+        assert!(truncate(&path, positions[3].start()).is_err());
+        assert!(checkpoint.exists());
+        let removed = truncate(&path, positions[2].start()).unwrap();
+        assert_eq!(removed, size - positions[2].start());
+        assert!(!checkpoint.exists());
+        let verification = verify(&path).unwrap();
+        assert!(verification.is_valid());
+        assert_eq!(verification.valid_entries, 2);
+    }
+
+    #[test]
+    fn wal_in_use() {
+        let dir = tempdir::TempDir::new("wal_in_use").unwrap();
+        let path = dir.path().join("wal");
+        let positions = write_wal(&path);
+
+        // The wal of a running validator can be inspected, but not truncated
+        let file = wal::open_file_for_wal(&path).unwrap();
+        let size = file.metadata().unwrap().len();
+        assert!(verify(&path).unwrap().is_valid());
+        assert!(truncate(&path, positions[2].start()).is_err());
+        assert_eq!(fs::metadata(&path).unwrap().len(), size);
+        drop(file);
+
+        // Nor can a validator open the wal while it is truncated
+        let locked = wal::lock_exclusive(&path).unwrap();
+        assert!(wal::open_file_for_wal(&path).is_err());
+        drop(locked);
+        truncate(&path, positions[2].start()).unwrap();
+    }
+}
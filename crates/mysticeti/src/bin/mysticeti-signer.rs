@@ -0,0 +1,96 @@
+// Copyright (c) Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Reference signer daemon. It holds the key of a validator and signs its blocks over a Unix
+//! socket, refusing to sign two blocks for the same round.
+
+use std::{
+    fs,
+    fs::Permissions,
+    io,
+    os::{
+        fd::RawFd,
+        unix::{fs::PermissionsExt, net::UnixListener},
+    },
+    path::PathBuf,
+};
+
+use clap::Parser;
+use eyre::{eyre, Context, Result};
+use mysticeti_core::{
+    block_signer::SignerDaemon,
+    committee::Committee,
+    config::{ImportExport, NodePrivateConfig},
+    keystore::Password,
+};
+use tracing_subscriber::{filter::LevelFilter, EnvFilter, FmtSubscriber};
+
+#[derive(Parser)]
+#[command(author, version, about, long_about = None)]
+struct Args {
+    /// Path to the file holding the private validator configurations (including keys).
+    #[clap(long, value_name = "FILE")]
+    private_config_path: PathBuf,
+    /// Path to the file holding the public committee information.
+    #[clap(long, value_name = "FILE")]
+    committee_path: PathBuf,
+    /// The Unix socket on which the validator connects to the signer.
+    #[clap(long, value_name = "FILE")]
+    socket_path: PathBuf,
+    /// The environment variable holding the password of the private config.
+    #[clap(long, value_name = "VAR", conflicts_with = "password_fd")]
+    password_env: Option<String>,
+    /// The file descriptor (such as a pipe) to read the password of the private config from.
+    #[clap(long, value_name = "FD")]
+    password_fd: Option<RawFd>,
+}
+
+fn main() -> Result<()> {
+    color_eyre::install()?;
+    let filter = EnvFilter::builder()
+        .with_default_directive(LevelFilter::INFO.into())
+        .from_env_lossy();
+    let subscriber = FmtSubscriber::builder().with_env_filter(filter).finish();
+    tracing::subscriber::set_global_default(subscriber).expect("setting default subscriber failed");
+
+    let args = Args::parse();
+    let password = match (&args.password_env, args.password_fd) {
+        (Some(variable), _) => Some(Password::from_env(variable)?),
+        (None, Some(fd)) => Some(Password::from_fd(fd)?),
+        (None, None) => None,
+    };
+    let committee = Committee::load(&args.committee_path).wrap_err(format!(
+        "Failed to load committee file '{}'",
+        args.committee_path.display()
+    ))?;
+    let private_config =
+        NodePrivateConfig::load_with_password(&args.private_config_path, password.as_ref())
+            .wrap_err(format!(
+                "Failed to load private configuration file '{}'",
+                args.private_config_path.display()
+            ))?;
+    let authority = private_config.authority();
+    let guard_path = private_config.signer_daemon_guard();
+    let signer = private_config.keypair.ok_or(eyre!(
+        "The private config of the signer should hold the keypair"
+    ))?;
+    let mut daemon = SignerDaemon::new(authority, signer, guard_path, &committee)?;
+
+    match fs::remove_file(&args.socket_path) {
+        Ok(_) => {}
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+        Err(e) => return Err(e).wrap_err("Failed to remove stale socket"),
+    }
+    let listener = UnixListener::bind(&args.socket_path).wrap_err(format!(
+        "Failed to bind socket '{}'",
+        args.socket_path.display()
+    ))?;
+    // Only the owner (the validator) may request signatures.
+    fs::set_permissions(&args.socket_path, Permissions::from_mode(0o600))?;
+    tracing::info!(
+        "Signer of validator {authority} listening on {}",
+        args.socket_path.display()
+    );
+    daemon.serve(listener)?;
+    Ok(())
+}
@@ -3,7 +3,8 @@
 
 use std::{
     fs,
-    net::{IpAddr, Ipv4Addr},
+    net::{IpAddr, Ipv4Addr, SocketAddr},
+    os::fd::RawFd,
     path::PathBuf,
     sync::Arc,
 };
@@ -11,11 +12,26 @@ use std::{
 use clap::{command, Parser};
 use eyre::{eyre, Context, Result};
 use mysticeti_core::{
+    address_book::AddressBook,
     committee::Committee,
-    config::{ClientParameters, ImportExport, NodeParameters, NodePrivateConfig, NodePublicConfig},
-    types::AuthorityIndex,
+    config::{
+        ClientParameters,
+        ImportExport,
+        NodeIdentifier,
+        NodeParameters,
+        NodePrivateConfig,
+        NodePublicConfig,
+    },
+    dag_export::{self, DagFormat},
+    keystore::{EncryptedNodePrivateConfig, Password},
+    observer::Observer,
+    reload::LiveParameters,
+    resolver::{Host, PeerAddress},
+    types::{AuthorityIndex, RoundNumber},
     validator::Validator,
+    wal_inspect,
 };
+use tokio::signal::unix::{signal, SignalKind};
 use tracing::{warn, info};
 use tracing_subscriber::{fmt, EnvFilter, FmtSubscriber, layer::SubscriberExt};
 use tracing_subscriber::filter::LevelFilter;
@@ -30,11 +46,54 @@ struct Args {
 #[derive(Parser)]
 enum Operation {
     /// Generate a committee file, parameters files and the private config files of all validators
-    /// from a list of initial peers. This is only suitable for benchmarks as it exposes all keys.
+    /// from a list of initial peers. This is only suitable for benchmarks as it exposes all keys;
+    /// real deployments should use `keygen` and `assemble-genesis` instead.
     BenchmarkGenesis {
-        /// The list of ip addresses of the all validators.
-        #[clap(long, value_name = "ADDR", value_delimiter = ' ', num_args(4..))]
-        ips: Vec<IpAddr>,
+        /// The hosts of all validators: IP addresses (IPv6 ones may be written in brackets) or
+        /// hostnames, which the peers resolve whenever they connect.
+        #[clap(long = "ips", value_name = "HOST", value_delimiter = ' ', num_args(4..))]
+        hosts: Vec<Host>,
+        /// The working directory where the files will be generated.
+        #[clap(long, value_name = "FILE", default_value = "genesis")]
+        working_directory: PathBuf,
+        /// Path to the file holding the node parameters. If not provided, default parameters are used.
+        #[clap(long, value_name = "FILE")]
+        node_parameters_path: Option<PathBuf>,
+    },
+    /// Generate the keypair of a validator on the host that will run it. The private config
+    /// (holding the key) stays on this host; only the public identifier file is meant to be
+    /// shared with the other validators.
+    Keygen {
+        /// The authority index agreed upon for this node.
+        #[clap(long, value_name = "INT")]
+        authority: AuthorityIndex,
+        /// The address at which the peers reach this node, `host:port` where the host is an IP
+        /// address or a hostname.
+        #[clap(long, value_name = "ADDR")]
+        network_address: PeerAddress,
+        /// The address on which this node exposes its metrics.
+        #[clap(long, value_name = "ADDR")]
+        metrics_address: PeerAddress,
+        /// The local address on which this node accepts connections from its peers, when it
+        /// differs from the network address. Defaults to all interfaces on the network port.
+        #[clap(long, value_name = "ADDR")]
+        listen_address: Option<SocketAddr>,
+        /// The working directory where the files will be generated.
+        #[clap(long, value_name = "FILE", default_value = "genesis")]
+        working_directory: PathBuf,
+        /// The directory where the node stores its data. Defaults to a directory inside the
+        /// working directory.
+        #[clap(long, value_name = "FILE")]
+        storage_path: Option<PathBuf>,
+        #[clap(flatten)]
+        password: PasswordArgs,
+    },
+    /// Assemble the committee file and the public config file from the public identifier files
+    /// collected from all validators.
+    AssembleGenesis {
+        /// The identifier files of all validators, in the order of their authority index.
+        #[clap(long, value_name = "FILE", value_delimiter = ' ', num_args(1..))]
+        identifiers: Vec<PathBuf>,
         /// The working directory where the files will be generated.
         #[clap(long, value_name = "FILE", default_value = "genesis")]
         working_directory: PathBuf,
@@ -42,6 +101,32 @@ enum Operation {
         #[clap(long, value_name = "FILE")]
         node_parameters_path: Option<PathBuf>,
     },
+    /// Check that the keypair of a private config matches its slot in the committee and the
+    /// public config.
+    VerifyGenesis {
+        /// Path to the file holding the public committee information.
+        #[clap(long, value_name = "FILE")]
+        committee_path: String,
+        /// Path to the file holding the public validator configurations (such as network addresses).
+        #[clap(long, value_name = "FILE")]
+        public_config_path: String,
+        /// Path to the file holding the private validator configurations (including keys).
+        #[clap(long, value_name = "FILE")]
+        private_config_path: String,
+        #[clap(flatten)]
+        password: PasswordArgs,
+    },
+    /// Encrypt the key of a plaintext private config with a password.
+    EncryptPrivateConfig {
+        /// Path to the file holding the plaintext private validator configurations.
+        #[clap(long, value_name = "FILE")]
+        private_config_path: PathBuf,
+        /// Path where the encrypted private config is written.
+        #[clap(long, value_name = "FILE")]
+        output_path: PathBuf,
+        #[clap(flatten)]
+        password: PasswordArgs,
+    },
     /// Run a validator node.
     Run {
         /// The authority index of this node.
@@ -59,6 +144,59 @@ enum Operation {
         /// Path to the file holding the client parameters (for benchmarks).
         #[clap(long, value_name = "FILE")]
         client_parameters_path: String,
+        #[clap(flatten)]
+        password: PasswordArgs,
+    },
+    /// Run a read-only observer node following the validators listed in the public config.
+    Observe {
+        /// The index of this observer in the list of observers of the public config.
+        #[clap(long, value_name = "INT")]
+        observer: usize,
+        /// Path to the file holding the public committee information.
+        #[clap(long, value_name = "FILE")]
+        committee_path: String,
+        /// Path to the file holding the public validator configurations (such as network addresses).
+        #[clap(long, value_name = "FILE")]
+        public_config_path: String,
+        /// The directory where the observer stores its data.
+        #[clap(long, value_name = "FILE")]
+        storage_path: PathBuf,
+    },
+    /// Export part of the dag stored in the wal of a validator that is not running, to debug the
+    /// commit rule.
+    ExportDag {
+        /// The authority index of the validator that wrote the wal.
+        #[clap(long, value_name = "INT")]
+        authority: AuthorityIndex,
+        /// Path to the file holding the public committee information.
+        #[clap(long, value_name = "FILE")]
+        committee_path: String,
+        /// Path to the file holding the public validator configurations (for the commit rule).
+        #[clap(long, value_name = "FILE")]
+        public_config_path: String,
+        /// Path to the wal file of the validator.
+        #[clap(long, value_name = "FILE")]
+        wal_path: PathBuf,
+        /// The first round exported. Defaults to a few rounds below the last one.
+        #[clap(long, value_name = "INT")]
+        from: Option<RoundNumber>,
+        /// The last round exported. Defaults to the highest round in the wal.
+        #[clap(long, value_name = "INT")]
+        to: Option<RoundNumber>,
+        /// The output format: dot (Graphviz), json or draw (the syntax of `Dag::draw`).
+        #[clap(long, value_name = "FORMAT", default_value = "dot")]
+        format: DagFormat,
+        /// Annotate the votes and certificates for the leaders.
+        #[clap(long)]
+        annotate: bool,
+        /// The file where the export is written. Defaults to the standard output.
+        #[clap(long, value_name = "FILE")]
+        output_path: Option<PathBuf>,
+    },
+    /// Inspect or repair the wal of a validator that is not running.
+    Wal {
+        #[clap(subcommand)]
+        operation: WalOperation,
     },
     /// Deploy a local validator for test. Dryrun mode uses default keys and committee configurations.
     DryRun {
@@ -71,6 +209,66 @@ enum Operation {
     },
 }
 
+#[derive(Parser)]
+enum WalOperation {
+    /// Count the entries of the wal per tag.
+    Stats {
+        /// Path to the wal file of the validator.
+        #[clap(long, value_name = "FILE")]
+        wal_path: PathBuf,
+    },
+    /// Decode the entries of the wal written in a range of positions.
+    Dump {
+        /// Path to the wal file of the validator.
+        #[clap(long, value_name = "FILE")]
+        wal_path: PathBuf,
+        /// The first position dumped. Defaults to the start of the wal.
+        #[clap(long, value_name = "INT")]
+        from: Option<u64>,
+        /// The position after the last entry dumped. Defaults to the end of the wal.
+        #[clap(long, value_name = "INT")]
+        to: Option<u64>,
+    },
+    /// Check the crc of the entries of the wal and that they can be deserialized.
+    Verify {
+        /// Path to the wal file of the validator.
+        #[clap(long, value_name = "FILE")]
+        wal_path: PathBuf,
+    },
+    /// Cut the wal at a position, typically the first invalid entry reported by `verify`. The
+    /// entries from this position are lost.
+    Truncate {
+        /// Path to the wal file of the validator.
+        #[clap(long, value_name = "FILE")]
+        wal_path: PathBuf,
+        /// The position of the first entry removed.
+        #[clap(long, value_name = "INT")]
+        position: u64,
+    },
+}
+
+/// Where to read the password of an encrypted private config from. Passing the password on the
+/// command line is not supported as it would be visible to other users of the host.
+#[derive(clap::Args)]
+struct PasswordArgs {
+    /// The environment variable holding the password of the private config.
+    #[clap(long, value_name = "VAR", conflicts_with = "password_fd")]
+    password_env: Option<String>,
+    /// The file descriptor (such as a pipe) to read the password of the private config from.
+    #[clap(long, value_name = "FD")]
+    password_fd: Option<RawFd>,
+}
+
+impl PasswordArgs {
+    fn password(&self) -> Result<Option<Password>> {
+        match (&self.password_env, self.password_fd) {
+            (Some(variable), _) => Password::from_env(variable).map(Some),
+            (None, Some(fd)) => Password::from_fd(fd).map(Some),
+            (None, None) => Ok(None),
+        }
+    }
+}
+
 #[tokio::main]
 async fn main() -> Result<()> {
     // Nice colored error messages.
@@ -99,16 +297,55 @@ async fn main() -> Result<()> {
     // Parse the command line arguments.
     match Args::parse().operation {
         Operation::BenchmarkGenesis {
-            ips,
+            hosts,
+            working_directory,
+            node_parameters_path,
+        } => benchmark_genesis(hosts, working_directory, node_parameters_path)?,
+        Operation::Keygen {
+            authority,
+            network_address,
+            metrics_address,
+            listen_address,
+            working_directory,
+            storage_path,
+            password,
+        } => keygen(
+            authority,
+            network_address,
+            metrics_address,
+            listen_address,
+            working_directory,
+            storage_path,
+            password,
+        )?,
+        Operation::AssembleGenesis {
+            identifiers,
             working_directory,
             node_parameters_path,
-        } => benchmark_genesis(ips, working_directory, node_parameters_path)?,
+        } => assemble_genesis(identifiers, working_directory, node_parameters_path)?,
+        Operation::VerifyGenesis {
+            committee_path,
+            public_config_path,
+            private_config_path,
+            password,
+        } => verify_genesis(
+            committee_path,
+            public_config_path,
+            private_config_path,
+            password,
+        )?,
+        Operation::EncryptPrivateConfig {
+            private_config_path,
+            output_path,
+            password,
+        } => encrypt_private_config(private_config_path, output_path, password)?,
         Operation::Run {
             authority,
             committee_path,
             public_config_path,
             private_config_path,
             client_parameters_path,
+            password,
         } => {
             run(
                 authority,
@@ -116,9 +353,38 @@ async fn main() -> Result<()> {
                 public_config_path,
                 private_config_path,
                 client_parameters_path,
+                password,
             )
             .await?
         }
+        Operation::Observe {
+            observer,
+            committee_path,
+            public_config_path,
+            storage_path,
+        } => observe(observer, committee_path, public_config_path, storage_path).await?,
+        Operation::ExportDag {
+            authority,
+            committee_path,
+            public_config_path,
+            wal_path,
+            from,
+            to,
+            format,
+            annotate,
+            output_path,
+        } => export_dag(
+            authority,
+            committee_path,
+            public_config_path,
+            wal_path,
+            from,
+            to,
+            format,
+            annotate,
+            output_path,
+        )?,
+        Operation::Wal { operation } => wal(operation)?,
         Operation::DryRun {
             authority,
             committee_size,
@@ -129,7 +395,7 @@ async fn main() -> Result<()> {
 }
 
 fn benchmark_genesis(
-    ips: Vec<IpAddr>,
+    hosts: Vec<Host>,
     working_directory: PathBuf,
     node_parameters_path: Option<PathBuf>,
 ) -> Result<()> {
@@ -140,7 +406,7 @@ fn benchmark_genesis(
     ))?;
 
     // Generate the committee file.
-    let committee_size = ips.len();
+    let committee_size = hosts.len();
     let mut committee_path = working_directory.clone();
     committee_path.push(Committee::DEFAULT_FILENAME);
     Committee::new_for_benchmarks(committee_size)
@@ -157,7 +423,7 @@ fn benchmark_genesis(
         None => NodeParameters::default(),
     };
 
-    let node_public_config = NodePublicConfig::new_for_benchmarks(ips, Some(node_parameters));
+    let node_public_config = NodePublicConfig::new_for_benchmarks(hosts, Some(node_parameters));
     let mut node_public_config_path = working_directory.clone();
     node_public_config_path.push(NodePublicConfig::DEFAULT_FILENAME);
     node_public_config
@@ -184,6 +450,174 @@ fn benchmark_genesis(
     Ok(())
 }
 
+/// Generate the keypair of a single validator, without exposing its private key.
+fn keygen(
+    authority: AuthorityIndex,
+    network_address: PeerAddress,
+    metrics_address: PeerAddress,
+    listen_address: Option<SocketAddr>,
+    working_directory: PathBuf,
+    storage_path: Option<PathBuf>,
+    password: PasswordArgs,
+) -> Result<()> {
+    tracing::info!("Generating keypair of validator {authority}");
+    fs::create_dir_all(&working_directory).wrap_err(format!(
+        "Failed to create directory '{}'",
+        working_directory.display()
+    ))?;
+
+    // Never overwrite an existing key, it may already be registered in a committee.
+    let private_config_path =
+        working_directory.join(NodePrivateConfig::default_filename(authority));
+    if private_config_path.exists() {
+        return Err(eyre!(
+            "Private config file '{}' already exists",
+            private_config_path.display()
+        ));
+    }
+
+    let storage_path = storage_path.unwrap_or_else(|| {
+        working_directory.join(NodePrivateConfig::default_storage_path(authority))
+    });
+    fs::create_dir_all(&storage_path).wrap_err(format!(
+        "Failed to create directory '{}'",
+        storage_path.display()
+    ))?;
+    let private_config = NodePrivateConfig::generate(authority, storage_path);
+    match password.password()? {
+        Some(password) => EncryptedNodePrivateConfig::encrypt(&private_config, &password)?
+            .print(&private_config_path),
+        None => private_config.print(&private_config_path),
+    }
+    .wrap_err("Failed to print private config file")?;
+    tracing::info!(
+        "Generated private config file: {}",
+        private_config_path.display()
+    );
+
+    let identifier = NodeIdentifier {
+        public_key: private_config.public_key()?,
+        network_address,
+        metrics_address,
+        listen_address,
+    };
+    let identifier_path = working_directory.join(NodeIdentifier::default_filename(authority));
+    identifier
+        .print(&identifier_path)
+        .wrap_err("Failed to print identifier file")?;
+    tracing::info!("Generated identifier file: {}", identifier_path.display());
+
+    Ok(())
+}
+
+/// Assemble the genesis files from the public identifiers of all validators.
+fn assemble_genesis(
+    identifiers: Vec<PathBuf>,
+    working_directory: PathBuf,
+    node_parameters_path: Option<PathBuf>,
+) -> Result<()> {
+    tracing::info!(
+        "Assembling genesis files from {} validators",
+        identifiers.len()
+    );
+    fs::create_dir_all(&working_directory).wrap_err(format!(
+        "Failed to create directory '{}'",
+        working_directory.display()
+    ))?;
+
+    let identifiers = identifiers
+        .iter()
+        .map(|path| {
+            NodeIdentifier::load(path).wrap_err(format!(
+                "Failed to load identifier file '{}'",
+                path.display()
+            ))
+        })
+        .collect::<Result<Vec<_>>>()?;
+    let node_parameters = match node_parameters_path {
+        Some(path) => NodeParameters::load(&path).wrap_err(format!(
+            "Failed to load parameters file '{}'",
+            path.display()
+        ))?,
+        None => NodeParameters::default(),
+    };
+    let (committee, node_public_config) =
+        NodePublicConfig::new_for_genesis(identifiers, node_parameters)
+            .wrap_err("Invalid identifiers")?;
+
+    let committee_path = working_directory.join(Committee::DEFAULT_FILENAME);
+    committee
+        .print(&committee_path)
+        .wrap_err("Failed to print committee file")?;
+    tracing::info!("Generated committee file: {}", committee_path.display());
+
+    let node_public_config_path = working_directory.join(NodePublicConfig::DEFAULT_FILENAME);
+    node_public_config
+        .print(&node_public_config_path)
+        .wrap_err("Failed to print parameters file")?;
+    tracing::info!(
+        "Generated public node config file: {}",
+        node_public_config_path.display()
+    );
+
+    Ok(())
+}
+
+/// Check that a private config matches the genesis files.
+fn verify_genesis(
+    committee_path: String,
+    public_config_path: String,
+    private_config_path: String,
+    password: PasswordArgs,
+) -> Result<()> {
+    let committee = Committee::load(&committee_path)
+        .wrap_err(format!("Failed to load committee file '{committee_path}'"))?;
+    let public_config = NodePublicConfig::load(&public_config_path).wrap_err(format!(
+        "Failed to load parameters file '{public_config_path}'"
+    ))?;
+    let private_config =
+        NodePrivateConfig::load_with_password(&private_config_path, password.password()?.as_ref())
+            .wrap_err(format!(
+                "Failed to load private configuration file '{private_config_path}'"
+            ))?;
+
+    private_config
+        .verify(&committee, &public_config)
+        .wrap_err("Private config does not match the genesis files")?;
+    tracing::info!(
+        "Private config of validator {} matches the genesis files",
+        private_config.authority()
+    );
+    Ok(())
+}
+
+/// Convert a plaintext private config into an encrypted one.
+fn encrypt_private_config(
+    private_config_path: PathBuf,
+    output_path: PathBuf,
+    password: PasswordArgs,
+) -> Result<()> {
+    let password = password
+        .password()?
+        .ok_or(eyre!("Either --password-env or --password-fd is required"))?;
+    let private_config = NodePrivateConfig::load(&private_config_path).wrap_err(format!(
+        "Failed to load private configuration file '{}'",
+        private_config_path.display()
+    ))?;
+    EncryptedNodePrivateConfig::encrypt(&private_config, &password)?
+        .print(&output_path)
+        .wrap_err("Failed to print encrypted private config file")?;
+    tracing::info!(
+        "Generated encrypted private config file: {}",
+        output_path.display()
+    );
+    tracing::warn!(
+        "The plaintext private config '{}' still holds the key, delete it once the encrypted config is in place",
+        private_config_path.display()
+    );
+    Ok(())
+}
+
 /// Boot a single validator node.
 async fn run(
     authority: AuthorityIndex,
@@ -191,6 +625,7 @@ async fn run(
     public_config_path: String,
     private_config_path: String,
     client_parameters_path: String,
+    password: PasswordArgs,
 ) -> Result<()> {
     tracing::info!("Starting validator {authority}");
 
@@ -199,29 +634,17 @@ async fn run(
     let public_config = NodePublicConfig::load(&public_config_path).wrap_err(format!(
         "Failed to load parameters file '{public_config_path}'"
     ))?;
-    let private_config = NodePrivateConfig::load(&private_config_path).wrap_err(format!(
-        "Failed to load private configuration file '{private_config_path}'"
-    ))?;
+    let private_config =
+        NodePrivateConfig::load_with_password(&private_config_path, password.password()?.as_ref())
+            .wrap_err(format!(
+                "Failed to load private configuration file '{private_config_path}'"
+            ))?;
     let client_parameters = ClientParameters::load(&client_parameters_path).wrap_err(format!(
         "Failed to load client parameters file '{client_parameters_path}'"
     ))?;
 
     let committee = Arc::new(committee);
 
-    let network_address = public_config
-        .network_address(authority)
-        .ok_or(eyre!("No network address for authority {authority}"))
-        .wrap_err("Unknown authority")?;
-    let mut binding_network_address = network_address;
-    binding_network_address.set_ip(IpAddr::V4(Ipv4Addr::UNSPECIFIED));
-
-    let metrics_address = public_config
-        .metrics_address(authority)
-        .ok_or(eyre!("No metrics address for authority {authority}"))
-        .wrap_err("Unknown authority")?;
-    let mut binding_metrics_address = metrics_address;
-    binding_metrics_address.set_ip(IpAddr::V4(Ipv4Addr::UNSPECIFIED));
-
     // Boot the validator node.
     let validator = Validator::start(
         authority,
@@ -231,8 +654,169 @@ async fn run(
         client_parameters,
     )
     .await?;
-    let (network_result, _metrics_result) = validator.await_completion().await;
-    network_result.expect("Validator crashed");
+    tokio::spawn(reload_on_hangup(
+        public_config_path,
+        validator.address_book(),
+        validator.parameters(),
+    ));
+    // Crashes abort the process (see the release profile), otherwise the validator runs until an
+    // operator stops it through the admin routes
+    validator.await_shutdown_request().await;
+    tracing::info!("Stopping validator {authority}");
+    validator.stop().await;
+    Ok(())
+}
+
+/// Reload the addresses of the peers and the reloadable parameters from the public config whenever
+/// the process receives SIGHUP, so that operators can point the validator to peers that moved or
+/// tune it without restarting it.
+async fn reload_on_hangup(
+    public_config_path: String,
+    address_book: AddressBook,
+    parameters: LiveParameters,
+) {
+    let mut hangup = match signal(SignalKind::hangup()) {
+        Ok(hangup) => hangup,
+        Err(e) => {
+            warn!("Failed to listen for SIGHUP, the public config can not be reloaded: {e}");
+            return;
+        }
+    };
+    while hangup.recv().await.is_some() {
+        match NodePublicConfig::load(&public_config_path) {
+            Ok(public_config) => {
+                let addresses: Vec<_> = public_config.all_network_addresses().collect();
+                address_book.reload(&addresses);
+                info!("Reloaded the addresses from '{public_config_path}'");
+                match parameters.reload(public_config.parameters) {
+                    Ok(changed) => info!(
+                        "Reloaded the parameters from '{public_config_path}': [{}]",
+                        changed.join(", ")
+                    ),
+                    Err(e) => warn!("Failed to reload the parameters: {e}"),
+                }
+            }
+            Err(e) => warn!("Failed to reload '{public_config_path}': {e}"),
+        }
+    }
+}
+
+/// Boot a single observer node.
+async fn observe(
+    observer: usize,
+    committee_path: String,
+    public_config_path: String,
+    storage_path: PathBuf,
+) -> Result<()> {
+    tracing::info!("Starting observer {observer}");
+
+    let committee = Committee::load(&committee_path)
+        .wrap_err(format!("Failed to load committee file '{committee_path}'"))?;
+    let public_config = NodePublicConfig::load(&public_config_path).wrap_err(format!(
+        "Failed to load parameters file '{public_config_path}'"
+    ))?;
+    fs::create_dir_all(&storage_path).wrap_err(format!(
+        "Failed to create directory '{}'",
+        storage_path.display()
+    ))?;
+
+    let observer = Observer::start(
+        observer,
+        Arc::new(committee),
+        public_config,
+        &storage_path,
+    )
+    .await?;
+    let (network_result, _metrics_result) = observer.await_completion().await;
+    network_result.expect("Observer crashed");
+    Ok(())
+}
+
+#[allow(clippy::too_many_arguments)]
+fn export_dag(
+    authority: AuthorityIndex,
+    committee_path: String,
+    public_config_path: String,
+    wal_path: PathBuf,
+    from: Option<RoundNumber>,
+    to: Option<RoundNumber>,
+    format: DagFormat,
+    annotate: bool,
+    output_path: Option<PathBuf>,
+) -> Result<()> {
+    let committee = Committee::load(&committee_path)
+        .wrap_err(format!("Failed to load committee file '{committee_path}'"))?;
+    let public_config = NodePublicConfig::load(&public_config_path).wrap_err(format!(
+        "Failed to load parameters file '{public_config_path}'"
+    ))?;
+    let export = dag_export::export_wal(
+        wal_path,
+        authority,
+        Arc::new(committee),
+        &public_config.parameters,
+        from,
+        to,
+        annotate,
+    )?;
+    let rendered = export.render(format);
+    match output_path {
+        Some(path) => {
+            fs::write(&path, rendered)
+                .wrap_err(format!("Failed to write file '{}'", path.display()))?;
+            tracing::info!(
+                "Exported rounds {} to {} to {}",
+                export.from_round,
+                export.to_round,
+                path.display()
+            );
+        }
+        None => println!("{rendered}"),
+    }
+    Ok(())
+}
+
+/// Inspect or repair a wal file offline.
+fn wal(operation: WalOperation) -> Result<()> {
+    match operation {
+        WalOperation::Stats { wal_path } => {
+            let stats = wal_inspect::stats(wal_path)?;
+            println!("{} bytes", stats.size_bytes);
+            for (tag, tag_stats) in stats.entries {
+                println!(
+                    "{tag}: {} entries, {} bytes",
+                    tag_stats.count, tag_stats.bytes
+                );
+            }
+        }
+        WalOperation::Dump { wal_path, from, to } => {
+            for entry in wal_inspect::dump(wal_path, from, to)? {
+                println!("{entry}");
+            }
+        }
+        WalOperation::Verify { wal_path } => {
+            let verification = wal_inspect::verify(&wal_path)?;
+            if let (Some(error), Some(position)) =
+                (verification.error, verification.invalid_position)
+            {
+                return Err(eyre!(
+                    "{error} (after {} valid entries), truncate the wal at position {position} to drop it and the entries after it",
+                    verification.valid_entries
+                ));
+            }
+            tracing::info!(
+                "Wal '{}' is valid ({} entries)",
+                wal_path.display(),
+                verification.valid_entries
+            );
+        }
+        WalOperation::Truncate { wal_path, position } => {
+            let removed = wal_inspect::truncate(&wal_path, position)?;
+            tracing::info!(
+                "Truncated wal '{}' at position {position}, removed {removed} bytes",
+                wal_path.display()
+            );
+        }
+    }
     Ok(())
 }
 
@@ -278,7 +862,7 @@ async fn dryrun(authority: AuthorityIndex, committee_size: usize) -> Result<()>
         client_parameters,
     )
     .await?;
-    let (network_result, _metrics_result) = validator.await_completion().await;
+    let (network_result, _metrics_result, _admin_result) = validator.await_completion().await;
     network_result.expect("Validator crashed");
 
     Ok(())